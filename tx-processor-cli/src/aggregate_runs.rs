@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::io::{self, Write};
+
+use clap::Args;
+use rust_decimal::Decimal;
+
+use tx_processor::account::{AccountRecord, AccountView};
+use tx_processor::encryption::StateKey;
+use tx_processor::io::{self as tx_io, AccountColumns};
+use tx_processor::ledger::Ledger;
+use tx_processor::report;
+use tx_processor::snapshot::Snapshot;
+use tx_processor::transaction::PositiveDecimal;
+
+#[derive(Args)]
+pub(crate) struct AggregateArgs {
+    /// The accounts written by runs, e.g. one per region, as CSV files ending in `.csv` or as
+    /// checkpoints and closing states. Prefix each with a namespace, e.g. `eu=eu.csv`, when the
+    /// runs' client ids may collide; the accounts are then written with a leading `namespace`
+    /// column
+    #[clap(required = true, value_name = "[NAMESPACE=]FILE", parse(from_str = parse_run))]
+    pub(crate) runs: Vec<Run>,
+    /// The key file the snapshots were encrypted with, see `--state-key-file` of processing
+    #[clap(long, value_name = "KEY_FILE")]
+    pub(crate) state_key_file: Option<String>,
+    /// The columns of the accounts written to standard output, see processing
+    #[clap(long, value_name = "VERSION", default_value = "v1", parse(try_from_str = crate::parse_account_columns))]
+    pub(crate) output_compat: AccountColumns,
+}
+
+/// The output of a run, and the namespace of its client ids
+pub(crate) struct Run {
+    namespace: Option<String>,
+    path: String,
+}
+
+impl Run {
+    fn name(&self) -> &str {
+        self.namespace.as_deref().unwrap_or(&self.path)
+    }
+}
+
+fn parse_run(s: &str) -> Run {
+    // `=` can't start a path, nor follow a URL's scheme
+    match s.split_once('=') {
+        Some((namespace, path)) if !namespace.is_empty() && !namespace.contains(['/', ':']) => {
+            Run {
+                namespace: Some(namespace.to_string()),
+                path: path.to_string(),
+            }
+        }
+        _ => Run {
+            namespace: None,
+            path: s.to_string(),
+        },
+    }
+}
+
+/// An account as a run's accounts report wrote it. Unlike opening balances, its held funds
+/// are only added up, so they needn't be backed by a dispute.
+struct ReportedAccount {
+    client_id: u16,
+    available: PositiveDecimal,
+    held: PositiveDecimal,
+    locked: bool,
+}
+
+impl TryFrom<AccountRecord> for ReportedAccount {
+    type Error = Box<dyn Error>;
+
+    fn try_from(record: AccountRecord) -> Result<Self, Self::Error> {
+        Ok(ReportedAccount {
+            client_id: record.client_id,
+            available: PositiveDecimal::try_from(record.available)?,
+            held: PositiveDecimal::try_from(record.held)?,
+            locked: record.locked,
+        })
+    }
+}
+
+impl AccountView for ReportedAccount {
+    fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    fn available(&self) -> PositiveDecimal {
+        self.available
+    }
+
+    fn held(&self) -> PositiveDecimal {
+        self.held
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    fn flags(&self) -> &BTreeSet<String> {
+        static NO_FLAGS: BTreeSet<String> = BTreeSet::new();
+        &NO_FLAGS
+    }
+}
+
+/// The accounts of a run, read from its accounts report or restored from its snapshot
+enum RunAccounts {
+    Report(Vec<ReportedAccount>),
+    Snapshot(Box<Ledger>),
+}
+
+impl RunAccounts {
+    fn iter(&self) -> Box<dyn Iterator<Item = &dyn AccountView> + '_> {
+        match self {
+            RunAccounts::Report(accounts) => {
+                Box::new(accounts.iter().map(|account| account as &dyn AccountView))
+            }
+            RunAccounts::Snapshot(ledger) => Box::new(ledger.accounts()),
+        }
+    }
+}
+
+/// The accounts of a run's accounts report or snapshot
+fn read_run(run: &Run, key: Option<&StateKey>) -> Result<RunAccounts, Box<dyn Error>> {
+    let input = tx_io::decompress(&run.path, tx_io::open_input(&run.path)?);
+    if run.path.trim_end_matches(".gz").ends_with(".csv") {
+        let records = tx_io::read_account_records(input)?;
+        Ok(RunAccounts::Report(
+            records
+                .into_iter()
+                .map(ReportedAccount::try_from)
+                .collect::<Result<_, _>>()?,
+        ))
+    } else {
+        Ok(RunAccounts::Snapshot(
+            Snapshot::read(input, key)?
+                .restore(Ledger::default())?
+                .into(),
+        ))
+    }
+}
+
+/// The totals of the accounts of a run, or of all runs
+#[derive(Default)]
+struct Totals {
+    accounts: usize,
+    locked: usize,
+    available: Decimal,
+    held: Decimal,
+}
+
+impl Totals {
+    fn add(&mut self, account: &dyn AccountView) {
+        self.accounts += 1;
+        self.locked += usize::from(account.is_locked());
+        self.available += Decimal::from(account.available());
+        self.held += Decimal::from(account.held());
+    }
+}
+
+impl std::fmt::Display for Totals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} accounts, {} locked, available {}, held {}, total {}",
+            self.accounts,
+            self.locked,
+            self.available,
+            self.held,
+            self.available + self.held
+        )
+    }
+}
+
+pub(crate) fn run(args: &AggregateArgs) -> Result<(), Box<dyn Error>> {
+    let namespaced = args.runs[0].namespace.is_some();
+    if args
+        .runs
+        .iter()
+        .any(|run| run.namespace.is_some() != namespaced)
+    {
+        return Err("Give every run a namespace or none of them".into());
+    }
+    let key = crate::state_key(args.state_key_file.as_deref())?;
+    let mut ledgers = Vec::with_capacity(args.runs.len());
+    for run in &args.runs {
+        let accounts = read_run(run, key.as_ref()).map_err(|e| {
+            format!(
+                "Failed to read {}: {}",
+                run.path,
+                crate::describe(e.as_ref())
+            )
+        })?;
+        ledgers.push(accounts);
+    }
+
+    // the runs of every client, to find the ids that collide
+    let mut clients = BTreeMap::<_, Vec<&Run>>::new();
+    let mut total = Totals::default();
+    for (run, ledger) in args.runs.iter().zip(&ledgers) {
+        let mut totals = Totals::default();
+        for account in ledger.iter() {
+            totals.add(account);
+            total.add(account);
+            clients.entry(account.client_id()).or_default().push(run);
+        }
+        eprintln!("{}: {}", run.name(), totals);
+    }
+    eprintln!("Total: {}", total);
+
+    for (client_id, runs) in &clients {
+        if runs.len() < 2 {
+            continue;
+        }
+        let names = runs.iter().map(|run| run.name()).collect::<Vec<_>>();
+        let same_namespace = runs.iter().enumerate().any(|(i, run)| {
+            runs[..i]
+                .iter()
+                .any(|other| other.namespace == run.namespace)
+        });
+        if !namespaced {
+            return Err(format!(
+                "Client {} is in {}, prefix the runs with namespaces to keep them apart",
+                client_id,
+                names.join(" and ")
+            )
+            .into());
+        }
+        if same_namespace {
+            return Err(format!(
+                "Client {} is in more than one run of the same namespace: {}",
+                client_id,
+                names.join(" and ")
+            )
+            .into());
+        }
+        eprintln!(
+            "Client {} is in {}, kept apart by namespace",
+            client_id,
+            names.join(" and ")
+        );
+    }
+
+    let mut stdout = io::stdout().lock();
+    if namespaced {
+        write_namespaced_accounts_csv(&args.runs, &ledgers, args.output_compat, &mut stdout)?;
+    } else {
+        report::write_account_views_csv(
+            ledgers.iter().flat_map(RunAccounts::iter),
+            args.output_compat,
+            &mut stdout,
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Writes the accounts report of every run, each row preceded by the namespace of its run
+fn write_namespaced_accounts_csv(
+    runs: &[Run],
+    ledgers: &[RunAccounts],
+    columns: AccountColumns,
+    writer: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for (i, (run, ledger)) in runs.iter().zip(ledgers).enumerate() {
+        let mut accounts = Vec::new();
+        report::write_account_views_csv(ledger.iter(), columns, &mut accounts)?;
+        let mut reader = csv::Reader::from_reader(accounts.as_slice());
+        if i == 0 {
+            writer.write_record(["namespace"].into_iter().chain(reader.headers()?))?;
+        }
+        // runs are namespaced whenever one is
+        let namespace = run.namespace.as_deref().unwrap_or_default();
+        for row in reader.records() {
+            writer.write_record([namespace].into_iter().chain(&row?))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use csv::{ReaderBuilder, Trim};
+use hdrhistogram::Histogram;
+use log::warn;
+
+use tx_processor::ledger::Ledger;
+use tx_processor::transaction::{Transaction, TransactionRecord};
+
+#[derive(Args)]
+pub(crate) struct BenchArgs {
+    /// The input file of transactions to process
+    pub(crate) input_file: String,
+}
+
+/// Throughput and latency of processing a single file
+struct BenchReport {
+    records: u64,
+    elapsed: Duration,
+    /// Per-record parse and apply latency, in nanoseconds
+    latencies: Histogram<u64>,
+    /// Peak resident set size in KiB, if the platform reports it
+    peak_rss_kib: Option<u64>,
+}
+
+pub(crate) fn run(args: &BenchArgs) -> Result<(), Box<dyn Error>> {
+    let mut reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .from_path(&args.input_file)?;
+    let mut ledger = Ledger::default();
+    let mut latencies = Histogram::<u64>::new(3)?;
+    let mut records = 0;
+
+    let mut results = reader.deserialize::<TransactionRecord>();
+    let start = Instant::now();
+    loop {
+        // the latency of a record covers reading, parsing and applying it
+        let record_start = Instant::now();
+        let result = match results.next() {
+            Some(result) => result,
+            None => break,
+        };
+        let applied = result
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|record| Ok(Transaction::try_from(record)?))
+            .and_then(|transaction| Ok(ledger.add_tx(transaction)?));
+        latencies.record(record_start.elapsed().as_nanos() as u64)?;
+        if let Err(e) = applied {
+            warn!("Rejected record: {:?}", e);
+        }
+        records += 1;
+    }
+
+    let report = BenchReport {
+        records,
+        elapsed: start.elapsed(),
+        latencies,
+        peak_rss_kib: peak_rss_kib(),
+    };
+    report.print();
+    Ok(())
+}
+
+impl BenchReport {
+    fn print(&self) {
+        let seconds = self.elapsed.as_secs_f64();
+        println!("records:      {}", self.records);
+        println!("elapsed:      {:.3}s", seconds);
+        println!(
+            "throughput:   {:.0} records/sec",
+            self.records as f64 / seconds.max(f64::EPSILON)
+        );
+        println!("latency p50:  {}ns", self.latencies.value_at_quantile(0.50));
+        println!("latency p99:  {}ns", self.latencies.value_at_quantile(0.99));
+        match self.peak_rss_kib {
+            Some(kib) => println!("peak RSS:     {} KiB", kib),
+            None => println!("peak RSS:     unavailable"),
+        }
+    }
+}
+
+/// Reads the high water mark of the resident set size, only available on Linux
+fn peak_rss_kib() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
@@ -0,0 +1,151 @@
+//! Turning a panic while processing into a machine-readable failure summary and a best-effort
+//! checkpoint, so a long run that hits a bug still leaves a trace of how far it got
+
+use std::cell::{Cell, RefCell};
+use std::panic;
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+
+use tx_processor::transaction::{Provenance, TransactionRecord};
+
+/// The message and location of the last panic, recorded by the hook of [install_hook]
+static PANIC: Mutex<Option<(String, Option<String>)>> = Mutex::new(None);
+
+/// Records the message and location of every panic for [FailureSummary], after the default
+/// hook prints them as usual
+pub(crate) fn install_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default(info);
+        let payload = info.payload();
+        let message = match (
+            payload.downcast_ref::<&str>(),
+            payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => "unknown panic payload".to_string(),
+        };
+        let location = info.location().map(ToString::to_string);
+        *PANIC.lock().unwrap_or_else(PoisonError::into_inner) = Some((message, location));
+    }));
+}
+
+/// The records handed to the ledger so far, and where the last of them was read
+#[derive(Default)]
+pub(crate) struct Position {
+    records: Cell<u64>,
+    provenance: RefCell<Option<Provenance>>,
+    /// `true` while the next record is read, rather than the last one processed
+    reading: Cell<bool>,
+}
+
+impl Position {
+    /// The number of records the ledger finished processing. A panic while the ledger
+    /// processed a record may have applied part of it.
+    fn processed(&self) -> u64 {
+        if self.reading.get() {
+            self.records.get()
+        } else {
+            self.records.get().saturating_sub(1)
+        }
+    }
+}
+
+/// Tracks the [Position] of the records passing through, as the last stage before the ledger
+pub(crate) struct Track<'a, I> {
+    records: I,
+    position: &'a Position,
+    /// Panics on this record, to exercise the failure summary. Only debug builds read it from
+    /// the environment, so a stray variable can't crash a release build.
+    panic_at: Option<u64>,
+}
+
+impl<'a, I> Track<'a, I> {
+    pub(crate) fn new(records: I, position: &'a Position) -> Self {
+        Track {
+            records,
+            position,
+            panic_at: std::env::var("TX_PROCESSOR_PANIC_AT_RECORD")
+                .ok()
+                .filter(|_| cfg!(debug_assertions))
+                .and_then(|record| record.parse().ok()),
+        }
+    }
+}
+
+impl<I, E> Iterator for Track<'_, I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    type Item = Result<TransactionRecord, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.position.reading.set(true);
+        let record = self.records.next()?;
+        self.position.reading.set(false);
+        let records = self.position.records.get() + 1;
+        self.position.records.set(records);
+        if let Ok(record) = &record {
+            self.position.provenance.replace(record.provenance.clone());
+        }
+        if self.panic_at == Some(records) {
+            panic!("injected panic at record {}", records);
+        }
+        Some(record)
+    }
+}
+
+/// What a run that panicked got through, printed as a single line of JSON
+#[derive(Serialize)]
+pub(crate) struct FailureSummary<'a> {
+    status: &'static str,
+    message: String,
+    /// The source location of the panic
+    location: Option<String>,
+    /// The number of records processed before the panic, including those of the run resumed
+    records: u64,
+    /// Where the last record handed to the ledger was read
+    source: Option<String>,
+    line: Option<u64>,
+    /// The byte of the source the record starts at
+    offset: Option<u64>,
+    /// The checkpoint written to resume from, retrying the record being processed, unless the
+    /// balances the panic left didn't match the transaction log
+    checkpoint: Option<&'a str>,
+}
+
+impl<'a> FailureSummary<'a> {
+    /// The summary of the panic recorded by the hook, after `position`, on top of the
+    /// `resumed` records of an earlier run
+    pub(crate) fn new(position: &Position, resumed: u64) -> Self {
+        let (message, location) = PANIC
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .unwrap_or_else(|| ("unknown panic".to_string(), None));
+        let provenance = position.provenance.borrow();
+        FailureSummary {
+            status: "panicked",
+            message,
+            location,
+            records: resumed + position.processed(),
+            source: provenance
+                .as_ref()
+                .map(|provenance| provenance.source.to_string()),
+            line: provenance.as_ref().map(|provenance| provenance.line),
+            offset: provenance.as_ref().map(|provenance| provenance.offset),
+            checkpoint: None,
+        }
+    }
+
+    pub(crate) fn records(&self) -> u64 {
+        self.records
+    }
+
+    pub(crate) fn with_checkpoint(mut self, path: &'a str) -> Self {
+        self.checkpoint = Some(path);
+        self
+    }
+}
@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fs::File;
+use std::io;
+
+use clap::Args;
+use csv::WriterBuilder;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tx_processor::generate::{Workload, WorkloadConfig};
+
+#[derive(Args)]
+pub(crate) struct GenArgs {
+    /// Number of records to generate
+    #[clap(long, default_value_t = 1000)]
+    pub(crate) rows: usize,
+    /// Number of distinct clients the records are spread over
+    #[clap(long, default_value_t = 100)]
+    pub(crate) clients: u16,
+    /// Seed for the random number generator, the same seed always produces the same file
+    #[clap(long)]
+    pub(crate) seed: Option<u64>,
+    /// Probability that a record disputes one of the previous deposits or withdrawals
+    #[clap(long, default_value_t = 0.05)]
+    pub(crate) dispute_rate: f64,
+    /// Probability that a record resolves one of the open disputes
+    #[clap(long, default_value_t = 0.03)]
+    pub(crate) resolve_rate: f64,
+    /// Probability that a record charges back one of the open disputes
+    #[clap(long, default_value_t = 0.01)]
+    pub(crate) chargeback_rate: f64,
+    /// Write the records to this file instead of stdout
+    #[clap(long)]
+    pub(crate) output: Option<String>,
+}
+
+pub(crate) fn run(args: &GenArgs) -> Result<(), Box<dyn Error>> {
+    let rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let writer: Box<dyn io::Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut writer = WriterBuilder::new().from_writer(writer);
+
+    let workload = Workload::new(WorkloadConfig {
+        clients: args.clients,
+        dispute_rate: args.dispute_rate,
+        resolve_rate: args.resolve_rate,
+        chargeback_rate: args.chargeback_rate,
+        ..Default::default()
+    });
+    for record in rng.sample_iter(&workload).take(args.rows) {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use clap::Args;
+use csv::{ReaderBuilder, StringRecord, Trim};
+
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+const TRANSACTION_TYPES: [&str; 8] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "flag",
+    "unflag",
+    "remap",
+];
+
+#[derive(Args)]
+pub(crate) struct InspectArgs {
+    /// The file to inspect
+    pub(crate) input_file: String,
+    /// Number of rows sampled from the start of the file
+    #[clap(long, default_value_t = 1000)]
+    pub(crate) sample_rows: usize,
+}
+
+/// What the sampled rows of a single column look like
+#[derive(Default)]
+struct ColumnStats {
+    values: usize,
+    empty: usize,
+    transaction_types: usize,
+    client_ids: usize,
+    transaction_ids: usize,
+    decimals: usize,
+    fractional: usize,
+    negative: usize,
+}
+
+impl ColumnStats {
+    fn add(&mut self, value: &str) {
+        if value.is_empty() {
+            self.empty += 1;
+            return;
+        }
+        self.values += 1;
+        if TRANSACTION_TYPES.contains(&value.to_lowercase().as_str()) {
+            self.transaction_types += 1;
+        }
+        if value.parse::<u16>().is_ok() {
+            self.client_ids += 1;
+        }
+        if value.parse::<u32>().is_ok() {
+            self.transaction_ids += 1;
+        }
+        if value.parse::<f64>().is_ok() {
+            self.decimals += 1;
+            if value.contains('.') {
+                self.fractional += 1;
+            }
+            if value.starts_with('-') {
+                self.negative += 1;
+            }
+        }
+    }
+
+    /// `true` if at least 80% of the non-empty values matched, so a few malformed records don't
+    /// hide a column
+    fn mostly(&self, matched: usize) -> bool {
+        self.values > 0 && matched * 5 >= self.values * 4
+    }
+}
+
+pub(crate) fn run(args: &InspectArgs) -> Result<(), Box<dyn Error>> {
+    let mut lines = Vec::new();
+    let mut sampled_bytes = 0;
+    for line in BufReader::new(File::open(&args.input_file)?)
+        .lines()
+        .take(args.sample_rows + 1)
+    {
+        let line = line?;
+        sampled_bytes += line.len() + 1;
+        lines.push(line);
+    }
+    let file_size = std::fs::metadata(&args.input_file)?.len();
+    let mut anomalies = Vec::new();
+
+    let delimiter = detect_delimiter(&lines);
+    let mut records = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .trim(Trim::All)
+        .from_reader(lines.join("\n").as_bytes())
+        .into_records()
+        .collect::<Result<Vec<StringRecord>, _>>()?;
+
+    let has_header = records.first().is_some_and(|first| {
+        first.iter().all(|field| field.parse::<f64>().is_err())
+            && !first
+                .iter()
+                .any(|field| TRANSACTION_TYPES.contains(&field.to_lowercase().as_str()))
+    });
+    let header = if has_header {
+        Some(records.remove(0))
+    } else {
+        None
+    };
+
+    let columns = records.iter().map(StringRecord::len).max().unwrap_or(0);
+    let mut stats = (0..columns)
+        .map(|_| ColumnStats::default())
+        .collect::<Vec<_>>();
+    for (i, record) in records.iter().enumerate() {
+        if record.len() != columns {
+            anomalies.push(format!(
+                "row {} has {} of {} columns",
+                i + 1,
+                record.len(),
+                columns
+            ));
+        }
+        for (column, value) in record.iter().enumerate() {
+            stats[column].add(value);
+        }
+    }
+    if lines.iter().any(|line| line.trim().is_empty()) {
+        anomalies.push("the sample contains empty lines".to_owned());
+    }
+
+    let name = |column: usize| match &header {
+        Some(header) => format!("{} ({:?})", column, header.get(column).unwrap_or("")),
+        None => column.to_string(),
+    };
+    let candidates = |matches: &dyn Fn(&ColumnStats) -> bool| {
+        let candidates = stats
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| matches(stats))
+            .map(|(column, _)| name(column))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            "none".to_owned()
+        } else {
+            candidates.join(", ")
+        }
+    };
+
+    for (column, stats) in stats.iter().enumerate() {
+        if stats.negative > 0 {
+            anomalies.push(format!(
+                "column {} has {} negative values",
+                name(column),
+                stats.negative
+            ));
+        }
+        if stats.mostly(stats.transaction_types) && stats.transaction_types < stats.values {
+            anomalies.push(format!(
+                "column {} has {} values that aren't transaction types",
+                name(column),
+                stats.values - stats.transaction_types
+            ));
+        }
+        if stats.mostly(stats.decimals) && stats.decimals < stats.values {
+            anomalies.push(format!(
+                "column {} has {} values that aren't numbers",
+                name(column),
+                stats.values - stats.decimals
+            ));
+        }
+    }
+
+    let average_row_bytes = sampled_bytes as f64 / lines.len().max(1) as f64;
+    println!("file:         {}", args.input_file);
+    println!("sampled rows: {}", records.len());
+    println!("delimiter:    '{}'", char::from(delimiter).escape_default());
+    println!("header:       {}", if has_header { "yes" } else { "no" });
+    println!(
+        "rows (est.):  {:.0}",
+        file_size as f64 / average_row_bytes.max(1.0) - has_header as u8 as f64
+    );
+    println!("columns:      {}", columns);
+    println!(
+        "type:         {}",
+        candidates(&|s| s.mostly(s.transaction_types))
+    );
+    println!("client:       {}", candidates(&|s| s.mostly(s.client_ids)));
+    println!(
+        "tx:           {}",
+        candidates(&|s| s.mostly(s.transaction_ids))
+    );
+    println!(
+        "amount:       {}",
+        candidates(&|s| s.mostly(s.decimals) && (s.fractional > 0 || s.empty > 0))
+    );
+    if anomalies.is_empty() {
+        println!("anomalies:    none");
+    } else {
+        println!("anomalies:");
+        for anomaly in anomalies {
+            println!("  - {}", anomaly);
+        }
+    }
+
+    Ok(())
+}
+
+/// The delimiter found on the most sampled lines, ties broken by the number of occurrences
+fn detect_delimiter(lines: &[String]) -> u8 {
+    DELIMITERS
+        .iter()
+        .copied()
+        .max_by_key(|&delimiter| {
+            let occurrences = lines
+                .iter()
+                .map(|line| line.bytes().filter(|&b| b == delimiter).count())
+                .collect::<Vec<_>>();
+            (
+                occurrences.iter().filter(|&&count| count > 0).count(),
+                occurrences.iter().sum::<usize>(),
+            )
+        })
+        .unwrap()
+}
@@ -1,41 +1,1221 @@
+use std::collections::BTreeSet;
 use std::error::Error;
-use std::io;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::ops::ControlFlow;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-use csv::{ReaderBuilder, Trim, WriterBuilder};
+use clap::{Args, CommandFactory, ErrorKind, Parser, Subcommand};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
 
+use tx_processor::aggregate::{self, AggregationKey};
+use tx_processor::aliases::SequentialClientIds;
+use tx_processor::amount::AmountParsing;
+use tx_processor::availability::AvailabilityDelay;
+use tx_processor::build_info::BuildInfo;
+use tx_processor::cancel::CancellationToken;
+use tx_processor::checksum::Checksum;
+use tx_processor::clients::{self, ClientFilter, TestClients};
+use tx_processor::consistency;
+use tx_processor::csv_limits::CsvLimits;
+use tx_processor::dedup::Dedup;
+use tx_processor::dispute_rules;
+use tx_processor::encryption::StateKey;
+use tx_processor::error::TxError;
+use tx_processor::gaps::{GapDetector, SequenceKey};
+use tx_processor::io::{
+    self as tx_io, AccountColumns, AccountOrder, CsvOptions, CsvSource, Durability, IterSource,
+    JsonSource, OutputFile, TransactionSource,
+};
+use tx_processor::journal::ChartOfAccounts;
 use tx_processor::ledger::Ledger;
+use tx_processor::limits::Limits;
+use tx_processor::normalize::Normalize;
+use tx_processor::policies::{NegativeAmountPolicy, Policy, UnknownTransactionPolicy};
+use tx_processor::pseudonym::Pseudonymizer;
+use tx_processor::reorder::ReorderBuffer;
+use tx_processor::reserve;
+use tx_processor::risk::FreezeThresholds;
+use tx_processor::sample::Sample;
+use tx_processor::snapshot::{InputDigest, RunHistory, Snapshot};
+use tx_processor::tags::{self, Tagger};
+use tx_processor::throttle::{Replay, Throttle};
+use tx_processor::transaction::TransactionRecordType;
+use tx_processor::types::{TypeAlias, TypeTable};
+use tx_processor::{report, rollup, settlement};
+
+mod aggregate_runs;
+mod bench;
+mod crash;
+mod generate;
+mod inspect;
+mod policies;
+#[cfg(feature = "profiling")]
+mod profiling;
+mod query;
+mod schema;
+mod shards;
+mod split;
+mod verify_input;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// The input file of transactions
-    pub(crate) input_file: String,
+    #[clap(subcommand)]
+    pub(crate) command: Option<Command>,
+    #[clap(flatten)]
+    pub(crate) process: ProcessArgs,
+    /// Sample the run and write a flamegraph of it to this SVG file
+    #[cfg(feature = "profiling")]
+    #[clap(long, global = true)]
+    pub(crate) profile: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a file of random transactions
+    Gen(generate::GenArgs),
+    /// Process a file and report throughput, per-record latency and peak memory usage
+    Bench(bench::BenchArgs),
+    /// Sample a file and report its layout, likely column mapping and anomalies
+    Inspect(inspect::InspectArgs),
+    /// Print what a checkpoint or closing state records about an account, a transaction or the
+    /// disputes, as JSON
+    Query(query::QueryArgs),
+    /// Print the policies a run with these flags applies, and what they do
+    ExplainPolicies(policies::PolicyArgs),
+    /// Check that a checkpoint or closing state was derived from these input files, by the
+    /// checksums it records
+    VerifyInput(verify_input::VerifyInputArgs),
+    /// Print the JSON Schema of an input record, a report row or a snapshot
+    Schema(schema::SchemaArgs),
+    /// Split a file of transactions into shards by a hash of the client, to process with
+    /// `shards`
+    Split(split::SplitArgs),
+    /// Process a directory of files split by client, one shard per file concurrently, and write
+    /// the accounts of all of them
+    Shards(shards::ShardsArgs),
+    /// Combine the accounts written by several runs, e.g. one per region, checking that their
+    /// client ids don't collide, and report their totals
+    Aggregate(aggregate_runs::AggregateArgs),
+}
+
+/// Processing a file of transactions is the default command
+#[derive(Args)]
+struct ProcessArgs {
+    /// The input files of transactions, processed in order, or `-` to read them from standard
+    /// input
+    pub(crate) input_files: Vec<String>,
+    /// A CSV file of opening balances, in the same format as this program's output
+    #[clap(long)]
+    pub(crate) opening_balances: Option<String>,
+    /// Write a report of every chargeback applied during this run to this file
+    #[clap(long)]
+    pub(crate) chargeback_report: Option<String>,
+    /// Write the net settlement of every client for this run to this file
+    #[clap(long)]
+    pub(crate) settlement: Option<String>,
+    /// Client ids of test accounts, e.g. `60000-65535`, whose transactions are applied but left
+    /// out of the settlement, aggregate and category reports. May be given multiple times
+    #[clap(long, value_name = "RANGE", parse(try_from_str = parse_client_range))]
+    pub(crate) test_clients: Vec<RangeInclusive<u16>>,
+    /// Write the net settlement of the `--test-clients` for this run to this file
+    #[clap(long, requires = "test-clients")]
+    pub(crate) test_settlement: Option<String>,
+    /// Give the external client ids of a `client_ref` column, e.g. UUIDs, the client ids from
+    /// this one up that have no account yet. The ids given are kept in checkpoints and closing
+    /// states, so later runs resolve the same external ids to the same clients
+    #[clap(long, value_name = "ID")]
+    pub(crate) client_ids_from: Option<u16>,
+    /// Write every external client id and the client id it resolved to to this file
+    #[clap(long, value_name = "FILE")]
+    pub(crate) client_alias_log: Option<String>,
+    /// Write the counts and totals of this run's transactions grouped by `--aggregate-by` to
+    /// this file, as JSON if its name ends in `.json` and as CSV otherwise
+    #[clap(long)]
+    pub(crate) aggregate_report: Option<String>,
+    /// What to group the aggregate report by: `client`, `category`, `type` or `day`, the day of
+    /// the `timestamp` column in seconds since the Unix epoch
+    #[clap(long, value_name = "KEY", default_value = "client", parse(try_from_str = parse_aggregation_key))]
+    pub(crate) aggregate_by: AggregationKey,
+    /// Write the balance at the end of each day of every client with transactions that day to
+    /// this file, by the day of the `timestamp` column
+    #[clap(long)]
+    pub(crate) daily_balances: Option<String>,
+    /// Replace client ids in every report, including the accounts written to standard output,
+    /// with their HMAC-SHA256 under the key in this file. Checkpoints and closing states keep
+    /// the ids, to be read back by later runs
+    #[clap(long, value_name = "KEY_FILE")]
+    pub(crate) pseudonymize: Option<String>,
+    /// A TOML file of `[[rule]]` tables categorizing transactions, e.g. deposits from an
+    /// employer as `payroll`. Records with a `category` column keep their category
+    #[clap(long, value_name = "FILE")]
+    pub(crate) tag_rules: Option<String>,
+    /// Write the number and amount of every client's transactions by category to this file
+    #[clap(long)]
+    pub(crate) category_report: Option<String>,
+    /// Reject every transaction of this type, e.g. `chargeback`. May be given multiple times
+    #[clap(long = "disable", value_name = "TYPE", parse(try_from_str = parse_transaction_type))]
+    pub(crate) disabled: Vec<TransactionRecordType>,
+    /// Apply only the transactions of these clients, e.g. `1,2,3`, rejecting the others. May
+    /// be given multiple times
+    #[clap(long, value_name = "IDS", parse(try_from_str = parse_client_ids))]
+    pub(crate) allow_clients: Vec<BTreeSet<u16>>,
+    /// A file of the only clients to apply transactions of, one id per line
+    #[clap(long, value_name = "FILE")]
+    pub(crate) allow_clients_file: Option<String>,
+    /// Reject every transaction of these clients, e.g. sanctioned or test accounts. May be
+    /// given multiple times
+    #[clap(long, value_name = "IDS", parse(try_from_str = parse_client_ids))]
+    pub(crate) deny_clients: Vec<BTreeSet<u16>>,
+    /// A file of clients to reject every transaction of, one id per line
+    #[clap(long, value_name = "FILE")]
+    pub(crate) deny_clients_file: Option<String>,
+    /// Read records of type NAME as records of TYPE, e.g. `payment=deposit`, or skip them with
+    /// `NAME=ignore`. May be given multiple times
+    #[clap(long = "type-alias", value_name = "NAME=TYPE", parse(try_from_str = parse_type_alias))]
+    pub(crate) type_aliases: Vec<(String, TypeAlias)>,
+    /// Write every record set aside by `--unknown-types quarantine` to this file
+    #[clap(long)]
+    pub(crate) unknown_types_report: Option<String>,
+    /// Write every rejected transaction and why it was rejected to this file
+    #[clap(long)]
+    pub(crate) reject_report: Option<String>,
+    /// Handle disputes by the reason code in their `note` column as this file says, one
+    /// `REASON,ACTION` per line: `hold`, `escalate`, flagging the account `escalated`, or
+    /// `resolve`, optionally followed by the largest amount to resolve right away
+    #[clap(long, value_name = "FILE")]
+    pub(crate) dispute_rules: Option<String>,
+    /// Write every dispute resolved or escalated by `--dispute-rules` to this file
+    #[clap(long, requires = "dispute-rules")]
+    pub(crate) dispute_decisions: Option<String>,
+    /// Freeze the accounts whose risk score, from 0 to 100, exceeds this as they transact,
+    /// flagging them `frozen` and rejecting their withdrawals until they're unflagged
+    #[clap(long, value_name = "SCORE")]
+    pub(crate) freeze_risk_score: Option<u8>,
+    /// Freeze the accounts with more chargebacks than this as they transact
+    #[clap(long, value_name = "N")]
+    pub(crate) freeze_chargebacks: Option<u32>,
+    /// Post deposits as pending, holding their funds until this many more records were
+    /// processed
+    #[clap(long, value_name = "N", conflicts_with = "pending-seconds")]
+    pub(crate) pending_records: Option<u64>,
+    /// Post deposits as pending, holding their funds until a record timestamped this many
+    /// seconds later arrives
+    #[clap(long, value_name = "SECONDS")]
+    pub(crate) pending_seconds: Option<i64>,
+    /// Release the deposits still pending at the end of the run before the accounts are written
+    #[clap(long)]
+    pub(crate) release_pending: bool,
+    /// Hold a share of the deposits of the clients in this file as a rolling reserve, one
+    /// `CLIENT,PERCENT,DAYS` per line, e.g. `42,10,30` to hold 10% of every deposit of client 42
+    /// for 30 days after its timestamp
+    #[clap(long, value_name = "FILE")]
+    pub(crate) reserves: Option<String>,
+    /// Close the statement period at the end of the run as this label, e.g. `2024-01`, so
+    /// records timestamped within it are rejected by later runs opened with the closing state
+    #[clap(long, value_name = "LABEL")]
+    pub(crate) close_period: Option<String>,
+    /// Write the transactions of the period closed by `--close-period` to this file, whose
+    /// SHA-256 is reported
+    #[clap(long, value_name = "FILE", requires = "close-period")]
+    pub(crate) period_archive: Option<String>,
+    /// Write every record timestamped within a closed period, and what `--late-arrivals` did
+    /// with it, to this file
+    #[clap(long, value_name = "FILE")]
+    pub(crate) late_arrival_log: Option<String>,
+    /// Post every transaction to the system accounts and write their trial balance to this file
+    #[clap(long)]
+    pub(crate) trial_balance: Option<String>,
+    /// Write every record rewritten by `--negative-amounts normalize` to this file, as read and
+    /// as applied
+    #[clap(long)]
+    pub(crate) audit_log: Option<String>,
+    /// Park disputes, resolves and chargebacks of unknown transactions until the transaction
+    /// arrives, and write the ones that never matched to this file
+    #[clap(long)]
+    pub(crate) suspense_report: Option<String>,
+    /// Post every transaction to the system accounts and write the journal entries to this file
+    #[clap(long, requires = "gl-date")]
+    pub(crate) gl_export: Option<String>,
+    /// The date of the exported journal entries
+    #[clap(long, value_name = "DATE")]
+    pub(crate) gl_date: Option<String>,
+    /// A CSV file of `account,type,code` rows mapping the system accounts to general ledger
+    /// account codes, optionally for a single transaction type
+    #[clap(long)]
+    pub(crate) chart_of_accounts: Option<String>,
+    /// Stop processing after this many seconds and write a checkpoint to resume from
+    #[clap(long, value_name = "SECONDS", requires = "checkpoint")]
+    pub(crate) time_budget: Option<f64>,
+    /// Where to write the checkpoint when the time budget is exceeded or the run is
+    /// interrupted
+    #[clap(long)]
+    pub(crate) checkpoint: Option<String>,
+    /// Report the SHA-256 of every input file, and record them in checkpoints and closing states
+    /// to check with `verify-input`
+    #[clap(long)]
+    pub(crate) checksums: bool,
+    /// A file of 64 hex digits, the AES-256 key checkpoints and closing states are encrypted
+    /// with and opening states and checkpoints to resume from decrypted with. Defaults to the
+    /// `TX_PROCESSOR_STATE_KEY` environment variable; without either they are plain JSON
+    #[clap(long, value_name = "KEY_FILE")]
+    pub(crate) state_key_file: Option<String>,
+    /// Resume the run that wrote this checkpoint
+    #[clap(long, conflicts_with = "opening-balances")]
+    pub(crate) resume: Option<String>,
+    /// Write the accounts, transactions and open disputes at the end of this run to this file,
+    /// to open the next run with
+    #[clap(long)]
+    pub(crate) closing_state: Option<String>,
+    /// Keep the transactions of only this many runs, including this one, in the closing state
+    /// for later disputes. Open disputes are always kept.
+    #[clap(long, value_name = "RUNS", requires = "closing-state")]
+    pub(crate) retention: Option<u64>,
+    /// Start from the closing state of a previous run, so its disputes can be resolved or
+    /// charged back by this run's records
+    #[clap(long, conflicts_with_all = &["opening-balances", "resume"])]
+    pub(crate) opening_state: Option<String>,
+    /// Delay records by this many `seq` values to apply slightly out of order records in order
+    #[clap(long, value_name = "SEQ")]
+    pub(crate) reorder_window: Option<u64>,
+    /// Write the ranges of sequence numbers missing from each client's records to this file
+    #[clap(long)]
+    pub(crate) gap_report: Option<String>,
+    /// The column numbering each client's records without gaps: `seq` or `tx`
+    #[clap(long, value_name = "COLUMN", default_value = "seq", parse(try_from_str = parse_sequence_key))]
+    pub(crate) gap_key: SequenceKey,
+    /// Apply at most this many records per second, e.g. when replaying history into a live
+    /// system
+    #[clap(long, value_name = "RECORDS_PER_SECOND", parse(try_from_str = parse_rate))]
+    pub(crate) rate_limit: Option<f64>,
+    /// Replay the records by their `timestamp` column, this many times faster than they
+    /// happened, e.g. `60` to replay an hour of history in a minute
+    #[clap(long, value_name = "SPEED", parse(try_from_str = parse_replay_speed))]
+    pub(crate) replay_speed: Option<f64>,
+    /// Process only the first N records of the input, e.g. to try out flags on a huge file
+    #[clap(long, value_name = "N")]
+    pub(crate) limit: Option<usize>,
+    /// Process only the records of this fraction of the clients, e.g. `0.01`, chosen by
+    /// `--seed`. Every record of a sampled client is processed, so its disputes still find
+    /// their transactions
+    #[clap(long, value_name = "RATE", parse(try_from_str = parse_sample_rate))]
+    pub(crate) sample: Option<f64>,
+    /// The seed choosing the clients of `--sample`; the same seed samples the same clients
+    #[clap(long, value_name = "SEED", default_value_t = 0, requires = "sample")]
+    pub(crate) seed: u64,
+    /// Replay history: apply the records as usual, but only log rejections at debug level
+    #[clap(long)]
+    pub(crate) backfill: bool,
+    /// An id of this run, recorded along with the file and line of every transaction in the
+    /// chargeback and reject reports
+    #[clap(long)]
+    pub(crate) batch: Option<String>,
+    /// Maximum number of accounts, active or locked
+    #[clap(long, value_name = "N")]
+    pub(crate) max_accounts: Option<usize>,
+    /// Maximum number of transactions retained for disputes
+    #[clap(long, value_name = "N")]
+    pub(crate) max_transactions: Option<usize>,
+    /// Maximum number of disputes open at the same time
+    #[clap(long, value_name = "N")]
+    pub(crate) max_open_disputes: Option<usize>,
+    /// Maximum number of rejected transactions kept for the reject report, beyond which they're
+    /// only counted
+    #[clap(long, value_name = "N")]
+    pub(crate) max_quarantined: Option<usize>,
+    /// How amounts are parsed: `lenient` accepts scientific notation and rounds amounts with
+    /// more than four decimal places, `strict` rejects both
+    #[clap(long, value_name = "MODE", default_value = "lenient", parse(try_from_str = parse_amount_parsing))]
+    pub(crate) amounts: AmountParsing,
+    /// Reject input fields longer than this many bytes
+    #[clap(long, value_name = "BYTES", default_value_t = 1024)]
+    pub(crate) max_field_length: usize,
+    /// Skip input records longer than this many bytes without buffering them
+    #[clap(long, value_name = "BYTES", default_value_t = 16 * 1024)]
+    pub(crate) max_record_length: usize,
+    /// Reject input records with more than this many fields
+    #[clap(long, value_name = "N", default_value_t = 64)]
+    pub(crate) max_fields: usize,
+    /// Recompute every balance from the transactions of this run after processing, and fail
+    /// the run if any differs from the balance written
+    #[clap(long, conflicts_with_all = &["opening-balances", "opening-state", "resume", "max-transactions"])]
+    pub(crate) verify: bool,
+    /// The columns of the accounts written to standard output: `v1`, the original
+    /// `client,available,held,total,locked`, `v2`, which adds the `flags` of every account, or
+    /// `v3`, which adds the part of `held` that is `reserved` by `--reserves`
+    #[clap(long, value_name = "VERSION", default_value = "v1", parse(try_from_str = parse_account_columns))]
+    pub(crate) output_compat: AccountColumns,
+    /// Leave the accounts with nothing available or held out of the accounts written to standard
+    /// output, e.g. those opened by withdrawals rejected for insufficient funds
+    #[clap(long)]
+    pub(crate) skip_empty: bool,
+    /// Sync every report, checkpoint and closing state to disk before exiting, so none is lost
+    /// if the machine crashes right after the run
+    #[clap(long)]
+    pub(crate) fsync: bool,
+    #[clap(flatten)]
+    pub(crate) policies: policies::PolicyArgs,
+}
+
+/// Number of records processed between checks of the time budget
+const CHUNK_SIZE: usize = 1000;
+
+fn parse_transaction_type(s: &str) -> Result<TransactionRecordType, String> {
+    TransactionRecordType::deserialize(s.into_deserializer())
+        .map_err(|e: serde::de::value::Error| e.to_string())
+}
+
+fn parse_type_alias(s: &str) -> Result<(String, TypeAlias), String> {
+    let (name, alias) = s
+        .split_once('=')
+        .ok_or_else(|| format!("`{}` is not of the form NAME=TYPE", s))?;
+    let alias = match alias {
+        "ignore" => TypeAlias::Ignore,
+        transaction_type => TypeAlias::Type(parse_transaction_type(transaction_type)?),
+    };
+    Ok((name.to_string(), alias))
+}
+
+fn parse_client_ids(s: &str) -> Result<BTreeSet<u16>, String> {
+    clients::parse_client_ids(s).map_err(|e| e.to_string())
+}
+
+fn parse_client_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    clients::parse_client_range(s).map_err(|e| e.to_string())
+}
+
+fn parse_sequence_key(s: &str) -> Result<SequenceKey, String> {
+    match s {
+        "seq" => Ok(SequenceKey::Seq),
+        "tx" => Ok(SequenceKey::TransactionId),
+        _ => Err(format!("unknown column `{}`, expected `seq` or `tx`", s)),
+    }
+}
+
+fn parse_aggregation_key(s: &str) -> Result<AggregationKey, String> {
+    match s {
+        "client" => Ok(AggregationKey::Client),
+        "category" => Ok(AggregationKey::Category),
+        "type" => Ok(AggregationKey::Type),
+        "day" => Ok(AggregationKey::Day),
+        _ => Err(format!(
+            "unknown key `{}`, expected `client`, `category`, `type` or `day`",
+            s
+        )),
+    }
+}
+
+fn parse_account_columns(s: &str) -> Result<AccountColumns, String> {
+    match s {
+        "v1" => Ok(AccountColumns::V1),
+        "v2" => Ok(AccountColumns::V2),
+        "v3" => Ok(AccountColumns::V3),
+        _ => Err(format!(
+            "unknown version `{}`, expected `v1`, `v2` or `v3`",
+            s
+        )),
+    }
+}
+
+fn parse_amount_parsing(s: &str) -> Result<AmountParsing, String> {
+    match s {
+        "lenient" => Ok(AmountParsing::Lenient),
+        "strict" => Ok(AmountParsing::Strict),
+        _ => Err(format!(
+            "unknown mode `{}`, expected `lenient` or `strict`",
+            s
+        )),
+    }
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("`{}` is not a positive number of records", s)),
+    }
+}
+
+fn parse_replay_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed > 0.0 => Ok(speed),
+        _ => Err(format!("`{}` is not a positive speed", s)),
+    }
+}
+
+fn parse_sample_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok(rate),
+        _ => Err(format!("`{}` is not a fraction between 0 and 1", s)),
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
+    crash::install_hook();
 
-    let mut writer = WriterBuilder::new()
-        .has_headers(false)
-        .flexible(true)
-        .from_writer(io::stdout());
     let cli = Cli::parse();
+    #[cfg(feature = "profiling")]
+    let profiler = cli
+        .profile
+        .as_deref()
+        .map(profiling::Profiler::start)
+        .transpose()?;
+
+    let result = match cli.command {
+        Some(Command::Gen(args)) => generate::run(&args),
+        Some(Command::Bench(args)) => bench::run(&args),
+        Some(Command::Inspect(args)) => inspect::run(&args),
+        Some(Command::Query(args)) => query::run(&args),
+        Some(Command::ExplainPolicies(args)) => policies::run(&args),
+        Some(Command::VerifyInput(args)) => verify_input::run(&args),
+        Some(Command::Schema(args)) => schema::run(&args),
+        Some(Command::Split(args)) => split::run(&args),
+        Some(Command::Shards(args)) => shards::run(&args),
+        Some(Command::Aggregate(args)) => aggregate_runs::run(&args),
+        None => process(&cli.process),
+    };
+
+    #[cfg(feature = "profiling")]
+    if let Some(profiler) = profiler {
+        profiler.finish()?;
+    }
+    result
+}
+
+/// The key of encrypted snapshots, read from `path` or the `TX_PROCESSOR_STATE_KEY` environment
+/// variable
+pub(crate) fn state_key(path: Option<&str>) -> Result<Option<StateKey>, Box<dyn Error>> {
+    let hex = match path {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => match std::env::var("TX_PROCESSOR_STATE_KEY") {
+            Ok(hex) => hex,
+            Err(_) => return Ok(None),
+        },
+    };
+    Ok(Some(StateKey::from_hex(&hex)?))
+}
+
+/// The checksums of the inputs read so far, for the run summary and snapshots
+fn input_digests(checksums: &[(&String, Checksum)]) -> Vec<InputDigest> {
+    checksums
+        .iter()
+        .map(|(source, checksum)| InputDigest {
+            source: source.to_string(),
+            sha256: checksum.sha256(),
+        })
+        .collect()
+}
+
+/// Cancels `token` on SIGINT or SIGTERM, so the run stops reading records and writes what it
+/// processed so far. A second signal exits at once, e.g. while blocked reading standard input.
+fn cancel_on_signal(token: CancellationToken) -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(move || {
+        if token.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!("Interrupted, stopping after the current record");
+        token.cancel();
+    })
+}
+
+/// `error` and the errors that caused it, e.g. `I/O Error: No space left on device`
+fn describe(error: &dyn Error) -> String {
+    let mut description = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        description = format!("{}: {}", description, error);
+        source = error.source();
+    }
+    description
+}
+
+/// Writes a report or snapshot to `path` and finishes the file, failing with its path if any
+/// of it couldn't be written, e.g. to a full disk
+fn write_file(
+    path: &str,
+    durability: Durability,
+    write: impl FnOnce(&mut OutputFile) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let write_and_finish = || -> Result<(), Box<dyn Error>> {
+        let mut file = OutputFile::create(path, durability)?;
+        write(&mut file)?;
+        Ok(file.finish()?)
+    };
+    write_and_finish()
+        .map_err(|e| format!("Failed to write {}: {}", path, describe(e.as_ref())).into())
+}
+
+/// Writes a checkpoint of `ledger` after `records` records to `path`, to resume from
+fn write_checkpoint(
+    path: &str,
+    durability: Durability,
+    ledger: &Ledger,
+    records: u64,
+    checksums: &[(&String, Checksum)],
+    build: &BuildInfo,
+    key: Option<&StateKey>,
+) -> Result<(), Box<dyn Error>> {
+    write_file(path, durability, |file| {
+        Ok(Snapshot::new(ledger, records)
+            .with_inputs(input_digests(checksums))
+            .with_build(build.clone())
+            .write(file, key)?)
+    })
+}
+
+/// Writes CSV reports, replacing client ids with pseudonyms if a pseudonymizer is given
+struct Reports {
+    pseudonymizer: Option<Pseudonymizer>,
+    durability: Durability,
+}
+
+impl Reports {
+    /// The id of `client_id` in what the run prints, its pseudonym if there is a pseudonymizer
+    fn client(&self, client_id: u16) -> String {
+        match &self.pseudonymizer {
+            None => client_id.to_string(),
+            Some(pseudonymizer) => pseudonymizer.client(client_id),
+        }
+    }
+
+    fn write(
+        &self,
+        path: &str,
+        write: impl FnOnce(&mut dyn Write) -> Result<(), TxError>,
+    ) -> Result<(), Box<dyn Error>> {
+        write_file(
+            path,
+            self.durability,
+            |file| Ok(self.write_to(file, write)?),
+        )
+    }
+
+    fn write_to(
+        &self,
+        writer: &mut dyn Write,
+        write: impl FnOnce(&mut dyn Write) -> Result<(), TxError>,
+    ) -> Result<(), TxError> {
+        match &self.pseudonymizer {
+            None => write(writer),
+            Some(pseudonymizer) => {
+                let mut report = Vec::new();
+                write(&mut report)?;
+                pseudonymizer.rewrite_csv(report.as_slice(), writer)
+            }
+        }
+    }
+}
+
+/// The clients allowed and denied by flags and files
+fn client_filter(args: &ProcessArgs) -> Result<ClientFilter, Box<dyn Error>> {
+    let read = |path: &String| -> Result<BTreeSet<u16>, Box<dyn Error>> {
+        let mut list = String::new();
+        tx_io::open_input(path)?.read_to_string(&mut list)?;
+        clients::parse_client_ids(&list)
+            .map_err(|e| format!("Failed to read the clients of {}: {}", path, e).into())
+    };
+    let mut filter = ClientFilter::default();
+    for client_ids in &args.allow_clients {
+        filter = filter.allow(client_ids.iter().copied());
+    }
+    if let Some(path) = &args.allow_clients_file {
+        filter = filter.allow(read(path)?);
+    }
+    for client_ids in &args.deny_clients {
+        filter = filter.deny(client_ids.iter().copied());
+    }
+    if let Some(path) = &args.deny_clients_file {
+        filter = filter.deny(read(path)?);
+    }
+    Ok(filter)
+}
+
+fn process(args: &ProcessArgs) -> Result<(), Box<dyn Error>> {
+    if args.input_files.is_empty() {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "an input file of transactions is required",
+            )
+            .exit();
+    }
+
+    let mut types = TypeTable::new(args.policies.unknown_types);
+    for (name, alias) in &args.type_aliases {
+        types = types.alias(name, *alias);
+    }
+    let csv_options = CsvOptions {
+        limits: CsvLimits {
+            max_field_len: args.max_field_length,
+            max_record_len: args.max_record_length,
+            max_fields: args.max_fields,
+        },
+        batch: args.batch.clone(),
+        amounts: args.amounts,
+        types: types.clone(),
+    };
+    let mut inputs = Vec::<Box<dyn TransactionSource>>::with_capacity(args.input_files.len());
+    let mut checksums = Vec::new();
+    for input_file in &args.input_files {
+        let mut input = tx_io::open_input(input_file)?;
+        if args.checksums {
+            // of the file as stored, compressed or not
+            let checksum = Checksum::default();
+            input = Box::new(checksum.reader(input));
+            checksums.push((input_file, checksum));
+        }
+        let input = tx_io::decompress(input_file, input);
+        if input_file.trim_end_matches(".gz").ends_with(".jsonl") {
+            inputs.push(Box::new(JsonSource::new(
+                input,
+                input_file,
+                args.batch.as_deref(),
+            )));
+        } else {
+            inputs.push(Box::new(CsvSource::new(input, input_file, &csv_options)?));
+        }
+    }
+    let mut builder = Ledger::builder();
+    if let Some(path) = &args.opening_balances {
+        let records = tx_io::read_account_records(tx_io::open_input(path)?)?;
+        builder = builder.opening_balances(records.into_iter().map(Into::into));
+    }
+    for &transaction_type in &args.disabled {
+        builder = builder.disable(transaction_type);
+    }
+    builder = builder.clients(client_filter(args)?);
+    builder = builder.test_clients(
+        args.test_clients
+            .iter()
+            .fold(TestClients::default(), |test_clients, range| {
+                test_clients.range(range.clone())
+            }),
+    );
+    if let Some(first) = args.client_ids_from {
+        builder = builder.client_id_resolver(SequentialClientIds::new(first..=u16::MAX));
+    }
+    if let Some(path) = &args.dispute_rules {
+        let mut rules = String::new();
+        tx_io::open_input(path)?.read_to_string(&mut rules)?;
+        builder = builder.dispute_rules(
+            dispute_rules::parse_dispute_rules(&rules)
+                .map_err(|e| format!("Failed to read the dispute rules of {}: {}", path, e))?,
+        );
+    }
+    if let Some(path) = &args.reserves {
+        let mut reserves = String::new();
+        tx_io::open_input(path)?.read_to_string(&mut reserves)?;
+        builder = builder.reserves(
+            reserve::parse_reserves(&reserves)
+                .map_err(|e| format!("Failed to read the reserves of {}: {}", path, e))?,
+        );
+    }
+    let mut policies = args.policies.policies();
+    if args.suspense_report.is_some() {
+        policies.unknown_transactions = UnknownTransactionPolicy::Suspend;
+    }
+    let build = BuildInfo::current().with_policies(&policies);
+    builder = builder
+        .freeze_thresholds(FreezeThresholds {
+            max_risk_score: args.freeze_risk_score,
+            max_chargebacks: args.freeze_chargebacks,
+        })
+        .limits(Limits {
+            max_accounts: args.max_accounts,
+            max_transactions: args.max_transactions,
+            max_open_disputes: args.max_open_disputes,
+            max_quarantined: args.max_quarantined,
+            policy: policies.limits,
+        })
+        .policies(&policies);
+    if args.backfill {
+        builder = builder.backfill();
+    }
+    if let Some(records) = args.pending_records {
+        builder = builder.availability(AvailabilityDelay::Records(records));
+    }
+    if let Some(seconds) = args.pending_seconds {
+        builder = builder.availability(AvailabilityDelay::Seconds(seconds));
+    }
+    if args.trial_balance.is_some() || args.gl_export.is_some() {
+        builder = builder.double_entry();
+    }
+    let cancellation = CancellationToken::new();
+    cancel_on_signal(cancellation.clone())?;
+    builder = builder.cancellation_token(cancellation);
+    let mut ledger = builder.build()?;
+    let key = state_key(args.state_key_file.as_deref())?;
+    let durability = if args.fsync {
+        Durability::Sync
+    } else {
+        Durability::Flush
+    };
+    let mut resumed_records = 0;
+    let mut history = RunHistory::default();
+    if let Some(path) = &args.opening_state {
+        let snapshot = Snapshot::read(BufReader::new(tx_io::open_input(path)?), key.as_ref())?;
+        history = snapshot.history();
+        ledger = snapshot.restore(ledger)?;
+    }
+    if let Some(path) = &args.resume {
+        let snapshot = Snapshot::read(BufReader::new(tx_io::open_input(path)?), key.as_ref())?;
+        resumed_records = snapshot.records;
+        ledger = snapshot.restore(ledger)?;
+    }
+    let tagger: Tagger = match &args.tag_rules {
+        Some(path) => toml::from_str(&std::fs::read_to_string(path)?)?,
+        None => Tagger::default(),
+    };
+    let mut gaps = GapDetector::new(
+        inputs
+            .into_iter()
+            .flat_map(TransactionSource::records)
+            .take(args.limit.unwrap_or(usize::MAX)),
+        args.gap_key,
+    );
+    let mut normalize = Normalize::new(
+        &mut gaps,
+        policies.negative_amounts == NegativeAmountPolicy::Normalize,
+    );
+    let mut tagged = (&mut normalize).map(|record| record.map(|record| tagger.tag(record)));
+    let mut dedup = Dedup::new(&mut tagged, args.policies.dedup);
+    let mut reorder = ReorderBuffer::new(&mut dedup, args.reorder_window.unwrap_or(0));
+    let mut sample = Sample::new(&mut reorder, args.sample.unwrap_or(1.0), args.seed);
+    let position = crash::Position::default();
+    let records = crash::Track::new(
+        Throttle::new(
+            Replay::new(
+                (&mut sample).skip(resumed_records as usize),
+                args.replay_speed.unwrap_or(f64::INFINITY),
+            ),
+            args.rate_limit.unwrap_or(f64::INFINITY),
+        ),
+        &position,
+    );
+
+    let mut timed_out = false;
+    let processing = panic::catch_unwind(AssertUnwindSafe(|| match args.time_budget {
+        None => ledger.process_source(&mut IterSource::new(records)),
+        Some(seconds) => {
+            let deadline = Instant::now() + Duration::from_secs_f64(seconds);
+            ledger.process_chunks(records, CHUNK_SIZE, |_| {
+                timed_out = Instant::now() >= deadline;
+                if timed_out {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+        }
+    }));
+    let progress = match processing {
+        Ok(progress) => progress,
+        Err(_) => {
+            // a checkpoint of the ledger as the panic left it, which is all there is to resume
+            // from after hours of processing. The panic may have applied part of a record, so
+            // the checkpoint is only written if the balances still match the transaction log,
+            // which only a ledger that started without balances can show, as with `--verify`.
+            let mut summary = crash::FailureSummary::new(&position, resumed_records);
+            if let Some(path) = &args.checkpoint {
+                let verifiable = args.opening_balances.is_none()
+                    && args.opening_state.is_none()
+                    && args.resume.is_none()
+                    && args.max_transactions.is_none();
+                let checkpoint = panic::catch_unwind(AssertUnwindSafe(|| {
+                    if !verifiable {
+                        return Err(
+                            "Not writing a checkpoint after the panic, the balances of a \
+                                    run with opening balances or a limit on the retained \
+                                    transactions can't be checked"
+                                .into(),
+                        );
+                    }
+                    let divergences = consistency::check_balances(&ledger)?;
+                    if !divergences.is_empty() {
+                        return Err(format!(
+                            "Not writing a checkpoint after the panic, {} balances diverge from \
+                             their transactions",
+                            divergences.len()
+                        )
+                        .into());
+                    }
+                    write_checkpoint(
+                        path,
+                        durability,
+                        &ledger,
+                        summary.records(),
+                        &checksums,
+                        &build,
+                        key.as_ref(),
+                    )
+                }));
+                match checkpoint {
+                    Ok(Ok(())) => summary = summary.with_checkpoint(path),
+                    Ok(Err(e)) => eprintln!("{}", e),
+                    Err(_) => {}
+                }
+            }
+            eprintln!("{}", serde_json::to_string(&summary)?);
+            return Err(format!("panicked after {} records", summary.records()).into());
+        }
+    };
+    let processed = resumed_records + progress.records as u64;
+    if timed_out {
+        // `requires` guarantees a checkpoint path along with the time budget
+        let path = args.checkpoint.as_deref().unwrap();
+        write_checkpoint(
+            path,
+            durability,
+            &ledger,
+            processed,
+            &checksums,
+            &build,
+            key.as_ref(),
+        )?;
+        eprintln!(
+            "Time budget exceeded after {} records, resume with --resume {}",
+            processed, path
+        );
+        return Ok(());
+    }
+    if progress.cancelled {
+        match &args.checkpoint {
+            Some(path) => {
+                write_checkpoint(
+                    path,
+                    durability,
+                    &ledger,
+                    processed,
+                    &checksums,
+                    &build,
+                    key.as_ref(),
+                )?;
+                eprintln!(
+                    "Interrupted after {} records, resume with --resume {}",
+                    processed, path
+                );
+            }
+            None => eprintln!(
+                "Interrupted after {} records, the reports cover only those",
+                processed
+            ),
+        }
+    }
+
+    if args.release_pending {
+        let pending = ledger.pending_deposits().count();
+        ledger.release_pending()?;
+        eprintln!("Released {} pending deposits", pending);
+    }
+
+    // an interrupted run is resumed from its checkpoint, which closes the period once it ends
+    if let (Some(label), false) = (&args.close_period, progress.cancelled) {
+        let archive = ledger.close_period(label.as_str());
+        eprintln!(
+            "Closed period {} of {} transactions, SHA-256 {}",
+            archive.label(),
+            archive.transactions().len(),
+            archive.sha256()
+        );
+        if let Some(path) = &args.period_archive {
+            let contents = archive.contents();
+            write_file(path, durability, |file| {
+                Ok(file.write_all(contents.as_bytes())?)
+            })?;
+        }
+    }
+
+    eprintln!("Run by {}", build);
+    for digest in input_digests(&checksums) {
+        eprintln!("Read {}, SHA-256 {}", digest.source, digest.sha256);
+    }
+
+    if let Some(rate) = args.sample {
+        eprintln!(
+            "Sampled {} of the clients with seed {}, skipping {} records",
+            rate,
+            args.seed,
+            sample.skipped()
+        );
+    }
+
+    let reports = Reports {
+        pseudonymizer: match &args.pseudonymize {
+            Some(path) => Some(Pseudonymizer::new(
+                std::fs::read_to_string(path)?.trim().as_bytes(),
+            )),
+            None => None,
+        },
+        durability,
+    };
+
+    for freeze in ledger.freezes() {
+        eprintln!(
+            "Froze the account of client {} at transaction {} for {}",
+            reports.client(freeze.client_id),
+            freeze.transaction_id,
+            freeze.reason
+        );
+    }
+
+    let pending = ledger.pending_deposits().count();
+    if pending > 0 {
+        eprintln!("Left {} deposits pending", pending);
+    }
+
+    if !args.test_clients.is_empty() {
+        eprintln!(
+            "Left {} transactions of test clients out of the settlement and statistics",
+            ledger.test_transactions()
+        );
+    }
+
+    if args.reorder_window.is_some() {
+        let stats = reorder.stats();
+        eprintln!(
+            "Reordered {} records, {} arrived too late to be reordered",
+            stats.reordered, stats.late
+        );
+    }
+
+    if args.policies.dedup {
+        eprintln!("Skipped {} duplicate records", dedup.duplicates());
+    }
+
+    if ledger.quarantine_dropped() > 0 {
+        eprintln!(
+            "Left {} rejected records out of the reject report",
+            ledger.quarantine_dropped()
+        );
+    }
+
+    let unknown_types = types.unknown_types();
+    for (name, count) in &unknown_types.skipped {
+        eprintln!("Skipped {} records of type `{}`", count, name);
+    }
+    if !unknown_types.quarantined.is_empty() {
+        eprintln!(
+            "Set aside {} records of unknown types",
+            unknown_types.quarantined.len()
+        );
+    }
+
+    if policies.negative_amounts == NegativeAmountPolicy::Normalize {
+        eprintln!(
+            "Normalized {} records with negative amounts",
+            normalize.audit_log().len()
+        );
+    }
+
+    for (transaction_type, records) in [
+        (TransactionRecordType::Deposit, "deposits"),
+        (TransactionRecordType::Withdrawal, "withdrawals"),
+    ] {
+        if let (Some(count), Some(policy)) = (
+            ledger.missing_amounts().get(&transaction_type),
+            policies.missing_amounts(transaction_type),
+        ) {
+            eprintln!(
+                "Read {} {} without an amount, missing amount policy `{}`",
+                count,
+                records,
+                policy.name()
+            );
+        }
+    }
+
+    if let Some(limit) = ledger.limit_exceeded() {
+        return Err(TxError::LimitExceeded(limit).into());
+    }
+
+    if args.verify {
+        let divergences = consistency::check_balances(&ledger)?;
+        for divergence in &divergences {
+            eprintln!(
+                "Balance of client {} diverges from its transactions: available {} (expected {}), held {} (expected {})",
+                reports.client(divergence.client_id),
+                divergence.available,
+                divergence.expected_available,
+                divergence.held,
+                divergence.expected_held
+            );
+        }
+        if !divergences.is_empty() {
+            return Err(format!(
+                "{} balances diverge from their transactions",
+                divergences.len()
+            )
+            .into());
+        }
+        eprintln!(
+            "Verified {} balances against {} transactions",
+            ledger.accounts().count(),
+            ledger.transactions().len()
+        );
+    }
+
+    if args.skip_empty {
+        eprintln!(
+            "Skipped {} empty accounts",
+            ledger
+                .accounts()
+                .filter(|account| account.is_empty())
+                .count()
+        );
+    }
+    let mut stdout = io::stdout().lock();
+    reports
+        .write_to(&mut stdout, |writer| {
+            if args.skip_empty {
+                report::write_account_views_csv(
+                    report::non_empty(ledger.accounts()),
+                    args.output_compat,
+                    writer,
+                )
+            } else {
+                ledger.write_accounts_ordered(args.output_compat, AccountOrder::ClientId, writer)
+            }
+        })
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|()| Ok(stdout.flush()?))
+        .map_err(|e| {
+            format!(
+                "Failed to write the accounts to standard output: {}",
+                describe(e.as_ref())
+            )
+        })?;
+
+    if let Some(path) = &args.chargeback_report {
+        let report = ledger.chargeback_report()?;
+        reports.write(path, |writer| report.write_csv(writer))?;
+    }
+
+    if let Some(path) = &args.reject_report {
+        reports.write(path, |writer| {
+            report::write_rejects_csv(ledger.quarantined(), ledger.incomplete(), writer)
+        })?;
+    }
+
+    if let Some(path) = &args.dispute_decisions {
+        reports.write(path, |writer| {
+            report::write_dispute_decisions_csv(ledger.dispute_decisions(), writer)
+        })?;
+    }
+
+    if let Some(path) = &args.late_arrival_log {
+        reports.write(path, |writer| {
+            report::write_late_arrivals_csv(ledger.late_arrivals(), writer)
+        })?;
+    }
+
+    if let Some(path) = &args.client_alias_log {
+        reports.write(path, |writer| {
+            report::write_client_aliases_csv(ledger.client_aliases(), writer)
+        })?;
+    }
+
+    if let Some(path) = &args.unknown_types_report {
+        reports.write(path, |writer| {
+            report::write_unknown_types_csv(&unknown_types.quarantined, writer)
+        })?;
+    }
 
-    let mut reader = ReaderBuilder::new()
-        .trim(Trim::All)
-        .flexible(true)
-        .from_path(&cli.input_file)?;
-    let mut ledger = Ledger::default();
-    ledger.process_csv_transactions(reader.deserialize());
+    if let Some(path) = &args.audit_log {
+        reports.write(path, |writer| {
+            report::write_audit_log_csv(normalize.audit_log(), writer)
+        })?;
+    }
+
+    if let Some(path) = &args.suspense_report {
+        reports.write(path, |writer| {
+            report::write_suspense_csv(&ledger.suspended(), writer)
+        })?;
+    }
+
+    // the closing state of an interrupted run would open the next run without the rest of
+    // this one, which is resumed from the checkpoint instead
+    if let (Some(path), false) = (&args.closing_state, progress.cancelled) {
+        write_file(path, durability, |file| {
+            Ok(Snapshot::closing(&ledger, &history, args.retention)
+                .with_inputs(input_digests(&checksums))
+                .with_build(build.clone())
+                .write(file, key.as_ref())?)
+        })?;
+    }
 
-    writer.write_record(&vec!["client", "available", "held", "total", "locked"])?;
+    if let Some(path) = &args.gap_report {
+        let gaps = gaps.resolved_gaps(ledger.client_aliases());
+        reports.write(path, |writer| report::write_gaps_csv(&gaps, writer))?;
+    }
+
+    if let (Some(path), Some(journal)) = (&args.trial_balance, ledger.journal()) {
+        let trial_balance = journal.trial_balance()?;
+        reports.write(path, |writer| trial_balance.write_csv(writer))?;
+    }
+
+    if let (Some(path), Some(journal)) = (&args.gl_export, ledger.journal()) {
+        let chart = match &args.chart_of_accounts {
+            Some(path) => ChartOfAccounts::read_csv(File::open(path)?)?,
+            None => ChartOfAccounts::default(),
+        };
+        // `requires` guarantees a date along with the export
+        let date = args.gl_date.as_deref().unwrap();
+        reports.write(path, |writer| journal.write_gl_csv(&chart, date, writer))?;
+    }
 
-    for account in ledger.active_accounts() {
-        writer.serialize(account)?;
+    if let Some(path) = &args.settlement {
+        let settlement = ledger.settlement()?;
+        reports.write(path, |writer| {
+            settlement::write_settlement_csv(&settlement, writer)
+        })?;
     }
-    for account in ledger.locked_accounts() {
-        writer.serialize(account)?;
+
+    if let Some(path) = &args.test_settlement {
+        let settlement = ledger.test_settlement()?;
+        reports.write(path, |writer| {
+            settlement::write_settlement_csv(&settlement, writer)
+        })?;
+    }
+
+    if let Some(path) = &args.aggregate_report {
+        let mut aggregates = ledger.aggregate(args.aggregate_by)?;
+        if let (AggregationKey::Client, Some(pseudonymizer)) =
+            (args.aggregate_by, &reports.pseudonymizer)
+        {
+            for aggregate in &mut aggregates {
+                aggregate.key = pseudonymizer.client(aggregate.key.parse()?);
+            }
+        }
+        write_file(path, durability, |file| {
+            if path.ends_with(".json") {
+                aggregate::write_aggregates_json(&aggregates, file)?;
+            } else {
+                aggregate::write_aggregates_csv(&aggregates, file)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    if let Some(path) = &args.daily_balances {
+        reports.write(path, |writer| {
+            rollup::write_daily_balances_csv(&ledger.daily_balances(), writer)
+        })?;
+    }
+
+    if let Some(path) = &args.category_report {
+        let totals = ledger.category_totals()?;
+        reports.write(path, |writer| {
+            tags::write_category_totals_csv(&totals, writer)
+        })?;
+    }
+
+    if progress.cancelled {
+        return Err(format!("interrupted after {} records", processed).into());
     }
 
     Ok(())
@@ -0,0 +1,97 @@
+use std::error::Error;
+
+use clap::Args;
+
+use tx_processor::limits::LimitPolicy;
+use tx_processor::policies::{
+    DuplicatePolicy, LateArrivalPolicy, MissingAmountPolicy, NegativeAmountPolicy, Policies,
+    Policy, UnknownClientPolicy, UnknownTransactionPolicy, UnknownTypePolicy,
+    WithdrawalDisputePolicy,
+};
+
+/// The flags choosing the policies of a run, shared by processing and `explain-policies`
+#[derive(Args)]
+pub(crate) struct PolicyArgs {
+    /// Whether withdrawals can be disputed: `hold` their amount like deposits, or `reject`
+    #[clap(long, value_name = "POLICY", default_value = "hold", parse(try_from_str = parse_policy))]
+    pub(crate) withdrawal_disputes: WithdrawalDisputePolicy,
+    /// Skip records repeating the transaction id and type of an earlier record, e.g. where
+    /// re-sent files overlap
+    #[clap(long)]
+    pub(crate) dedup: bool,
+    /// What to do with transactions of clients without an opening balance: `open` an account,
+    /// or `reject` them
+    #[clap(long, value_name = "POLICY", default_value = "open", parse(try_from_str = parse_policy))]
+    pub(crate) unknown_clients: UnknownClientPolicy,
+    /// What to do with disputes, resolves and chargebacks of transactions that haven't arrived:
+    /// `reject` them, or `suspend` them until the transaction arrives
+    #[clap(long, value_name = "POLICY", default_value = "reject", parse(try_from_str = parse_policy))]
+    pub(crate) unknown_transactions: UnknownTransactionPolicy,
+    /// What to do with records of types that are neither known nor aliased with `--type-alias`:
+    /// `reject` them, `quarantine` them in the unknown types report, or `skip` them
+    #[clap(long, value_name = "POLICY", default_value = "reject", parse(try_from_str = parse_policy))]
+    pub(crate) unknown_types: UnknownTypePolicy,
+    /// What to do with deposits without an amount: `reject` them, apply them with a `zero`
+    /// amount, or `quarantine` them in the reject report
+    #[clap(long, value_name = "POLICY", default_value = "reject", parse(try_from_str = parse_policy))]
+    pub(crate) missing_deposit_amounts: MissingAmountPolicy,
+    /// What to do with withdrawals without an amount: `reject` them, apply them with a `zero`
+    /// amount, or `quarantine` them in the reject report
+    #[clap(long, value_name = "POLICY", default_value = "reject", parse(try_from_str = parse_policy))]
+    pub(crate) missing_withdrawal_amounts: MissingAmountPolicy,
+    /// What to do with deposits and withdrawals of a negative amount: `reject` them, or
+    /// `normalize` them to the opposite type, e.g. refunds exported as negative withdrawals
+    #[clap(long, value_name = "POLICY", default_value = "reject", parse(try_from_str = parse_policy))]
+    pub(crate) negative_amounts: NegativeAmountPolicy,
+    /// What to do when a limit is reached: `abort` the run, or `degrade` by rejecting
+    /// transactions beyond the account and dispute limits and forgetting the oldest transactions
+    #[clap(long, value_name = "POLICY", default_value = "abort", parse(try_from_str = parse_policy))]
+    pub(crate) on_limit: LimitPolicy,
+    /// What to do with records timestamped within a period closed by an earlier run: `reject`
+    /// them, `adjust` the open period with them, or `reopen` the closed period
+    #[clap(long, value_name = "POLICY", default_value = "reject", parse(try_from_str = parse_policy))]
+    pub(crate) late_arrivals: LateArrivalPolicy,
+}
+
+impl PolicyArgs {
+    pub(crate) fn policies(&self) -> Policies {
+        Policies {
+            withdrawal_disputes: self.withdrawal_disputes,
+            duplicates: if self.dedup {
+                DuplicatePolicy::Skip
+            } else {
+                DuplicatePolicy::Apply
+            },
+            unknown_clients: self.unknown_clients,
+            unknown_transactions: self.unknown_transactions,
+            unknown_types: self.unknown_types,
+            missing_deposit_amounts: self.missing_deposit_amounts,
+            missing_withdrawal_amounts: self.missing_withdrawal_amounts,
+            negative_amounts: self.negative_amounts,
+            limits: self.on_limit,
+            late_arrivals: self.late_arrivals,
+        }
+    }
+}
+
+fn parse_policy<P: Policy>(s: &str) -> Result<P, String> {
+    P::from_name(s).ok_or_else(|| {
+        let names = P::ALL
+            .iter()
+            .map(|policy| format!("`{}`", policy.name()))
+            .collect::<Vec<_>>();
+        format!(
+            "unknown policy `{}`, expected one of {}",
+            s,
+            names.join(", ")
+        )
+    })
+}
+
+pub(crate) fn run(args: &PolicyArgs) -> Result<(), Box<dyn Error>> {
+    for (subject, name, description) in args.policies().describe() {
+        println!("{}: {}", subject, name);
+        println!("    {}", description);
+    }
+    Ok(())
+}
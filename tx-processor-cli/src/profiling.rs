@@ -0,0 +1,25 @@
+use std::error::Error;
+use std::fs::File;
+
+use pprof::ProfilerGuard;
+
+/// Samples the whole run and renders a flamegraph when finished
+pub(crate) struct Profiler {
+    guard: ProfilerGuard<'static>,
+    output: String,
+}
+
+impl Profiler {
+    pub(crate) fn start(output: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Profiler {
+            guard: ProfilerGuard::new(1000)?,
+            output: output.to_owned(),
+        })
+    }
+
+    pub(crate) fn finish(self) -> Result<(), Box<dyn Error>> {
+        let report = self.guard.report().build()?;
+        report.flamegraph(File::create(&self.output)?)?;
+        Ok(())
+    }
+}
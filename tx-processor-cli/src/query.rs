@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+use tx_processor::diagram::{self, DiagramFormat, DisputeFlow};
+use tx_processor::ledger::Ledger;
+use tx_processor::risk::RiskScore;
+use tx_processor::snapshot::{
+    AccountSnapshot, DisputeSnapshot, RejectionSnapshot, Snapshot, TransactionSnapshot,
+};
+use tx_processor::transaction::TransactionRecordType;
+
+#[derive(Args)]
+pub(crate) struct QueryArgs {
+    /// The checkpoint or closing state to query
+    #[clap(long)]
+    pub(crate) snapshot: String,
+    /// The key file the snapshot was encrypted with, see `--state-key-file` of processing
+    #[clap(long, value_name = "KEY_FILE")]
+    pub(crate) state_key_file: Option<String>,
+    #[clap(subcommand)]
+    pub(crate) query: Query,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Query {
+    /// The balances and flags of an account
+    Account {
+        client: u16,
+        /// Add the account's risk score and the features it was computed from
+        #[clap(long)]
+        risk: bool,
+    },
+    /// Every record of a transaction id: the transaction, its disputes, and whether it is
+    /// disputed, suspended or quarantined
+    Tx { tx: u32 },
+    /// Every dispute, resolve and chargeback applied. Closing states keep only the open
+    /// disputes
+    Disputes {
+        /// Only the disputes still open, with the amount they hold
+        #[clap(long)]
+        open: bool,
+    },
+    /// The risk score of every client with a transaction, riskiest first
+    Risk {
+        /// Only the clients scoring at least this, from 0 to 100
+        #[clap(long, value_name = "SCORE", default_value = "0")]
+        min: u8,
+    },
+    /// A diagram of the disputes of a client or a transaction, from the disputed transaction to
+    /// the locked account
+    Diagram {
+        #[clap(long, required_unless_present = "tx", conflicts_with = "tx")]
+        client: Option<u16>,
+        #[clap(long)]
+        tx: Option<u32>,
+        /// `mermaid` or `graphviz`
+        #[clap(long, default_value = "mermaid", parse(try_from_str = parse_diagram_format))]
+        format: DiagramFormat,
+    },
+}
+
+fn parse_diagram_format(s: &str) -> Result<DiagramFormat, String> {
+    match s {
+        "mermaid" => Ok(DiagramFormat::Mermaid),
+        "graphviz" => Ok(DiagramFormat::Graphviz),
+        _ => Err(format!(
+            "unknown format `{}`, expected `mermaid` or `graphviz`",
+            s
+        )),
+    }
+}
+
+/// An account and, if asked for, its risk score
+#[derive(Serialize)]
+struct AccountReport {
+    #[serde(flatten)]
+    account: AccountSnapshot,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    risk: Option<RiskScore>,
+}
+
+/// Everything a snapshot knows about a transaction id
+#[derive(Serialize)]
+struct TxReport<'a> {
+    transactions: Vec<&'a TransactionSnapshot>,
+    dispute: Option<&'a DisputeSnapshot>,
+    suspense: Vec<&'a TransactionSnapshot>,
+    quarantine: Vec<&'a RejectionSnapshot>,
+}
+
+pub(crate) fn run(args: &QueryArgs) -> Result<(), Box<dyn Error>> {
+    let key = crate::state_key(args.state_key_file.as_deref())?;
+    let snapshot = Snapshot::read(BufReader::new(File::open(&args.snapshot)?), key.as_ref())?;
+    let mut stdout = io::stdout().lock();
+    match args.query {
+        Query::Account {
+            client,
+            risk: false,
+        } => {
+            write_json::<AccountSnapshot>(&mut stdout, find_account(&snapshot, client)?)?;
+        }
+        Query::Account { client, risk: true } => {
+            let account = find_account(&snapshot, client)?.clone();
+            let ledger = snapshot.restore(Ledger::builder().build()?)?;
+            let report = AccountReport {
+                account,
+                risk: ledger
+                    .risk_scores()
+                    .into_iter()
+                    .find(|score| score.client_id == client),
+            };
+            write_json(&mut stdout, &report)?;
+        }
+        Query::Risk { min } => {
+            let ledger = snapshot.restore(Ledger::builder().build()?)?;
+            let mut scores = ledger.risk_scores();
+            scores.retain(|score| score.score >= min);
+            scores.sort_by_key(|score| core::cmp::Reverse(score.score));
+            write_json(&mut stdout, &scores)?;
+        }
+        Query::Tx { tx } => {
+            let report = TxReport {
+                transactions: snapshot
+                    .transactions
+                    .iter()
+                    .filter(|transaction| transaction.record.transaction_id == tx)
+                    .collect(),
+                dispute: snapshot.disputes.iter().find(|dispute| dispute.tx == tx),
+                suspense: snapshot
+                    .suspense
+                    .iter()
+                    .filter(|transaction| transaction.record.transaction_id == tx)
+                    .collect(),
+                quarantine: snapshot
+                    .quarantine
+                    .iter()
+                    .filter(|rejection| rejection.transaction.transaction_id == tx)
+                    .collect(),
+            };
+            if report.transactions.is_empty()
+                && report.suspense.is_empty()
+                && report.quarantine.is_empty()
+            {
+                return Err(format!("no transaction {} in the snapshot", tx).into());
+            }
+            write_json(&mut stdout, &report)?;
+        }
+        Query::Disputes { open: true } => write_json(&mut stdout, &snapshot.disputes)?,
+        Query::Disputes { open: false } => {
+            let disputes = snapshot
+                .transactions
+                .iter()
+                .filter(|transaction| {
+                    matches!(
+                        transaction.record.transaction_type,
+                        TransactionRecordType::Dispute
+                            | TransactionRecordType::Resolve
+                            | TransactionRecordType::Chargeback
+                    )
+                })
+                .collect::<Vec<_>>();
+            write_json(&mut stdout, &disputes)?;
+        }
+        Query::Diagram { client, tx, format } => {
+            let ledger = snapshot.restore(Ledger::builder().build()?)?;
+            let flows = match (client, tx) {
+                (Some(client), _) => DisputeFlow::for_client(&ledger, client),
+                (None, Some(tx)) => vec![DisputeFlow::for_tx(&ledger, tx)
+                    .ok_or_else(|| format!("no deposit or withdrawal {} in the snapshot", tx))?],
+                // `required_unless_present` guarantees a client or a transaction
+                (None, None) => unreachable!(),
+            };
+            let mut rendered = String::new();
+            diagram::write_diagram(&flows, format, &mut rendered)?;
+            stdout.write_all(rendered.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn find_account(snapshot: &Snapshot, client: u16) -> Result<&AccountSnapshot, String> {
+    snapshot
+        .accounts
+        .iter()
+        .find(|account| account.client == client)
+        .ok_or_else(|| format!("no account of client {} in the snapshot", client))
+}
+
+fn write_json<T: Serialize + ?Sized>(
+    writer: &mut impl Write,
+    value: &T,
+) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(&mut *writer, value)?;
+    writeln!(writer)?;
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use std::error::Error;
+
+use clap::Args;
+
+use tx_processor::schema::Format;
+
+#[derive(Args)]
+pub(crate) struct SchemaArgs {
+    /// The format: `transaction`, `account`, `audit` or `snapshot`
+    #[clap(parse(try_from_str = parse_format))]
+    pub(crate) format: Format,
+}
+
+fn parse_format(s: &str) -> Result<Format, String> {
+    Format::from_name(s).ok_or_else(|| {
+        let names = Format::ALL
+            .iter()
+            .map(|format| format!("`{}`", format.name()))
+            .collect::<Vec<_>>();
+        format!(
+            "unknown format `{}`, expected one of {}",
+            s,
+            names.join(", ")
+        )
+    })
+}
+
+pub(crate) fn run(args: &SchemaArgs) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(&args.format.schema())?);
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use clap::Args;
+
+use tx_processor::cancel::CancellationToken;
+use tx_processor::error::TxError;
+use tx_processor::io::{
+    self as tx_io, AccountColumns, CsvOptions, CsvSource, JsonSource, TransactionSource,
+};
+use tx_processor::ledger::Ledger;
+use tx_processor::shards::{self, ShardError};
+use tx_processor::types::TypeTable;
+
+use crate::policies;
+
+#[derive(Args)]
+pub(crate) struct ShardsArgs {
+    /// The directory of input files, each holding every record of its clients, e.g. as written
+    /// by `split`
+    pub(crate) dir: String,
+    /// The columns of the accounts written to standard output, see processing
+    #[clap(long, value_name = "VERSION", default_value = "v1", parse(try_from_str = crate::parse_account_columns))]
+    pub(crate) output_compat: AccountColumns,
+    #[clap(flatten)]
+    pub(crate) policies: policies::PolicyArgs,
+}
+
+/// The files of `dir`, ordered by name
+fn shard_files(dir: &str) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub(crate) fn run(args: &ShardsArgs) -> Result<(), Box<dyn Error>> {
+    let files = shard_files(&args.dir)?;
+    if files.is_empty() {
+        return Err(format!("{} holds no input files", args.dir).into());
+    }
+    let csv_options = CsvOptions {
+        types: TypeTable::new(args.policies.unknown_types),
+        ..CsvOptions::default()
+    };
+    let cancellation = CancellationToken::new();
+    crate::cancel_on_signal(cancellation.clone())?;
+    let template = Ledger::builder()
+        .policies(&args.policies.policies())
+        .cancellation_token(cancellation)
+        .build()?;
+
+    let shards = shards::process_shards(&template, &files, |path| {
+        let input = tx_io::decompress(path, tx_io::open_input(path)?);
+        let source: Box<dyn TransactionSource> = if path.trim_end_matches(".gz").ends_with(".jsonl")
+        {
+            Box::new(JsonSource::new(input, path, None))
+        } else {
+            Box::new(CsvSource::new(input, path, &csv_options)?)
+        };
+        Ok(source)
+    })
+    .map_err(|e| match e {
+        ShardError::Open { shard, error } => format!("Failed to read {}: {}", files[shard], error),
+        ShardError::Overlap {
+            client_id,
+            first,
+            second,
+        } => format!(
+            "Client {} has records in {} and {}, which weren't split by client",
+            client_id, files[first], files[second]
+        ),
+        e => e.to_string(),
+    })?;
+    let progress = shards.total_progress();
+    eprintln!(
+        "Processed {} records in {} shards, {} applied and {} rejected",
+        progress.records,
+        files.len(),
+        progress.applied,
+        progress.rejected
+    );
+    if let Some(limit) = progress.limit_exceeded {
+        return Err(TxError::LimitExceeded(limit).into());
+    }
+
+    let mut stdout = io::stdout().lock();
+    shards.write_accounts_csv_as(args.output_compat, &mut stdout)?;
+    stdout.flush()?;
+    if progress.cancelled {
+        return Err(format!("interrupted after {} records", progress.records).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+
+use tx_processor::io::{self as tx_io, Durability, OutputFile};
+use tx_processor::shards;
+
+#[derive(Args)]
+pub(crate) struct SplitArgs {
+    /// The input file of transactions to split, or `-` to read it from standard input
+    pub(crate) input_file: String,
+    /// The number of shards to split the input into
+    #[clap(long, value_name = "N")]
+    pub(crate) shards: usize,
+    /// The directory to write the shards to, as `shard-000.csv`, `shard-001.csv`, ...
+    #[clap(long, value_name = "DIR")]
+    pub(crate) output_dir: String,
+}
+
+pub(crate) fn run(args: &SplitArgs) -> Result<(), Box<dyn Error>> {
+    if args.shards == 0 {
+        return Err("the input can't be split into 0 shards".into());
+    }
+    std::fs::create_dir_all(&args.output_dir)?;
+    let paths = (0..args.shards)
+        .map(|shard| Path::new(&args.output_dir).join(format!("shard-{:03}.csv", shard)))
+        .collect::<Vec<_>>();
+    let mut outputs = paths
+        .iter()
+        .map(|path| OutputFile::create(path, Durability::Flush))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let input = tx_io::decompress(&args.input_file, tx_io::open_input(&args.input_file)?);
+    let counts = shards::split_csv(input, &mut outputs)?;
+    for ((output, path), count) in outputs.into_iter().zip(&paths).zip(counts) {
+        output
+            .finish()
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        eprintln!("Wrote {} records to {}", count, path.display());
+    }
+    Ok(())
+}
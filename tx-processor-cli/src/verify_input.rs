@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+
+use clap::Args;
+
+use tx_processor::checksum::sha256_file;
+use tx_processor::snapshot::Snapshot;
+
+#[derive(Args)]
+pub(crate) struct VerifyInputArgs {
+    /// The checkpoint or closing state, written with `--checksums`
+    #[clap(long)]
+    pub(crate) snapshot: String,
+    /// The key file the snapshot was encrypted with, see `--state-key-file` of processing
+    #[clap(long, value_name = "KEY_FILE")]
+    pub(crate) state_key_file: Option<String>,
+    /// The input files of the run, in the order they were processed
+    #[clap(required = true)]
+    pub(crate) input_files: Vec<String>,
+}
+
+pub(crate) fn run(args: &VerifyInputArgs) -> Result<(), Box<dyn Error>> {
+    let key = crate::state_key(args.state_key_file.as_deref())?;
+    let snapshot = Snapshot::read(BufReader::new(File::open(&args.snapshot)?), key.as_ref())?;
+    if snapshot.inputs.is_empty() {
+        return Err("the snapshot records no input checksums, write it with --checksums".into());
+    }
+    if snapshot.inputs.len() != args.input_files.len() {
+        return Err(format!(
+            "the snapshot was derived from {} input files, not {}",
+            snapshot.inputs.len(),
+            args.input_files.len()
+        )
+        .into());
+    }
+
+    let mut mismatches = 0;
+    for (input, path) in snapshot.inputs.iter().zip(&args.input_files) {
+        let sha256 = sha256_file(path)?;
+        if sha256 == input.sha256 {
+            println!("OK {}", path);
+        } else {
+            mismatches += 1;
+            println!(
+                "MISMATCH {}: SHA-256 {}, the snapshot records {} for {}",
+                path, sha256, input.sha256, input.source
+            );
+        }
+    }
+    if mismatches > 0 {
+        return Err(format!("{} input files don't match the snapshot", mismatches).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,1715 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+const TX_INPUT_1_ACCOUNTS: &str = "\
+client,available,held,total,locked
+1,1.5000,0,1.5000,false
+2,2.0000,0,2.0000,false
+";
+
+fn cli() -> Command {
+    let mut cmd = Command::cargo_bin("tx-processor-cli").unwrap();
+    cmd.current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/.."));
+    cmd
+}
+
+#[test]
+fn test_file_input() {
+    cli()
+        .arg("resources/input/tx-input1.csv")
+        .assert()
+        .success()
+        .stdout(TX_INPUT_1_ACCOUNTS);
+}
+
+#[test]
+fn test_stdin_input() {
+    cli()
+        .arg("-")
+        .pipe_stdin(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../resources/input/tx-input1.csv"
+        ))
+        .unwrap()
+        .assert()
+        .success()
+        .stdout(TX_INPUT_1_ACCOUNTS);
+}
+
+#[test]
+fn test_compressed_and_json_input() {
+    for input in [
+        "resources/input/tx-input1.csv.gz",
+        "resources/input/tx-input1.jsonl",
+    ] {
+        cli()
+            .arg(input)
+            .assert()
+            .success()
+            .stdout(TX_INPUT_1_ACCOUNTS);
+    }
+}
+
+#[test]
+fn test_malformed_records_are_skipped() {
+    cli()
+        .arg("resources/input/bad-record.csv")
+        .env("RUST_LOG", "error")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,0.5000,0,0.5000,false\n2,2.0000,0,2.0000,false\n")
+        .stderr(predicate::str::contains("Malformed CSV Record"));
+}
+
+#[test]
+fn test_input_limits() {
+    cli()
+        .args(["-", "--max-field-length", "8"])
+        .write_stdin(
+            "type,client,tx,amount,note\n\
+             deposit,1,1,1.0,a-long-note\n\
+             deposit,2,2,2.0,\n",
+        )
+        .env("RUST_LOG", "error")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n2,2.0000,0,2.0000,false\n")
+        .stderr(predicate::str::contains(
+            "FieldLength { line: 2, field: 5, max: 8 }",
+        ));
+}
+
+#[test]
+#[cfg_attr(
+    feature = "fixed-point",
+    ignore = "the amount exceeds the fixed-point range"
+)]
+fn test_strict_amounts() {
+    cli()
+        .args(["-", "--amounts", "strict"])
+        .write_stdin(
+            "type,client,tx,amount\n\
+             deposit,1,1,1e2\n\
+             deposit,1,2,1.00005\n\
+             deposit,1,3,12345678901234567.1234\n",
+        )
+        .env("RUST_LOG", "error")
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             1,12345678901234567.1234,0,12345678901234567.1234,false\n",
+        )
+        .stderr(predicate::str::contains("MalformedAmount"))
+        .stderr(predicate::str::contains("InexactAmount"));
+}
+
+#[test]
+fn test_missing_amount_policies() {
+    let reject_report = std::env::temp_dir().join("tx-processor-cli-test-incomplete.csv");
+    cli()
+        .args([
+            "-",
+            "--missing-deposit-amounts",
+            "zero",
+            "--missing-withdrawal-amounts",
+            "quarantine",
+        ])
+        .arg("--reject-report")
+        .arg(&reject_report)
+        .write_stdin(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,\n\
+             withdrawal,1,3,\n",
+        )
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,10.0000,0,10.0000,false\n")
+        .stderr(predicate::str::contains(
+            "Read 1 deposits without an amount, missing amount policy `zero`",
+        ))
+        .stderr(predicate::str::contains(
+            "Read 1 withdrawals without an amount, missing amount policy `quarantine`",
+        ));
+    assert_eq!(
+        std::fs::read_to_string(&reject_report).unwrap(),
+        "type,client,tx,amount,reason,source,line,batch\n\
+         withdrawal,1,3,,Missing amount in transaction data,-,4,\n"
+    );
+    std::fs::remove_file(reject_report).unwrap();
+}
+
+#[test]
+fn test_negative_amounts_normalized() {
+    let audit_log = std::env::temp_dir().join("tx-processor-cli-test-audit-log.csv");
+    cli()
+        .args(["-", "--negative-amounts", "normalize"])
+        .arg("--audit-log")
+        .arg(&audit_log)
+        .write_stdin(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,-2.5\n\
+             deposit,1,3,-1.5\n",
+        )
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,11.0000,0,11.0000,false\n")
+        .stderr(predicate::str::contains(
+            "Normalized 2 records with negative amounts",
+        ));
+    assert_eq!(
+        std::fs::read_to_string(&audit_log).unwrap(),
+        "original_type,original_amount,type,client,tx,amount,source,line,batch\n\
+         withdrawal,-2.5,deposit,1,2,2.5,-,3,\n\
+         deposit,-1.5,withdrawal,1,3,1.5,-,4,\n"
+    );
+    std::fs::remove_file(audit_log).unwrap();
+}
+
+#[test]
+fn test_tag_rules() {
+    let rules = std::env::temp_dir().join("tx-processor-cli-test-tag-rules.toml");
+    let report = std::env::temp_dir().join("tx-processor-cli-test-categories.csv");
+    std::fs::write(
+        &rules,
+        "[[rule]]\n\
+         category = \"payroll\"\n\
+         types = [\"deposit\"]\n\
+         counterparty = \"ACME Payroll\"\n\
+         \n\
+         [[rule]]\n\
+         category = \"transfer-in\"\n\
+         types = [\"deposit\"]\n\
+         max_amount = 500\n",
+    )
+    .unwrap();
+    cli()
+        .arg("-")
+        .arg("--tag-rules")
+        .arg(&rules)
+        .arg("--category-report")
+        .arg(&report)
+        .write_stdin(
+            "type,client,tx,amount,counterparty,category\n\
+             deposit,1,1,1000.0,ACME Payroll,\n\
+             deposit,1,2,20.0,Bob,\n\
+             deposit,1,3,1200.0,ACME Payroll,\n\
+             deposit,2,4,30.0,,gambling\n\
+             withdrawal,1,5,5.0,,\n",
+        )
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&report).unwrap(),
+        "client,category,count,amount\n\
+         1,payroll,2,2200.0000\n\
+         1,transfer-in,1,20.0000\n\
+         2,gambling,1,30.0000\n"
+    );
+    std::fs::remove_file(rules).unwrap();
+    std::fs::remove_file(report).unwrap();
+}
+
+#[test]
+fn test_aggregate_report() {
+    let report = std::env::temp_dir().join("tx-processor-cli-test-aggregates.csv");
+    cli()
+        .args(["-", "--aggregate-by", "day"])
+        .arg("--aggregate-report")
+        .arg(&report)
+        .write_stdin(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,10.0,1704067200\n\
+             deposit,2,2,5.0,1704153599\n\
+             withdrawal,1,3,4.0,1704153600\n\
+             deposit,2,4,1.0,\n",
+        )
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&report).unwrap(),
+        "key,count,deposits,withdrawals,net\n\
+         2024-01-01,2,15.0000,0,15.0000\n\
+         2024-01-02,1,0,4.0000,-4.0000\n"
+    );
+    std::fs::remove_file(report).unwrap();
+}
+
+#[test]
+fn test_daily_balances() {
+    let report = std::env::temp_dir().join("tx-processor-cli-test-daily-balances.csv");
+    cli()
+        .arg("-")
+        .arg("--daily-balances")
+        .arg(&report)
+        .write_stdin(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,10.0,1704067200\n\
+             deposit,1,2,5.0,1704153600\n\
+             dispute,1,2,,1704153700\n",
+        )
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&report).unwrap(),
+        "date,client,available,held,locked\n\
+         2024-01-01,1,10.0000,0,false\n\
+         2024-01-02,1,10.0000,5.0000,false\n"
+    );
+    std::fs::remove_file(report).unwrap();
+}
+
+#[test]
+fn test_verify() {
+    cli()
+        .arg("-")
+        .arg("--verify")
+        .write_stdin(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,4.0\n\
+             deposit,2,3,5.0\n\
+             dispute,2,3,\n\
+             chargeback,2,3,\n\
+             withdrawal,1,4,100.0\n",
+        )
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Verified 2 balances against 5 transactions",
+        ));
+    cli()
+        .arg("-")
+        .arg("--verify")
+        .arg("--opening-balances")
+        .arg("balances.csv")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_output_compat() {
+    let input = "type,client,tx,amount,note\n\
+                 deposit,1,1,1.0,\n\
+                 flag,1,0,,vip\n";
+    cli()
+        .args(["-", "--output-compat", "v1"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.0000,0,1.0000,false\n");
+    cli()
+        .args(["-", "--output-compat", "v2"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked,flags\n1,1.0000,0,1.0000,false,vip\n");
+    cli()
+        .args(["-", "--output-compat", "v0"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("expected `v1`, `v2` or `v3`"));
+}
+
+#[test]
+fn test_remap() {
+    cli()
+        .args(["-", "--output-compat", "v2"])
+        .write_stdin(
+            "type,client,tx,amount,note\n\
+             deposit,1,1,1.0,\n\
+             flag,1,2,,vip\n\
+             deposit,2,3,2.0,\n\
+             remap,1,4,,2\n\
+             dispute,2,1,,\n\
+             remap,2,5,,2\n",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked,flags\n\
+             2,2.0000,1.0000,3.0000,false,vip\n",
+        );
+}
+
+#[test]
+fn test_skip_empty() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,1.0\n\
+                 withdrawal,2,2,1.0\n\
+                 deposit,3,3,1.0\n\
+                 withdrawal,3,4,1.0\n";
+    cli()
+        .args(["-", "--skip-empty"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.0000,0,1.0000,false\n")
+        .stderr(predicate::str::contains("Skipped 2 empty accounts"));
+    cli()
+        .arg("-")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\n2,0,0,0,false\n"));
+}
+
+#[test]
+fn test_dispute_rules() {
+    let rules = std::env::temp_dir().join("tx-processor-cli-test-dispute-rules.csv");
+    let decisions = std::env::temp_dir().join("tx-processor-cli-test-dispute-decisions.csv");
+    std::fs::write(
+        &rules,
+        "duplicate,resolve,5 # small duplicates\nfraud,escalate\n",
+    )
+    .unwrap();
+    let input = "type,client,tx,amount,note\n\
+                 deposit,1,1,1.0,\n\
+                 deposit,1,2,10.0,\n\
+                 deposit,2,3,1.0,\n\
+                 dispute,1,1,,duplicate\n\
+                 dispute,1,2,,duplicate\n\
+                 dispute,2,3,,fraud\n";
+    cli()
+        .args(["-", "--output-compat", "v2", "--dispute-rules"])
+        .arg(&rules)
+        .arg("--dispute-decisions")
+        .arg(&decisions)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked,flags\n\
+             1,1.0000,10.0000,11.0000,false,\n\
+             2,0.0000,1.0000,1.0000,false,escalated\n",
+        );
+    assert_eq!(
+        std::fs::read_to_string(&decisions).unwrap(),
+        "client,tx,reason,action,amount,source,line,batch\n\
+         1,1,duplicate,resolve,1.0000,-,5,\n\
+         2,3,fraud,escalate,1.0000,-,7,\n"
+    );
+
+    std::fs::write(&rules, "fraud,refund\n").unwrap();
+    cli()
+        .args(["-", "--dispute-rules"])
+        .arg(&rules)
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "`fraud,refund` is not a dispute rule",
+        ));
+}
+
+#[test]
+fn test_pending_deposits() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 withdrawal,1,2,5.0\n\
+                 deposit,2,3,10.0\n\
+                 withdrawal,1,4,5.0\n";
+    cli()
+        .args(["-", "--pending-records", "2"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             1,5.0000,0.0000,5.0000,false\n\
+             2,0,10.0000,10.0000,false\n",
+        )
+        .stderr(predicate::str::contains("Left 1 deposits pending"));
+    cli()
+        .args(["-", "--pending-records", "2", "--release-pending"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             1,5.0000,0.0000,5.0000,false\n\
+             2,10.0000,0.0000,10.0000,false\n",
+        )
+        .stderr(predicate::str::contains("Released 1 pending deposits"));
+}
+
+#[test]
+fn test_reserves() {
+    let reserves = std::env::temp_dir().join("tx-processor-cli-test-reserves.csv");
+    std::fs::write(&reserves, "1,10,1 # high risk\n").unwrap();
+    let input = "type,client,tx,amount,timestamp\n\
+                 deposit,1,1,10.0,0\n\
+                 deposit,1,2,20.0,3600\n\
+                 deposit,2,3,10.0,3600\n\
+                 withdrawal,2,4,1.0,86400\n";
+    cli()
+        .args(["-", "--output-compat", "v3", "--reserves"])
+        .arg(&reserves)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked,flags,reserved\n\
+             1,28.0000,2.0000,30.0000,false,,2.0000\n\
+             2,9.0000,0,9.0000,false,,0\n",
+        );
+
+    std::fs::write(&reserves, "1,110,1\n").unwrap();
+    cli()
+        .args(["-", "--reserves"])
+        .arg(&reserves)
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("`1,110,1` is not a reserve"));
+}
+
+#[test]
+fn test_close_period() {
+    let state = std::env::temp_dir().join("tx-processor-cli-test-period-state.json");
+    let archive = std::env::temp_dir().join("tx-processor-cli-test-period-archive.csv");
+    cli()
+        .args(["-", "--close-period", "2024-01", "--period-archive"])
+        .arg(&archive)
+        .arg("--closing-state")
+        .arg(&state)
+        .write_stdin(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,10.0,100\n\
+             deposit,1,2,4.0,200\n",
+        )
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Closed period 2024-01 of 2 transactions, SHA-256 ",
+        ));
+    assert_eq!(
+        std::fs::read_to_string(&archive).unwrap(),
+        "type,client,tx,amount,timestamp,note\n\
+         deposit,1,1,10.0000,100,\n\
+         deposit,1,2,4.0000,200,\n"
+    );
+
+    // the back-dated deposit is rejected, while the period's deposit can still be disputed
+    cli()
+        .arg("-")
+        .arg("--opening-state")
+        .arg(&state)
+        .write_stdin(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,3,5.0,150\n\
+             dispute,1,2,,300\n",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             1,10.0000,4.0000,14.0000,false\n",
+        );
+    std::fs::remove_file(state).unwrap();
+    std::fs::remove_file(archive).unwrap();
+}
+
+#[test]
+fn test_late_arrivals() {
+    let state = std::env::temp_dir().join("tx-processor-cli-test-late-state.json");
+    let log = std::env::temp_dir().join("tx-processor-cli-test-late-arrivals.csv");
+    cli()
+        .args(["-", "--close-period", "2024-01", "--closing-state"])
+        .arg(&state)
+        .write_stdin(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,10.0,100\n",
+        )
+        .assert()
+        .success();
+
+    // the back-dated deposit adjusts the open period
+    cli()
+        .args(["-", "--late-arrivals", "adjust", "--late-arrival-log"])
+        .arg(&log)
+        .arg("--opening-state")
+        .arg(&state)
+        .write_stdin(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,2,5.0,50\n\
+             deposit,1,3,1.0,200\n",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             1,16.0000,0.0000,16.0000,false\n",
+        );
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        "client,tx,timestamp,period,action,source,line,batch\n\
+         1,2,50,,adjust,-,2,\n"
+    );
+    std::fs::remove_file(state).unwrap();
+    std::fs::remove_file(log).unwrap();
+}
+
+#[test]
+fn test_client_refs() {
+    let state = std::env::temp_dir().join("tx-processor-cli-test-client-refs-state.json");
+    let log = std::env::temp_dir().join("tx-processor-cli-test-client-aliases.csv");
+    cli()
+        .args(["-", "--client-ids-from", "1000", "--closing-state"])
+        .arg(&state)
+        .write_stdin(
+            "type,client,client_ref,tx,amount\n\
+             deposit,,cus_8f2a,1,10.0\n\
+             deposit,7,,2,5.0\n",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             7,5.0000,0,5.0000,false\n\
+             1000,10.0000,0,10.0000,false\n",
+        );
+
+    // the alias is kept in the closing state, so no resolver is needed for known references
+    cli()
+        .args(["-", "--client-alias-log"])
+        .arg(&log)
+        .arg("--opening-state")
+        .arg(&state)
+        .write_stdin(
+            "type,client_ref,tx,amount\n\
+             withdrawal,cus_8f2a,3,4.0\n\
+             deposit,cus_0c9d,4,1.0\n",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             7,5.0000,0.0000,5.0000,false\n\
+             1000,6.0000,0.0000,6.0000,false\n",
+        );
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        "client_ref,client\n\
+         cus_8f2a,1000\n"
+    );
+    std::fs::remove_file(state).unwrap();
+    std::fs::remove_file(log).unwrap();
+
+    // a denied client id isn't given to a new external id
+    cli()
+        .args(["-", "--client-ids-from", "1000", "--deny-clients", "1000"])
+        .write_stdin("type,client_ref,tx,amount\ndeposit,cus_8f2a,1,10.0\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1001,10.0000,0,10.0000,false\n");
+}
+
+/// A deposit of each of 50 clients given by an external id
+fn client_ref_input() -> String {
+    (0..50).fold(
+        String::from("type,client_ref,tx,amount\n"),
+        |input, client| input + &format!("deposit,cus_{},{},1.0\n", client, client + 1),
+    )
+}
+
+#[test]
+fn test_sample_client_refs() {
+    // external clients are sampled by their reference, rather than all or none of them
+    for seed in ["1", "2", "3"] {
+        let output = cli()
+            .args([
+                "-",
+                "--client-ids-from",
+                "1",
+                "--sample",
+                "0.5",
+                "--seed",
+                seed,
+            ])
+            .write_stdin(client_ref_input())
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let accounts = String::from_utf8(output).unwrap().lines().count() - 1;
+        assert!((10..40).contains(&accounts), "{} clients sampled", accounts);
+    }
+}
+
+#[test]
+fn test_split_client_refs() {
+    let dir = std::env::temp_dir().join("tx-processor-cli-test-split-client-refs");
+    let _ = std::fs::remove_dir_all(&dir);
+    let input = (0..50).fold(client_ref_input(), |input, client| {
+        input + &format!("withdrawal,cus_{},{},0.5\n", client, client + 51)
+    });
+    cli()
+        .args(["split", "-", "--shards", "3", "--output-dir"])
+        .arg(&dir)
+        .write_stdin(input)
+        .assert()
+        .success();
+    // every external client's records are in one shard, and the clients spread across shards
+    let shards = (0..3)
+        .map(|shard| std::fs::read_to_string(dir.join(format!("shard-{:03}.csv", shard))).unwrap())
+        .collect::<Vec<_>>();
+    assert!(shards.iter().all(|shard| shard.lines().count() > 1));
+    for client in 0..50 {
+        let deposit = format!("deposit,cus_{},", client);
+        let withdrawal = format!("withdrawal,cus_{},", client);
+        let shard = shards
+            .iter()
+            .position(|shard| shard.contains(&deposit))
+            .unwrap();
+        assert!(shards[shard].contains(&withdrawal));
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_freeze_thresholds() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 deposit,1,2,10.0\n\
+                 deposit,2,3,10.0\n\
+                 dispute,1,1,\n\
+                 withdrawal,1,4,5.0\n\
+                 withdrawal,2,5,5.0\n";
+    cli()
+        .args(["-", "--output-compat", "v2", "--freeze-risk-score", "20"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked,flags\n\
+             1,10.0000,10.0000,20.0000,false,frozen\n\
+             2,5.0000,0,5.0000,false,\n",
+        )
+        .stderr(predicate::str::contains(
+            "Froze the account of client 1 at transaction 1 for a risk score over 20",
+        ));
+
+    // the frozen client is named by its pseudonym like in the reports
+    let key = std::env::temp_dir().join("tx-processor-cli-test-freeze-pseudonym-key");
+    std::fs::write(&key, "secret\n").unwrap();
+    let output = cli()
+        .args(["-", "--freeze-risk-score", "20", "--pseudonymize"])
+        .arg(&key)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let accounts = String::from_utf8(output.stdout).unwrap();
+    // client 1 holds the disputed deposit
+    let frozen = accounts
+        .lines()
+        .find(|account| account.contains(",10.0000,10.0000,"))
+        .unwrap();
+    let pseudonym = frozen.split(',').next().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!(
+        "Froze the account of client {} at transaction 1",
+        pseudonym
+    )));
+    std::fs::remove_file(key).unwrap();
+}
+
+#[test]
+fn test_pseudonymize() {
+    let key = std::env::temp_dir().join("tx-processor-cli-test-pseudonym-key");
+    let reject_report = std::env::temp_dir().join("tx-processor-cli-test-pseudonym-rejects.csv");
+    std::fs::write(&key, "secret\n").unwrap();
+    let output = cli()
+        .arg("-")
+        .arg("--pseudonymize")
+        .arg(&key)
+        .arg("--reject-report")
+        .arg(&reject_report)
+        .write_stdin(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,20.0\n",
+        )
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let accounts = String::from_utf8(output).unwrap();
+    let pseudonym = accounts.lines().nth(1).unwrap().split(',').next().unwrap();
+    assert_eq!(pseudonym.len(), 16);
+    assert_eq!(
+        accounts,
+        format!(
+            "client,available,held,total,locked\n{},10.0000,0,10.0000,false\n",
+            pseudonym
+        )
+    );
+    // consistent across reports
+    assert!(std::fs::read_to_string(&reject_report)
+        .unwrap()
+        .contains(&format!("withdrawal,{},2,", pseudonym)));
+    std::fs::remove_file(key).unwrap();
+    std::fs::remove_file(reject_report).unwrap();
+}
+
+#[test]
+fn test_type_aliases() {
+    cli()
+        .args([
+            "-",
+            "--type-alias",
+            "payment=deposit",
+            "--type-alias",
+            "inquiry=ignore",
+            "--unknown-types",
+            "skip",
+        ])
+        .write_stdin(
+            "type,client,tx,amount\n\
+             payment,1,1,10.0\n\
+             inquiry,1,2,\n\
+             bonus,1,3,1.0\n",
+        )
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,10.0000,0,10.0000,false\n")
+        .stderr(predicate::str::contains(
+            "Skipped 1 records of type `bonus`",
+        ))
+        .stderr(predicate::str::contains(
+            "Skipped 1 records of type `inquiry`",
+        ));
+    cli()
+        .args(["-", "--type-alias", "payment"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("is not of the form NAME=TYPE"));
+}
+
+#[test]
+fn test_missing_input_file() {
+    cli()
+        .arg("resources/input/does-not-exist.csv")
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr(predicate::str::contains("No such file or directory"));
+}
+
+#[test]
+fn test_no_input_file() {
+    cli()
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "an input file of transactions is required",
+        ));
+}
+
+#[test]
+fn test_empty_input() {
+    cli()
+        .arg("-")
+        .write_stdin("type,client,tx,amount\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n");
+}
+
+#[test]
+fn test_disabled_transaction_types() {
+    let reject_report = std::env::temp_dir().join("tx-processor-cli-test-rejects.csv");
+    cli()
+        .args(["resources/input/chargeback.csv", "--disable", "chargeback"])
+        .args(["--batch", "2022-06-01"])
+        .arg("--reject-report")
+        .arg(&reject_report)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.5000,0,1.5000,false\n2,2.0000,0,2.0000,false\n3,1980.0000,10.0000,1990.0000,false\n");
+    assert_eq!(
+        std::fs::read_to_string(&reject_report).unwrap(),
+        "type,client,tx,amount,reason,source,line,batch\n\
+         withdrawal,2,5,3.0000,Insufficient Funds,resources/input/chargeback.csv,6,2022-06-01\n\
+         chargeback,3,7,,Transactions of this type are disabled,resources/input/chargeback.csv,10,2022-06-01\n"
+    );
+    std::fs::remove_file(reject_report).unwrap();
+}
+
+#[test]
+fn test_client_allow_and_deny_lists() {
+    let deny_list = std::env::temp_dir().join("tx-processor-cli-test-deny-list.txt");
+    let reject_report = std::env::temp_dir().join("tx-processor-cli-test-denied-rejects.csv");
+    std::fs::write(&deny_list, "# sanctioned\n3\n").unwrap();
+    cli()
+        .args(["resources/input/chargeback.csv", "--deny-clients-file"])
+        .arg(&deny_list)
+        .arg("--reject-report")
+        .arg(&reject_report)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.5000,0,1.5000,false\n2,2.0000,0,2.0000,false\n");
+    let rejects = std::fs::read_to_string(&reject_report).unwrap();
+    assert_eq!(
+        rejects
+            .matches("The client is excluded from this ledger by an allow or deny list")
+            .count(),
+        4
+    );
+    cli()
+        .args(["resources/input/chargeback.csv", "--allow-clients", "1,3"])
+        .args(["--deny-clients", "3"])
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.5000,0,1.5000,false\n");
+    cli()
+        .args(["resources/input/chargeback.csv", "--allow-clients", "1,x"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("`x` is not a client id"));
+    std::fs::remove_file(deny_list).unwrap();
+    std::fs::remove_file(reject_report).unwrap();
+}
+
+#[test]
+fn test_test_clients() {
+    let settlement = std::env::temp_dir().join("tx-processor-cli-test-prod-settlement.csv");
+    let test_settlement = std::env::temp_dir().join("tx-processor-cli-test-test-settlement.csv");
+    cli()
+        .args(["resources/input/chargeback.csv", "--test-clients", "3-9"])
+        .arg("--settlement")
+        .arg(&settlement)
+        .arg("--test-settlement")
+        .arg(&test_settlement)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "3,1980.0000,0.0000,1980.0000,true",
+        ))
+        .stderr(predicate::str::contains(
+            "Left 4 transactions of test clients out of the settlement and statistics",
+        ));
+    assert_eq!(
+        std::fs::read_to_string(&settlement).unwrap(),
+        "client,deposits,withdrawals,chargebacks,net\n\
+         1,3.0000,1.5000,0,1.5000\n\
+         2,2.0000,0,0,2.0000\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(&test_settlement).unwrap(),
+        "client,deposits,withdrawals,chargebacks,net\n\
+         3,2000.0000,10.0000,10.0000,1980.0000\n"
+    );
+    std::fs::remove_file(settlement).unwrap();
+    std::fs::remove_file(test_settlement).unwrap();
+}
+
+#[test]
+fn test_unknown_disabled_transaction_type() {
+    cli()
+        .args(["resources/input/chargeback.csv", "--disable", "transfer"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("unknown variant `transfer`"));
+}
+
+#[test]
+fn test_inspect() {
+    cli()
+        .args(["inspect", "resources/input/bad-record.csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("delimiter:    ','"))
+        .stdout(predicate::str::contains("header:       yes"))
+        .stdout(predicate::str::contains("type:         0 (\"type\")"))
+        .stdout(predicate::str::contains("amount:       3 (\"amount\")"))
+        .stdout(predicate::str::contains(
+            "column 0 (\"type\") has 1 values that aren't transaction types",
+        ));
+}
+
+#[test]
+fn test_time_budget_checkpoint_and_resume() {
+    let dir = std::env::temp_dir();
+    let input = dir.join("tx-processor-cli-test-budget-input.csv");
+    let checkpoint = dir.join("tx-processor-cli-test-budget-checkpoint.json");
+    cli()
+        .args(["gen", "--rows", "2500", "--clients", "20", "--seed", "7"])
+        .arg("--output")
+        .arg(&input)
+        .assert()
+        .success();
+    let full_run = cli().arg(&input).assert().success();
+    let expected = String::from_utf8(full_run.get_output().stdout.clone()).unwrap();
+
+    // a zero budget stops after the first chunk
+    cli()
+        .arg(&input)
+        .args(["--time-budget", "0", "--checkpoint"])
+        .arg(&checkpoint)
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Time budget exceeded after 1000 records",
+        ));
+    cli()
+        .arg(&input)
+        .arg("--resume")
+        .arg(&checkpoint)
+        .assert()
+        .success()
+        .stdout(expected);
+
+    // a budget that runs out on the last chunk doesn't stop the run, which processed it all
+    cli()
+        .args([
+            "resources/input/tx-input1.csv",
+            "--time-budget",
+            "0",
+            "--checkpoint",
+        ])
+        .arg(&checkpoint)
+        .assert()
+        .success()
+        .stdout(TX_INPUT_1_ACCOUNTS)
+        .stderr(predicate::str::contains("Time budget exceeded").not());
+
+    std::fs::remove_file(input).unwrap();
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[test]
+fn test_panic_summary_and_checkpoint() {
+    let checkpoint = std::env::temp_dir().join("tx-processor-cli-test-panic-checkpoint.json");
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,1.0\n\
+                 deposit,1,2,2.0\n\
+                 deposit,1,3,4.0\n";
+    cli()
+        .args(["-", "--checkpoint"])
+        .arg(&checkpoint)
+        .env("TX_PROCESSOR_PANIC_AT_RECORD", "2")
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            r#"{"status":"panicked","message":"injected panic at record 2","#,
+        ))
+        .stderr(predicate::str::contains(format!(
+            r#""records":1,"source":"-","line":3,"offset":38,"checkpoint":"{}"}}"#,
+            checkpoint.display()
+        )));
+    cli()
+        .arg("-")
+        .arg("--resume")
+        .arg(&checkpoint)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,7.0000,0.0000,7.0000,false\n");
+
+    // the balances of a resumed run can't be checked, so a panic leaves no checkpoint
+    let unchecked = std::env::temp_dir().join("tx-processor-cli-test-panic-unchecked.json");
+    cli()
+        .args(["-", "--resume"])
+        .arg(&checkpoint)
+        .arg("--checkpoint")
+        .arg(&unchecked)
+        .env("TX_PROCESSOR_PANIC_AT_RECORD", "1")
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Not writing a checkpoint after the panic",
+        ))
+        .stderr(predicate::str::contains(r#""checkpoint":null}"#));
+    assert!(!unchecked.exists());
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupt_checkpoint_and_resume() {
+    use std::io::Write;
+    use std::process::Stdio;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    let checkpoint = std::env::temp_dir().join("tx-processor-cli-test-interrupt-checkpoint.json");
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("tx-processor-cli"))
+        .arg("-")
+        .arg("--checkpoint")
+        .arg(&checkpoint)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"type,client,tx,amount\ndeposit,1,1,1.0\n")
+        .unwrap();
+    // let the run install its handler and block reading the next record
+    sleep(Duration::from_millis(500));
+    let status = std::process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    sleep(Duration::from_millis(200));
+    // the record the run was waiting for is applied, the ones after it aren't read
+    stdin
+        .write_all(b"deposit,1,2,2.0\ndeposit,1,3,4.0\n")
+        .unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,3.0000,0,3.0000,false\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Interrupted after 2 records, resume with --resume"));
+
+    cli()
+        .arg("-")
+        .arg("--resume")
+        .arg(&checkpoint)
+        .write_stdin("type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndeposit,1,3,4.0\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,7.0000,0.0000,7.0000,false\n");
+
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[test]
+fn test_fsync_reports() {
+    let reject_report = std::env::temp_dir().join("tx-processor-cli-test-fsync-rejects.csv");
+    cli()
+        .arg("resources/input/tx-input1.csv")
+        .arg("--fsync")
+        .arg("--reject-report")
+        .arg(&reject_report)
+        .assert()
+        .success()
+        .stdout(TX_INPUT_1_ACCOUNTS);
+    let report = std::fs::read_to_string(&reject_report).unwrap();
+    assert_eq!(report.lines().count(), 2);
+    std::fs::remove_file(reject_report).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_full_disk_fails() {
+    cli()
+        .args([
+            "resources/input/tx-input1.csv",
+            "--reject-report",
+            "/dev/full",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Failed to write /dev/full: I/O Error: No space left on device",
+        ));
+
+    let output = std::process::Command::new(assert_cmd::cargo::cargo_bin("tx-processor-cli"))
+        .arg("resources/input/tx-input1.csv")
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/.."))
+        .stdout(std::fs::File::create("/dev/full").unwrap())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Failed to write the accounts to standard output"));
+}
+
+#[test]
+fn test_split_and_shards() {
+    let dir = std::env::temp_dir().join("tx-processor-cli-test-shards");
+    let _ = std::fs::remove_dir_all(&dir);
+    cli()
+        .args(["split", "resources/input/tx-input1.csv", "--shards", "3"])
+        .arg("--output-dir")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("shard-002.csv"));
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    cli()
+        .arg("shards")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(TX_INPUT_1_ACCOUNTS)
+        .stderr(predicate::str::contains("Processed 5 records in 3 shards"));
+
+    // a client in two shards means the input wasn't split by client
+    std::fs::copy(
+        "../resources/input/tx-input1.csv",
+        dir.join("shard-003.csv"),
+    )
+    .unwrap();
+    cli()
+        .arg("shards")
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("which weren't split by client"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_limit_and_sample() {
+    cli()
+        .args(["resources/input/tx-input1.csv", "--limit", "2"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            "client,available,held,total,locked\n",
+            "1,1.0000,0,1.0000,false\n",
+            "2,2.0000,0,2.0000,false\n"
+        ));
+
+    let input = std::env::temp_dir().join("tx-processor-cli-test-sample-input.csv");
+    cli()
+        .args(["gen", "--rows", "2000", "--clients", "100", "--seed", "7"])
+        .arg("--output")
+        .arg(&input)
+        .assert()
+        .success();
+    let full_run = cli().arg(&input).assert().success();
+    let full_run = String::from_utf8(full_run.get_output().stdout.clone()).unwrap();
+    let sample = || {
+        let run = cli()
+            .arg(&input)
+            .args(["--sample", "0.2", "--seed", "9"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "Sampled 0.2 of the clients with seed 9",
+            ));
+        String::from_utf8(run.get_output().stdout.clone()).unwrap()
+    };
+    let sampled = sample();
+    assert_eq!(sample(), sampled);
+    // whole clients are sampled, so their accounts are those of the full run
+    let accounts = sampled.lines().skip(1).collect::<Vec<_>>();
+    assert!((5..40).contains(&accounts.len()), "{}", sampled);
+    assert!(accounts
+        .iter()
+        .all(|account| full_run.lines().any(|line| line == *account)));
+
+    std::fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn test_disputes_across_runs() {
+    let state = std::env::temp_dir().join("tx-processor-cli-test-closing-state.json");
+    cli()
+        .arg("resources/input/dispute-day-1.csv")
+        .arg("--closing-state")
+        .arg(&state)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,5.0000,10.0000,15.0000,false\n");
+    // the dispute opened yesterday is resolved, and yesterday's deposit disputed
+    cli()
+        .arg("resources/input/dispute-day-2.csv")
+        .arg("--opening-state")
+        .arg(&state)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,10.0000,5.0000,15.0000,false\n");
+    std::fs::remove_file(state).unwrap();
+}
+
+#[test]
+fn test_encrypted_state() {
+    let state = std::env::temp_dir().join("tx-processor-cli-test-encrypted-state");
+    cli()
+        .arg("resources/input/dispute-day-1.csv")
+        .arg("--closing-state")
+        .arg(&state)
+        .env("TX_PROCESSOR_STATE_KEY", "0f".repeat(32))
+        .assert()
+        .success();
+    assert!(std::fs::read(&state).unwrap().starts_with(b"TXENC1\n"));
+    cli()
+        .arg("resources/input/dispute-day-2.csv")
+        .arg("--opening-state")
+        .arg(&state)
+        .env("TX_PROCESSOR_STATE_KEY", "0f".repeat(32))
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,10.0000,5.0000,15.0000,false\n");
+    cli()
+        .arg("resources/input/dispute-day-2.csv")
+        .arg("--opening-state")
+        .arg(&state)
+        .env("TX_PROCESSOR_STATE_KEY", "f0".repeat(32))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("DecryptionFailed"));
+    cli()
+        .arg("resources/input/dispute-day-2.csv")
+        .arg("--opening-state")
+        .arg(&state)
+        .env_remove("TX_PROCESSOR_STATE_KEY")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("MissingKey"));
+    std::fs::remove_file(state).unwrap();
+}
+
+#[test]
+fn test_verify_input() {
+    let state = std::env::temp_dir().join("tx-processor-cli-test-checksum-state.json");
+    cli()
+        .arg("resources/input/dispute-day-1.csv")
+        .arg("--checksums")
+        .arg("--closing-state")
+        .arg(&state)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Read resources/input/dispute-day-1.csv, SHA-256 ",
+        ))
+        .stderr(predicate::str::contains("Run by tx-processor 0.1.0 ("));
+    // the state records the build and policies that wrote it
+    let snapshot: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&state).unwrap()).unwrap();
+    assert_eq!(snapshot["build"]["version"], "0.1.0");
+    assert!(snapshot["build"]["git_hash"].is_string());
+    assert_eq!(snapshot["build"]["policies"]["withdrawal disputes"], "hold");
+    let verify_input = |input: &str| {
+        let mut command = cli();
+        command
+            .arg("verify-input")
+            .arg("--snapshot")
+            .arg(&state)
+            .arg(input);
+        command
+    };
+    verify_input("resources/input/dispute-day-1.csv")
+        .assert()
+        .success()
+        .stdout("OK resources/input/dispute-day-1.csv\n");
+    verify_input("resources/input/dispute-day-2.csv")
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with(
+            "MISMATCH resources/input/dispute-day-2.csv",
+        ));
+    std::fs::remove_file(state).unwrap();
+}
+
+#[test]
+fn test_rate_limit_and_backfill() {
+    cli()
+        .args(["resources/input/resolve.csv", "--rate-limit", "1000"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "3,1990.0000,0.0000,1990.0000,false",
+        ));
+    cli()
+        .args([
+            "resources/input/resolve.csv",
+            "--rate-limit",
+            "1000",
+            "--backfill",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "3,1990.0000,0.0000,1990.0000,false",
+        ));
+    cli()
+        .args(["resources/input/resolve.csv", "--rate-limit", "0"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_replay_speed() {
+    // two seconds of history replayed ten times faster
+    let start = std::time::Instant::now();
+    cli()
+        .args(["-", "--replay-speed", "10"])
+        .write_stdin(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,1.0,1700000000\n\
+             deposit,1,2,1.0,1700000001\n\
+             withdrawal,1,3,0.5,1700000002\n",
+        )
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.5000,0,1.5000,false\n");
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+    cli()
+        .args(["-", "--replay-speed", "0"])
+        .write_stdin("type,client,tx,amount\n")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_aggregate_runs() {
+    let dir = std::env::temp_dir();
+    let eu = dir.join("tx-processor-cli-test-aggregate-eu.csv");
+    let us = dir.join("tx-processor-cli-test-aggregate-us.json");
+    std::fs::write(
+        &eu,
+        "client,available,held,total,locked\n2,2.0000,0,2.0000,false\n3,1.0000,0,1.0000,true\n",
+    )
+    .unwrap();
+    cli()
+        .args(["resources/input/dispute-day-1.csv", "--closing-state"])
+        .arg(&us)
+        .assert()
+        .success();
+
+    cli()
+        .arg("aggregate")
+        .args([&eu, &us])
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n\
+             1,5.0000,10.0000,15.0000,false\n\
+             2,2.0000,0.0000,2.0000,false\n\
+             3,1.0000,0.0000,1.0000,true\n",
+        )
+        .stderr(predicate::str::contains(
+            "Total: 3 accounts, 1 locked, available 8.0000, held 10.0000, total 18.0000",
+        ));
+
+    // the same client in two runs collides, unless they're namespaced
+    cli()
+        .arg("aggregate")
+        .args([&eu, &eu])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "prefix the runs with namespaces to keep them apart",
+        ));
+    cli()
+        .arg("aggregate")
+        .arg(format!("eu={}", eu.display()))
+        .arg(format!("eu2={}", eu.display()))
+        .assert()
+        .success()
+        .stdout(
+            "namespace,client,available,held,total,locked\n\
+             eu,2,2.0000,0.0000,2.0000,false\n\
+             eu,3,1.0000,0.0000,1.0000,true\n\
+             eu2,2,2.0000,0.0000,2.0000,false\n\
+             eu2,3,1.0000,0.0000,1.0000,true\n",
+        )
+        .stderr(predicate::str::contains(
+            "Client 2 is in eu and eu2, kept apart by namespace",
+        ));
+    std::fs::remove_file(eu).unwrap();
+    std::fs::remove_file(us).unwrap();
+}
+
+#[test]
+fn test_explain_policies() {
+    cli()
+        .args([
+            "explain-policies",
+            "--withdrawal-disputes",
+            "reject",
+            "--dedup",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("withdrawal disputes: reject\n"))
+        .stdout(predicate::str::contains("duplicate records: skip\n"))
+        .stdout(predicate::str::contains("unknown clients: open\n"));
+    cli()
+        .args(["explain-policies", "--unknown-clients", "close"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("expected one of `open`, `reject`"));
+}
+
+#[test]
+fn test_schema() {
+    // the schemas shipped in `schemas/` are those of the current types
+    for format in ["transaction", "account", "audit", "snapshot"] {
+        let shipped = std::fs::read_to_string(format!(
+            "{}/../schemas/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            format
+        ))
+        .unwrap();
+        cli()
+            .args(["schema", format])
+            .assert()
+            .success()
+            .stdout(shipped);
+    }
+    cli()
+        .args(["schema", "accounts"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("expected one of `transaction`"));
+}
+
+#[test]
+fn test_withdrawal_disputes_rejected() {
+    // the dispute of withdrawal 7 is rejected, so the resolve is too
+    cli()
+        .args([
+            "resources/input/resolve.csv",
+            "--withdrawal-disputes",
+            "reject",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3,1990.0000,0,1990.0000,false"));
+}
+
+#[test]
+fn test_dedup_across_files() {
+    cli()
+        .args([
+            "resources/input/dispute-day-1.csv",
+            "resources/input/dispute-day-1.csv",
+            "--dedup",
+        ])
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,5.0000,10.0000,15.0000,false\n")
+        .stderr(predicate::str::contains("Skipped 3 duplicate records"));
+}
+
+#[test]
+fn test_query_snapshot() {
+    let state = std::env::temp_dir().join("tx-processor-cli-test-query-state.json");
+    cli()
+        .arg("resources/input/dispute-day-1.csv")
+        .arg("--closing-state")
+        .arg(&state)
+        .assert()
+        .success();
+    let query = |args: &[&str]| {
+        let mut command = cli();
+        command
+            .arg("query")
+            .arg("--snapshot")
+            .arg(&state)
+            .args(args);
+        command
+    };
+    query(&["account", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""held": "10.0000""#));
+    query(&["tx", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""type": "deposit""#))
+        .stdout(predicate::str::contains(r#""dispute": {"#));
+    query(&["disputes", "--open"]).assert().success().stdout(
+        "[\n  {\n    \"client\": 1,\n    \"tx\": 1,\n    \"amount\": \"10.0000\"\n  }\n]\n",
+    );
+    query(&["diagram", "--tx", "1"]).assert().success().stdout(
+        "flowchart LR\n    tx1[\"tx 1: deposit of 10.0000 by client 1\"]\n    \
+         tx1 --> tx1_1[\"dispute (open)\"]\n",
+    );
+    query(&["account", "1", "--risk"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""held": "10.0000""#))
+        .stdout(predicate::str::contains(r#""risk": {"#))
+        .stdout(predicate::str::contains(r#""deposits": 2,"#));
+    query(&["risk"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""client": 1,"#));
+    query(&["risk", "--min", "100"])
+        .assert()
+        .success()
+        .stdout("[]\n");
+    query(&["account", "9"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("no account of client 9"));
+    std::fs::remove_file(state).unwrap();
+}
+
+#[test]
+fn test_time_budget_requires_checkpoint() {
+    cli()
+        .args(["resources/input/tx-input1.csv", "--time-budget", "10"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_account_limit_aborts() {
+    cli()
+        .args(["resources/input/tx-input1.csv", "--max-accounts", "1"])
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr(predicate::str::contains("LimitExceeded(Accounts)"));
+}
+
+#[test]
+fn test_account_limit_degrades() {
+    cli()
+        .args(["resources/input/tx-input1.csv", "--max-accounts", "1"])
+        .args(["--on-limit", "degrade"])
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.5000,0,1.5000,false\n")
+        .stderr(predicate::str::contains(
+            "Left 2 rejected records out of the reject report",
+        ));
+}
+
+#[test]
+fn test_trial_balance() {
+    let trial_balance = std::env::temp_dir().join("tx-processor-cli-test-trial-balance.csv");
+    cli()
+        .arg("resources/input/chargeback.csv")
+        .arg("--trial-balance")
+        .arg(&trial_balance)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&trial_balance).unwrap(),
+        "account,debits,credits\n\
+         cash,2005.0000,21.5000\n\
+         payables,21.5000,2005.0000\n\
+         held_payables,10.0000,10.0000\n\
+         chargeback_losses,10.0000,10.0000\n\
+         total,2046.5000,2046.5000\n"
+    );
+    std::fs::remove_file(trial_balance).unwrap();
+}
+
+#[test]
+fn test_gl_export() {
+    let gl_export = std::env::temp_dir().join("tx-processor-cli-test-gl-export.csv");
+    cli()
+        .arg("resources/input/tx-input1.csv")
+        .arg("--gl-export")
+        .arg(&gl_export)
+        .args(["--gl-date", "2022-06-01"])
+        .assert()
+        .success()
+        .stdout(TX_INPUT_1_ACCOUNTS);
+    assert_eq!(
+        std::fs::read_to_string(&gl_export).unwrap(),
+        "date,debit,credit,amount,reference\n\
+         2022-06-01,cash,payables,1.0000,deposit-1\n\
+         2022-06-01,cash,payables,2.0000,deposit-2\n\
+         2022-06-01,cash,payables,2.0000,deposit-3\n\
+         2022-06-01,payables,cash,1.5000,withdrawal-4\n"
+    );
+    std::fs::remove_file(gl_export).unwrap();
+
+    cli()
+        .args(["resources/input/tx-input1.csv", "--gl-export", "gl.csv"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_suspense_report() {
+    let suspense_report = std::env::temp_dir().join("tx-processor-cli-test-suspense.csv");
+    cli()
+        .arg("resources/input/out-of-order.csv")
+        .arg("--suspense-report")
+        .arg(&suspense_report)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,0.0000,15.0000,15.0000,false\n");
+    assert_eq!(
+        std::fs::read_to_string(&suspense_report).unwrap(),
+        "type,client,tx,amount,source,line,batch\n\
+         resolve,2,3,,resources/input/out-of-order.csv,5,\n"
+    );
+    std::fs::remove_file(suspense_report).unwrap();
+}
+
+#[test]
+fn test_gap_report() {
+    let gap_report = std::env::temp_dir().join("tx-processor-cli-test-gaps.csv");
+    cli()
+        .arg("resources/input/gaps-seq.csv")
+        .arg("--gap-report")
+        .arg(&gap_report)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&gap_report).unwrap(),
+        "client,first,last\n1,3,5\n1,7,8\n"
+    );
+    std::fs::remove_file(&gap_report).unwrap();
+
+    cli()
+        .arg("resources/input/gaps-seq.csv")
+        .arg("--gap-report")
+        .arg(&gap_report)
+        .args(["--gap-key", "tx"])
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&gap_report).unwrap(),
+        "client,first,last\n1,2,2\n1,4,6\n1,8,8\n2,3,7\n"
+    );
+
+    // external clients are numbered by their reference, and reported by their client id
+    cli()
+        .args(["-", "--client-ids-from", "100", "--gap-report"])
+        .arg(&gap_report)
+        .write_stdin(
+            "type,client_ref,tx,amount,seq\n\
+             deposit,cus_a,1,1.0,1\n\
+             deposit,cus_b,2,1.0,1\n\
+             deposit,cus_a,3,1.0,3\n\
+             deposit,cus_b,4,1.0,2\n",
+        )
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&gap_report).unwrap(),
+        "client,first,last\n100,2,2\n"
+    );
+    std::fs::remove_file(gap_report).unwrap();
+}
+
+#[test]
+fn test_reorder_window() {
+    // the withdrawal of 12 only succeeds once the deposit that arrived late was applied
+    cli()
+        .args([
+            "resources/input/out-of-order-seq.csv",
+            "--reorder-window",
+            "2",
+        ])
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,0.0000,0,0,false\n")
+        .stderr(predicate::str::contains(
+            "Reordered 1 records, 0 arrived too late to be reordered",
+        ));
+}
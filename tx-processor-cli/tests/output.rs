@@ -0,0 +1,34 @@
+use std::process::Command;
+
+fn run(input_file: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_tx-processor-cli"))
+        .arg(input_file)
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/.."))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_accounts_have_exactly_five_columns() {
+    let output = run("resources/input/chargeback.csv");
+    let mut lines = output.lines();
+    assert_eq!(lines.next(), Some("client,available,held,total,locked"));
+    let rows = lines.collect::<Vec<_>>();
+    assert_eq!(rows.len(), 3);
+    for row in rows {
+        assert_eq!(row.split(',').count(), 5, "unexpected row {:?}", row);
+    }
+}
+
+#[test]
+fn test_accounts_ordered_by_client() {
+    let output = run("resources/input/chargeback.csv");
+    let clients = output
+        .lines()
+        .skip(1)
+        .map(|row| row.split(',').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(clients, vec!["1", "2", "3"]);
+}
@@ -0,0 +1,205 @@
+//! Compares strategies for finding the transaction a dispute references, over dispute-heavy
+//! inputs generated by [Workload].
+//!
+//! Results on a single-core Intel Xeon VM, median time to process the whole stream
+//! (`cargo bench --features generate --bench dispute_lookup`):
+//!
+//! |   records | linear scan | tx id index | per-client log |   `Ledger` | transfers only |
+//! |----------:|------------:|------------:|---------------:|-----------:|---------------:|
+//! |     1 000 |    87.9 µs  |    53.5 µs  |      100.1 µs  |   895.5 µs |       680.0 µs |
+//! |    10 000 |    6.98 ms  |   652.2 µs  |      898.6 µs  |    5.83 ms |        4.85 ms |
+//! |    30 000 |    98.6 ms  |    2.12 ms  |       2.40 ms  |    28.4 ms |        18.5 ms |
+//! | 1 000 000 |           – |           – |              – |     1.67 s |        1.29 s  |
+//!
+//! The linear scan is quadratic in the number of records, so it falls more than an order of
+//! magnitude behind both indexed strategies as files grow. The `Ledger` looks disputes up in
+//! its per-client index, and resolves and chargebacks of undisputed transactions in an index of
+//! every retained id. Before that index it scanned the log for them, which took 1.06 ms,
+//! 24.3 ms, 72.5 ms and 3.17 s on the inputs above.
+//!
+//! Unlike the other strategies, which only look transactions up, the `Ledger` applies every
+//! record: it updates the accounts, appends to its log and keeps its indexes, and a dispute
+//! also moves funds to held. Most of its time goes to the deposits and withdrawals, as the
+//! same input without the disputes, resolves and chargebacks ("transfers only") shows, so its
+//! lookups cost about as much per record as the tx id index's.
+use std::collections::HashMap;
+
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, Throughput,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tx_processor::generate::{Workload, WorkloadConfig};
+use tx_processor::ledger::Ledger;
+use tx_processor::transaction::{Transaction, TransactionType};
+
+const SIZES: [usize; 3] = [1_000, 10_000, 30_000];
+/// Only the `Ledger` is run on the largest input, where the linear scan would take minutes
+const LEDGER_SIZES: [usize; 1] = [1_000_000];
+
+fn dispute_heavy(rows: usize, chargeback_rate: f64) -> Vec<Transaction> {
+    let workload = Workload::new(WorkloadConfig {
+        clients: 1_000,
+        dispute_rate: 0.3,
+        resolve_rate: 0.1,
+        chargeback_rate,
+        // mostly deposits, so disputes rarely fail for lack of funds
+        deposit_ratio: 0.9,
+    });
+    StdRng::seed_from_u64(1416)
+        .sample_iter(&workload)
+        .take(rows)
+        .flat_map(Transaction::try_from)
+        .collect()
+}
+
+/// What `Account::dispute` used to do, before the `Ledger` kept its `Disputable` index: search
+/// the whole log for the referenced transaction
+fn linear_scan(transactions: &[Transaction]) -> usize {
+    let mut log = Vec::new();
+    let mut found = 0;
+    for transaction in transactions {
+        match transaction.tx_type {
+            TransactionType::Dispute => {
+                found += log
+                    .iter()
+                    .find(|t: &&Transaction| t.transaction_id == transaction.transaction_id)
+                    .map_or(0, |_| 1);
+            }
+            _ => log.push(transaction.clone()),
+        }
+    }
+    found
+}
+
+/// Keep a `transaction_id -> position in the log` index next to the log
+fn tx_id_index(transactions: &[Transaction]) -> usize {
+    let mut log = Vec::new();
+    let mut index = HashMap::new();
+    let mut found = 0;
+    for transaction in transactions {
+        match transaction.tx_type {
+            TransactionType::Dispute => {
+                found += index
+                    .get(&transaction.transaction_id)
+                    .map(|&i: &usize| &log[i])
+                    .map_or(0, |_: &Transaction| 1);
+            }
+            _ => {
+                index.entry(transaction.transaction_id).or_insert(log.len());
+                log.push(transaction.clone());
+            }
+        }
+    }
+    found
+}
+
+/// Keep the positions of each client's transactions and only scan those
+fn per_client_log(transactions: &[Transaction]) -> usize {
+    let mut log = Vec::new();
+    let mut by_client: HashMap<u16, Vec<usize>> = HashMap::new();
+    let mut found = 0;
+    for transaction in transactions {
+        match transaction.tx_type {
+            TransactionType::Dispute => {
+                found += by_client
+                    .get(&transaction.client_id)
+                    .and_then(|positions| {
+                        positions.iter().find(|&&i| {
+                            let t: &Transaction = &log[i];
+                            t.transaction_id == transaction.transaction_id
+                        })
+                    })
+                    .map_or(0, |_| 1);
+            }
+            _ => {
+                by_client
+                    .entry(transaction.client_id)
+                    .or_default()
+                    .push(log.len());
+                log.push(transaction.clone());
+            }
+        }
+    }
+    found
+}
+
+fn bench_ledger(group: &mut BenchmarkGroup<WallTime>, transactions: &[Transaction]) {
+    group.bench_with_input(
+        BenchmarkId::new("ledger", transactions.len()),
+        transactions,
+        |b, txs| {
+            b.iter(|| {
+                let mut ledger = Ledger::default();
+                ledger.process_transactions(txs.iter().cloned());
+                ledger
+            })
+        },
+    );
+    // the same input without the records that look a transaction up, to tell the cost of the
+    // lookups from the cost of applying the deposits and withdrawals
+    let transfers = transactions
+        .iter()
+        .filter(|transaction| {
+            matches!(
+                transaction.tx_type,
+                TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. }
+            )
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    group.bench_with_input(
+        BenchmarkId::new("ledger_transfers_only", transactions.len()),
+        &transfers,
+        |b, txs| {
+            b.iter(|| {
+                let mut ledger = Ledger::default();
+                ledger.process_transactions(txs.iter().cloned());
+                ledger
+            })
+        },
+    );
+}
+
+fn bench_dispute_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispute_lookup");
+    group.sample_size(10);
+    for rows in SIZES {
+        let transactions = dispute_heavy(rows, 0.01);
+        // every strategy must find the same disputed transactions
+        let expected = linear_scan(&transactions);
+        assert_eq!(tx_id_index(&transactions), expected);
+        assert_eq!(per_client_log(&transactions), expected);
+
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_with_input(
+            BenchmarkId::new("linear_scan", rows),
+            &transactions,
+            |b, txs| b.iter(|| linear_scan(txs)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("tx_id_index", rows),
+            &transactions,
+            |b, txs| b.iter(|| tx_id_index(txs)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("per_client_log", rows),
+            &transactions,
+            |b, txs| b.iter(|| per_client_log(txs)),
+        );
+        bench_ledger(&mut group, &transactions);
+    }
+    for rows in LEDGER_SIZES {
+        // without chargebacks, which would lock every account long before the input ends and
+        // have the rest of it rejected before any lookup
+        let transactions = dispute_heavy(rows, 0.0);
+        group.throughput(Throughput::Elements(rows as u64));
+        bench_ledger(&mut group, &transactions);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dispute_lookup);
+criterion_main!(benches);
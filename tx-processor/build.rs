@@ -0,0 +1,38 @@
+//! Records the git commit the crate is built from, see `tx_processor::build_info`
+
+use std::path::Path;
+use std::process::Command;
+
+/// The trimmed output of a git command run in the crate's directory, if git succeeded
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
+fn main() {
+    // builds outside a checkout, e.g. of a source archive, can be given the hash
+    println!("cargo:rerun-if-env-changed=TX_PROCESSOR_GIT_HASH");
+    let hash = std::env::var("TX_PROCESSOR_GIT_HASH")
+        .ok()
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=TX_PROCESSOR_GIT_HASH={}", hash);
+
+    // rebuild when HEAD moves, watching only files that exist so cargo doesn't rerun this on
+    // every build
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        let head = git(&["symbolic-ref", "-q", "HEAD"]);
+        for path in ["HEAD", "packed-refs"]
+            .into_iter()
+            .chain(head.as_deref())
+            .map(|path| git_dir.join(path))
+            .filter(|path| path.exists())
+        {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
@@ -1,25 +1,127 @@
-use std::collections::HashMap;
-use std::convert::From;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use core::convert::From;
+use core::fmt;
 
-use serde::{ser, ser::SerializeStruct, Serialize, Serializer};
+use rust_decimal::Decimal;
+use serde::{ser, ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
+use crate::disputable::Disputable;
 use crate::error::TxError;
-use crate::transaction::{PositiveDecimal, Transact, Transaction, TransactionType};
+use crate::map::Map;
+use crate::transaction::{PositiveDecimal, Transact};
 
 /// The detailing of the amounts available for spending in a client's [Account](crate::account::Account)
 /// The total amount of money can be derived by adding the `available` and `held` in this `Balance`
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub(crate) struct Balance {
     /// Amount ready for immediate spending
     available: PositiveDecimal,
     /// Amount held by disputed transactions
     held: PositiveDecimal,
+    /// The part of `held` reserved by a [RollingReserve](crate::reserve::RollingReserve)
+    reserved: PositiveDecimal,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Account<const IS_LOCKED: bool> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account<const IS_LOCKED: bool = false> {
     pub(crate) client_id: u16,
     pub(crate) balance: Balance,
+    /// Annotations attached by admin records, e.g. `under_review` or `vip`
+    pub(crate) flags: BTreeSet<String>,
+}
+
+/// The columns an [Account] is serialized to, in order
+pub const ACCOUNT_COLUMNS: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// The columns of [AccountColumns::V2](crate::report::AccountColumns::V2), in order
+pub const ACCOUNT_COLUMNS_V2: [&str; 6] =
+    ["client", "available", "held", "total", "locked", "flags"];
+
+/// The columns of [AccountColumns::V3](crate::report::AccountColumns::V3), in order
+pub const ACCOUNT_COLUMNS_V3: [&str; 7] = [
+    "client",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "flags",
+    "reserved",
+];
+
+/// A row of a previously written accounts report, e.g. yesterday's closing balances, used
+/// to seed a [Ledger](crate::ledger::Ledger) via `Ledger::from_opening_balances`
+#[derive(Debug, Deserialize)]
+pub struct AccountRecord {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+}
+
+/// An object-safe, read-only view of an account, regardless of whether it is locked
+pub trait AccountView {
+    fn client_id(&self) -> u16;
+
+    fn available(&self) -> PositiveDecimal;
+
+    fn held(&self) -> PositiveDecimal;
+
+    fn is_locked(&self) -> bool;
+
+    fn flags(&self) -> &BTreeSet<String>;
+
+    /// The part of the held funds reserved by a
+    /// [RollingReserve](crate::reserve::RollingReserve), included in [AccountView::held]
+    fn reserved(&self) -> PositiveDecimal {
+        PositiveDecimal::default()
+    }
+
+    fn total(&self) -> Result<PositiveDecimal, TxError> {
+        self.available().checked_add(self.held())
+    }
+
+    /// `true` if nothing is available or held, e.g. for an account opened by a withdrawal that
+    /// was rejected for insufficient funds
+    fn is_empty(&self) -> bool {
+        Decimal::from(self.available()).is_zero() && Decimal::from(self.held()).is_zero()
+    }
+}
+
+/// An account a [Ledger](crate::ledger::Ledger) keeps for each client, [Account] by default.
+/// Implement it to wrap an [Account] with logging, external-balance mirroring or custom rules:
+/// the ledger applies deposits, withdrawals, disputes and resolves through [Transact], and
+/// chargebacks through [LedgerAccount::lock], while the rest of its bookkeeping, e.g. pending
+/// and reserved funds or flags, works on the wrapped account.
+pub trait LedgerAccount: Transact + AccountView + Clone + fmt::Debug + Eq {
+    /// The account a chargeback locks this one into
+    type Locked: LockedAccount;
+
+    /// Wraps `account`, e.g. the empty account of a new client
+    fn wrap(account: Account) -> Self;
+
+    fn account(&self) -> &Account;
+
+    fn account_mut(&mut self) -> &mut Account;
+
+    /// Charges back the disputed `transaction_id` like [Transact::chargeback], returning the
+    /// locked account, or the account as it was if the chargeback failed
+    fn lock(
+        self,
+        transaction_id: u32,
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
+    ) -> (Result<Self::Locked, TxError>, Option<Self>);
+}
+
+/// The locked counterpart of a [LedgerAccount], [`Account<true>`] by default
+pub trait LockedAccount: AccountView + Clone + fmt::Debug + Eq {
+    /// Wraps `account`, e.g. one locked in the opening balances
+    fn wrap(account: Account<true>) -> Self;
+
+    fn account(&self) -> &Account<true>;
+
+    fn account_mut(&mut self) -> &mut Account<true>;
 }
 
 impl Balance {
@@ -57,6 +159,7 @@ impl From<Account<false>> for Account<true> {
         Account {
             client_id: account.client_id,
             balance: account.balance,
+            flags: account.flags,
         }
     }
 }
@@ -66,58 +169,269 @@ impl Account<false> {
         Account {
             client_id,
             balance: Balance::default(),
+            flags: BTreeSet::new(),
+        }
+    }
+
+    pub(crate) fn with_balance(
+        client_id: u16,
+        available: PositiveDecimal,
+        held: PositiveDecimal,
+    ) -> Self {
+        Account {
+            client_id,
+            balance: Balance {
+                available,
+                held,
+                reserved: PositiveDecimal::default(),
+            },
+            flags: BTreeSet::new(),
+        }
+    }
+    /// Moves `amount` from the available to the held funds, like a dispute without a disputed
+    /// transaction
+    pub(crate) fn hold(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        self.balance.available = self.balance.available.checked_sub(amount)?;
+        self.balance.held = self.balance.held.checked_add(amount)?;
+        Ok(())
+    }
+
+    /// Adds `amount` to the held funds, for a deposit that isn't available yet, see
+    /// [AvailabilityDelay](crate::availability::AvailabilityDelay)
+    pub(crate) fn deposit_pending(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        self.balance.held = self.balance.held.checked_add(amount)?;
+        Ok(())
+    }
+
+    /// Adds `reserved` to the held funds as reserve, for the part of a deposit a
+    /// [RollingReserve](crate::reserve::RollingReserve) holds, and the `rest` of the deposit to
+    /// the held funds if `pending` or the available ones otherwise. Fails without changing
+    /// anything if a balance overflows.
+    pub(crate) fn deposit_reserved(
+        &mut self,
+        reserved: PositiveDecimal,
+        rest: PositiveDecimal,
+        pending: bool,
+    ) -> Result<(), TxError> {
+        let held = self.balance.held.checked_add(reserved)?;
+        let (available, held) = if pending {
+            (self.balance.available, held.checked_add(rest)?)
+        } else {
+            (self.balance.available.checked_add(rest)?, held)
+        };
+        self.balance = Balance {
+            available,
+            held,
+            reserved: self.balance.reserved.checked_add(reserved)?,
+        };
+        Ok(())
+    }
+
+    /// Keeps `amount` of the reserve held, but no longer as reserve, e.g. by a dispute
+    pub(crate) fn unreserve(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        self.balance.reserved = self.balance.reserved.checked_sub(amount)?;
+        Ok(())
+    }
+
+    /// Adds the balances and flags of `other`, for accounts merged by
+    /// [Ledger::remap_client](crate::ledger::Ledger::remap_client). Fails without changing
+    /// anything if a balance overflows.
+    pub(crate) fn merge(&mut self, other: Account<false>) -> Result<(), TxError> {
+        self.balance = Balance {
+            available: self
+                .balance
+                .available
+                .checked_add(other.balance.available)?,
+            held: self.balance.held.checked_add(other.balance.held)?,
+            reserved: self.balance.reserved.checked_add(other.balance.reserved)?,
+        };
+        self.flags.extend(other.flags);
+        Ok(())
+    }
+
+    /// Reserves `reserved` of the held funds, for an account restored from a snapshot
+    #[cfg(feature = "snapshot")]
+    pub(crate) fn with_reserved(mut self, reserved: PositiveDecimal) -> Self {
+        self.balance.reserved = reserved;
+        self
+    }
+}
+
+impl<const IS_LOCKED: bool> Account<IS_LOCKED> {
+    /// Moves `amount` from the held to the available funds, for a pending deposit that became
+    /// available. Locked accounts keep the released funds, like the rest of their balance.
+    pub(crate) fn release(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        let available = self.balance.available.checked_add(amount)?;
+        self.balance.held = self.balance.held.checked_sub(amount)?;
+        self.balance.available = available;
+        Ok(())
+    }
+
+    /// Makes `amount` of the reserve available, once the reserve period of its deposit ended
+    pub(crate) fn release_reserve(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        let reserved = self.balance.reserved.checked_sub(amount)?;
+        self.release(amount)?;
+        self.balance.reserved = reserved;
+        Ok(())
+    }
+}
+
+impl From<AccountRecord> for (u16, Decimal, Decimal, bool) {
+    fn from(record: AccountRecord) -> Self {
+        (
+            record.client_id,
+            record.available,
+            record.held,
+            record.locked,
+        )
+    }
+}
+
+impl<const IS_LOCKED: bool> AccountView for Account<IS_LOCKED> {
+    fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    fn available(&self) -> PositiveDecimal {
+        self.balance.available
+    }
+
+    fn held(&self) -> PositiveDecimal {
+        self.balance.held
+    }
+
+    fn is_locked(&self) -> bool {
+        IS_LOCKED
+    }
+
+    fn flags(&self) -> &BTreeSet<String> {
+        &self.flags
+    }
+
+    fn reserved(&self) -> PositiveDecimal {
+        self.balance.reserved
+    }
+}
+
+impl LedgerAccount for Account {
+    type Locked = Account<true>;
+
+    fn wrap(account: Account) -> Self {
+        account
+    }
+
+    fn account(&self) -> &Account {
+        self
+    }
+
+    fn account_mut(&mut self) -> &mut Account {
+        self
+    }
+
+    fn lock(
+        self,
+        transaction_id: u32,
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
+    ) -> (Result<Account<true>, TxError>, Option<Account>) {
+        self.chargeback(transaction_id, disputed_tx_map)
+    }
+}
+
+impl LockedAccount for Account<true> {
+    fn wrap(account: Account<true>) -> Self {
+        account
+    }
+
+    fn account(&self) -> &Account<true> {
+        self
+    }
+
+    fn account_mut(&mut self) -> &mut Account<true> {
+        self
+    }
+}
+
+/// An account moved out of its ledger by
+/// [Ledger::archive_account](crate::ledger::Ledger::archive_account), kept as it was when
+/// archived
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchivedAccount<A: LedgerAccount = Account> {
+    Active(A),
+    Locked(A::Locked),
+}
+
+impl<A: LedgerAccount> ArchivedAccount<A> {
+    fn view(&self) -> &dyn AccountView {
+        match self {
+            ArchivedAccount::Active(account) => account,
+            ArchivedAccount::Locked(account) => account,
         }
     }
 }
 
+impl<A: LedgerAccount> AccountView for ArchivedAccount<A> {
+    fn client_id(&self) -> u16 {
+        self.view().client_id()
+    }
+
+    fn available(&self) -> PositiveDecimal {
+        self.view().available()
+    }
+
+    fn held(&self) -> PositiveDecimal {
+        self.view().held()
+    }
+
+    fn is_locked(&self) -> bool {
+        self.view().is_locked()
+    }
+
+    fn flags(&self) -> &BTreeSet<String> {
+        self.view().flags()
+    }
+
+    fn reserved(&self) -> PositiveDecimal {
+        self.view().reserved()
+    }
+}
+
 impl Transact for Account<false> {
+    #[cfg_attr(feature = "profiling", inline(never))]
     fn deposit(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
         self.balance.available = self.balance.available.checked_add(amount)?;
         Ok(())
     }
 
+    #[cfg_attr(feature = "profiling", inline(never))]
     fn withdraw(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
         self.balance.available = self.balance.available.checked_sub(amount)?;
         Ok(())
     }
 
-    /// Assumption: the `transaction_log` **must** be ordered chronologically
+    /// Looks the disputed amount up in the client's transactions in the [Disputable] index
+    #[cfg_attr(feature = "profiling", inline(never))]
     fn dispute(
         &mut self,
         disputed_tx_id: u32,
-        transaction_log: &[Transaction],
-        disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        disputable: &Disputable,
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> Result<(), TxError> {
         if disputed_tx_map.contains_key(&disputed_tx_id) {
-            return Err(TxError::BadDispute);
+            return Err(TxError::AlreadyDisputed);
         }
 
-        if let Some(disputed_transaction) = transaction_log
-            .iter()
-            .find(|&t| t.transaction_id == disputed_tx_id)
-        {
-            if self.client_id != disputed_transaction.client_id {
-                return Err(TxError::InsufficientPermission);
-            }
-
-            match disputed_transaction.tx_type {
-                TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
-                    self.balance.available = self.balance.available.checked_sub(amount)?;
-                    self.balance.held = self.balance.held.checked_add(amount)?;
-                    disputed_tx_map.insert(disputed_tx_id, (self.client_id, amount));
-                    Ok(())
-                }
-                _ => Err(TxError::BadDispute),
-            }
-        } else {
-            Err(TxError::NotFound)
-        }
+        let amount = disputable.get(self.client_id, disputed_tx_id)?;
+        self.balance.available = self.balance.available.checked_sub(amount)?;
+        self.balance.held = self.balance.held.checked_add(amount)?;
+        disputed_tx_map.insert(disputed_tx_id, (self.client_id, amount));
+        Ok(())
     }
 
+    #[cfg_attr(feature = "profiling", inline(never))]
     fn resolve(
         &mut self,
         transaction_id: u32,
-        disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> Result<(), TxError> {
         if let Some(&(client_id, amount)) = disputed_tx_map.get(&transaction_id) {
             if self.client_id != client_id {
@@ -133,10 +447,11 @@ impl Transact for Account<false> {
         }
     }
 
+    #[cfg_attr(feature = "profiling", inline(never))]
     fn chargeback(
         mut self,
         transaction_id: u32,
-        disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> (Result<Account<true>, TxError>, Option<Account<false>>) {
         if let Some(&(client_id, amount)) = disputed_tx_map.get(&transaction_id) {
             if client_id != self.client_id {
@@ -170,8 +485,8 @@ impl Transact for Account<true> {
     fn dispute(
         &mut self,
         _disputed_tx_id: u32,
-        _transaction_log: &[Transaction],
-        _disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        _disputable: &Disputable,
+        _disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> Result<(), TxError> {
         Err(TxError::LockedAccount)
     }
@@ -179,7 +494,7 @@ impl Transact for Account<true> {
     fn resolve(
         &mut self,
         _transaction_id: u32,
-        _disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        _disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> Result<(), TxError> {
         Err(TxError::LockedAccount)
     }
@@ -187,7 +502,7 @@ impl Transact for Account<true> {
     fn chargeback(
         self,
         _transaction_id: u32,
-        _disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        _disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> (Result<Account<true>, TxError>, Option<Account<false>>) {
         (Err(TxError::LockedAccount), None)
     }
@@ -217,7 +532,11 @@ impl<const IS_LOCKED: bool> Serialize for Account<IS_LOCKED> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use rust_decimal::prelude::*;
+    use crate::transaction::{Transaction, TransactionType};
+
+    fn disputable(transactions: &[Transaction]) -> Disputable {
+        transactions.iter().collect()
+    }
 
     #[test]
     fn test_transact_locked_account() {
@@ -226,18 +545,29 @@ mod test {
         assert!(locked_account.deposit(amount).is_err());
         assert!(locked_account.withdraw(amount).is_err());
         assert!(locked_account
-            .dispute(888, &vec![], &mut HashMap::new())
+            .dispute(888, &Disputable::default(), &mut Map::default())
             .is_err());
-        assert!(locked_account.resolve(888, &mut HashMap::new()).is_err());
+        assert!(locked_account.resolve(888, &mut Map::default()).is_err());
         assert!(locked_account
-            .chargeback(888, &mut HashMap::new())
+            .chargeback(888, &mut Map::default())
             .0
             .is_err());
         let locked_account: Account<true> = Account::<true>::from(Account::new(1));
         assert!(locked_account
-            .chargeback(888, &mut HashMap::new())
+            .chargeback(888, &mut Map::default())
             .1
             .is_none());
+
+        // every operation but chargeback is usable through a trait object
+        let mut accounts: Vec<Box<dyn Transact>> = vec![
+            Box::new(Account::new(1)),
+            Box::new(Account::<true>::from(Account::new(2))),
+        ];
+        let results = accounts
+            .iter_mut()
+            .map(|account| account.deposit(amount).is_ok())
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![true, false]);
     }
 
     #[test]
@@ -294,17 +624,17 @@ mod test {
         let client_id: u16 = 5;
         let zero = PositiveDecimal::try_from(Decimal::ZERO).unwrap();
         let amount = PositiveDecimal::try_from(10000.1000).unwrap();
-        let mut map = HashMap::new();
+        let mut map = Map::default();
         map.insert(disputed_tx_id, (client_id, zero));
 
         // can't dispute something that's already disputed
         let mut account = Account::new(client_id);
-        let res = account.dispute(disputed_tx_id, &vec![], &mut map);
+        let res = account.dispute(disputed_tx_id, &Disputable::default(), &mut map);
         assert!(res.is_err());
 
         // can't find a transaction
         map.clear();
-        let res = account.dispute(disputed_tx_id, &vec![], &mut map);
+        let res = account.dispute(disputed_tx_id, &Disputable::default(), &mut map);
         assert!(res.is_err());
 
         // can't dispute a transaction from someone else
@@ -313,18 +643,18 @@ mod test {
             disputed_tx_id,
             TransactionType::Deposit { amount },
         );
-        let res = account.dispute(disputed_tx_id, &vec![tx], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx]), &mut map);
         assert!(res.is_err());
 
         // can't dispute a transaction other than a deposit or withdrawal
         let tx = Transaction::new(client_id, disputed_tx_id, TransactionType::Dispute);
-        let res = account.dispute(disputed_tx_id, &vec![tx], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx]), &mut map);
         assert!(res.is_err());
         let tx = Transaction::new(client_id, disputed_tx_id, TransactionType::Resolve);
-        let res = account.dispute(disputed_tx_id, &vec![tx], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx]), &mut map);
         assert!(res.is_err());
         let tx = Transaction::new(client_id, disputed_tx_id, TransactionType::Chargeback);
-        let res = account.dispute(disputed_tx_id, &vec![tx], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx]), &mut map);
         assert!(res.is_err());
 
         // cant dispute deposits or withdrawals without funds
@@ -334,7 +664,7 @@ mod test {
             TransactionType::Deposit { amount },
         );
         assert!(map.is_empty());
-        let res = account.dispute(disputed_tx_id, &vec![tx], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx]), &mut map);
         assert!(res.is_err());
         assert!(map.is_empty());
 
@@ -344,7 +674,7 @@ mod test {
             TransactionType::Withdrawal { amount },
         );
         assert!(map.is_empty());
-        let res = account.dispute(disputed_tx_id, &vec![tx], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx]), &mut map);
         assert!(res.is_err());
         assert!(map.is_empty());
 
@@ -366,7 +696,7 @@ mod test {
             TransactionType::Deposit { amount },
         );
         assert!(map.is_empty());
-        let res = account.dispute(disputed_tx_id, &vec![tx_1, tx_2], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx_1, tx_2]), &mut map);
         assert!(res.is_ok());
         assert_eq!(map.len(), 1);
         assert_eq!(map.get(&disputed_tx_id).unwrap(), &(client_id, amount));
@@ -390,7 +720,7 @@ mod test {
         );
         map.clear();
         assert!(map.is_empty());
-        let res = account.dispute(disputed_tx_id, &vec![tx_1, tx_2], &mut map);
+        let res = account.dispute(disputed_tx_id, &disputable(&[tx_1, tx_2]), &mut map);
         assert!(res.is_ok());
         assert_eq!(map.len(), 1);
         assert_eq!(map.get(&disputed_tx_id).unwrap(), &(client_id, amount));
@@ -412,7 +742,7 @@ mod test {
         let client_id: u16 = 5;
         let zero = PositiveDecimal::try_from(Decimal::ZERO).unwrap();
         let amount = PositiveDecimal::try_from(10000.1000).unwrap();
-        let mut map = HashMap::new();
+        let mut map = Map::default();
 
         // can't resolve something that's not in the map
         let mut account = Account::new(client_id);
@@ -440,7 +770,7 @@ mod test {
             TransactionType::Deposit { amount },
         );
         account
-            .dispute(disputed_tx_id, &vec![tx], &mut map)
+            .dispute(disputed_tx_id, &disputable(&[tx]), &mut map)
             .unwrap();
         assert_eq!(account.balance.available, zero);
         assert_eq!(account.balance.held, amount);
@@ -457,7 +787,7 @@ mod test {
         let client_id: u16 = 5;
         let zero = PositiveDecimal::try_from(Decimal::ZERO).unwrap();
         let amount = PositiveDecimal::try_from(10000.1000).unwrap();
-        let mut map = HashMap::new();
+        let mut map = Map::default();
 
         // can't chargeback something that's not in the map
         let account = Account::new(client_id);
@@ -487,7 +817,7 @@ mod test {
             TransactionType::Deposit { amount },
         );
         account
-            .dispute(disputed_tx_id, &vec![tx], &mut map)
+            .dispute(disputed_tx_id, &disputable(&[tx]), &mut map)
             .unwrap();
         assert_eq!(account.balance.available, zero);
         assert_eq!(account.balance.held, amount);
@@ -0,0 +1,126 @@
+//! Acknowledgement of messages consumed from streaming sources, e.g. Kafka offsets or socket
+//! sequence numbers, once the ledger state they led to is durable
+
+use crate::error::TxError;
+use crate::ledger::Ledger;
+
+/// Acknowledges the messages of a streaming source in batches, each only after the ledger state
+/// including it was persisted, e.g. written as a [Snapshot](crate::snapshot::Snapshot). A crash
+/// loses nothing that was acknowledged, and the source redelivers the rest: processing is
+/// at-least-once. Passing the redelivered records through a
+/// [Dedup](crate::dedup::Dedup::with_seen) seeded with the restored ledger's transactions makes
+/// it effectively-once.
+///
+/// Offsets are acknowledged cumulatively, as Kafka commits them: only the last offset of each
+/// batch is passed to `ack`. Persisting after every `batch_size` messages trades the cost of
+/// writing the state against the number of messages redelivered after a crash.
+pub struct BatchAck<O, P, A> {
+    batch_size: usize,
+    /// The offset of the last message processed but not acknowledged, if any
+    last: Option<O>,
+    unacked: usize,
+    persist: P,
+    ack: A,
+}
+
+impl<O, P, A> BatchAck<O, P, A>
+where
+    O: Clone,
+    P: FnMut(&Ledger) -> Result<(), TxError>,
+    A: FnMut(O) -> Result<(), TxError>,
+{
+    /// Acknowledges in batches of `batch_size` messages, at least one, calling `persist` with
+    /// the ledger before `ack` with the offset of the batch's last message
+    pub fn new(batch_size: usize, persist: P, ack: A) -> Self {
+        BatchAck {
+            batch_size: batch_size.max(1),
+            last: None,
+            unacked: 0,
+            persist,
+            ack,
+        }
+    }
+
+    /// Records that `ledger` processed the message at `offset`, whether its transaction was
+    /// applied or rejected, and acknowledges the batch once it is full. Returns whether it did.
+    /// If persisting or acknowledging fails, the batch stays unacknowledged.
+    pub fn processed(&mut self, ledger: &Ledger, offset: O) -> Result<bool, TxError> {
+        self.last = Some(offset);
+        self.unacked += 1;
+        if self.unacked < self.batch_size {
+            return Ok(false);
+        }
+        self.flush(ledger)?;
+        Ok(true)
+    }
+
+    /// Persists `ledger` and acknowledges the messages processed since the last batch, e.g.
+    /// before shutting down or when the source goes idle
+    pub fn flush(&mut self, ledger: &Ledger) -> Result<(), TxError> {
+        let Some(offset) = self.last.clone() else {
+            return Ok(());
+        };
+        (self.persist)(ledger)?;
+        (self.ack)(offset)?;
+        self.last = None;
+        self.unacked = 0;
+        Ok(())
+    }
+
+    /// The number of messages processed but not acknowledged
+    pub fn unacked(&self) -> usize {
+        self.unacked
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    use crate::transaction::{PositiveDecimal, Transaction, TransactionType};
+
+    #[test]
+    fn test_batch_ack() {
+        let persisted = RefCell::new(Vec::new());
+        let acked = RefCell::new(Vec::new());
+        let fail = RefCell::new(false);
+        let mut batches = BatchAck::new(
+            2,
+            |ledger: &Ledger| {
+                if *fail.borrow() {
+                    return Err(TxError::Unknown);
+                }
+                persisted.borrow_mut().push(ledger.transactions().len());
+                Ok(())
+            },
+            |offset: u64| {
+                acked.borrow_mut().push(offset);
+                Ok(())
+            },
+        );
+
+        let mut ledger = Ledger::default();
+        let amount = PositiveDecimal::try_from(1.0).unwrap();
+        for offset in 0..5 {
+            let deposit = Transaction::new(1, offset, TransactionType::Deposit { amount });
+            ledger.add_tx(deposit).unwrap();
+            let full = batches.processed(&ledger, u64::from(offset) + 100).unwrap();
+            assert_eq!(full, offset % 2 == 1);
+        }
+        // every acknowledgement follows the state including its messages
+        assert_eq!(*persisted.borrow(), vec![2, 4]);
+        assert_eq!(*acked.borrow(), vec![101, 103]);
+        assert_eq!(batches.unacked(), 1);
+
+        *fail.borrow_mut() = true;
+        assert!(batches.flush(&ledger).is_err());
+        assert_eq!(batches.unacked(), 1);
+        assert_eq!(*acked.borrow(), vec![101, 103]);
+        *fail.borrow_mut() = false;
+        batches.flush(&ledger).unwrap();
+        assert_eq!(*acked.borrow(), vec![101, 103, 104]);
+        assert_eq!(batches.unacked(), 0);
+    }
+}
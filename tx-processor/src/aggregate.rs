@@ -0,0 +1,241 @@
+//! Totals and counts of a log of transactions grouped by client, category, type or day, see
+//! [Ledger::aggregate](crate::ledger::Ledger::aggregate)
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(any(feature = "csv", feature = "snapshot"))]
+use std::io;
+
+#[cfg(feature = "csv")]
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::error::TxError;
+use crate::transaction::{PositiveDecimal, Transaction, TransactionType};
+
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// What to group transactions by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationKey {
+    Client,
+    /// The transaction's category, see [Tagger](crate::tags::Tagger). Uncategorized
+    /// transactions are left out.
+    Category,
+    /// The transaction's type, as in the `type` column
+    Type,
+    /// The UTC day of the transaction's timestamp, e.g. `2024-01-31`. Transactions without a
+    /// timestamp are left out.
+    Day,
+}
+
+/// The transactions of one group
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Aggregate {
+    /// The client id, category, type or day the transactions share
+    pub key: String,
+    pub count: u64,
+    pub deposits: PositiveDecimal,
+    pub withdrawals: PositiveDecimal,
+    /// `deposits` less `withdrawals`
+    pub net: Decimal,
+}
+
+impl AggregationKey {
+    fn of(&self, transaction: &Transaction) -> Option<String> {
+        match self {
+            AggregationKey::Client => Some(transaction.client_id.to_string()),
+            AggregationKey::Category => transaction.category.clone(),
+            AggregationKey::Type => Some(transaction.tx_type.record_type().name().to_string()),
+            AggregationKey::Day => transaction
+                .timestamp
+                .map(|timestamp| date(timestamp.div_euclid(SECONDS_PER_DAY))),
+        }
+    }
+}
+
+/// Groups a log of transactions by `by`, ordered by key. Clients are ordered by id, days
+/// chronologically.
+pub fn aggregate(
+    transactions: impl IntoIterator<Item = Transaction>,
+    by: AggregationKey,
+) -> Result<Vec<Aggregate>, TxError> {
+    let mut aggregates = BTreeMap::<_, Aggregate>::new();
+    for transaction in transactions {
+        let Some(key) = by.of(&transaction) else {
+            continue;
+        };
+        // zero-padded so clients sort by id
+        let order = match by {
+            AggregationKey::Client => format!("{:05}", transaction.client_id),
+            _ => key.clone(),
+        };
+        let aggregate = aggregates.entry(order).or_insert_with(|| Aggregate {
+            key,
+            ..Default::default()
+        });
+        aggregate.count += 1;
+        match transaction.tx_type {
+            TransactionType::Deposit { amount } => {
+                aggregate.deposits = aggregate.deposits.checked_add(amount)?
+            }
+            TransactionType::Withdrawal { amount } => {
+                aggregate.withdrawals = aggregate.withdrawals.checked_add(amount)?
+            }
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Chargeback
+            | TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. }
+            | TransactionType::Other { .. } => {}
+        }
+    }
+
+    aggregates
+        .into_values()
+        .map(|mut aggregate| {
+            aggregate.net = Decimal::from(aggregate.deposits)
+                .checked_sub(aggregate.withdrawals.into())
+                .ok_or(TxError::InvalidAmount)?;
+            Ok(aggregate)
+        })
+        .collect()
+}
+
+/// The date of a day counted from the Unix epoch, e.g. `1970-01-02` for day 1
+pub fn date(days: i64) -> String {
+    // Howard Hinnant's `civil_from_days`, with eras of 400 years starting on March 1st
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Writes one row per aggregate, preceded by a header row
+#[cfg(feature = "csv")]
+pub fn write_aggregates_csv<W: io::Write>(
+    aggregates: &[Aggregate],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for aggregate in aggregates {
+        writer.serialize(aggregate)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the aggregates as a JSON array
+#[cfg(feature = "snapshot")]
+pub fn write_aggregates_json<W: io::Write>(
+    aggregates: &[Aggregate],
+    mut writer: W,
+) -> Result<(), TxError> {
+    serde_json::to_writer_pretty(&mut writer, aggregates)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_date() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(-1), "1969-12-31");
+        assert_eq!(date(19_782), "2024-02-29");
+        assert_eq!(date(19_783), "2024-03-01");
+        assert_eq!(date(20_088), "2024-12-31");
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_aggregate() {
+        let amount = |amount: f64| PositiveDecimal::try_from(amount).unwrap();
+        let at = |client_id, transaction_id, tx_type, timestamp| Transaction {
+            timestamp,
+            ..Transaction::new(client_id, transaction_id, tx_type)
+        };
+        let transactions = vec![
+            at(
+                10,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(10.0),
+                },
+                Some(0),
+            ),
+            at(
+                9,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(5.0),
+                },
+                Some(SECONDS_PER_DAY - 1),
+            ),
+            at(
+                10,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(4.0),
+                },
+                Some(SECONDS_PER_DAY),
+            ),
+            at(10, 1, TransactionType::Dispute, None),
+        ];
+        let keys = |by| {
+            aggregate(transactions.iter().cloned(), by)
+                .unwrap()
+                .into_iter()
+                .map(|aggregate| (aggregate.key, aggregate.count, aggregate.net))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(AggregationKey::Client),
+            vec![
+                ("9".into(), 1, Decimal::new(5, 0)),
+                ("10".into(), 3, Decimal::new(6, 0))
+            ]
+        );
+        assert_eq!(
+            keys(AggregationKey::Day),
+            vec![
+                ("1970-01-01".into(), 2, Decimal::new(15, 0)),
+                ("1970-01-02".into(), 1, Decimal::new(-4, 0))
+            ]
+        );
+        assert_eq!(
+            keys(AggregationKey::Type),
+            vec![
+                ("deposit".into(), 2, Decimal::new(15, 0)),
+                ("dispute".into(), 1, Decimal::ZERO),
+                ("withdrawal".into(), 1, Decimal::new(-4, 0))
+            ]
+        );
+        assert!(keys(AggregationKey::Category).is_empty());
+
+        let aggregates = aggregate(transactions, AggregationKey::Day).unwrap();
+        let mut csv = Vec::new();
+        write_aggregates_csv(&aggregates[1..], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "key,count,deposits,withdrawals,net\n1970-01-02,1,0,4.0000,-4.0000\n"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Client ids of other systems, e.g. the UUIDs or account numbers of sources that don't number
+//! their clients with small integers, mapped to the client ids of a ledger
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt;
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::error::TxError;
+use crate::logging::info;
+
+/// Picks the client id of an external client id a ledger has no alias for yet, see
+/// [LedgerBuilder::client_id_resolver](crate::ledger::LedgerBuilder::client_id_resolver)
+pub trait ClientIdResolver: Send + Sync {
+    /// The client id `client_ref` is given, or `None` if it can't be given one. `in_use` tells
+    /// whether a client id already has an account or an alias, or is denied by the
+    /// [ClientFilter](crate::clients::ClientFilter) of the ledger. Returning a client id in use
+    /// makes `client_ref` another alias of that client, e.g. when an external system knows
+    /// which existing client it refers to.
+    fn resolve(&self, client_ref: &str, in_use: &dyn Fn(u16) -> bool) -> Option<u16>;
+}
+
+/// A hash of the external client id `client_ref`, for the stages that key records by client
+/// before a ledger resolved their client id: 64-bit FNV-1a, which stays the same across
+/// versions as [shard_of_ref](crate::shards::shard_of_ref) depends on it
+pub(crate) fn hash_client_ref(client_ref: &str) -> u64 {
+    client_ref
+        .bytes()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+        })
+}
+
+/// Gives every new external client id the lowest client id of a range not in use. Ids are
+/// handed out in order and each at most once, so a client id freed again isn't reused.
+pub struct SequentialClientIds {
+    ids: RangeInclusive<u16>,
+    next: AtomicU32,
+}
+
+impl SequentialClientIds {
+    pub fn new(ids: RangeInclusive<u16>) -> Self {
+        SequentialClientIds {
+            next: AtomicU32::new(u32::from(*ids.start())),
+            ids,
+        }
+    }
+}
+
+impl ClientIdResolver for SequentialClientIds {
+    fn resolve(&self, _client_ref: &str, in_use: &dyn Fn(u16) -> bool) -> Option<u16> {
+        let end = u32::from(*self.ids.end());
+        while let Ok(next) = self
+            .next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                (next <= end).then(|| next + 1)
+            })
+        {
+            let client_id = next as u16;
+            if !in_use(client_id) {
+                return Some(client_id);
+            }
+        }
+        None
+    }
+}
+
+/// The client ids of the external client ids a ledger has seen, see
+/// [Ledger::client_aliases](crate::ledger::Ledger::client_aliases). Several external ids can
+/// refer to the same client, e.g. its ids in two upstream systems.
+#[derive(Default, Clone)]
+pub struct ClientAliases {
+    clients: BTreeMap<String, u16>,
+    /// The client ids with an alias
+    aliased: BTreeSet<u16>,
+    resolver: Option<Arc<dyn ClientIdResolver>>,
+}
+
+impl ClientAliases {
+    pub(crate) fn new(resolver: Option<Arc<dyn ClientIdResolver>>) -> Self {
+        ClientAliases {
+            resolver,
+            ..ClientAliases::default()
+        }
+    }
+
+    /// The client id of `client_ref`, if it has one
+    pub fn get(&self, client_ref: &str) -> Option<u16> {
+        self.clients.get(client_ref).copied()
+    }
+
+    /// `true` if an external client id refers to `client_id`
+    pub fn is_aliased(&self, client_id: u16) -> bool {
+        self.aliased.contains(&client_id)
+    }
+
+    /// The external client ids and their client ids, ordered by external id
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.clients
+            .iter()
+            .map(|(client_ref, &client_id)| (client_ref.as_str(), client_id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    pub(crate) fn insert(&mut self, client_ref: String, client_id: u16) {
+        self.aliased.insert(client_id);
+        self.clients.insert(client_ref, client_id);
+    }
+
+    /// The client id of `client_ref`, given one by the resolver if it has none yet. Fails with
+    /// [TxError::UnresolvedClient] if it's empty, or new and the resolver can't give it one.
+    /// `has_account` tells the client ids with an account.
+    pub(crate) fn resolve(
+        &mut self,
+        client_ref: &str,
+        has_account: impl Fn(u16) -> bool,
+    ) -> Result<u16, TxError> {
+        if let Some(client_id) = self.get(client_ref) {
+            return Ok(client_id);
+        }
+        let in_use = |client_id| self.is_aliased(client_id) || has_account(client_id);
+        let client_id = self
+            .resolver
+            .as_ref()
+            .filter(|_| !client_ref.is_empty())
+            .and_then(|resolver| resolver.resolve(client_ref, &in_use))
+            .ok_or(TxError::UnresolvedClient)?;
+        info!("Client {} given client id {}", client_ref, client_id);
+        self.insert(client_ref.into(), client_id);
+        Ok(client_id)
+    }
+
+    /// Makes the aliases of `from` refer to `to`, see
+    /// [Ledger::remap_client](crate::ledger::Ledger::remap_client)
+    pub(crate) fn remap(&mut self, from: u16, to: u16) {
+        if self.aliased.remove(&from) {
+            self.aliased.insert(to);
+            for client_id in self.clients.values_mut() {
+                if *client_id == from {
+                    *client_id = to;
+                }
+            }
+        }
+    }
+}
+
+/// Only the aliases are compared, not the resolver
+impl PartialEq for ClientAliases {
+    fn eq(&self, other: &Self) -> bool {
+        self.clients == other.clients
+    }
+}
+
+impl Eq for ClientAliases {}
+
+impl fmt::Debug for ClientAliases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_aliases() {
+        let mut aliases = ClientAliases::new(Some(Arc::new(SequentialClientIds::new(10..=12))));
+        let has_account = |client_id| client_id == 11;
+        assert_eq!(aliases.resolve("cus_a", has_account).unwrap(), 10);
+        // skips the client with an account
+        assert_eq!(aliases.resolve("cus_b", has_account).unwrap(), 12);
+        assert_eq!(aliases.resolve("cus_a", has_account).unwrap(), 10);
+        assert!(matches!(
+            aliases.resolve("cus_c", has_account),
+            Err(TxError::UnresolvedClient)
+        ));
+        assert!(matches!(
+            aliases.resolve("", has_account),
+            Err(TxError::UnresolvedClient)
+        ));
+        assert_eq!(
+            aliases.iter().collect::<Vec<_>>(),
+            [("cus_a", 10), ("cus_b", 12)]
+        );
+
+        aliases.remap(10, 12);
+        assert_eq!(aliases.get("cus_a"), Some(12));
+        assert!(!aliases.is_aliased(10));
+
+        // known aliases resolve without a resolver, e.g. after a restore
+        let mut restored = ClientAliases::new(None);
+        restored.insert("cus_a".into(), 12);
+        assert_eq!(restored.resolve("cus_a", has_account).unwrap(), 12);
+        assert!(matches!(
+            restored.resolve("cus_b", has_account),
+            Err(TxError::UnresolvedClient)
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Parsing of amounts from the decimal strings of the input, without going through `f64`, so
+//! the ledger applies exactly the amounts the source system wrote
+
+use core::fmt;
+use core::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::de::{self, Deserializer, Visitor};
+
+use crate::error::TxError;
+use crate::transaction::NUM_DECIMAL_PLACES;
+
+/// How strictly amounts are parsed, see [parse_amount]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AmountParsing {
+    /// Decimals with any number of places, rounded to [NUM_DECIMAL_PLACES] when applied, and
+    /// scientific notation, e.g. `1e5`
+    #[default]
+    Lenient,
+    /// Only decimals with at most [NUM_DECIMAL_PLACES] places, which are applied exactly as
+    /// written, for audit parity with the source system
+    Strict,
+}
+
+/// Parses `amount`. Rejects what isn't a decimal in either mode with
+/// [TxError::MalformedAmount], and in [AmountParsing::Strict] mode scientific notation with
+/// [TxError::MalformedAmount] and more than [NUM_DECIMAL_PLACES] places with
+/// [TxError::InexactAmount].
+pub fn parse_amount(amount: &str, parsing: AmountParsing) -> Result<Decimal, TxError> {
+    match parsing {
+        AmountParsing::Lenient => Decimal::from_str(amount)
+            .or_else(|_| Decimal::from_scientific(amount))
+            .map_err(|_| TxError::MalformedAmount),
+        AmountParsing::Strict => {
+            let decimal = Decimal::from_str_exact(amount).map_err(|_| TxError::MalformedAmount)?;
+            if decimal.scale() > NUM_DECIMAL_PLACES {
+                return Err(TxError::InexactAmount);
+            }
+            Ok(decimal)
+        }
+    }
+}
+
+/// Deserializes an optional amount from a string with [AmountParsing::Lenient], or from an
+/// integer, but never from a float
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct AmountVisitor;
+
+    impl<'de> Visitor<'de> for AmountVisitor {
+        type Value = Option<Decimal>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a decimal amount")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        // `null` within flattened structs, e.g. snapshots of transactions
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_str(self)
+        }
+
+        fn visit_str<E: de::Error>(self, amount: &str) -> Result<Self::Value, E> {
+            parse_amount(amount, AmountParsing::Lenient)
+                .map(Some)
+                .map_err(E::custom)
+        }
+
+        fn visit_i64<E: de::Error>(self, amount: i64) -> Result<Self::Value, E> {
+            Ok(Some(Decimal::from(amount)))
+        }
+
+        fn visit_u64<E: de::Error>(self, amount: u64) -> Result<Self::Value, E> {
+            Ok(Some(Decimal::from(amount)))
+        }
+    }
+
+    deserializer.deserialize_option(AmountVisitor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        use AmountParsing::*;
+
+        // beyond the 15 to 17 significant digits an f64 holds
+        let exact = "12345678901234567.1234";
+        assert_eq!(
+            parse_amount(exact, Strict).unwrap().to_string(),
+            exact.to_string()
+        );
+        assert_eq!(
+            parse_amount("1e5", Lenient).unwrap(),
+            Decimal::from(100_000)
+        );
+        assert!(matches!(
+            parse_amount("1e5", Strict),
+            Err(TxError::MalformedAmount)
+        ));
+        assert_eq!(
+            parse_amount("1.00005", Lenient).unwrap(),
+            Decimal::new(100005, 5)
+        );
+        assert!(matches!(
+            parse_amount("1.00005", Strict),
+            Err(TxError::InexactAmount)
+        ));
+        assert_eq!(parse_amount("1.5000", Strict).unwrap(), Decimal::new(15, 1));
+        for parsing in [Lenient, Strict] {
+            assert!(matches!(
+                parse_amount("one", parsing),
+                Err(TxError::MalformedAmount)
+            ));
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Funds availability schedules, under which deposits are posted as pending and only become
+//! available after a delay, like ACH deposits that clear a few days after they were made
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+use crate::transaction::{PositiveDecimal, Transaction};
+
+/// How long a deposit stays pending, see
+/// [LedgerBuilder::availability](crate::ledger::LedgerBuilder::availability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AvailabilityDelay {
+    /// Until this many more records were processed by the ledger, applied or not
+    Records(u64),
+    /// Until a record timestamped this many seconds after the deposit arrives. Deposits without
+    /// a timestamp are dated by the latest timestamp the ledger has seen.
+    Seconds(i64),
+}
+
+/// When a [PendingDeposit] becomes available
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Due {
+    /// Once the ledger processed this many records since it was built or restored
+    Record(u64),
+    /// Once a record with this timestamp or a later one arrives
+    Time(i64),
+}
+
+/// A deposit, or the reserved part of one, whose funds are held until it is due. Pending
+/// deposits can also be released early with
+/// [Ledger::release_deposit](crate::ledger::Ledger::release_deposit).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingDeposit {
+    pub client_id: u16,
+    pub transaction_id: u32,
+    pub amount: PositiveDecimal,
+    pub due: Due,
+}
+
+/// Deposits, or parts of deposits, held until they're due, in the order they're due
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Schedule {
+    deposits: BTreeMap<u32, PendingDeposit>,
+    queue: BTreeSet<(Due, u32)>,
+}
+
+impl Schedule {
+    pub(crate) fn insert(&mut self, deposit: PendingDeposit) {
+        self.queue.insert((deposit.due, deposit.transaction_id));
+        self.deposits.insert(deposit.transaction_id, deposit);
+    }
+
+    /// Removes a deposit, whether it is due or not
+    pub(crate) fn take(&mut self, transaction_id: u32) -> Option<PendingDeposit> {
+        let deposit = self.deposits.remove(&transaction_id)?;
+        self.queue.remove(&(deposit.due, transaction_id));
+        Some(deposit)
+    }
+
+    pub(crate) fn get(&self, transaction_id: u32) -> Option<&PendingDeposit> {
+        self.deposits.get(&transaction_id)
+    }
+
+    /// The deposits, ordered by transaction id
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &PendingDeposit> {
+        self.deposits.values()
+    }
+
+    /// Moves the deposits of `from` to `to`
+    fn remap(&mut self, from: u16, to: u16) {
+        for deposit in self.deposits.values_mut() {
+            if deposit.client_id == from {
+                deposit.client_id = to;
+            }
+        }
+    }
+
+    /// Takes the deposits due after `records` records, or at `time`
+    fn take_due(&mut self, records: u64, time: Option<i64>) -> Vec<PendingDeposit> {
+        if self.queue.is_empty() {
+            return Vec::new();
+        }
+        let mut due = self
+            .queue
+            .range(..=(Due::Record(records), u32::MAX))
+            .map(|&(_, transaction_id)| transaction_id)
+            .collect::<Vec<_>>();
+        if let Some(time) = time {
+            due.extend(
+                self.queue
+                    .range((Due::Time(i64::MIN), 0)..=(Due::Time(time), u32::MAX))
+                    .map(|&(_, transaction_id)| transaction_id),
+            );
+        }
+        due.into_iter()
+            .filter_map(|transaction_id| self.take(transaction_id))
+            .collect()
+    }
+}
+
+/// The deposits pending under an [AvailabilityDelay] and the reserves of
+/// [RollingReserves](crate::reserve::RollingReserves), and the records and timestamps counting
+/// towards their release
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Availability {
+    /// `None` makes every deposit available at once
+    delay: Option<AvailabilityDelay>,
+    /// Whether records are counted, with a delay or reserves
+    counts: bool,
+    /// The records processed since the ledger was built or restored
+    records: u64,
+    /// The latest timestamp seen
+    time: Option<i64>,
+    pub(crate) pending: Schedule,
+    /// The reserved parts of deposits
+    pub(crate) reserves: Schedule,
+}
+
+impl Availability {
+    pub(crate) fn new(delay: Option<AvailabilityDelay>, reserves: bool) -> Self {
+        Availability {
+            delay,
+            counts: delay.is_some() || reserves,
+            ..Availability::default()
+        }
+    }
+
+    /// `true` if deposits are posted as pending
+    pub(crate) fn delays(&self) -> bool {
+        self.delay.is_some()
+    }
+
+    /// Counts a record timestamped `timestamp`, and takes the pending deposits and the reserves
+    /// it makes due. Without a delay or reserves nothing is counted, and deposits restored
+    /// pending are only released explicitly.
+    pub(crate) fn advance(
+        &mut self,
+        timestamp: Option<i64>,
+    ) -> (Vec<PendingDeposit>, Vec<PendingDeposit>) {
+        if !self.counts {
+            return (Vec::new(), Vec::new());
+        }
+        self.records += 1;
+        self.time = self.time.max(timestamp);
+        (
+            self.pending.take_due(self.records, self.time),
+            self.reserves.take_due(self.records, self.time),
+        )
+    }
+
+    /// Moves the pending deposits and reserves of `from` to `to`, see
+    /// [Ledger::remap_client](crate::ledger::Ledger::remap_client)
+    pub(crate) fn remap(&mut self, from: u16, to: u16) {
+        self.pending.remap(from, to);
+        self.reserves.remap(from, to);
+    }
+
+    /// When `transaction` is `delay` old
+    fn due(&self, transaction: &Transaction, delay: AvailabilityDelay) -> Due {
+        match delay {
+            AvailabilityDelay::Records(records) => Due::Record(self.records + records),
+            AvailabilityDelay::Seconds(seconds) => {
+                let posted = transaction.timestamp.or(self.time).unwrap_or_default();
+                Due::Time(posted.saturating_add(seconds))
+            }
+        }
+    }
+
+    /// Posts `amount` of an applied deposit as pending until it is due
+    pub(crate) fn post(&mut self, transaction: &Transaction, amount: PositiveDecimal) {
+        if let Some(delay) = self.delay {
+            self.pending.insert(PendingDeposit {
+                client_id: transaction.client_id,
+                transaction_id: transaction.transaction_id,
+                amount,
+                due: self.due(transaction, delay),
+            });
+        }
+    }
+
+    /// Posts the reserved `amount` of an applied deposit, held for `period`
+    pub(crate) fn post_reserve(
+        &mut self,
+        transaction: &Transaction,
+        amount: PositiveDecimal,
+        period: AvailabilityDelay,
+    ) {
+        self.reserves.insert(PendingDeposit {
+            client_id: transaction.client_id,
+            transaction_id: transaction.transaction_id,
+            amount,
+            due: self.due(transaction, period),
+        });
+    }
+
+    /// The records left until `deposit` is due, if it is due after a number of records
+    #[cfg(feature = "snapshot")]
+    pub(crate) fn records_left(&self, deposit: &PendingDeposit) -> Option<u64> {
+        match deposit.due {
+            Due::Record(record) => Some(record.saturating_sub(self.records)),
+            Due::Time(_) => None,
+        }
+    }
+
+    /// Makes a deposit due after `records` more records
+    #[cfg(feature = "snapshot")]
+    pub(crate) fn due_after(&self, records: u64) -> Due {
+        Due::Record(self.records + records)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    fn test_availability() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let deposit = |transaction_id, timestamp| Transaction {
+            timestamp,
+            ..Transaction::new(1, transaction_id, TransactionType::Deposit { amount })
+        };
+
+        let mut availability = Availability::new(Some(AvailabilityDelay::Records(2)), false);
+        assert!(availability.advance(None).0.is_empty());
+        availability.post(&deposit(1, None), amount);
+        assert!(availability.advance(None).0.is_empty());
+        availability.post(&deposit(2, None), amount);
+        let (due, _) = availability.advance(None);
+        assert_eq!(
+            due.iter().map(|d| d.transaction_id).collect::<Vec<_>>(),
+            [1]
+        );
+        assert_eq!(availability.pending.iter().count(), 1);
+        assert_eq!(availability.pending.get(2).unwrap().due, Due::Record(4));
+
+        let mut availability = Availability::new(Some(AvailabilityDelay::Seconds(60)), false);
+        availability.advance(Some(100));
+        availability.post(&deposit(1, Some(100)), amount);
+        // dated by the latest timestamp seen
+        availability.post(&deposit(2, None), amount);
+        availability.post(&deposit(3, Some(150)), amount);
+        assert_eq!(availability.pending.get(2).unwrap().due, Due::Time(160));
+        assert!(availability.advance(Some(159)).0.is_empty());
+        assert!(availability.advance(None).0.is_empty());
+        let (due, _) = availability.advance(Some(160));
+        assert_eq!(
+            due.iter().map(|d| d.transaction_id).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        // an out of order timestamp doesn't turn the clock back
+        assert!(availability.advance(Some(0)).0.is_empty());
+        assert_eq!(availability.pending.take(3).unwrap().due, Due::Time(210));
+        assert!(availability.pending.take(3).is_none());
+        assert!(availability.advance(Some(300)).0.is_empty());
+    }
+}
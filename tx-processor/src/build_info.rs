@@ -0,0 +1,89 @@
+//! The version and commit of the processor and the policies of a run, recorded in what the run
+//! writes so any snapshot or summary can be traced back to the code and configuration that
+//! produced it
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::policies::Policies;
+
+/// The version of this crate
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The abbreviated hash of the git commit this crate was built from, or `unknown` if it wasn't
+/// built from a checkout. Set `TX_PROCESSOR_GIT_HASH` when building to override it.
+pub const GIT_HASH: &str = env!("TX_PROCESSOR_GIT_HASH");
+
+/// What produced an output: the version and commit of the processor, and the policies the run
+/// applied, if known
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_hash: String,
+    /// The name of the choice of every policy by its subject, e.g. `withdrawal disputes` to
+    /// `hold`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub policies: BTreeMap<String, String>,
+}
+
+impl BuildInfo {
+    /// The build of this processor, without policies
+    pub fn current() -> Self {
+        BuildInfo {
+            version: VERSION.to_string(),
+            git_hash: GIT_HASH.to_string(),
+            policies: BTreeMap::new(),
+        }
+    }
+
+    /// Records the choice of every policy of `policies`
+    pub fn with_policies(mut self, policies: &Policies) -> Self {
+        self.policies = policies
+            .describe()
+            .into_iter()
+            .map(|(subject, name, _)| (subject.to_string(), name.to_string()))
+            .collect();
+        self
+    }
+}
+
+/// `tx-processor 0.1.0 (0123456789ab)`, followed by the policies as `subject=choice` pairs
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tx-processor {} ({})", self.version, self.git_hash)?;
+        for (subject, name) in &self.policies {
+            write!(f, ", {}={}", subject, name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::policies::WithdrawalDisputePolicy;
+
+    #[test]
+    fn test_build_info() {
+        let build = BuildInfo::current();
+        assert_eq!(build.version, VERSION);
+        assert!(!build.git_hash.is_empty());
+        assert_eq!(
+            build.to_string(),
+            alloc::format!("tx-processor {} ({})", VERSION, GIT_HASH)
+        );
+
+        let policies = Policies {
+            withdrawal_disputes: WithdrawalDisputePolicy::Reject,
+            ..Policies::default()
+        };
+        let build = build.with_policies(&policies);
+        assert_eq!(build.policies["withdrawal disputes"], "reject");
+        assert_eq!(build.policies.len(), policies.describe().len());
+        assert!(build.to_string().contains(", withdrawal disputes=reject"));
+    }
+}
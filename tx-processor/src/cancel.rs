@@ -0,0 +1,26 @@
+//! Cooperative cancellation of long processing runs
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A handle to stop a [Ledger](crate::ledger::Ledger) from processing further records. Clones
+/// share the same state, so a token can be handed to the ledger via
+/// [LedgerBuilder::cancellation_token](crate::ledger::LedgerBuilder::cancellation_token) and
+/// cancelled from another thread. The ledger checks it between records and returns what it
+/// processed so far.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,61 @@
+use alloc::vec::Vec;
+
+use crate::error::TxError;
+use crate::ledger::Ledger;
+use crate::transaction::Transaction;
+
+/// A controlled change to an otherwise valid transaction stream, modelling the faults our
+/// upstream pipeline occasionally introduces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Perturbation {
+    /// The record at this index is lost
+    Drop(usize),
+    /// The record at this index is delivered twice in a row
+    Duplicate(usize),
+    /// The records at this index and the next one are delivered in the opposite order
+    SwapWithNext(usize),
+}
+
+impl Perturbation {
+    /// Every perturbation of every class that applies to a stream of `len` records
+    pub fn all(len: usize) -> impl Iterator<Item = Perturbation> {
+        (0..len)
+            .map(Perturbation::Drop)
+            .chain((0..len).map(Perturbation::Duplicate))
+            .chain((0..len.saturating_sub(1)).map(Perturbation::SwapWithNext))
+    }
+
+    /// Returns a copy of `transactions` with this perturbation applied
+    pub fn apply(&self, transactions: &[Transaction]) -> Vec<Transaction> {
+        let mut perturbed = transactions.to_vec();
+        match *self {
+            Perturbation::Drop(index) => {
+                perturbed.remove(index);
+            }
+            Perturbation::Duplicate(index) => {
+                perturbed.insert(index, transactions[index].clone());
+            }
+            Perturbation::SwapWithNext(index) => perturbed.swap(index, index + 1),
+        }
+        perturbed
+    }
+}
+
+/// Checks the invariant every ledger that started from zero must hold, however its input was
+/// perturbed: each client's total funds equal the net of the deposits, withdrawals and
+/// chargebacks that were actually applied to it, and calculated amounts were rounded within
+/// the bounds of [RoundingReserve::check](crate::rounding::RoundingReserve::check).
+pub fn check_conservation(ledger: &Ledger) -> Result<(), TxError> {
+    ledger.rounding_reserve().check()?;
+    for settlement in ledger.settlement()? {
+        let client_id = settlement.client_id;
+        let total = ledger
+            .account(client_id)
+            .ok_or(TxError::NotFound)?
+            .total()?;
+        if settlement.net != total.into() {
+            return Err(TxError::InvalidAmount);
+        }
+    }
+    Ok(())
+}
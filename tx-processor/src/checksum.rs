@@ -0,0 +1,79 @@
+//! SHA-256 checksums of input files, recorded in snapshots so a run's state can be traced to
+//! the exact files it was derived from
+
+use std::fs::File;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+
+/// The SHA-256 of everything read through its [ChecksumReader]s so far. Clones share the
+/// digest, so it can be read after the reader was handed to a CSV reader.
+#[derive(Clone, Default)]
+pub struct Checksum(Arc<Mutex<Sha256>>);
+
+/// Hashes everything read from `inner` into a [Checksum]
+pub struct ChecksumReader<R> {
+    inner: R,
+    checksum: Checksum,
+}
+
+impl Checksum {
+    pub fn reader<R: io::Read>(&self, inner: R) -> ChecksumReader<R> {
+        ChecksumReader {
+            inner,
+            checksum: self.clone(),
+        }
+    }
+
+    /// The hex-encoded SHA-256 of what was read so far
+    pub fn sha256(&self) -> String {
+        hex(&self.0.lock().expect(POISONED).clone().finalize())
+    }
+}
+
+impl<R: io::Read> io::Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.checksum.0.lock().expect(POISONED).update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// The hex-encoded SHA-256 of the file at `path`
+pub fn sha256_file(path: &str) -> io::Result<String> {
+    let checksum = Checksum::default();
+    io::copy(&mut checksum.reader(File::open(path)?), &mut io::sink())?;
+    Ok(checksum.sha256())
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+const POISONED: &str = "a thread panicked while hashing an input";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_checksum() {
+        let checksum = Checksum::default();
+        let mut read = String::new();
+        checksum
+            .reader("abc".as_bytes())
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, "abc");
+        assert_eq!(
+            checksum.sha256(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            Checksum::default().sha256(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
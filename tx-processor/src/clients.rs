@@ -0,0 +1,159 @@
+//! Restricting which clients a ledger transacts for, e.g. to keep sanctioned or test accounts
+//! out of a production settlement run, and marking the clients whose transactions are
+//! synthetic
+
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use thiserror::Error;
+
+/// The clients a ledger applies transactions of, see
+/// [LedgerBuilder::clients](crate::ledger::LedgerBuilder::clients). Transactions of any other
+/// client are rejected with [TxError::ExcludedClient](crate::error::TxError::ExcludedClient).
+/// Accounts opened with a balance keep it, but don't transact.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientFilter {
+    /// Only these clients transact, if given
+    allowed: Option<BTreeSet<u16>>,
+    /// These clients never transact, even if allowed
+    denied: BTreeSet<u16>,
+}
+
+impl ClientFilter {
+    /// Only lets these clients transact. Allowing more clients later adds to them.
+    pub fn allow(mut self, client_ids: impl IntoIterator<Item = u16>) -> Self {
+        self.allowed
+            .get_or_insert_with(Default::default)
+            .extend(client_ids);
+        self
+    }
+
+    /// Rejects every transaction of these clients
+    pub fn deny(mut self, client_ids: impl IntoIterator<Item = u16>) -> Self {
+        self.denied.extend(client_ids);
+        self
+    }
+
+    /// `true` if `client_id` is denied, so it isn't given to new external client ids either
+    pub fn denies(&self, client_id: u16) -> bool {
+        self.denied.contains(&client_id)
+    }
+
+    /// `true` if transactions of `client_id` are applied
+    pub fn admits(&self, client_id: u16) -> bool {
+        !self.denied.contains(&client_id)
+            && self
+                .allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(&client_id))
+    }
+}
+
+/// The clients of synthetic transactions, e.g. test accounts mixed into a production feed, see
+/// [LedgerBuilder::test_clients](crate::ledger::LedgerBuilder::test_clients). Their
+/// transactions are applied like any other, but left out of the settlement and statistics of
+/// the ledger, which reports them separately.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TestClients(Vec<RangeInclusive<u16>>);
+
+impl TestClients {
+    /// Marks the clients of `range` as test clients
+    pub fn range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.0.push(range);
+        self
+    }
+
+    /// `true` if `client_id` is a test client
+    pub fn contains(&self, client_id: u16) -> bool {
+        self.0.iter().any(|range| range.contains(&client_id))
+    }
+
+    /// `true` if no client is a test client
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Parses a range of client ids, e.g. `60000-65535`, or a single id
+pub fn parse_client_range(range: &str) -> Result<RangeInclusive<u16>, InvalidClientId> {
+    let parse = |id: &str| {
+        id.trim()
+            .parse()
+            .map_err(|_| InvalidClientId(id.trim().to_string()))
+    };
+    match range.split_once('-') {
+        Some((start, end)) => Ok(parse(start)?..=parse(end)?),
+        None => parse(range).map(|id| id..=id),
+    }
+}
+
+/// An entry of a list of client ids that isn't one
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("`{0}` is not a client id")]
+pub struct InvalidClientId(pub String);
+
+/// Parses a list of client ids separated by commas or newlines, e.g. `1,2,3` or a file of one
+/// id per line. Blank entries and `#` comments to the end of a line are ignored.
+pub fn parse_client_ids(list: &str) -> Result<BTreeSet<u16>, InvalidClientId> {
+    list.lines()
+        .map(|line| line.split_once('#').map_or(line, |(ids, _)| ids))
+        .flat_map(|ids| ids.split(','))
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(|_| InvalidClientId(id.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_filter() {
+        let everyone = ClientFilter::default();
+        assert!(everyone.admits(1));
+
+        let filter = ClientFilter::default().allow([1, 2]).deny([2, 3]);
+        assert!(filter.admits(1));
+        assert!(!filter.admits(2));
+        assert!(!filter.admits(3));
+        assert!(!filter.admits(4));
+
+        let denied = ClientFilter::default().deny([3]);
+        assert!(denied.admits(4));
+        assert!(!denied.admits(3));
+    }
+
+    #[test]
+    fn test_test_clients() {
+        assert!(!TestClients::default().contains(1));
+        let test_clients = TestClients::default()
+            .range(parse_client_range("60000-65535").unwrap())
+            .range(parse_client_range("7").unwrap());
+        assert!(test_clients.contains(7));
+        assert!(test_clients.contains(u16::MAX));
+        assert!(!test_clients.contains(8));
+        assert_eq!(
+            parse_client_range("1-x"),
+            Err(InvalidClientId("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_client_ids() {
+        assert_eq!(
+            parse_client_ids("1, 2,3\n# sanctioned\n\n7 # test account\n"),
+            Ok(BTreeSet::from([1, 2, 3, 7]))
+        );
+        assert_eq!(
+            parse_client_ids("1,x"),
+            Err(InvalidClientId("x".to_string()))
+        );
+        assert_eq!(
+            parse_client_ids("70000"),
+            Err(InvalidClientId("70000".to_string()))
+        );
+    }
+}
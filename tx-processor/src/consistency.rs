@@ -0,0 +1,152 @@
+//! Recomputation of the balances of a [Ledger] from its transaction log, as a check of the
+//! balances it updated transaction by transaction
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use rust_decimal::Decimal;
+
+use crate::error::TxError;
+use crate::ledger::Ledger;
+use crate::transaction::TransactionType;
+
+/// An account whose balance differs from the one recomputed from the transaction log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub client_id: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub expected_available: Decimal,
+    pub expected_held: Decimal,
+}
+
+#[derive(Default)]
+struct Recomputed {
+    available: Decimal,
+    held: Decimal,
+    /// Whether a transaction of a custom type, whose effect only its handler knows, was applied
+    custom: bool,
+}
+
+/// Replays the transaction log of `ledger` with plain decimal arithmetic, independently of the
+/// account types, and returns the accounts whose balance differs, ordered by client id.
+///
+/// Only ledgers that started without balances and retained every transaction can be checked:
+/// opening balances, restored snapshots and transactions forgotten under
+/// [LimitPolicy::Degrade](crate::limits::LimitPolicy::Degrade) show up as divergences. Clients
+/// with transactions of custom types are skipped.
+pub fn check_balances(ledger: &Ledger) -> Result<Vec<Divergence>, TxError> {
+    let mut amounts = BTreeMap::new();
+    let mut recomputed = BTreeMap::<u16, Recomputed>::new();
+    for transaction in ledger.transactions().iter() {
+        let client_id = transaction.client_id;
+        let balance = recomputed.entry(client_id).or_default();
+        let key = (client_id, transaction.transaction_id);
+        // the amount a dispute, resolve or chargeback refers to, as the ledger found it in
+        // [Disputable](crate::disputable::Disputable)
+        let disputed =
+            |amounts: &BTreeMap<_, Decimal>| amounts.get(&key).copied().ok_or(TxError::NotFound);
+        match transaction.tx_type {
+            TransactionType::Deposit { amount } => {
+                let amount = Decimal::from(amount);
+                amounts.entry(key).or_insert(amount);
+                balance.available += amount;
+            }
+            TransactionType::Withdrawal { amount } => {
+                let amount = Decimal::from(amount);
+                amounts.entry(key).or_insert(amount);
+                balance.available -= amount;
+            }
+            TransactionType::Dispute => {
+                let amount = disputed(&amounts)?;
+                balance.available -= amount;
+                balance.held += amount;
+            }
+            TransactionType::Resolve => {
+                let amount = disputed(&amounts)?;
+                balance.available += amount;
+                balance.held -= amount;
+            }
+            TransactionType::Chargeback => balance.held -= disputed(&amounts)?,
+            TransactionType::Other { .. } => balance.custom = true,
+            TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. } => {}
+        }
+    }
+
+    // the funds of pending deposits and reserves are held until they're released
+    for deposit in ledger.pending_deposits().chain(ledger.reserves()) {
+        let balance = recomputed.entry(deposit.client_id).or_default();
+        balance.available -= Decimal::from(deposit.amount);
+        balance.held += Decimal::from(deposit.amount);
+    }
+
+    let mut accounts = ledger.accounts().collect::<Vec<_>>();
+    accounts.sort_unstable_by_key(|account| account.client_id());
+    let mut divergences = Vec::new();
+    for account in accounts {
+        let client_id = account.client_id();
+        let expected = recomputed.remove(&client_id).unwrap_or_default();
+        let (available, held): (Decimal, Decimal) =
+            (account.available().into(), account.held().into());
+        if !expected.custom && (available, held) != (expected.available, expected.held) {
+            divergences.push(Divergence {
+                client_id,
+                available,
+                held,
+                expected_available: expected.available,
+                expected_held: expected.held,
+            });
+        }
+    }
+    // transactions of clients without an account
+    for (client_id, expected) in recomputed {
+        if !expected.custom && (expected.available, expected.held) != (Decimal::ZERO, Decimal::ZERO)
+        {
+            divergences.push(Divergence {
+                client_id,
+                available: Decimal::ZERO,
+                held: Decimal::ZERO,
+                expected_available: expected.available,
+                expected_held: expected.held,
+            });
+        }
+    }
+    divergences.sort_unstable_by_key(|divergence| divergence.client_id);
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{PositiveDecimal, Transaction};
+
+    #[test]
+    fn test_check_balances() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Deposit { amount }),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Chargeback),
+        ]);
+        assert_eq!(check_balances(&ledger).unwrap(), vec![]);
+
+        // a balance that drifted from the log
+        ledger.active_accounts.get_mut(&1).unwrap().balance = Default::default();
+        assert_eq!(
+            check_balances(&ledger).unwrap(),
+            vec![Divergence {
+                client_id: 1,
+                available: Decimal::ZERO,
+                held: Decimal::ZERO,
+                expected_available: Decimal::from(10),
+                expected_held: Decimal::from(10),
+            }]
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Guardrails against adversarial CSV input, e.g. a single record of several gigabytes, millions
+//! of columns or binary data, enforced before the input reaches the `csv` crate's buffers
+
+use core::fmt;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use csv::ByteRecord;
+
+use crate::error::TxError;
+
+/// Upper bounds on the size and content of CSV records, see [LimitedReader] and
+/// [CsvLimits::check]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvLimits {
+    /// Bytes per field, after trimming
+    pub max_field_len: usize,
+    /// Bytes per record, including delimiters and quotes
+    pub max_record_len: usize,
+    /// Fields per record
+    pub max_fields: usize,
+}
+
+impl Default for CsvLimits {
+    /// Generous for transaction records, whose longest fields are notes and provenance
+    fn default() -> Self {
+        CsvLimits {
+            max_field_len: 1024,
+            max_record_len: 16 * 1024,
+            max_fields: 64,
+        }
+    }
+}
+
+/// A limit of [CsvLimits] exceeded by the record starting on `line`, or the presence of a NUL
+/// byte, which no transaction record contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputLimit {
+    FieldLength { line: u64, field: usize, max: usize },
+    RecordLength { line: u64, max: usize },
+    FieldCount { line: u64, count: usize, max: usize },
+    NulByte { line: u64, field: usize },
+}
+
+impl fmt::Display for InputLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputLimit::FieldLength { line, field, max } => write!(
+                f,
+                "field {} of line {} is longer than {} bytes",
+                field, line, max
+            ),
+            InputLimit::RecordLength { line, max } => {
+                write!(f, "record on line {} is longer than {} bytes", line, max)
+            }
+            InputLimit::FieldCount { line, count, max } => write!(
+                f,
+                "record on line {} has {} fields, more than {}",
+                line, count, max
+            ),
+            InputLimit::NulByte { line, field } => {
+                write!(f, "field {} of line {} contains a NUL byte", field, line)
+            }
+        }
+    }
+}
+
+impl InputLimit {
+    /// The line the offending record starts on
+    pub fn line(&self) -> u64 {
+        match *self {
+            InputLimit::FieldLength { line, .. }
+            | InputLimit::RecordLength { line, .. }
+            | InputLimit::FieldCount { line, .. }
+            | InputLimit::NulByte { line, .. } => line,
+        }
+    }
+}
+
+impl From<InputLimit> for csv::Error {
+    fn from(limit: InputLimit) -> Self {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            TxError::InputLimitExceeded(limit),
+        )
+        .into()
+    }
+}
+
+impl CsvLimits {
+    /// Checks the number of fields of `record`, and the length and bytes of each of them
+    pub fn check(&self, record: &ByteRecord) -> Result<(), InputLimit> {
+        let line = record.position().map_or(0, |position| position.line());
+        if record.len() > self.max_fields {
+            return Err(InputLimit::FieldCount {
+                line,
+                count: record.len(),
+                max: self.max_fields,
+            });
+        }
+        for (index, field) in record.iter().enumerate() {
+            let field_number = index + 1;
+            if field.len() > self.max_field_len {
+                return Err(InputLimit::FieldLength {
+                    line,
+                    field: field_number,
+                    max: self.max_field_len,
+                });
+            }
+            if field.contains(&0) {
+                return Err(InputLimit::NulByte {
+                    line,
+                    field: field_number,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the reader is within a record, to tell the newlines ending records from newlines
+/// within quoted fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    /// A quote within a quoted field, either closing it or escaping a second quote
+    QuoteInQuoted,
+}
+
+/// Passes comma separated input through one complete record at a time, so the `csv` crate never
+/// buffers more than [CsvLimits::max_record_len] bytes of a record. A longer record is replaced
+/// by a placeholder spanning the same lines, a single quoted field starting with a NUL byte,
+/// which [TransactionRecord::read_csv](crate::transaction::TransactionRecord::read_csv) reports
+/// as the [LimitedReader::skipped] record; the records after it are read as usual.
+pub struct LimitedReader<R> {
+    inner: R,
+    limits: CsvLimits,
+    /// Bytes of complete records, not yet read from this reader
+    ready: Vec<u8>,
+    /// How much of `ready` was read
+    position: usize,
+    /// The number of bytes moved to `ready` before it
+    offset: u64,
+    /// The record being read from `inner`
+    record: Vec<u8>,
+    record_line: u64,
+    line: u64,
+    quoting: Quoting,
+    /// Discarding the rest of a record that exceeded the limit
+    skipping: bool,
+    skipped: Arc<Mutex<VecDeque<SkippedRecord>>>,
+    eof: bool,
+}
+
+/// A record of a [LimitedReader] replaced by a placeholder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkippedRecord {
+    /// The byte offset of the placeholder in the reader's output
+    pub offset: u64,
+    pub limit: InputLimit,
+}
+
+impl<R: io::Read> LimitedReader<R> {
+    pub fn new(inner: R, limits: CsvLimits) -> Self {
+        LimitedReader {
+            inner,
+            limits,
+            ready: Vec::new(),
+            position: 0,
+            offset: 0,
+            record: Vec::new(),
+            record_line: 1,
+            line: 1,
+            quoting: Quoting::FieldStart,
+            skipping: false,
+            skipped: Arc::default(),
+            eof: false,
+        }
+    }
+
+    pub fn limits(&self) -> &CsvLimits {
+        &self.limits
+    }
+
+    /// The records replaced by placeholders whose placeholders weren't read yet, in the order
+    /// they were read. The queue is shared with the reader, so it can be taken from while the
+    /// reader is owned by a `csv::Reader`.
+    pub fn skipped(&self) -> Arc<Mutex<VecDeque<SkippedRecord>>> {
+        self.skipped.clone()
+    }
+
+    /// Reads a chunk of `inner`, moving the records it completes to `ready`
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = [0; 8 * 1024];
+        let read = self.inner.read(&mut chunk)?;
+        if read == 0 {
+            self.eof = true;
+            if self.skipping {
+                self.ready.push(b'"');
+            } else {
+                self.ready.append(&mut self.record);
+            }
+            return Ok(());
+        }
+        for &byte in &chunk[..read] {
+            self.quoting = match (self.quoting, byte) {
+                (Quoting::FieldStart, b'"') => Quoting::Quoted,
+                (Quoting::Quoted, b'"') => Quoting::QuoteInQuoted,
+                (Quoting::QuoteInQuoted, b'"') => Quoting::Quoted,
+                (Quoting::Quoted, _) => Quoting::Quoted,
+                (_, b',' | b'\n') => Quoting::FieldStart,
+                _ => Quoting::Unquoted,
+            };
+            let record_end = byte == b'\n' && self.quoting == Quoting::FieldStart;
+            if byte == b'\n' {
+                self.line += 1;
+            }
+
+            if self.skipping {
+                if record_end {
+                    self.ready.extend_from_slice(b"\"\n");
+                    self.skipping = false;
+                } else if byte == b'\n' {
+                    self.ready.push(byte);
+                }
+            } else {
+                self.record.push(byte);
+                if record_end {
+                    self.ready.append(&mut self.record);
+                } else if self.record.len() > self.limits.max_record_len {
+                    self.skipped
+                        .lock()
+                        .expect(POISONED)
+                        .push_back(SkippedRecord {
+                            offset: self.offset + self.ready.len() as u64,
+                            limit: InputLimit::RecordLength {
+                                line: self.record_line,
+                                max: self.limits.max_record_len,
+                            },
+                        });
+                    self.ready.extend_from_slice(b"\"\0");
+                    self.ready
+                        .extend(self.record.iter().filter(|&&byte| byte == b'\n'));
+                    self.record.clear();
+                    self.skipping = true;
+                }
+            }
+            if record_end {
+                self.record_line = self.line;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: io::Read> io::Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.ready.len() {
+            self.offset += self.ready.len() as u64;
+            self.ready.clear();
+            self.position = 0;
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let ready = &self.ready[self.position..];
+        let read = ready.len().min(buf.len());
+        buf[..read].copy_from_slice(&ready[..read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+const POISONED: &str = "a thread panicked while holding the skipped records";
@@ -0,0 +1,146 @@
+//! Skipping of records repeated within or across input files, e.g. re-sent daily dumps that
+//! overlap
+
+use crate::map::Map;
+use crate::transaction::{Transaction, TransactionRecord, TransactionRecordType};
+
+/// Skips records whose transaction id and type were already seen, counting them. Keeps one byte
+/// of seen types per transaction id, so memory grows with the number of distinct ids rather than
+/// records.
+///
+/// Records are only told apart by id and type, so a transaction disputed again after a resolve
+/// is skipped as well. Records that failed to parse are passed through unchanged. Pass it by
+/// mutable reference to read [Dedup::duplicates] afterwards.
+pub struct Dedup<I> {
+    records: I,
+    /// The types seen per transaction id, one bit per [TransactionRecordType], `None` when
+    /// disabled
+    seen: Option<Map<u32, u8>>,
+    duplicates: usize,
+}
+
+impl<I, E> Dedup<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    /// Skips repeated records of `records`, or passes them all through unless `enabled`
+    pub fn new(records: impl IntoIterator<IntoIter = I>, enabled: bool) -> Self {
+        Dedup {
+            records: records.into_iter(),
+            seen: enabled.then(Map::default),
+            duplicates: 0,
+        }
+    }
+
+    /// Also skips records of `transactions`, e.g. those of a ledger restored from a snapshot,
+    /// so a source redelivering records applied before the snapshot doesn't apply them twice
+    pub fn with_seen<'a>(
+        mut self,
+        transactions: impl IntoIterator<Item = &'a Transaction>,
+    ) -> Self {
+        if let Some(seen) = &mut self.seen {
+            for transaction in transactions {
+                *seen.entry(transaction.transaction_id).or_default() |=
+                    type_bit(transaction.tx_type.record_type());
+            }
+        }
+        self
+    }
+
+    /// The number of records skipped so far
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
+
+fn type_bit(transaction_type: TransactionRecordType) -> u8 {
+    1 << transaction_type as u8
+}
+
+impl<I, E> Iterator for Dedup<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    type Item = Result<TransactionRecord, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = self.records.next()?;
+            if let (Some(seen), Ok(record)) = (&mut self.seen, &record) {
+                let types = seen.entry(record.transaction_id).or_default();
+                let bit = type_bit(record.transaction_type);
+                if *types & bit != 0 {
+                    self.duplicates += 1;
+                    continue;
+                }
+                *types |= bit;
+            }
+            return Some(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{PositiveDecimal, TransactionType};
+    use alloc::vec::Vec;
+
+    fn record(
+        transaction_type: TransactionRecordType,
+        transaction_id: u32,
+    ) -> Result<TransactionRecord, ()> {
+        Ok(TransactionRecord::new(transaction_type, 1, transaction_id))
+    }
+
+    #[test]
+    fn test_dedup() {
+        let records = || {
+            vec![
+                record(TransactionRecordType::Deposit, 1),
+                record(TransactionRecordType::Deposit, 2),
+                record(TransactionRecordType::Dispute, 1),
+                // the second file repeats the end of the first one
+                record(TransactionRecordType::Deposit, 2),
+                record(TransactionRecordType::Dispute, 1),
+                record(TransactionRecordType::Chargeback, 1),
+            ]
+        };
+        let ids = |dedup: &mut Dedup<_>| {
+            dedup
+                .map(|record: Result<TransactionRecord, ()>| {
+                    let record = record.unwrap();
+                    (record.transaction_type, record.transaction_id)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut dedup = Dedup::new(records(), true);
+        assert_eq!(
+            ids(&mut dedup),
+            vec![
+                (TransactionRecordType::Deposit, 1),
+                (TransactionRecordType::Deposit, 2),
+                (TransactionRecordType::Dispute, 1),
+                (TransactionRecordType::Chargeback, 1),
+            ]
+        );
+        assert_eq!(dedup.duplicates(), 2);
+
+        let mut dedup = Dedup::new(records(), false);
+        assert_eq!(ids(&mut dedup).len(), 6);
+        assert_eq!(dedup.duplicates(), 0);
+
+        // records applied before a restored snapshot are skipped when redelivered
+        let applied = [Transaction::new(
+            1,
+            2,
+            TransactionType::Deposit {
+                amount: PositiveDecimal::try_from(1.0).unwrap(),
+            },
+        )];
+        let mut dedup = Dedup::new(records(), true).with_seen(&applied);
+        assert_eq!(ids(&mut dedup).len(), 3);
+        assert_eq!(dedup.duplicates(), 3);
+    }
+}
@@ -0,0 +1,243 @@
+//! Diagrams of the disputes of a [Ledger], from the disputed deposit or withdrawal through its
+//! disputes, resolves and chargebacks to the locked account, as Mermaid or Graphviz graphs
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use rust_decimal::Decimal;
+
+use crate::ledger::Ledger;
+use crate::transaction::{Transaction, TransactionType};
+
+/// The graph languages [DisputeFlow]s can be rendered in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramFormat {
+    #[default]
+    Mermaid,
+    Graphviz,
+}
+
+/// A deposit or withdrawal and the disputes, resolves and chargebacks applied to it, in the
+/// order they were applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisputeFlow {
+    pub transaction: Transaction,
+    pub events: Vec<Transaction>,
+    /// `true` if the transaction is disputed, even if the dispute isn't among the `events`
+    pub open: bool,
+}
+
+impl DisputeFlow {
+    /// The flows of every disputed deposit and withdrawal of `client_id`
+    pub fn for_client(ledger: &Ledger, client_id: u16) -> Vec<Self> {
+        ledger
+            .transactions_for_client(client_id)
+            .filter_map(|transaction| DisputeFlow::new(ledger, transaction))
+            .filter(|flow| flow.open || !flow.events.is_empty())
+            .collect()
+    }
+
+    /// The flow of the deposit or withdrawal `transaction_id`, disputed or not
+    pub fn for_tx(ledger: &Ledger, transaction_id: u32) -> Option<Self> {
+        ledger
+            .transactions()
+            .iter()
+            .filter(|transaction| transaction.transaction_id == transaction_id)
+            .find_map(|transaction| DisputeFlow::new(ledger, transaction))
+    }
+
+    fn new(ledger: &Ledger, transaction: Transaction) -> Option<Self> {
+        if !matches!(
+            transaction.tx_type,
+            TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. }
+        ) {
+            return None;
+        }
+        let events = ledger
+            .transactions_for_client(transaction.client_id)
+            .filter(|event| {
+                event.transaction_id == transaction.transaction_id
+                    && event.client_id == transaction.client_id
+                    && matches!(
+                        event.tx_type,
+                        TransactionType::Dispute
+                            | TransactionType::Resolve
+                            | TransactionType::Chargeback
+                    )
+            })
+            .collect();
+        Some(DisputeFlow {
+            open: ledger
+                .disputed_tx_map
+                .contains_key(&transaction.transaction_id),
+            transaction,
+            events,
+        })
+    }
+
+    /// The nodes of this flow, from the disputed transaction to the locked account
+    fn nodes(&self) -> Vec<Node<'_>> {
+        let mut nodes = Vec::with_capacity(self.events.len() + 2);
+        nodes.push(Node::Transaction(&self.transaction));
+        let mut events = self.events.as_slice();
+        if self.open {
+            // closing states keep open disputes but not the records that opened them
+            if let [rest @ .., last] = events {
+                if last.tx_type == TransactionType::Dispute {
+                    events = rest;
+                }
+            }
+        }
+        nodes.extend(events.iter().map(Node::Event));
+        if self.open {
+            nodes.push(Node::OpenDispute);
+        } else if events
+            .last()
+            .is_some_and(|event| event.tx_type == TransactionType::Chargeback)
+        {
+            nodes.push(Node::Locked(self.transaction.client_id));
+        }
+        nodes
+    }
+}
+
+/// A node of a [DisputeFlow], displayed as its label
+enum Node<'a> {
+    Transaction(&'a Transaction),
+    /// A dispute, resolve or chargeback
+    Event(&'a Transaction),
+    OpenDispute,
+    Locked(u16),
+}
+
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Transaction(transaction) => {
+                let (name, amount) = match transaction.tx_type {
+                    TransactionType::Deposit { amount } => ("deposit", amount),
+                    TransactionType::Withdrawal { amount } => ("withdrawal", amount),
+                    _ => unreachable!("only deposits and withdrawals have dispute flows"),
+                };
+                write!(
+                    f,
+                    "tx {}: {} of {} by client {}",
+                    transaction.transaction_id,
+                    name,
+                    Decimal::from(amount),
+                    transaction.client_id
+                )
+            }
+            Node::Event(event) => f.write_str(event.tx_type.record_type().name()),
+            Node::OpenDispute => f.write_str("dispute (open)"),
+            Node::Locked(client_id) => write!(f, "client {} locked", client_id),
+        }
+    }
+}
+
+/// The id of the `index`th node of the flow of transaction `tx`
+struct NodeId(u32, usize);
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeId(tx, 0) => write!(f, "tx{}", tx),
+            NodeId(tx, index) => write!(f, "tx{}_{}", tx, index),
+        }
+    }
+}
+
+/// Renders `flows` as a single graph in `format`, one chain of nodes per flow
+pub fn write_diagram(
+    flows: &[DisputeFlow],
+    format: DiagramFormat,
+    writer: &mut impl fmt::Write,
+) -> fmt::Result {
+    match format {
+        DiagramFormat::Mermaid => writeln!(writer, "flowchart LR")?,
+        DiagramFormat::Graphviz => {
+            writeln!(writer, "digraph disputes {{")?;
+            writeln!(writer, "    rankdir=LR;")?;
+        }
+    }
+    for flow in flows {
+        let tx = flow.transaction.transaction_id;
+        for (index, node) in flow.nodes().iter().enumerate() {
+            let id = NodeId(tx, index);
+            match format {
+                DiagramFormat::Mermaid if index == 0 => {
+                    writeln!(writer, "    {}[\"{}\"]", id, node)?
+                }
+                DiagramFormat::Mermaid => writeln!(
+                    writer,
+                    "    {} --> {}[\"{}\"]",
+                    NodeId(tx, index - 1),
+                    id,
+                    node
+                )?,
+                DiagramFormat::Graphviz => {
+                    writeln!(writer, "    {} [label=\"{}\"];", id, node)?;
+                    if index > 0 {
+                        writeln!(writer, "    {} -> {};", NodeId(tx, index - 1), id)?;
+                    }
+                }
+            }
+        }
+    }
+    if format == DiagramFormat::Graphviz {
+        writeln!(writer, "}}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::PositiveDecimal;
+    use alloc::string::String;
+
+    #[test]
+    fn test_dispute_flows() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder().build().unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 3, TransactionType::Deposit { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Resolve),
+            Transaction::new(1, 3, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Chargeback),
+        ]);
+
+        let flows = DisputeFlow::for_client(&ledger, 1);
+        assert_eq!(flows.len(), 2);
+        let mut mermaid = String::new();
+        write_diagram(&flows, DiagramFormat::Mermaid, &mut mermaid).unwrap();
+        assert_eq!(
+            mermaid,
+            "flowchart LR\n\
+             \x20   tx1[\"tx 1: deposit of 10.0000 by client 1\"]\n\
+             \x20   tx1 --> tx1_1[\"dispute\"]\n\
+             \x20   tx1_1 --> tx1_2[\"resolve\"]\n\
+             \x20   tx1_2 --> tx1_3[\"dispute\"]\n\
+             \x20   tx1_3 --> tx1_4[\"chargeback\"]\n\
+             \x20   tx1_4 --> tx1_5[\"client 1 locked\"]\n\
+             \x20   tx3[\"tx 3: deposit of 10.0000 by client 1\"]\n\
+             \x20   tx3 --> tx3_1[\"dispute (open)\"]\n"
+        );
+
+        let flow = DisputeFlow::for_tx(&ledger, 2).unwrap();
+        let mut graphviz = String::new();
+        write_diagram(&[flow], DiagramFormat::Graphviz, &mut graphviz).unwrap();
+        assert_eq!(
+            graphviz,
+            "digraph disputes {\n\
+             \x20   rankdir=LR;\n\
+             \x20   tx2 [label=\"tx 2: deposit of 10.0000 by client 1\"];\n\
+             }\n"
+        );
+        assert!(DisputeFlow::for_tx(&ledger, 4).is_none());
+    }
+}
@@ -0,0 +1,215 @@
+//! The transactions each client can dispute, kept up to date as transactions are applied so
+//! disputes, resolves and chargebacks find the transaction they refer to in constant time,
+//! without going through the transaction log
+
+use alloc::collections::BTreeMap;
+
+use crate::error::TxError;
+use crate::intern::{AmountId, AmountPool};
+use crate::map::Map;
+use crate::transaction::{PositiveDecimal, Transaction, TransactionType};
+
+/// The retained deposits and withdrawals of every client by transaction id, along with the ids
+/// of other retained transactions. Maintained by the [Ledger](crate::ledger::Ledger) as its
+/// transaction log grows and forgets transactions. Amounts are kept in an [AmountPool], so a
+/// retained deposit or withdrawal takes 8 bytes besides its id, which is also counted across
+/// clients.
+#[derive(Debug, Default, Clone)]
+pub struct Disputable {
+    /// The amount of each deposit and withdrawal, and whether it is a withdrawal
+    clients: Map<u16, Map<u32, (AmountId, bool)>>,
+    amounts: AmountPool,
+    /// The number of retained deposits and withdrawals of any client by id, to tell disputes of
+    /// another client's transactions from disputes of unknown transactions
+    transfers: Map<u32, u32>,
+    /// The number of retained transactions that can't be disputed by id, e.g. disputes and
+    /// admin records, to tell disputes of them from disputes of unknown transactions
+    others: Map<u32, u32>,
+}
+
+impl Disputable {
+    /// The amount of the deposit or withdrawal `transaction_id` of `client_id`, or why it can't
+    /// be disputed by that client. Only rejections of disputes of another client's transactions
+    /// look beyond `client_id`'s own transactions.
+    pub fn get(&self, client_id: u16, transaction_id: u32) -> Result<PositiveDecimal, TxError> {
+        if let Some(&(amount, _)) = self
+            .clients
+            .get(&client_id)
+            .and_then(|transactions| transactions.get(&transaction_id))
+        {
+            return Ok(self.amounts.get(amount));
+        }
+        if self.transfers.contains_key(&transaction_id) {
+            Err(TxError::InsufficientPermission)
+        } else if self.others.contains_key(&transaction_id) {
+            Err(TxError::NotDisputable)
+        } else {
+            Err(TxError::NotFound)
+        }
+    }
+
+    /// Whether any retained transaction, of any client and type, has the id `transaction_id`
+    pub fn contains(&self, transaction_id: u32) -> bool {
+        self.transfers.contains_key(&transaction_id) || self.others.contains_key(&transaction_id)
+    }
+
+    /// Whether `transaction_id` is a withdrawal of `client_id`
+    pub fn is_withdrawal(&self, client_id: u16, transaction_id: u32) -> bool {
+        self.clients
+            .get(&client_id)
+            .and_then(|transactions| transactions.get(&transaction_id))
+            .is_some_and(|&(_, withdrawal)| withdrawal)
+    }
+
+    /// Whether `from` and `to` both have a retained deposit or withdrawal with the same id,
+    /// which disputes couldn't tell apart once their accounts are merged
+    pub(crate) fn conflicts(&self, from: u16, to: u16) -> bool {
+        match (self.clients.get(&from), self.clients.get(&to)) {
+            (Some(from), Some(to)) => from.keys().any(|id| to.contains_key(id)),
+            _ => false,
+        }
+    }
+
+    /// Moves the deposits and withdrawals of `from` to `to`, see [Disputable::conflicts]
+    pub(crate) fn remap(&mut self, from: u16, to: u16) {
+        if let Some(transactions) = self.clients.remove(&from) {
+            self.clients.entry(to).or_default().extend(transactions);
+        }
+    }
+
+    /// Adds a transaction appended to the log. A client's later transactions reusing the id of
+    /// one of its deposits or withdrawals don't replace it.
+    pub(crate) fn insert(&mut self, transaction: &Transaction) {
+        match transaction.tx_type {
+            TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                let withdrawal = matches!(transaction.tx_type, TransactionType::Withdrawal { .. });
+                *self
+                    .transfers
+                    .entry(transaction.transaction_id)
+                    .or_default() += 1;
+                let amounts = &mut self.amounts;
+                self.clients
+                    .entry(transaction.client_id)
+                    .or_default()
+                    .entry(transaction.transaction_id)
+                    .or_insert_with(|| (amounts.intern(amount), withdrawal));
+            }
+            _ => *self.others.entry(transaction.transaction_id).or_default() += 1,
+        }
+    }
+
+    /// Removes a transaction forgotten by the log
+    pub(crate) fn remove(&mut self, transaction: &Transaction) {
+        match transaction.tx_type {
+            TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. } => {
+                if let Some(transactions) = self.clients.get_mut(&transaction.client_id) {
+                    if let Some((amount, _)) = transactions.remove(&transaction.transaction_id) {
+                        self.amounts.release(amount);
+                    }
+                    if transactions.is_empty() {
+                        self.clients.remove(&transaction.client_id);
+                    }
+                }
+                uncount(&mut self.transfers, transaction.transaction_id);
+            }
+            _ => uncount(&mut self.others, transaction.transaction_id),
+        }
+    }
+}
+
+fn uncount(counts: &mut Map<u32, u32>, transaction_id: u32) {
+    if let Some(count) = counts.get_mut(&transaction_id) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(&transaction_id);
+        }
+    }
+}
+
+/// Compared by amount rather than by [AmountId], see [AmountPool]
+impl PartialEq for Disputable {
+    fn eq(&self, other: &Self) -> bool {
+        let amounts = |disputable: &Disputable| {
+            disputable
+                .clients
+                .iter()
+                .flat_map(|(&client_id, transactions)| {
+                    transactions
+                        .iter()
+                        .map(move |(&transaction_id, &(amount, withdrawal))| {
+                            ((client_id, transaction_id), (amount, withdrawal))
+                        })
+                })
+                .map(|(key, (amount, withdrawal))| {
+                    (key, (disputable.amounts.get(amount), withdrawal))
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        self.transfers == other.transfers
+            && self.others == other.others
+            && amounts(self) == amounts(other)
+    }
+}
+
+impl Eq for Disputable {}
+
+impl<'a> FromIterator<&'a Transaction> for Disputable {
+    fn from_iter<I: IntoIterator<Item = &'a Transaction>>(transactions: I) -> Self {
+        let mut disputable = Disputable::default();
+        for transaction in transactions {
+            disputable.insert(transaction);
+        }
+        disputable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_disputable() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let deposit = Transaction::new(1, 1, TransactionType::Deposit { amount });
+        let withdrawal = Transaction::new(2, 2, TransactionType::Withdrawal { amount });
+        let dispute = Transaction::new(1, 3, TransactionType::Dispute);
+        let mut disputable = [&deposit, &withdrawal, &dispute]
+            .into_iter()
+            .collect::<Disputable>();
+
+        assert_eq!(disputable.get(1, 1).unwrap(), amount);
+        assert!(disputable.is_withdrawal(2, 2));
+        assert!(!disputable.is_withdrawal(1, 1));
+        assert!(matches!(
+            disputable.get(1, 2),
+            Err(TxError::InsufficientPermission)
+        ));
+        assert!(matches!(disputable.get(1, 3), Err(TxError::NotDisputable)));
+        assert!(matches!(disputable.get(1, 4), Err(TxError::NotFound)));
+        assert!(disputable.contains(2) && disputable.contains(3));
+        assert!(!disputable.contains(4));
+
+        // forgotten transactions can't be disputed anymore
+        disputable.remove(&deposit);
+        disputable.remove(&dispute);
+        assert!(matches!(disputable.get(1, 1), Err(TxError::NotFound)));
+        assert!(matches!(disputable.get(1, 3), Err(TxError::NotFound)));
+        assert!(!disputable.contains(1));
+
+        // another client's transaction with the same id keeps it known until it is forgotten
+        disputable.insert(&Transaction::new(3, 2, TransactionType::Deposit { amount }));
+        disputable.remove(&withdrawal);
+        assert!(matches!(
+            disputable.get(1, 2),
+            Err(TxError::InsufficientPermission)
+        ));
+
+        // merged clients can't share a transaction id
+        let mut merged = [&deposit, &withdrawal].into_iter().collect::<Disputable>();
+        assert!(!merged.conflicts(1, 2));
+        merged.remap(1, 2);
+        assert_eq!(merged.get(2, 1).unwrap(), amount);
+        merged.insert(&Transaction::new(1, 2, TransactionType::Deposit { amount }));
+        assert!(merged.conflicts(1, 2));
+    }
+}
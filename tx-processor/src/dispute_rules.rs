@@ -0,0 +1,157 @@
+//! Automated handling of disputes by their reason code, e.g. resolving disputes of duplicate
+//! charges as soon as they're opened when little is at stake, and escalating every dispute for
+//! fraud to a reviewer
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::str::FromStr;
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::transaction::{PositiveDecimal, Provenance};
+
+/// The flag [DisputeAction::Escalate] attaches to the account of a dispute
+pub const ESCALATED: &str = "escalated";
+
+/// What a ledger does with a dispute of a reason code, see [DisputeRules]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisputeAction {
+    /// Holds the disputed funds until a resolve or chargeback arrives, as without a rule
+    Hold,
+    /// Resolves the dispute as soon as it's opened if the disputed amount is at most `max`, or
+    /// whatever the amount without one. Disputes of larger amounts are held.
+    Resolve { max: Option<PositiveDecimal> },
+    /// Holds the disputed funds and flags the account [ESCALATED] for review
+    Escalate,
+}
+
+impl DisputeAction {
+    /// The name of the action in a rules file and in the dispute decisions report
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisputeAction::Hold => "hold",
+            DisputeAction::Resolve { .. } => "resolve",
+            DisputeAction::Escalate => "escalate",
+        }
+    }
+}
+
+/// The [DisputeAction] of every reason code with a rule, see
+/// [LedgerBuilder::dispute_rules](crate::ledger::LedgerBuilder::dispute_rules). Disputes without
+/// a reason code, or of a code without a rule, are held.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DisputeRules(BTreeMap<String, DisputeAction>);
+
+impl DisputeRules {
+    /// Handles the disputes of `reason` with `action`, replacing an earlier rule for it
+    pub fn rule(mut self, reason: impl Into<String>, action: DisputeAction) -> Self {
+        self.0.insert(reason.into(), action);
+        self
+    }
+
+    /// The action of the rule for `reason`, if there is one
+    pub fn action(&self, reason: &str) -> Option<DisputeAction> {
+        self.0.get(reason).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A dispute a rule resolved or escalated, kept as an audit entry, see
+/// [Ledger::dispute_decisions](crate::ledger::Ledger::dispute_decisions)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisputeDecision {
+    pub client_id: u16,
+    pub transaction_id: u32,
+    pub reason: String,
+    /// The action of the rule, never [DisputeAction::Hold]
+    pub action: DisputeAction,
+    /// The disputed amount
+    pub amount: PositiveDecimal,
+    /// Where the dispute was read from
+    pub provenance: Option<Provenance>,
+}
+
+/// A line of a list of dispute rules that isn't one
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("`{0}` is not a dispute rule, e.g. `fraud,escalate` or `duplicate,resolve,50`")]
+pub struct InvalidDisputeRule(pub String);
+
+/// Parses a list of dispute rules, one `REASON,ACTION` per line, where the action is `hold`,
+/// `escalate` or `resolve`, optionally followed by the largest amount to resolve, e.g.
+/// `duplicate,resolve,50`. Blank lines and `#` comments to the end of a line are ignored.
+pub fn parse_dispute_rules(list: &str) -> Result<DisputeRules, InvalidDisputeRule> {
+    let mut rules = DisputeRules::default();
+    for line in list.lines() {
+        let rule = line.split_once('#').map_or(line, |(rule, _)| rule).trim();
+        if rule.is_empty() {
+            continue;
+        }
+        let invalid = || InvalidDisputeRule(rule.to_string());
+        let mut fields = rule.split(',').map(str::trim);
+        let reason = fields.next().filter(|reason| !reason.is_empty());
+        let (reason, action) = match (reason, fields.next(), fields.next()) {
+            (Some(reason), Some("hold"), None) => (reason, DisputeAction::Hold),
+            (Some(reason), Some("escalate"), None) => (reason, DisputeAction::Escalate),
+            (Some(reason), Some("resolve"), None) => (reason, DisputeAction::Resolve { max: None }),
+            (Some(reason), Some("resolve"), Some(max)) => {
+                let max = Decimal::from_str(max)
+                    .ok()
+                    .and_then(|max| PositiveDecimal::try_from(max).ok())
+                    .ok_or_else(invalid)?;
+                (reason, DisputeAction::Resolve { max: Some(max) })
+            }
+            _ => return Err(invalid()),
+        };
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        rules = rules.rule(reason, action);
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_dispute_rules() {
+        let rules = parse_dispute_rules(
+            "duplicate, resolve, 50 # small duplicates\n\n\
+             fraud,escalate\n\
+             other,hold\n\
+             unknown,resolve\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules.action("duplicate"),
+            Some(DisputeAction::Resolve {
+                max: Some(PositiveDecimal::try_from(50.0).unwrap())
+            })
+        );
+        assert_eq!(rules.action("fraud"), Some(DisputeAction::Escalate));
+        assert_eq!(rules.action("other"), Some(DisputeAction::Hold));
+        assert_eq!(
+            rules.action("unknown"),
+            Some(DisputeAction::Resolve { max: None })
+        );
+        assert_eq!(rules.action("goods_not_received"), None);
+
+        for invalid in [
+            "fraud",
+            "fraud,refund",
+            ",hold",
+            "fraud,escalate,1",
+            "dup,resolve,-1",
+        ] {
+            assert_eq!(
+                parse_dispute_rules(invalid),
+                Err(InvalidDisputeRule(invalid.to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Encryption of persisted ledger state, e.g. checkpoints and closing states, so it can be kept
+//! on shared disks, see [Snapshot::write](crate::snapshot::Snapshot::write)
+
+use alloc::vec::Vec;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+
+use crate::error::TxError;
+
+/// The start of every encrypted file, followed by the nonce and the AES-256-GCM ciphertext
+const MAGIC: &[u8] = b"TXENC1\n";
+
+const NONCE_LEN: usize = 12;
+
+/// An AES-256-GCM key for ledger state. Every file is encrypted under a fresh random nonce, and
+/// decrypting a file that was tampered with fails.
+#[derive(Clone)]
+pub struct StateKey(Aes256Gcm);
+
+impl StateKey {
+    /// Reads a key of 64 hex digits, e.g. as generated by `openssl rand -hex 32`
+    pub fn from_hex(hex: &str) -> Result<Self, TxError> {
+        let hex = hex.trim().as_bytes();
+        if hex.len() != 64 {
+            return Err(TxError::InvalidKey);
+        }
+        let mut key = [0; 32];
+        for (byte, digits) in key.iter_mut().zip(hex.chunks(2)) {
+            let digits = core::str::from_utf8(digits).map_err(|_| TxError::InvalidKey)?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| TxError::InvalidKey)?;
+        }
+        Ok(StateKey(Aes256Gcm::new(&key.into())))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .expect("AES-GCM only fails on plaintexts of over 64 GiB");
+        let mut encrypted = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        encrypted.extend_from_slice(MAGIC);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted
+    }
+
+    /// Decrypts what [StateKey::encrypt] encrypted under the same key
+    pub fn decrypt(&self, encrypted: &[u8]) -> Result<Vec<u8>, TxError> {
+        let rest = encrypted
+            .strip_prefix(MAGIC)
+            .filter(|rest| rest.len() >= NONCE_LEN)
+            .ok_or(TxError::DecryptionFailed)?;
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| TxError::DecryptionFailed)
+    }
+
+    /// Whether `data` was written by [StateKey::encrypt]
+    pub fn is_encrypted(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_state_key() {
+        let key = StateKey::from_hex(&"0f".repeat(32)).unwrap();
+        let encrypted = key.encrypt(b"{\"records\":3}");
+        assert!(StateKey::is_encrypted(&encrypted));
+        assert_ne!(key.encrypt(b"{\"records\":3}"), encrypted);
+        assert_eq!(key.decrypt(&encrypted).unwrap(), b"{\"records\":3}");
+
+        let other = StateKey::from_hex(&"f0".repeat(32)).unwrap();
+        assert!(matches!(
+            other.decrypt(&encrypted),
+            Err(TxError::DecryptionFailed)
+        ));
+        let mut tampered = encrypted.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            key.decrypt(&tampered),
+            Err(TxError::DecryptionFailed)
+        ));
+
+        assert!(matches!(
+            StateKey::from_hex("0f0f"),
+            Err(TxError::InvalidKey)
+        ));
+        assert!(matches!(
+            StateKey::from_hex(&"zz".repeat(32)),
+            Err(TxError::InvalidKey)
+        ));
+    }
+}
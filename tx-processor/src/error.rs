@@ -1,27 +1,97 @@
+#[cfg(feature = "std")]
 use std::io;
 
 use thiserror::Error;
 
+#[cfg(feature = "csv")]
+use crate::csv_limits::InputLimit;
+use crate::limits::Limit;
+
 #[derive(Error, Debug)]
 pub enum TxError {
+    #[cfg(feature = "csv")]
     #[error("CSV Error")]
     CsvError(#[from] csv::Error),
+    #[cfg(feature = "csv")]
+    #[error("Input limit exceeded: {0}")]
+    InputLimitExceeded(InputLimit),
+    #[cfg(feature = "std")]
     #[error("I/O Error")]
     IoError(#[from] io::Error),
+    #[cfg(feature = "snapshot")]
+    #[error("JSON Error")]
+    JsonError(#[from] serde_json::Error),
+    #[cfg(feature = "encryption")]
+    #[error("The key is not 64 hexadecimal digits")]
+    InvalidKey,
+    #[cfg(feature = "encryption")]
+    #[error("The file is encrypted, but no key was given")]
+    MissingKey,
+    #[cfg(feature = "encryption")]
+    #[error("The file could not be decrypted with the key given")]
+    DecryptionFailed,
     #[error("Insufficient Funds")]
     InsufficientFunds,
     #[error("Missing amount in transaction data")]
     MissingAmount,
-    #[error("Bad dispute")]
-    BadDispute,
+    #[error("Missing note in admin record")]
+    MissingNote,
+    #[error("The note of a remap record is not a client id")]
+    InvalidClientId,
+    #[error("The transaction is already disputed")]
+    AlreadyDisputed,
+    #[error("Only deposits and withdrawals can be disputed")]
+    NotDisputable,
+    #[error("The transaction is not disputed, resolves and chargebacks must follow a dispute")]
+    NotDisputed,
     #[error("Deposits and withdrawals must be positive amounts")]
     InvalidAmount,
+    #[error("The amount is not a decimal number")]
+    MalformedAmount,
+    #[error("The amount has more decimal places than the ledger keeps")]
+    InexactAmount,
     #[error("The account is locked")]
     LockedAccount,
+    #[error("The account is frozen")]
+    FrozenAccount,
+    #[error("No account exists for this client")]
+    UnknownClient,
+    #[error("The external client id has no client id, and none could be given to it")]
+    UnresolvedClient,
+    #[error("The account is archived")]
+    ArchivedAccount,
+    #[error("The transaction is dated within a closed period")]
+    ClosedPeriod,
     #[error("Given transaction could not be found")]
     NotFound,
     #[error("Tried to mutate a transaction not owned by you")]
     InsufficientPermission,
+    #[error("Transactions of this type are disabled")]
+    DisabledTransactionType,
+    #[error("The client is excluded from this ledger by an allow or deny list")]
+    ExcludedClient,
+    #[error("No handler is registered for transactions of this type")]
+    UnhandledTransactionType,
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(Limit),
+    #[error("Transaction id is reserved for synthetic transactions")]
+    ReservedTransactionId,
+    #[error("No synthetic transaction ids left")]
+    TxIdsExhausted,
+    #[error("Parked in suspense until the referenced transaction arrives")]
+    Suspended,
+    #[error("An account with this client id already exists")]
+    DuplicateAccount,
+    #[error("Opening balances can't hold funds, no dispute would release them")]
+    HeldWithoutDispute,
+    #[error("The accounts can't be merged: they are the same, or share a transaction id")]
+    RemapConflict,
+    #[error("The client's intake queue is full")]
+    QueueFull,
+    #[error("The intake no longer accepts transactions")]
+    IntakeClosed,
+    #[error("The idempotency key was used for a different transaction")]
+    IdempotencyKeyReused,
     #[error("Unknown error")]
     Unknown,
 }
@@ -0,0 +1,193 @@
+//! Detection of records dropped upstream, from gaps in the sequence numbers of each client
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::aliases::ClientAliases;
+use crate::transaction::TransactionRecord;
+
+/// The column a feed numbers the records of each client by, without gaps
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceKey {
+    /// [TransactionRecord::seq]
+    #[default]
+    Seq,
+    /// [TransactionRecord::transaction_id]
+    TransactionId,
+}
+
+/// A range of sequence numbers missing from a client's records, inclusive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    /// The client, 0 for a client given by an external id until [GapDetector::resolved_gaps]
+    /// looks it up
+    pub client_id: u16,
+    /// The external id of the client, if its records were given one
+    pub client_ref: Option<String>,
+    pub first: u64,
+    pub last: u64,
+}
+
+/// Passes records through unchanged while recording the sequence numbers seen for each client.
+/// Records may arrive in any order; only numbers missing once the input ended are gaps. Pass it
+/// by mutable reference to read its [GapDetector::gaps] afterwards.
+///
+/// Records given an external client id are numbered per
+/// [client_ref](TransactionRecord::client_ref), as the ledger only resolves their client id
+/// after this stage.
+pub struct GapDetector<I> {
+    records: I,
+    key: SequenceKey,
+    /// The ranges of sequence numbers seen per client, by their first number, inclusive
+    seen: BTreeMap<u16, BTreeMap<u64, u64>>,
+    /// The ranges of sequence numbers seen per external client id
+    seen_refs: BTreeMap<String, BTreeMap<u64, u64>>,
+}
+
+impl<I, E> GapDetector<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    pub fn new(records: impl IntoIterator<IntoIter = I>, key: SequenceKey) -> Self {
+        GapDetector {
+            records: records.into_iter(),
+            key,
+            seen: BTreeMap::new(),
+            seen_refs: BTreeMap::new(),
+        }
+    }
+
+    /// The numbers missing between the lowest and highest number seen for each client, ordered
+    /// by client and number, followed by those of each external client id ordered by it
+    pub fn gaps(&self) -> Vec<Gap> {
+        let clients = self
+            .seen
+            .iter()
+            .map(|(&client_id, ranges)| (client_id, None, ranges));
+        let refs = self
+            .seen_refs
+            .iter()
+            .map(|(client_ref, ranges)| (0, Some(client_ref), ranges));
+        let mut gaps = Vec::new();
+        for (client_id, client_ref, ranges) in clients.chain(refs) {
+            let mut ranges = ranges.iter();
+            let mut previous_last = match ranges.next() {
+                Some((_, &last)) => last,
+                None => continue,
+            };
+            for (&first, &last) in ranges {
+                gaps.push(Gap {
+                    client_id,
+                    client_ref: client_ref.cloned(),
+                    first: previous_last + 1,
+                    last: first - 1,
+                });
+                previous_last = last;
+            }
+        }
+        gaps
+    }
+
+    /// [GapDetector::gaps] with the client ids of the external client ids in `aliases`, e.g.
+    /// those the ledger the records were processed by resolved, ordered by client and number
+    pub fn resolved_gaps(&self, aliases: &ClientAliases) -> Vec<Gap> {
+        let mut gaps = self.gaps();
+        for gap in &mut gaps {
+            if let Some(client_id) = gap.client_ref.as_deref().and_then(|r| aliases.get(r)) {
+                gap.client_id = client_id;
+            }
+        }
+        gaps.sort_by_key(|gap| (gap.client_id, gap.first));
+        gaps
+    }
+
+    fn observe(&mut self, record: &TransactionRecord) {
+        let number = match self.key {
+            SequenceKey::Seq => match record.seq {
+                Some(seq) => seq,
+                None => return,
+            },
+            SequenceKey::TransactionId => record.transaction_id.into(),
+        };
+        let ranges = match &record.client_ref {
+            Some(client_ref) => match self.seen_refs.get_mut(client_ref.as_str()) {
+                Some(ranges) => ranges,
+                None => self.seen_refs.entry(client_ref.clone()).or_default(),
+            },
+            None => self.seen.entry(record.client_id).or_default(),
+        };
+
+        let mut first = number;
+        let mut last = number;
+        if let Some((&before_first, &before_last)) = ranges.range(..=number).next_back() {
+            if before_last >= number {
+                return;
+            }
+            if before_last + 1 == number {
+                first = before_first;
+            }
+        }
+        if let Some(after_last) = number
+            .checked_add(1)
+            .and_then(|after_first| ranges.remove(&after_first))
+        {
+            last = after_last;
+        }
+        ranges.insert(first, last);
+    }
+}
+
+impl<I, E> Iterator for GapDetector<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    type Item = Result<TransactionRecord, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        if let Ok(record) = &record {
+            self.observe(record);
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionRecordType;
+
+    fn record(client_id: u16, seq: u64) -> Result<TransactionRecord, ()> {
+        Ok(TransactionRecord {
+            seq: Some(seq),
+            ..TransactionRecord::new(TransactionRecordType::Deposit, client_id, 0)
+        })
+    }
+
+    #[test]
+    fn test_gaps() {
+        let records = vec![
+            record(1, 1),
+            record(1, 2),
+            record(1, 5),
+            record(2, 10),
+            record(1, 4),
+            record(1, 9),
+            record(2, 11),
+            record(1, 4),
+            record(1, 3),
+        ];
+        let mut detector = GapDetector::new(records, SequenceKey::Seq);
+        assert_eq!((&mut detector).count(), 9);
+        assert_eq!(
+            detector.gaps(),
+            vec![Gap {
+                client_id: 1,
+                client_ref: None,
+                first: 6,
+                last: 8
+            }]
+        );
+    }
+}
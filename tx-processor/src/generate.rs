@@ -0,0 +1,234 @@
+use std::cell::RefCell;
+
+use rand::distributions::{Distribution, Standard};
+use rand::Rng;
+use rand_distr::LogNormal;
+use rust_decimal::prelude::*;
+
+use crate::transaction::{TransactionRecord, TransactionRecordType, NUM_DECIMAL_PLACES};
+
+/// The shape of a generated [Workload]. Rates are the probability of a single record being of
+/// that type, the remaining records are deposits and withdrawals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadConfig {
+    /// Number of distinct clients the records are spread over
+    pub clients: u16,
+    /// Probability that a record disputes one of the previous deposits or withdrawals
+    pub dispute_rate: f64,
+    /// Probability that a record resolves one of the open disputes
+    pub resolve_rate: f64,
+    /// Probability that a record charges back one of the open disputes
+    pub chargeback_rate: f64,
+    /// Share of deposits among the records that move funds
+    pub deposit_ratio: f64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        WorkloadConfig {
+            clients: 100,
+            dispute_rate: 0.05,
+            resolve_rate: 0.03,
+            chargeback_rate: 0.01,
+            deposit_ratio: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct WorkloadState {
+    next_tx_id: u32,
+    /// `(client_id, transaction_id)` of every deposit and withdrawal generated so far
+    disputable: Vec<(u16, u32)>,
+    /// `(client_id, transaction_id)` of every dispute that hasn't been resolved or charged back
+    open_disputes: Vec<(u16, u32)>,
+}
+
+/// A stateful distribution of records where disputes, resolves and chargebacks reference
+/// earlier records of the same client, so the whole dispute lifecycle gets exercised.
+/// Sample it with `rng.sample(&workload)`; the same seed always produces the same records.
+#[derive(Debug)]
+pub struct Workload {
+    config: WorkloadConfig,
+    state: RefCell<WorkloadState>,
+}
+
+impl Workload {
+    pub fn new(config: WorkloadConfig) -> Self {
+        Workload {
+            config,
+            state: RefCell::new(WorkloadState {
+                next_tx_id: 1,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl Distribution<TransactionRecord> for Workload {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TransactionRecord {
+        let mut state = self.state.borrow_mut();
+        let roll = rng.gen::<f64>();
+        let chargeback_threshold = self.config.chargeback_rate;
+        let resolve_threshold = chargeback_threshold + self.config.resolve_rate;
+        let dispute_threshold = resolve_threshold + self.config.dispute_rate;
+
+        if roll < resolve_threshold && !state.open_disputes.is_empty() {
+            let index = rng.gen_range(0..state.open_disputes.len());
+            let (client_id, transaction_id) = state.open_disputes.swap_remove(index);
+            let transaction_type = if roll < chargeback_threshold {
+                TransactionRecordType::Chargeback
+            } else {
+                TransactionRecordType::Resolve
+            };
+            record(transaction_type, client_id, transaction_id, None)
+        } else if roll < dispute_threshold && !state.disputable.is_empty() {
+            let index = rng.gen_range(0..state.disputable.len());
+            let (client_id, transaction_id) = state.disputable.swap_remove(index);
+            state.open_disputes.push((client_id, transaction_id));
+            record(
+                TransactionRecordType::Dispute,
+                client_id,
+                transaction_id,
+                None,
+            )
+        } else {
+            let client_id = rng.gen_range(1..=self.config.clients.max(1));
+            let transaction_id = state.next_tx_id;
+            state.next_tx_id = state.next_tx_id.wrapping_add(1);
+            state.disputable.push((client_id, transaction_id));
+            let transaction_type = if rng.gen_bool(self.config.deposit_ratio) {
+                TransactionRecordType::Deposit
+            } else {
+                TransactionRecordType::Withdrawal
+            };
+            record(
+                transaction_type,
+                client_id,
+                transaction_id,
+                Some(sample_amount(rng)),
+            )
+        }
+    }
+}
+
+thread_local! {
+    /// The history [Standard] draws dispute-lifecycle records from, weighted like the types
+    /// of independent records: 60% deposits, 30% withdrawals, 6% disputes, 3% resolves and 1%
+    /// chargebacks
+    static STANDARD_WORKLOAD: Workload = Workload::new(WorkloadConfig {
+        clients: u16::MAX,
+        dispute_rate: 0.06,
+        resolve_rate: 0.03,
+        chargeback_rate: 0.01,
+        deposit_ratio: 2.0 / 3.0,
+    });
+}
+
+/// Records with weighted types, so `rng.gen::<TransactionRecord>()` works. Like [Workload],
+/// disputes, resolves and chargebacks refer to records generated before them, from a history
+/// kept per thread. The same seed only reproduces the same records on a fresh thread; sample a
+/// [Workload] of its own for a reproducible stream.
+impl Distribution<TransactionRecord> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TransactionRecord {
+        STANDARD_WORKLOAD.with(|workload| workload.sample(rng))
+    }
+}
+
+/// Payment amounts are roughly log-normally distributed: mostly small, with a long tail of
+/// large payments. The median amount is about 20.
+fn sample_amount<R: Rng + ?Sized>(rng: &mut R) -> Decimal {
+    let amounts = LogNormal::new(3.0, 1.2).unwrap();
+    let amount: f64 = amounts.sample(rng);
+    Decimal::from_f64(amount.max(0.0001))
+        .unwrap_or(Decimal::ONE)
+        .round_dp(NUM_DECIMAL_PLACES)
+}
+
+fn record(
+    transaction_type: TransactionRecordType,
+    client_id: u16,
+    transaction_id: u32,
+    amount: Option<Decimal>,
+) -> TransactionRecord {
+    TransactionRecord {
+        amount,
+        ..TransactionRecord::new(transaction_type, client_id, transaction_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn sample_workload(seed: u64, rows: usize) -> Vec<TransactionRecord> {
+        let config = WorkloadConfig {
+            clients: 10,
+            dispute_rate: 0.2,
+            resolve_rate: 0.1,
+            chargeback_rate: 0.05,
+            ..Default::default()
+        };
+        let workload = Workload::new(config);
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..rows).map(|_| rng.sample(&workload)).collect()
+    }
+
+    #[test]
+    fn test_workload_is_reproducible() {
+        let records = |seed| {
+            sample_workload(seed, 500)
+                .into_iter()
+                .map(|r| (r.client_id, r.transaction_id, r.amount))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(records(7), records(7));
+        assert_ne!(records(7), records(8));
+    }
+
+    #[test]
+    fn test_workload_references_earlier_records() {
+        let mut owners = HashMap::new();
+        let mut disputes = 0;
+        for record in sample_workload(1, 2000) {
+            match record.transaction_type {
+                TransactionRecordType::Deposit | TransactionRecordType::Withdrawal => {
+                    assert!(record.amount.unwrap() > Decimal::ZERO);
+                    owners.insert(record.transaction_id, record.client_id);
+                }
+                _ => {
+                    disputes += 1;
+                    assert!(record.amount.is_none());
+                    assert_eq!(owners[&record.transaction_id], record.client_id);
+                }
+            }
+        }
+        assert!(disputes > 0);
+    }
+
+    #[test]
+    fn test_standard_distribution() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let records = (0..1000)
+            .map(|_| rng.gen::<TransactionRecord>())
+            .collect::<Vec<_>>();
+        let deposits = records
+            .iter()
+            .filter(|r| r.transaction_type == TransactionRecordType::Deposit)
+            .count();
+        assert!(deposits > 500 && deposits < 700);
+
+        let mut owners = HashMap::new();
+        for record in records {
+            match record.transaction_type {
+                TransactionRecordType::Deposit | TransactionRecordType::Withdrawal => {
+                    owners.insert(record.transaction_id, record.client_id);
+                }
+                _ => assert_eq!(owners[&record.transaction_id], record.client_id),
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Handlers for transaction types this crate doesn't know, e.g. a processor's cashback or
+//! reversal records, registered by name so niche types don't need a fork of
+//! [TransactionType](crate::transaction::TransactionType)
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::account::{Account, AccountView};
+use crate::error::TxError;
+use crate::transaction::{PositiveDecimal, Transact, Transaction};
+
+/// What a [TransactionHandler] may do to an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountOp {
+    Deposit(PositiveDecimal),
+    Withdraw(PositiveDecimal),
+    /// Moves funds from the available to the held funds
+    Hold(PositiveDecimal),
+}
+
+/// The account a [TransactionHandler] applies a transaction to. Operations apply to a copy of
+/// the account, which replaces it only if the handler succeeds, so a handler failing halfway
+/// leaves the account untouched.
+pub struct HandlerAccount {
+    account: Account<false>,
+    ops: Vec<AccountOp>,
+}
+
+impl HandlerAccount {
+    pub(crate) fn new(account: &Account<false>) -> Self {
+        HandlerAccount {
+            account: account.clone(),
+            ops: Vec::new(),
+        }
+    }
+
+    /// Replaces `account` with the copy the operations were applied to, returning the operations
+    pub(crate) fn commit(self, account: &mut Account<false>) -> Vec<AccountOp> {
+        *account = self.account;
+        self.ops
+    }
+
+    /// The account as the operations so far left it
+    pub fn view(&self) -> &dyn AccountView {
+        &self.account
+    }
+
+    pub fn deposit(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        self.account.deposit(amount)?;
+        self.ops.push(AccountOp::Deposit(amount));
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        self.account.withdraw(amount)?;
+        self.ops.push(AccountOp::Withdraw(amount));
+        Ok(())
+    }
+
+    pub fn hold(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+        self.account.hold(amount)?;
+        self.ops.push(AccountOp::Hold(amount));
+        Ok(())
+    }
+}
+
+/// Applies transactions of a [TransactionRecordType::Other](crate::transaction::TransactionRecordType::Other)
+/// type, see [LedgerBuilder::handler](crate::ledger::LedgerBuilder::handler)
+pub trait TransactionHandler: Send + Sync {
+    /// Applies `transaction` to `account`. `amount` is the amount given in the record, if any.
+    /// Returning an error rejects the transaction, without touching the account.
+    fn apply(
+        &self,
+        account: &mut HandlerAccount,
+        transaction: &Transaction,
+        amount: Option<PositiveDecimal>,
+    ) -> Result<(), TxError>;
+}
+
+/// The [TransactionHandler]s of a ledger by the type they handle. Like account layers, they are
+/// shared between clones and ignored when comparing ledgers.
+#[derive(Clone, Default)]
+pub(crate) struct Handlers(pub(crate) BTreeMap<String, Arc<dyn TransactionHandler>>);
+
+impl PartialEq for Handlers {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Handlers {}
+
+impl Handlers {
+    pub(crate) fn get(&self, name: &str) -> Result<Arc<dyn TransactionHandler>, TxError> {
+        self.0
+            .get(name)
+            .cloned()
+            .ok_or(TxError::UnhandledTransactionType)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ledger::Ledger;
+    use crate::transaction::TransactionType;
+    use rust_decimal::Decimal;
+
+    /// Credits a tenth of the amount as cashback, held until the purchase settles
+    struct Cashback;
+
+    impl TransactionHandler for Cashback {
+        fn apply(
+            &self,
+            account: &mut HandlerAccount,
+            _transaction: &Transaction,
+            amount: Option<PositiveDecimal>,
+        ) -> Result<(), TxError> {
+            let amount = amount.ok_or(TxError::MissingAmount)?;
+            let cashback = PositiveDecimal::try_from(Decimal::from(amount) / Decimal::from(10))?;
+            account.deposit(cashback)?;
+            account.hold(cashback)
+        }
+    }
+
+    #[test]
+    fn test_handlers() {
+        let other = |transaction_id, name: &str, amount: Option<f64>| {
+            Transaction::new(
+                1,
+                transaction_id,
+                TransactionType::Other {
+                    name: name.into(),
+                    amount: amount.map(|amount| PositiveDecimal::try_from(amount).unwrap()),
+                },
+            )
+        };
+        let mut ledger = Ledger::builder()
+            .double_entry()
+            .handler("cashback", Cashback)
+            .build()
+            .unwrap();
+        ledger.add_tx(other(1, "cashback", Some(50.0))).unwrap();
+        assert!(matches!(
+            ledger.add_tx(other(2, "cashback", None)),
+            Err(TxError::MissingAmount)
+        ));
+        assert!(matches!(
+            ledger.add_tx(other(3, "bonus", Some(1.0))),
+            Err(TxError::UnhandledTransactionType)
+        ));
+
+        let account = &ledger.active_accounts()[&1];
+        assert_eq!(account.available(), PositiveDecimal::default());
+        assert_eq!(account.held(), PositiveDecimal::try_from(5.0).unwrap());
+        assert_eq!(ledger.transactions().len(), 1);
+        assert!(ledger
+            .journal()
+            .unwrap()
+            .trial_balance()
+            .unwrap()
+            .is_balanced()
+            .unwrap());
+    }
+}
@@ -0,0 +1,91 @@
+//! The transactions of each client, kept up to date as transactions are applied so the
+//! history of one client is found without going through the transactions of every other client
+
+use alloc::vec::Vec;
+
+use crate::map::Map;
+use crate::transaction::Transaction;
+
+/// The positions of the retained transactions of every client in the log of a
+/// [Ledger](crate::ledger::Ledger), in the order they were applied. Maintained by the ledger as
+/// its transaction log grows and forgets transactions, see
+/// [Ledger::transactions_for_client](crate::ledger::Ledger::transactions_for_client).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientHistory(Map<u16, Vec<usize>>);
+
+impl ClientHistory {
+    /// The positions in the log of the transactions of `client_id`, in ascending order
+    pub fn positions(&self, client_id: u16) -> &[usize] {
+        self.0.get(&client_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `transaction`, appended to the log at `position`
+    pub(crate) fn insert(&mut self, transaction: &Transaction, position: usize) {
+        self.0
+            .entry(transaction.client_id)
+            .or_default()
+            .push(position);
+    }
+
+    /// Shifts the positions after the oldest `forgotten` transactions were drained from the log
+    pub(crate) fn forget(&mut self, forgotten: usize) {
+        self.0.retain(|_, positions| {
+            positions.retain(|&position| position >= forgotten);
+            for position in positions.iter_mut() {
+                *position -= forgotten;
+            }
+            !positions.is_empty()
+        });
+    }
+
+    /// Moves the transactions of `from` to `to`, interleaved with those of `to` in the order
+    /// they were applied
+    pub(crate) fn remap(&mut self, from: u16, to: u16) {
+        if let Some(mut moved) = self.0.remove(&from) {
+            let positions = self.0.entry(to).or_default();
+            positions.append(&mut moved);
+            positions.sort_unstable();
+        }
+    }
+}
+
+/// Indexes a log of transactions
+impl<'a> FromIterator<&'a Transaction> for ClientHistory {
+    fn from_iter<I: IntoIterator<Item = &'a Transaction>>(transactions: I) -> Self {
+        let mut history = ClientHistory::default();
+        for (position, transaction) in transactions.into_iter().enumerate() {
+            history.insert(transaction, position);
+        }
+        history
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{PositiveDecimal, TransactionType};
+
+    #[test]
+    fn test_client_history() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let log = [
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 3, TransactionType::Withdrawal { amount }),
+            Transaction::new(3, 4, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Dispute),
+        ];
+        let mut history = log.iter().collect::<ClientHistory>();
+        assert_eq!(history.positions(1), [0, 2]);
+        assert_eq!(history.positions(2), [1, 4]);
+        assert!(history.positions(4).is_empty());
+
+        history.remap(2, 1);
+        assert_eq!(history.positions(1), [0, 1, 2, 4]);
+        assert!(history.positions(2).is_empty());
+
+        history.forget(3);
+        assert_eq!(history.positions(1), [1]);
+        assert_eq!(history.positions(3), [0]);
+    }
+}
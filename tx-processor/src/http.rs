@@ -0,0 +1,296 @@
+//! Reading inputs from `http://` and `https://` URLs as they are processed, e.g. daily files
+//! pulled straight from a provider's download endpoint. A download dropped midway is resumed
+//! where it stopped with a range request, if the server accepts them and identifies the
+//! version of the file with an ETag or a modification time, so a file changed in between fails
+//! the download rather than being spliced onto the old one.
+
+use std::io::{self, Read};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{
+    HeaderValue, ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::StatusCode;
+
+use crate::logging::warn;
+
+/// How many times in a row a dropped download is resumed, or a resuming request fails, before
+/// giving up
+const MAX_RETRIES: u32 = 5;
+
+/// `true` if `path` is an HTTP(S) URL rather than a local path
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// The body of a GET request to a URL, see [crate::io::open_input]
+pub struct HttpReader {
+    client: Client,
+    url: String,
+    response: Response,
+    /// The length of the whole body, if the server gave it
+    length: Option<u64>,
+    /// The number of bytes of the body read so far
+    offset: u64,
+    /// The ETag or modification time of the body, sent as `If-Range` to resume the download, if
+    /// the server accepts range requests
+    validator: Option<HeaderValue>,
+    retries: u32,
+}
+
+impl HttpReader {
+    pub fn open(url: &str) -> io::Result<Self> {
+        // downloads of large files take as long as they take, but connecting shouldn't
+        let client = Client::builder()
+            .timeout(None)
+            .connect_timeout(Duration::from_secs(30))
+            .build()
+            .map_err(io::Error::other)?;
+        let response = client
+            .get(url)
+            .send()
+            .and_then(Response::error_for_status)
+            .map_err(io::Error::other)?;
+        let headers = response.headers();
+        // weak ETags can't be used with If-Range
+        let validator = headers
+            .get(ETAG)
+            .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+            .or_else(|| headers.get(LAST_MODIFIED))
+            .filter(|_| {
+                headers
+                    .get(ACCEPT_RANGES)
+                    .is_some_and(|ranges| ranges == "bytes")
+            })
+            .cloned();
+        Ok(HttpReader {
+            client,
+            url: url.to_owned(),
+            length: response.content_length(),
+            response,
+            offset: 0,
+            validator,
+            retries: 0,
+        })
+    }
+
+    /// Requests the rest of the body from the current offset, after a backoff, as long as it's
+    /// the same version of the body
+    fn resume(&mut self, mut error: io::Error) -> io::Result<()> {
+        loop {
+            let Some(validator) = &self.validator else {
+                return Err(error);
+            };
+            if self.retries == MAX_RETRIES {
+                return Err(error);
+            }
+            self.retries += 1;
+            warn!(
+                "Download of {} dropped after {} bytes, resuming: {}",
+                self.url, self.offset, error
+            );
+            thread::sleep(Duration::from_millis(100 << self.retries));
+            let response = match self
+                .client
+                .get(&self.url)
+                .header(RANGE, format!("bytes={}-", self.offset))
+                .header(IF_RANGE, validator)
+                .send()
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    error = io::Error::other(e);
+                    continue;
+                }
+            };
+            // a server whose body changed answers with all of the new one
+            if response.status() != StatusCode::PARTIAL_CONTENT
+                || range_start(&response) != Some(self.offset)
+            {
+                return Err(io::Error::other(format!(
+                    "{} didn't resume the download at byte {}, it may have changed: {}",
+                    self.url,
+                    self.offset,
+                    response.status()
+                )));
+            }
+            self.response = response;
+            return Ok(());
+        }
+    }
+}
+
+/// The first byte of the `Content-Range: bytes first-last/length` of a partial response
+fn range_start(response: &Response) -> Option<u64> {
+    let range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = range.strip_prefix("bytes ")?.split_once('-')?;
+    start.parse().ok()
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.response.read(buf) {
+                Ok(0) if !buf.is_empty() && self.length.is_some_and(|len| self.offset < len) => {
+                    self.resume(io::ErrorKind::UnexpectedEof.into())?;
+                }
+                Ok(read) => {
+                    self.offset += read as u64;
+                    if read > 0 {
+                        self.retries = 0;
+                    }
+                    return Ok(read);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => self.resume(e)?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    const BODY: &str = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\n";
+
+    /// Reads a request up to its blank line, returning its lines
+    fn read_request(stream: &TcpStream) -> Vec<String> {
+        BufReader::new(stream)
+            .lines()
+            .map(Result::unwrap)
+            .take_while(|line| !line.is_empty())
+            .collect()
+    }
+
+    /// How a server answers a request resuming the download
+    enum Resume {
+        /// Serves the rest of `BODY`
+        Rest,
+        /// Closes the connection without answering
+        Drop,
+        /// Serves another body in full, as if the file changed since the first request
+        Changed,
+    }
+
+    /// Serves `BODY` tagged `"v1"`, dropping the first connection halfway, then answers the
+    /// requests resuming it in turn as told by `resumes` if `ranges` are accepted. Returns the
+    /// headers of the resuming requests.
+    fn serve(ranges: bool, resumes: Vec<Resume>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/tx.csv", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let half = BODY.len() / 2;
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&stream);
+            let accept_ranges = if ranges {
+                "Accept-Ranges: bytes\r\n"
+            } else {
+                ""
+            };
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"v1\"\r\n{}\r\n{}",
+                BODY.len(),
+                accept_ranges,
+                &BODY[..half]
+            )
+            .unwrap();
+            drop(stream);
+            let mut requests = Vec::new();
+            for resume in resumes {
+                let (mut stream, _) = listener.accept().unwrap();
+                requests.extend(read_request(&stream));
+                match resume {
+                    Resume::Rest => write!(
+                        stream,
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n{}",
+                        BODY.len() - half,
+                        half,
+                        BODY.len() - 1,
+                        BODY.len(),
+                        &BODY[half..]
+                    )
+                    .unwrap(),
+                    Resume::Drop => {}
+                    Resume::Changed => write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"v2\"\r\n\r\n{}",
+                        BODY.len(),
+                        BODY
+                    )
+                    .unwrap(),
+                }
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn has_header(requests: &[String], header: &str) -> bool {
+        requests
+            .iter()
+            .any(|request| request.to_ascii_lowercase() == header)
+    }
+
+    #[test]
+    fn test_resumed_download() {
+        let (url, server) = serve(true, vec![Resume::Rest]);
+        let mut body = String::new();
+        HttpReader::open(&url)
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, BODY);
+        let requests = server.join().unwrap();
+        assert!(has_header(
+            &requests,
+            &format!("range: bytes={}-", BODY.len() / 2)
+        ));
+        assert!(has_header(&requests, "if-range: \"v1\""));
+    }
+
+    #[test]
+    fn test_failed_reconnect() {
+        let (url, server) = serve(true, vec![Resume::Drop, Resume::Rest]);
+        let mut body = String::new();
+        HttpReader::open(&url)
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, BODY);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_changed_download() {
+        let (url, server) = serve(true, vec![Resume::Changed]);
+        let mut body = String::new();
+        let error = HttpReader::open(&url)
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap_err();
+        assert!(error.to_string().contains("may have changed"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_dropped_download_without_ranges() {
+        let (url, server) = serve(false, Vec::new());
+        let mut body = String::new();
+        let result = HttpReader::open(&url).unwrap().read_to_string(&mut body);
+        assert!(result.is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/tx.csv"));
+        assert!(!is_url("tx.csv"));
+    }
+}
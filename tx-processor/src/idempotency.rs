@@ -0,0 +1,119 @@
+//! Outcomes of recent submissions by idempotency key, so a producer retrying a submission, e.g.
+//! after a network error, gets the original outcome instead of applying it twice, see
+//! [SharedLedger::submit](crate::shared_ledger::SharedLedger::submit)
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::error::TxError;
+use crate::transaction::Transaction;
+
+/// What became of a submitted transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Applied,
+    /// Rejected by the ledger, for this reason
+    Rejected(String),
+}
+
+impl Outcome {
+    pub(crate) fn new(result: &Result<(), TxError>) -> Self {
+        match result {
+            Ok(()) => Outcome::Applied,
+            Err(e) => Outcome::Rejected(e.to_string()),
+        }
+    }
+}
+
+/// The outcome of a submission, and whether it is that of an earlier submission under the same
+/// key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub outcome: Outcome,
+    pub replayed: bool,
+}
+
+/// The outcomes of the last `capacity` submissions, forgetting the oldest first. A retry
+/// arriving after its key was forgotten is applied again, so the capacity should cover the
+/// submissions of the longest retry window.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IdempotencyCache {
+    capacity: usize,
+    outcomes: HashMap<String, (Transaction, Outcome)>,
+    /// The keys of `outcomes`, oldest first
+    keys: VecDeque<String>,
+}
+
+impl IdempotencyCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        IdempotencyCache {
+            capacity,
+            ..IdempotencyCache::default()
+        }
+    }
+
+    /// The outcome of the earlier submission of `transaction` under `key`, if any. A key of
+    /// another transaction is an error, as its submissions can't be told apart.
+    pub(crate) fn get(
+        &self,
+        key: &str,
+        transaction: &Transaction,
+    ) -> Result<Option<Outcome>, TxError> {
+        match self.outcomes.get(key) {
+            Some((submitted, _)) if submitted != transaction => Err(TxError::IdempotencyKeyReused),
+            Some((_, outcome)) => Ok(Some(outcome.clone())),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, transaction: Transaction, outcome: Outcome) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.keys.len() == self.capacity {
+            if let Some(oldest) = self.keys.pop_front() {
+                self.outcomes.remove(&oldest);
+            }
+        }
+        self.keys.push_back(key.into());
+        self.outcomes.insert(key.into(), (transaction, outcome));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{PositiveDecimal, TransactionType};
+
+    #[test]
+    fn test_idempotency_cache() {
+        let deposit = |transaction_id| {
+            Transaction::new(
+                1,
+                transaction_id,
+                TransactionType::Deposit {
+                    amount: PositiveDecimal::try_from(1.0).unwrap(),
+                },
+            )
+        };
+        let mut cache = IdempotencyCache::new(2);
+        cache.insert("a", deposit(1), Outcome::Applied);
+        cache.insert(
+            "b",
+            deposit(2),
+            Outcome::Rejected("Insufficient Funds".into()),
+        );
+        assert_eq!(cache.get("a", &deposit(1)).unwrap(), Some(Outcome::Applied));
+        assert!(matches!(
+            cache.get("a", &deposit(2)),
+            Err(TxError::IdempotencyKeyReused)
+        ));
+
+        // the oldest key is forgotten first
+        cache.insert("c", deposit(3), Outcome::Applied);
+        assert_eq!(cache.get("a", &deposit(1)).unwrap(), None);
+        assert_eq!(
+            cache.get("b", &deposit(2)).unwrap(),
+            Some(Outcome::Rejected("Insufficient Funds".into()))
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Intake of transactions from concurrent producers, serialized per client
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use crate::error::TxError;
+use crate::transaction::Transaction;
+
+/// A queue per client between producers, e.g. file readers, HTTP handlers or stream consumers,
+/// and workers applying transactions, e.g. to a [SharedLedger](crate::shared_ledger::SharedLedger).
+///
+/// Workers take transactions of different clients concurrently, but a client's transactions
+/// are handed out one at a time, in the order they were submitted: the next one only after the
+/// worker applying the previous one finished. A client whose queue holds `capacity`
+/// transactions pushes back on its producers, without holding up other clients.
+#[derive(Debug)]
+pub struct Intake {
+    capacity: usize,
+    state: Mutex<State>,
+    /// Signalled when a client becomes ready or the intake closes
+    ready: Condvar,
+    /// Signalled when a client's queue shrinks or the intake closes
+    space: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    queues: HashMap<u16, VecDeque<Transaction>>,
+    /// Clients with queued transactions and no worker applying one, in the order they became
+    /// ready
+    ready: VecDeque<u16>,
+    /// Clients a worker is applying a transaction of
+    busy: HashSet<u16>,
+    closed: bool,
+}
+
+impl State {
+    fn queued(&self, client_id: u16) -> usize {
+        self.queues.get(&client_id).map_or(0, VecDeque::len)
+    }
+
+    /// Marks `client_id` ready if it has queued transactions and no worker
+    fn schedule(&mut self, client_id: u16) {
+        if self.queued(client_id) > 0
+            && !self.busy.contains(&client_id)
+            && !self.ready.contains(&client_id)
+        {
+            self.ready.push_back(client_id);
+        }
+    }
+}
+
+impl Intake {
+    /// An intake holding at most `capacity` transactions per client, at least one
+    pub fn new(capacity: usize) -> Self {
+        Intake {
+            capacity: capacity.max(1),
+            state: Mutex::default(),
+            ready: Condvar::new(),
+            space: Condvar::new(),
+        }
+    }
+
+    /// Queues `transaction`, or returns [TxError::QueueFull] if its client's queue is full
+    pub fn try_submit(&self, transaction: Transaction) -> Result<(), TxError> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(TxError::IntakeClosed);
+        }
+        if state.queued(transaction.client_id) >= self.capacity {
+            return Err(TxError::QueueFull);
+        }
+        self.push(&mut state, transaction);
+        Ok(())
+    }
+
+    /// Queues `transaction`, waiting for room in its client's queue
+    pub fn submit(&self, transaction: Transaction) -> Result<(), TxError> {
+        let mut state = self.lock();
+        while !state.closed && state.queued(transaction.client_id) >= self.capacity {
+            state = self.space.wait(state).expect(POISONED);
+        }
+        if state.closed {
+            return Err(TxError::IntakeClosed);
+        }
+        self.push(&mut state, transaction);
+        Ok(())
+    }
+
+    /// The number of transactions queued for `client_id`, excluding one being applied
+    pub fn queued(&self, client_id: u16) -> usize {
+        self.lock().queued(client_id)
+    }
+
+    /// Stops accepting transactions. Workers apply what was already queued, then return.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+        self.space.notify_all();
+    }
+
+    /// Calls `apply` with queued transactions until the intake is closed and drained. Any
+    /// number of threads can work an intake; `apply` is never called with two transactions of
+    /// the same client at once.
+    pub fn work(&self, mut apply: impl FnMut(Transaction)) {
+        while let Some(transaction) = self.take() {
+            let client_id = transaction.client_id;
+            apply(transaction);
+
+            let mut state = self.lock();
+            state.busy.remove(&client_id);
+            state.schedule(client_id);
+            if state.ready.contains(&client_id) {
+                self.ready.notify_one();
+            }
+        }
+    }
+
+    /// The next transaction of a ready client, marking that client busy, or `None` once the
+    /// intake is closed and no transactions are left
+    fn take(&self) -> Option<Transaction> {
+        let mut state = self.lock();
+        loop {
+            if let Some(client_id) = state.ready.pop_front() {
+                let queue = state.queues.get_mut(&client_id).unwrap();
+                let transaction = queue.pop_front().unwrap();
+                if queue.is_empty() {
+                    state.queues.remove(&client_id);
+                }
+                state.busy.insert(client_id);
+                self.space.notify_all();
+                return Some(transaction);
+            }
+            if state.closed && state.queues.is_empty() {
+                return None;
+            }
+            state = self.ready.wait(state).expect(POISONED);
+        }
+    }
+
+    fn push(&self, state: &mut State, transaction: Transaction) {
+        let client_id = transaction.client_id;
+        state
+            .queues
+            .entry(client_id)
+            .or_default()
+            .push_back(transaction);
+        state.schedule(client_id);
+        self.ready.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect(POISONED)
+    }
+}
+
+const POISONED: &str = "a thread panicked while holding the intake's lock";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ledger::Ledger;
+    use crate::shared_ledger::SharedLedger;
+    use crate::transaction::{PositiveDecimal, TransactionType};
+    use std::thread;
+
+    #[test]
+    fn test_intake() {
+        let amount = PositiveDecimal::try_from(1.0).unwrap();
+        let deposit = |client_id, transaction_id| {
+            Transaction::new(
+                client_id,
+                transaction_id,
+                TransactionType::Deposit { amount },
+            )
+        };
+
+        let intake = Intake::new(2);
+        intake.try_submit(deposit(1, 1)).unwrap();
+        intake.try_submit(deposit(1, 2)).unwrap();
+        assert!(matches!(
+            intake.try_submit(deposit(1, 3)),
+            Err(TxError::QueueFull)
+        ));
+        // a full queue doesn't hold up other clients
+        intake.try_submit(deposit(2, 4)).unwrap();
+        assert_eq!(intake.queued(1), 2);
+
+        let ledger = SharedLedger::new(Ledger::default(), 2);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| intake.work(|transaction| ledger.add_tx(transaction).unwrap()));
+            }
+            // producers wait for room rather than fail
+            for transaction_id in 5..100 {
+                intake.submit(deposit(1, transaction_id)).unwrap();
+            }
+            intake.close();
+        });
+        assert!(matches!(
+            intake.submit(deposit(1, 100)),
+            Err(TxError::IntakeClosed)
+        ));
+
+        ledger.with_client(1, |shard| {
+            let applied: Vec<_> = shard
+                .transactions()
+                .iter()
+                .map(|transaction| transaction.transaction_id)
+                .collect();
+            assert_eq!(
+                applied,
+                [1, 2].into_iter().chain(5..100).collect::<Vec<_>>()
+            );
+        });
+        ledger.with_client(2, |shard| assert_eq!(shard.transactions().len(), 1));
+    }
+}
@@ -0,0 +1,106 @@
+//! Interning of amounts retained in bulk: many files only contain a small set of distinct
+//! amounts, so each is stored once and referred to by a 4-byte id rather than a 16-byte decimal
+
+use alloc::vec::Vec;
+
+use crate::map::Map;
+use crate::transaction::PositiveDecimal;
+
+/// An amount interned in an [AmountPool]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountId(u32);
+
+/// Every distinct amount interned and not released since. Amounts are counted, so an amount is
+/// dropped once every id handed out for it is released, and its id is reused by the next new
+/// amount. The pool pays off while the number of distinct amounts stays small next to the
+/// number of amounts interned.
+///
+/// Ids depend on the order amounts were interned and released in, so pools aren't compared:
+/// compare the amounts of their ids instead.
+#[derive(Debug, Default, Clone)]
+pub struct AmountPool {
+    /// Every amount and the number of times it is interned, zero for released ids
+    amounts: Vec<(PositiveDecimal, u32)>,
+    ids: Map<PositiveDecimal, AmountId>,
+    /// The ids of released amounts, to be reused
+    free: Vec<AmountId>,
+}
+
+impl AmountPool {
+    pub fn intern(&mut self, amount: PositiveDecimal) -> AmountId {
+        if let Some(&id) = self.ids.get(&amount) {
+            self.amounts[id.0 as usize].1 += 1;
+            return id;
+        }
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.amounts[id.0 as usize] = (amount, 1);
+                id
+            }
+            None => {
+                let id =
+                    u32::try_from(self.amounts.len()).expect("fewer than 2^32 distinct amounts");
+                self.amounts.push((amount, 1));
+                AmountId(id)
+            }
+        };
+        self.ids.insert(amount, id);
+        id
+    }
+
+    pub fn get(&self, id: AmountId) -> PositiveDecimal {
+        self.amounts[id.0 as usize].0
+    }
+
+    /// Gives back an id handed out by [AmountPool::intern], dropping its amount if it was the
+    /// last one
+    pub fn release(&mut self, id: AmountId) {
+        let (amount, count) = &mut self.amounts[id.0 as usize];
+        *count -= 1;
+        if *count == 0 {
+            self.ids.remove(amount);
+            self.free.push(id);
+        }
+    }
+
+    /// The number of distinct amounts interned
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_amount_pool() {
+        let mut pool = AmountPool::default();
+        let ten = PositiveDecimal::try_from(10.0).unwrap();
+        let one = PositiveDecimal::try_from(1.0).unwrap();
+        let id = pool.intern(ten);
+        assert_eq!(pool.intern(one), AmountId(1));
+        // equal amounts of any scale share an id
+        assert_eq!(
+            pool.intern(PositiveDecimal::try_from(Decimal::new(100, 1)).unwrap()),
+            id
+        );
+        assert_eq!(pool.get(id), ten);
+        assert_eq!(pool.len(), 2);
+
+        // an amount is dropped once all its ids are released, and its id reused
+        pool.release(id);
+        assert_eq!(pool.get(id), ten);
+        pool.release(id);
+        assert_eq!(pool.len(), 1);
+        let two = PositiveDecimal::try_from(2.0).unwrap();
+        assert_eq!(pool.intern(two), id);
+        assert_eq!(pool.get(id), two);
+        assert_eq!(pool.intern(ten), AmountId(2));
+    }
+}
@@ -0,0 +1,557 @@
+//! Reading transaction records from files and streams, and writing the accounts report.
+//!
+//! Every input format is a [TransactionSource], so a new format only needs an implementation
+//! of it to be processed like CSV files with
+//! [Ledger::process_source](crate::ledger::Ledger::process_source). Inputs are opened with
+//! [open_input], from files, standard input, TCP connections or, with the `http` and
+//! `object-store` features, URLs, S3 and GCS, and gzipped ones are decompressed with [decompress] with the `gzip`
+//! feature. Reports and snapshots are written
+//! to an [OutputFile], whose [OutputFile::finish] reports any write that didn't make it to the
+//! file.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, IntoInnerError, Write};
+#[cfg(feature = "json")]
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::path::Path;
+#[cfg(feature = "json")]
+use std::sync::Arc;
+
+use csv::{ReaderBuilder, Trim};
+use thiserror::Error;
+
+pub use crate::account::AccountRecord;
+use crate::amount::AmountParsing;
+use crate::csv_limits::{CsvLimits, LimitedReader};
+use crate::error::TxError;
+#[cfg(feature = "http")]
+use crate::http::{self, HttpReader};
+#[cfg(feature = "object-store")]
+use crate::remote::{self, ObjectReader, ObjectWriter};
+pub use crate::report::{write_accounts_csv, write_accounts_csv_as, AccountColumns, AccountOrder};
+#[cfg(feature = "json")]
+use crate::transaction::Provenance;
+use crate::transaction::{Transaction, TransactionRecord};
+use crate::types::TypeTable;
+
+/// Why a [TransactionSource] couldn't read a record
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error("CSV Error: {0}")]
+    Csv(#[from] csv::Error),
+    #[cfg(feature = "json")]
+    #[error("JSON Error on line {line}: {error}")]
+    Json { line: u64, error: serde_json::Error },
+    #[error("I/O Error: {0}")]
+    Io(#[from] io::Error),
+    /// A record that was read, but isn't a valid transaction, see [TransactionSource::next_tx]
+    #[error("Malformed Transaction: {0}")]
+    Transaction(TxError),
+}
+
+/// An input of transaction records, read one record at a time. Sources yield records rather
+/// than [Transaction]s, so the ledger can apply its policies to records that aren't valid
+/// transactions yet, e.g. deposits without an amount.
+pub trait TransactionSource {
+    /// The next record, or the error reading it. Sources may continue after an error, e.g.
+    /// past a malformed line; `None` means the source is exhausted.
+    fn next_record(&mut self) -> Option<Result<TransactionRecord, SourceError>>;
+
+    /// The next record as a transaction, for consumers applying transactions themselves, e.g.
+    /// with [Ledger::add_tx](crate::ledger::Ledger::add_tx)
+    fn next_tx(&mut self) -> Option<Result<Transaction, SourceError>> {
+        let record = match self.next_record()? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Transaction::try_from(record).map_err(SourceError::Transaction))
+    }
+
+    /// The records of this source as an iterator, e.g. for
+    /// [Ledger::process_csv_transactions](crate::ledger::Ledger::process_csv_transactions) or
+    /// the stages in front of it, like [Dedup](crate::dedup::Dedup)
+    fn records(self) -> Records<Self>
+    where
+        Self: Sized,
+    {
+        Records(self)
+    }
+}
+
+impl<S: TransactionSource + ?Sized> TransactionSource for &mut S {
+    fn next_record(&mut self) -> Option<Result<TransactionRecord, SourceError>> {
+        (**self).next_record()
+    }
+}
+
+impl<S: TransactionSource + ?Sized> TransactionSource for Box<S> {
+    fn next_record(&mut self) -> Option<Result<TransactionRecord, SourceError>> {
+        (**self).next_record()
+    }
+}
+
+/// The iterator returned by [TransactionSource::records]
+pub struct Records<S>(S);
+
+impl<S: TransactionSource> Iterator for Records<S> {
+    type Item = Result<TransactionRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_record()
+    }
+}
+
+/// A source of the records of an iterator, e.g. of stages like [Dedup](crate::dedup::Dedup)
+/// in front of other sources
+pub struct IterSource<I>(I);
+
+impl<I, E> IterSource<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+    E: Into<SourceError>,
+{
+    pub fn new(records: impl IntoIterator<IntoIter = I>) -> Self {
+        IterSource(records.into_iter())
+    }
+}
+
+impl<I, E> TransactionSource for IterSource<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+    E: Into<SourceError>,
+{
+    fn next_record(&mut self) -> Option<Result<TransactionRecord, SourceError>> {
+        Some(self.0.next()?.map_err(Into::into))
+    }
+}
+
+/// Transactions held in memory, e.g. built by a test or received by a server in one request
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    transactions: VecDeque<Transaction>,
+}
+
+impl MemorySource {
+    pub fn push(&mut self, transaction: Transaction) {
+        self.transactions.push_back(transaction);
+    }
+
+    /// The number of transactions not read yet
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+impl FromIterator<Transaction> for MemorySource {
+    fn from_iter<T: IntoIterator<Item = Transaction>>(transactions: T) -> Self {
+        MemorySource {
+            transactions: transactions.into_iter().collect(),
+        }
+    }
+}
+
+impl TransactionSource for MemorySource {
+    fn next_record(&mut self) -> Option<Result<TransactionRecord, SourceError>> {
+        Some(Ok(self.transactions.pop_front()?.into()))
+    }
+
+    fn next_tx(&mut self) -> Option<Result<Transaction, SourceError>> {
+        Some(Ok(self.transactions.pop_front()?))
+    }
+}
+
+/// How a [CsvSource] reads its input
+#[derive(Debug, Clone, Default)]
+pub struct CsvOptions {
+    pub limits: CsvLimits,
+    /// The batch every record is tagged with, see [Provenance::batch]
+    pub batch: Option<String>,
+    pub amounts: AmountParsing,
+    pub types: TypeTable,
+}
+
+/// The records of a CSV file with a header row, see
+/// [TransactionRecord::read_csv](crate::transaction::TransactionRecord::read_csv). Fields are
+/// trimmed, and records may have fewer columns than the header, e.g. no amount for disputes.
+pub struct CsvSource {
+    records: Box<dyn Iterator<Item = Result<TransactionRecord, csv::Error>>>,
+}
+
+impl CsvSource {
+    /// Reads the header row of `reader`, whose records are tagged with their line of `source`
+    pub fn new(
+        reader: impl io::Read + 'static,
+        source: &str,
+        options: &CsvOptions,
+    ) -> Result<Self, SourceError> {
+        let reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .from_reader(LimitedReader::new(reader, options.limits));
+        let records = TransactionRecord::read_csv(
+            reader,
+            source,
+            options.batch.as_deref(),
+            options.amounts,
+            options.types.clone(),
+        )?;
+        Ok(CsvSource {
+            records: Box::new(records),
+        })
+    }
+}
+
+impl TransactionSource for CsvSource {
+    fn next_record(&mut self) -> Option<Result<TransactionRecord, SourceError>> {
+        Some(self.records.next()?.map_err(SourceError::from))
+    }
+}
+
+/// The records of a JSON Lines file, one [TransactionRecord] object per line, e.g.
+/// `{"type": "deposit", "client": 1, "tx": 1, "amount": "1.5"}`. Amounts are decimal strings,
+/// never numbers, so they aren't rounded through floats. Blank lines are skipped.
+#[cfg(feature = "json")]
+pub struct JsonSource<R> {
+    reader: BufReader<R>,
+    source: Arc<str>,
+    batch: Option<Arc<str>>,
+    line: u64,
+    /// The byte the next line starts at
+    offset: u64,
+}
+
+#[cfg(feature = "json")]
+impl<R: io::Read> JsonSource<R> {
+    /// Reads the records of `reader`, tagging each with its line of `source` and with `batch`
+    pub fn new(reader: R, source: &str, batch: Option<&str>) -> Self {
+        JsonSource {
+            reader: BufReader::new(reader),
+            source: Arc::from(source),
+            batch: batch.map(Arc::from),
+            line: 0,
+            offset: 0,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<R: io::Read> TransactionSource for JsonSource<R> {
+    fn next_record(&mut self) -> Option<Result<TransactionRecord, SourceError>> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+            let offset = self.offset;
+            self.offset += line.len() as u64;
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str::<TransactionRecord>(&line)
+                .map(|record| TransactionRecord {
+                    provenance: Some(Provenance {
+                        source: self.source.clone(),
+                        line: self.line,
+                        offset,
+                        batch: self.batch.clone(),
+                    }),
+                    ..record
+                })
+                .map_err(|error| SourceError::Json {
+                    line: self.line,
+                    error,
+                });
+            return Some(record);
+        }
+    }
+}
+
+/// Opens the file at `path`, standard input for `-`, or a TCP connection to `host:port` for
+/// `tcp://host:port`, e.g. a feed streaming records until it closes the connection. With the
+/// `object-store` feature, `s3://` and `gs://` URLs are read from the object store, and with
+/// the `http` feature, `http://` and `https://` URLs are downloaded as they are read.
+pub fn open_input(path: &str) -> io::Result<Box<dyn io::Read>> {
+    Ok(match path {
+        "-" => Box::new(io::stdin()),
+        #[cfg(feature = "http")]
+        path if http::is_url(path) => Box::new(HttpReader::open(path)?),
+        #[cfg(feature = "object-store")]
+        path if remote::is_remote(path) => Box::new(ObjectReader::open(path)?),
+        path => match path.strip_prefix("tcp://") {
+            Some(address) => Box::new(TcpStream::connect(address)?),
+            None => Box::new(File::open(path)?),
+        },
+    })
+}
+
+/// Decompresses `reader` if `path` names a gzipped file, i.e. ends in `.gz`
+#[cfg(feature = "gzip")]
+pub fn decompress(path: &str, reader: Box<dyn io::Read>) -> Box<dyn io::Read> {
+    if path.ends_with(".gz") {
+        Box::new(flate2::read::MultiGzDecoder::new(reader))
+    } else {
+        reader
+    }
+}
+
+/// How durably an [OutputFile] is written by [OutputFile::finish]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Flushed to the operating system, which writes it to disk in its own time
+    #[default]
+    Flush,
+    /// Flushed and synced to disk, along with its directory entry, so the file survives a crash
+    /// of the machine once finished
+    Sync,
+}
+
+/// A buffered file of a report or snapshot. Dropping it flushes what's buffered but ignores
+/// any error, e.g. a full disk, so it must be [finished](OutputFile::finish) to know the file
+/// was written completely.
+#[derive(Debug)]
+pub struct OutputFile {
+    output: Output,
+    durability: Durability,
+}
+
+#[derive(Debug)]
+enum Output {
+    File {
+        writer: BufWriter<File>,
+        path: Box<Path>,
+    },
+    /// Uploaded when finished, see [ObjectWriter]
+    #[cfg(feature = "object-store")]
+    Object(ObjectWriter),
+}
+
+impl OutputFile {
+    /// Creates or truncates the file at `path`. With the `object-store` feature, `s3://` and
+    /// `gs://` URLs are written to the object store.
+    pub fn create(path: impl AsRef<Path>, durability: Durability) -> io::Result<Self> {
+        let path = path.as_ref();
+        #[cfg(feature = "object-store")]
+        if let Some(url) = path.to_str().filter(|path| remote::is_remote(path)) {
+            return Ok(OutputFile {
+                output: Output::Object(ObjectWriter::create(url)?),
+                durability,
+            });
+        }
+        Ok(OutputFile {
+            output: Output::File {
+                writer: BufWriter::new(File::create(path)?),
+                path: path.into(),
+            },
+            durability,
+        })
+    }
+
+    /// Flushes the file and, for [Durability::Sync], syncs it to disk. Objects are durable once
+    /// uploaded, whatever the durability.
+    pub fn finish(self) -> io::Result<()> {
+        match self.output {
+            Output::File { writer, path } => {
+                let file = writer.into_inner().map_err(IntoInnerError::into_error)?;
+                if self.durability == Durability::Sync {
+                    file.sync_all()?;
+                    sync_parent(&path)?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "object-store")]
+            Output::Object(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Syncs the directory entry of a newly created file
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories can't be opened to sync them outside Unix, where the file's sync is all there is
+#[cfg(not(unix))]
+fn sync_parent(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.output {
+            Output::File { writer, .. } => writer.write(buf),
+            #[cfg(feature = "object-store")]
+            Output::Object(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Output::File { writer, .. } => writer.flush(),
+            #[cfg(feature = "object-store")]
+            Output::Object(writer) => writer.flush(),
+        }
+    }
+}
+
+/// The rows of a previously written accounts report, e.g. to start a ledger from with
+/// [LedgerBuilder::opening_balances](crate::ledger::LedgerBuilder::opening_balances)
+pub fn read_account_records(reader: impl io::Read) -> Result<Vec<AccountRecord>, csv::Error> {
+    ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_reader(reader)
+        .deserialize()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{PositiveDecimal, TransactionRecordType, TransactionType};
+
+    #[test]
+    fn test_csv_source() {
+        let input =
+            "type, client, tx, amount\ndeposit, 1, 1, 1.5\nwithdrawal, 1, x, 1.0\ndispute, 1, 1\n";
+        let records = CsvSource::new(input.as_bytes(), "input.csv", &CsvOptions::default())
+            .unwrap()
+            .records()
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        let deposit = records[0].as_ref().unwrap();
+        assert_eq!(deposit.transaction_type, TransactionRecordType::Deposit);
+        assert_eq!(deposit.provenance.as_ref().unwrap().line, 2);
+        assert_eq!(deposit.provenance.as_ref().unwrap().offset, 25);
+        assert!(matches!(records[1], Err(SourceError::Csv(_))));
+        assert_eq!(records[2].as_ref().unwrap().amount, None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_source() {
+        let input = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": \"1.5\"}\n\
+                     \n\
+                     {\"type\": \"deposit\", \"client\": 1, \"tx\": 2, \"amount\": 1.5}\n\
+                     {\"type\": \"dispute\", \"client\": 1, \"tx\": 1}\n";
+        let records = JsonSource::new(input.as_bytes(), "input.jsonl", Some("batch-1"))
+            .records()
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        let deposit = records[0].as_ref().unwrap();
+        assert_eq!(deposit.amount, Some("1.5".parse().unwrap()));
+        assert_eq!(
+            deposit.provenance,
+            Some(Provenance {
+                source: "input.jsonl".into(),
+                line: 1,
+                offset: 0,
+                batch: Some("batch-1".into()),
+            })
+        );
+        assert!(matches!(records[1], Err(SourceError::Json { line: 3, .. })));
+        let dispute = records[2].as_ref().unwrap();
+        assert_eq!(dispute.amount, None);
+        assert_eq!(dispute.provenance.as_ref().unwrap().line, 4);
+        assert_eq!(dispute.provenance.as_ref().unwrap().offset, 117);
+    }
+
+    #[test]
+    fn test_next_tx() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.5\ndeposit,1,2,\n";
+        let mut source =
+            CsvSource::new(input.as_bytes(), "input.csv", &CsvOptions::default()).unwrap();
+        let transaction = source.next_tx().unwrap().unwrap();
+        assert_eq!(transaction.transaction_id, 1);
+        assert!(matches!(
+            source.next_tx(),
+            Some(Err(SourceError::Transaction(TxError::MissingAmount)))
+        ));
+        assert!(source.next_tx().is_none());
+    }
+
+    #[test]
+    fn test_memory_source() {
+        let amount = PositiveDecimal::try_from(1.5).unwrap();
+        let deposit = Transaction::new(1, 1, TransactionType::Deposit { amount });
+        let mut source = [
+            deposit.clone(),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ]
+        .into_iter()
+        .collect::<MemorySource>();
+        assert_eq!(source.next_tx().unwrap().unwrap(), deposit);
+        let dispute = source.next_record().unwrap().unwrap();
+        assert_eq!(dispute.transaction_type, TransactionRecordType::Dispute);
+        assert!(source.is_empty());
+        assert!(source.next_record().is_none());
+    }
+
+    #[test]
+    fn test_tcp_input() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let feed = std::thread::spawn(move || {
+            use std::io::Write;
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"type,client,tx,amount\ndeposit,1,1,1.5\n")
+                .unwrap();
+        });
+        let path = format!("tcp://{}", address);
+        let input = open_input(&path).unwrap();
+        let records = CsvSource::new(input, &path, &CsvOptions::default())
+            .unwrap()
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        feed.join().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].provenance.as_ref().unwrap().source, path.into());
+    }
+
+    #[test]
+    fn test_output_file() {
+        let path = std::env::temp_dir().join("tx-processor-test-output-file.csv");
+        for durability in [Durability::Flush, Durability::Sync] {
+            let mut file = OutputFile::create(&path, durability).unwrap();
+            file.write_all(b"client,available\n1,1.5\n").unwrap();
+            file.finish().unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&path).unwrap(),
+                "client,available\n1,1.5\n"
+            );
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_output_file_full_disk() {
+        let mut file = OutputFile::create("/dev/full", Durability::Flush).unwrap();
+        // buffered, so the disk being full only shows when finishing
+        file.write_all(b"client,available\n").unwrap();
+        let e = file.finish().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::StorageFull);
+    }
+
+    #[test]
+    fn test_read_account_records() {
+        let input = "client,available,held,total,locked,flags\n1, 1.5, 0, 1.5, false,vip\n";
+        let records = read_account_records(input.as_bytes()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].client_id, 1);
+        assert!(!records[0].locked);
+    }
+}
@@ -0,0 +1,503 @@
+//! Double-entry bookkeeping of the transactions applied to a [Ledger](crate::ledger::Ledger),
+//! see [LedgerBuilder::double_entry](crate::ledger::LedgerBuilder::double_entry)
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "csv")]
+use std::io;
+
+#[cfg(feature = "csv")]
+use csv::WriterBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::availability::PendingDeposit;
+use crate::error::TxError;
+use crate::handlers::AccountOp;
+use crate::transaction::{PositiveDecimal, Transaction, TransactionRecordType, TransactionType};
+
+/// The processor's own accounts every client transaction is posted to
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemAccount {
+    /// Funds held by the processor on behalf of its clients (asset)
+    Cash,
+    /// Available funds owed to clients (liability)
+    Payables,
+    /// Disputed funds owed to clients (liability)
+    HeldPayables,
+    /// Funds clawed back through chargebacks, net of what was recovered from the client's held
+    /// funds (expense)
+    ChargebackLosses,
+}
+
+impl SystemAccount {
+    pub const ALL: [SystemAccount; 4] = [
+        SystemAccount::Cash,
+        SystemAccount::Payables,
+        SystemAccount::HeldPayables,
+        SystemAccount::ChargebackLosses,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SystemAccount::Cash => "cash",
+            SystemAccount::Payables => "payables",
+            SystemAccount::HeldPayables => "held_payables",
+            SystemAccount::ChargebackLosses => "chargeback_losses",
+        }
+    }
+}
+
+impl fmt::Display for SystemAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Moves `amount` from the `credit` account to the `debit` account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// The transaction posted, `None` for opening balances
+    pub transaction_id: Option<u32>,
+    /// The type of the transaction posted, `None` for opening balances
+    pub transaction_type: Option<TransactionRecordType>,
+    pub client_id: u16,
+    pub debit: SystemAccount,
+    pub credit: SystemAccount,
+    pub amount: PositiveDecimal,
+}
+
+/// Every entry posted by a ledger in double-entry mode, in the order they were posted
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Journal {
+    pub(crate) entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    fn push(
+        &mut self,
+        transaction: Option<&Transaction>,
+        client_id: u16,
+        debit: SystemAccount,
+        credit: SystemAccount,
+        amount: PositiveDecimal,
+    ) {
+        self.entries.push(JournalEntry {
+            transaction_id: transaction.map(|transaction| transaction.transaction_id),
+            transaction_type: transaction.map(|transaction| transaction.tx_type.record_type()),
+            client_id,
+            debit,
+            credit,
+            amount,
+        });
+    }
+
+    /// Posts the funds an account was opened with, as if they were deposited and, for `held`,
+    /// disputed
+    pub(crate) fn post_opening_balance(
+        &mut self,
+        client_id: u16,
+        available: PositiveDecimal,
+        held: PositiveDecimal,
+    ) {
+        use SystemAccount::*;
+        if available != PositiveDecimal::default() {
+            self.push(None, client_id, Cash, Payables, available);
+        }
+        if held != PositiveDecimal::default() {
+            self.push(None, client_id, Cash, HeldPayables, held);
+        }
+    }
+
+    /// Posts an applied transaction. `amount` is the amount of the transaction, or of the
+    /// disputed transaction for disputes, resolves and chargebacks.
+    pub(crate) fn post(&mut self, transaction: &Transaction, amount: PositiveDecimal) {
+        use SystemAccount::*;
+        let tx = Some(transaction);
+        let client_id = transaction.client_id;
+        match transaction.tx_type {
+            TransactionType::Deposit { .. } => self.push(tx, client_id, Cash, Payables, amount),
+            TransactionType::Withdrawal { .. } => self.push(tx, client_id, Payables, Cash, amount),
+            TransactionType::Dispute => self.push(tx, client_id, Payables, HeldPayables, amount),
+            TransactionType::Resolve => self.push(tx, client_id, HeldPayables, Payables, amount),
+            TransactionType::Chargeback => {
+                // the funds are clawed back, and the loss is covered by the client's held funds
+                self.push(tx, client_id, ChargebackLosses, Cash, amount);
+                self.push(tx, client_id, HeldPayables, ChargebackLosses, amount);
+            }
+            TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. }
+            | TransactionType::Other { .. } => {}
+        }
+    }
+
+    /// Posts a deposit of which only `available` is available, while `held` is held until it's
+    /// released, pending or reserved, see [PendingDeposit]
+    pub(crate) fn post_deposit(
+        &mut self,
+        transaction: &Transaction,
+        available: PositiveDecimal,
+        held: PositiveDecimal,
+    ) {
+        use SystemAccount::*;
+        let tx = Some(transaction);
+        let client_id = transaction.client_id;
+        if available != PositiveDecimal::default() {
+            self.push(tx, client_id, Cash, Payables, available);
+        }
+        self.push(tx, client_id, Cash, HeldPayables, held);
+    }
+
+    /// Posts the release of a pending deposit or a reserve, referring to the deposit
+    pub(crate) fn post_release(&mut self, deposit: &PendingDeposit) {
+        self.entries.push(JournalEntry {
+            transaction_id: Some(deposit.transaction_id),
+            transaction_type: Some(TransactionRecordType::Deposit),
+            client_id: deposit.client_id,
+            debit: SystemAccount::HeldPayables,
+            credit: SystemAccount::Payables,
+            amount: deposit.amount,
+        });
+    }
+
+    /// Posts an operation of a [TransactionHandler](crate::handlers::TransactionHandler)
+    /// applying `transaction`
+    pub(crate) fn post_op(&mut self, transaction: &Transaction, op: AccountOp) {
+        use SystemAccount::*;
+        let tx = Some(transaction);
+        let client_id = transaction.client_id;
+        match op {
+            AccountOp::Deposit(amount) => self.push(tx, client_id, Cash, Payables, amount),
+            AccountOp::Withdraw(amount) => self.push(tx, client_id, Payables, Cash, amount),
+            AccountOp::Hold(amount) => self.push(tx, client_id, Payables, HeldPayables, amount),
+        }
+    }
+
+    /// The debits and credits of every [SystemAccount]
+    pub fn trial_balance(&self) -> Result<TrialBalance, TxError> {
+        let mut totals = BTreeMap::<_, (PositiveDecimal, PositiveDecimal)>::new();
+        for entry in &self.entries {
+            let debits = &mut totals.entry(entry.debit).or_default().0;
+            *debits = debits.checked_add(entry.amount)?;
+            let credits = &mut totals.entry(entry.credit).or_default().1;
+            *credits = credits.checked_add(entry.amount)?;
+        }
+
+        Ok(TrialBalance {
+            accounts: SystemAccount::ALL
+                .iter()
+                .map(|&account| {
+                    let (debits, credits) = totals.get(&account).copied().unwrap_or_default();
+                    TrialBalanceRow {
+                        account,
+                        debits,
+                        credits,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Writes one `date,debit,credit,amount,reference` row per entry, preceded by a header row,
+    /// with the accounts mapped through `chart` and every entry dated `date`. The reference is
+    /// the transaction type and id, e.g. `deposit-7`, or `opening-` and the client id for
+    /// opening balances.
+    #[cfg(feature = "csv")]
+    pub fn write_gl_csv<W: io::Write>(
+        &self,
+        chart: &ChartOfAccounts,
+        date: &str,
+        writer: W,
+    ) -> Result<(), TxError> {
+        #[derive(Serialize)]
+        struct Row<'a> {
+            date: &'a str,
+            debit: &'a str,
+            credit: &'a str,
+            amount: PositiveDecimal,
+            reference: &'a str,
+        }
+
+        let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+        for entry in &self.entries {
+            let reference = match (entry.transaction_type, entry.transaction_id) {
+                (Some(transaction_type), Some(id)) => {
+                    format!("{}-{}", transaction_type.name(), id)
+                }
+                _ => format!("opening-{}", entry.client_id),
+            };
+            writer.serialize(Row {
+                date,
+                debit: chart.code(entry.debit, entry.transaction_type),
+                credit: chart.code(entry.credit, entry.transaction_type),
+                amount: entry.amount,
+                reference: &reference,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrialBalanceRow {
+    pub account: SystemAccount,
+    pub debits: PositiveDecimal,
+    pub credits: PositiveDecimal,
+}
+
+/// The totals of a [Journal], see [Journal::trial_balance]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrialBalance {
+    /// Every [SystemAccount], in declaration order
+    pub accounts: Vec<TrialBalanceRow>,
+}
+
+impl TrialBalance {
+    /// The sum of all debits and the sum of all credits
+    pub fn totals(&self) -> Result<(PositiveDecimal, PositiveDecimal), TxError> {
+        self.accounts.iter().try_fold(
+            (PositiveDecimal::default(), PositiveDecimal::default()),
+            |(debits, credits), row| {
+                Ok((
+                    debits.checked_add(row.debits)?,
+                    credits.checked_add(row.credits)?,
+                ))
+            },
+        )
+    }
+
+    /// `true` if debits equal credits
+    pub fn is_balanced(&self) -> Result<bool, TxError> {
+        let (debits, credits) = self.totals()?;
+        Ok(debits == credits)
+    }
+
+    /// Writes one `account,debits,credits` row per account, preceded by a header row and
+    /// followed by a `total` row
+    #[cfg(feature = "csv")]
+    pub fn write_csv<W: io::Write>(&self, writer: W) -> Result<(), TxError> {
+        #[derive(Serialize)]
+        struct Row<'a> {
+            account: &'a str,
+            debits: PositiveDecimal,
+            credits: PositiveDecimal,
+        }
+
+        let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+        for row in &self.accounts {
+            writer.serialize(Row {
+                account: row.account.name(),
+                debits: row.debits,
+                credits: row.credits,
+            })?;
+        }
+        let (debits, credits) = self.totals()?;
+        writer.serialize(Row {
+            account: "total",
+            debits,
+            credits,
+        })?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Maps the [SystemAccount]s to the account codes of a general ledger. A code can be
+/// overridden for the entries of a single transaction type, e.g. to book the cash of
+/// withdrawals to a separate clearing account. Accounts without a code are exported under
+/// their [SystemAccount::name].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChartOfAccounts {
+    codes: BTreeMap<(SystemAccount, Option<TransactionRecordType>), String>,
+}
+
+/// A row of a chart of accounts file, see [ChartOfAccounts::read_csv]
+#[cfg(feature = "csv")]
+#[derive(Deserialize)]
+struct ChartRow {
+    account: SystemAccount,
+    #[serde(rename = "type")]
+    transaction_type: Option<TransactionRecordType>,
+    code: String,
+}
+
+impl ChartOfAccounts {
+    /// Books `account` to `code`, for the entries of `transaction_type` or, if `None`, for
+    /// every entry without a more specific code
+    pub fn insert(
+        &mut self,
+        account: SystemAccount,
+        transaction_type: Option<TransactionRecordType>,
+        code: impl Into<String>,
+    ) {
+        self.codes.insert((account, transaction_type), code.into());
+    }
+
+    /// The code `account` is booked to for entries of `transaction_type`
+    pub fn code(
+        &self,
+        account: SystemAccount,
+        transaction_type: Option<TransactionRecordType>,
+    ) -> &str {
+        transaction_type
+            .and_then(|transaction_type| self.codes.get(&(account, Some(transaction_type))))
+            .or_else(|| self.codes.get(&(account, None)))
+            .map_or(account.name(), String::as_str)
+    }
+
+    /// Reads `account,type,code` rows, where `type` may be empty
+    #[cfg(feature = "csv")]
+    pub fn read_csv<R: io::Read>(reader: R) -> Result<Self, TxError> {
+        let mut chart = ChartOfAccounts::default();
+        for row in csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader)
+            .deserialize::<ChartRow>()
+        {
+            let row = row?;
+            chart.insert(row.account, row.transaction_type, row.code);
+        }
+        Ok(chart)
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod test {
+    use super::*;
+    use crate::ledger::Ledger;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_trial_balance() {
+        let amount = |value: f64| PositiveDecimal::try_from(value).unwrap();
+        let mut ledger = Ledger::builder()
+            .opening_balances([(1, Decimal::new(5, 0), Decimal::ZERO, false)])
+            .double_entry()
+            .build()
+            .unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(10.0),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(3.0),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Resolve),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(4.0),
+                },
+            ),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Chargeback),
+            // rejected transactions aren't posted
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount(100.0),
+                },
+            ),
+        ]);
+
+        let journal = ledger.journal().unwrap();
+        assert_eq!(journal.entries().len(), 9);
+        assert_eq!(
+            journal.entries()[0],
+            JournalEntry {
+                transaction_id: None,
+                transaction_type: None,
+                client_id: 1,
+                debit: SystemAccount::Cash,
+                credit: SystemAccount::Payables,
+                amount: amount(5.0),
+            }
+        );
+
+        let trial_balance = journal.trial_balance().unwrap();
+        assert!(trial_balance.is_balanced().unwrap());
+        let mut csv = Vec::new();
+        trial_balance.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "account,debits,credits\n\
+             cash,19.0000,7.0000\n\
+             payables,17.0000,29.0000\n\
+             held_payables,14.0000,14.0000\n\
+             chargeback_losses,4.0000,4.0000\n\
+             total,54.0000,54.0000\n"
+        );
+    }
+
+    #[test]
+    fn test_gl_export() {
+        let amount = |value: f64| PositiveDecimal::try_from(value).unwrap();
+        let mut ledger = Ledger::builder()
+            .opening_balances([(1, Decimal::new(5, 0), Decimal::ZERO, false)])
+            .double_entry()
+            .build()
+            .unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(10.0),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(3.0),
+                },
+            ),
+        ]);
+        let chart = ChartOfAccounts::read_csv(
+            "account, type, code\n\
+             cash, , 1000\n\
+             payables, , 2100\n\
+             cash, withdrawal, 1010\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            chart.code(SystemAccount::HeldPayables, None),
+            "held_payables"
+        );
+
+        let mut csv = Vec::new();
+        ledger
+            .journal()
+            .unwrap()
+            .write_gl_csv(&chart, "2022-06-01", &mut csv)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "date,debit,credit,amount,reference\n\
+             2022-06-01,1000,2100,5.0000,opening-1\n\
+             2022-06-01,1000,2100,10.0000,deposit-1\n\
+             2022-06-01,2100,1010,3.0000,withdrawal-2\n"
+        );
+    }
+}
@@ -1,339 +1,3634 @@
-use std::collections::HashMap;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "csv")]
+use core::future::Future;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+#[cfg(feature = "csv")]
+use core::ops::ControlFlow;
+use core::ops::Range;
+#[cfg(feature = "csv")]
+use core::pin::Pin;
+#[cfg(feature = "csv")]
+use core::task::{Context, Poll};
+#[cfg(feature = "csv")]
+use std::io;
 
-use log::{error, warn};
+use rust_decimal::Decimal;
 
-use crate::account::Account;
+use crate::account::{Account, AccountView, ArchivedAccount, LedgerAccount, LockedAccount};
+use crate::aggregate::{self, Aggregate, AggregationKey};
+use crate::aliases::{ClientAliases, ClientIdResolver};
+use crate::availability::{Availability, AvailabilityDelay, PendingDeposit, Schedule};
+use crate::cancel::CancellationToken;
+use crate::clients::{ClientFilter, TestClients};
+use crate::disputable::Disputable;
+use crate::dispute_rules::{DisputeAction, DisputeDecision, DisputeRules, ESCALATED};
 use crate::error::TxError;
+use crate::handlers::{HandlerAccount, Handlers, TransactionHandler};
+use crate::history::ClientHistory;
+#[cfg(feature = "csv")]
+use crate::io::{IterSource, SourceError, TransactionSource};
+use crate::journal::Journal;
+use crate::limits::{Limit, LimitPolicy, Limits};
+#[cfg(feature = "csv")]
+use crate::logging::{debug, error};
+use crate::logging::{info, warn};
+#[cfg(feature = "std")]
+use crate::map::HashState;
+use crate::map::Map;
+use crate::period::{LateArrival, PeriodArchive, PeriodStats, Periods};
+use crate::policies::{
+    LateArrivalPolicy, MissingAmountPolicy, Policies, UnknownClientPolicy,
+    UnknownTransactionPolicy, WithdrawalDisputePolicy,
+};
+use crate::report::ChargebackReport;
+#[cfg(feature = "csv")]
+use crate::report::{self, AccountColumns, AccountOrder};
+use crate::reserve::RollingReserves;
+use crate::risk::{self, Freeze, FreezeThresholds, RiskScore, RiskTracker, FROZEN};
+use crate::rollup::{DailyBalance, Rollups};
+use crate::rounding::{self, RoundingReserve};
+use crate::settlement::{self, Settlement};
+use crate::tags::{self, CategoryTotal};
+use crate::transaction::TransactionRecord;
 use crate::transaction::{
-    PositiveDecimal, Transact, Transaction, TransactionRecord, TransactionType,
+    Origin, PositiveDecimal, Transaction, TransactionRecordType, TransactionType,
 };
+use crate::tx_id::TxIdAllocator;
+use crate::tx_log::TransactionLog;
+
+/// The accounts of every client and the transactions applied to them. Accounts are [Account]s
+/// unless the ledger was built with [LedgerBuilder::build_with_accounts], see [LedgerAccount].
+#[derive(Clone, PartialEq, Eq)]
+pub struct Ledger<A: LedgerAccount = Account> {
+    pub(crate) active_accounts: Map<u16, A>,
+    pub(crate) locked_accounts: Map<u16, A::Locked>,
+    /// See [Ledger::archive_account]
+    pub(crate) archived_accounts: Map<u16, ArchivedAccount<A>>,
+    pub(crate) transactions: TransactionLog,
+    /// The disputable transactions of `transactions` by client
+    pub(crate) disputable: Disputable,
+    /// The transactions of `transactions` by client
+    pub(crate) history: ClientHistory,
+    /// Map of `<transaction_id, (client_id, amount)`
+    pub(crate) disputed_tx_map: Map<u32, (u16, PositiveDecimal)>,
+    /// Transactions rejected while processing a batch, in the order they were rejected
+    pub(crate) quarantine: Vec<Rejection>,
+    /// See [Ledger::quarantine_dropped]
+    pub(crate) quarantine_dropped: u64,
+    /// Records without an amount set aside by [MissingAmountPolicy::Quarantine]
+    pub(crate) incomplete: Vec<TransactionRecord>,
+    /// The number of records without an amount, by type, whatever the policy did with them
+    pub(crate) missing_amounts: BTreeMap<TransactionRecordType, u64>,
+    /// Transaction types this ledger rejects with [TxError::DisabledTransactionType]
+    pub(crate) disabled: BTreeSet<TransactionRecordType>,
+    /// Clients this ledger rejects with [TxError::ExcludedClient] if they're not admitted
+    pub(crate) clients: ClientFilter,
+    /// See [LedgerBuilder::test_clients]
+    pub(crate) test_clients: TestClients,
+    /// See [Ledger::client_aliases]
+    pub(crate) aliases: ClientAliases,
+    /// See [LedgerBuilder::dispute_rules]
+    pub(crate) dispute_rules: DisputeRules,
+    /// See [Ledger::dispute_decisions]
+    pub(crate) dispute_decisions: Vec<DisputeDecision>,
+    /// See [LedgerBuilder::freeze_thresholds]
+    pub(crate) freeze_thresholds: FreezeThresholds,
+    /// The risk features of every client, tracked only with freeze thresholds
+    pub(crate) risk: Map<u16, RiskTracker>,
+    /// See [Ledger::freezes]
+    pub(crate) freezes: Vec<Freeze>,
+    /// See [LedgerBuilder::availability] and [LedgerBuilder::reserves]
+    pub(crate) availability: Availability,
+    /// See [LedgerBuilder::reserves]
+    pub(crate) reserves: RollingReserves,
+    pub(crate) layers: AccountLayers,
+    pub(crate) handlers: Handlers,
+    pub(crate) cancellation: Cancellation,
+    pub(crate) limits: Limits,
+    /// The limit that stopped this ledger, with [LimitPolicy::Abort]
+    pub(crate) limit_exceeded: Option<Limit>,
+    pub(crate) tx_ids: TxIdAllocator,
+    /// Present in double-entry mode, see [LedgerBuilder::double_entry]
+    pub(crate) journal: Option<Journal>,
+    /// Suspended transactions by the id of the transaction they refer to, see
+    /// [LedgerBuilder::suspense]
+    pub(crate) suspense: Option<Map<u32, Vec<Suspended>>>,
+    /// See [Ledger::set_backfill]
+    pub(crate) backfill: bool,
+    pub(crate) withdrawal_disputes: WithdrawalDisputePolicy,
+    pub(crate) unknown_clients: UnknownClientPolicy,
+    pub(crate) missing_deposit_amounts: MissingAmountPolicy,
+    pub(crate) missing_withdrawal_amounts: MissingAmountPolicy,
+    /// See [Ledger::add_calculated_tx]
+    pub(crate) rounding: RoundingReserve,
+    /// See [Ledger::daily_balances]
+    pub(crate) rollups: Rollups,
+    /// See [Ledger::close_period]
+    pub(crate) periods: Periods,
+}
+
+/// Configures a [Ledger] before any transaction is applied to it
+#[derive(Default)]
+pub struct LedgerBuilder {
+    opening_balances: Vec<(u16, Decimal, Decimal, bool)>,
+    disabled: BTreeSet<TransactionRecordType>,
+    clients: ClientFilter,
+    test_clients: TestClients,
+    client_id_resolver: Option<Arc<dyn ClientIdResolver>>,
+    dispute_rules: DisputeRules,
+    freeze_thresholds: FreezeThresholds,
+    availability: Option<AvailabilityDelay>,
+    reserves: RollingReserves,
+    #[cfg(feature = "std")]
+    hash_state: Option<HashState>,
+    layers: AccountLayers,
+    handlers: Handlers,
+    cancellation: Cancellation,
+    limits: Limits,
+    synthetic_tx_ids: Option<Range<u32>>,
+    double_entry: bool,
+    suspense: bool,
+    backfill: bool,
+    withdrawal_disputes: WithdrawalDisputePolicy,
+    unknown_clients: UnknownClientPolicy,
+    missing_deposit_amounts: MissingAmountPolicy,
+    missing_withdrawal_amounts: MissingAmountPolicy,
+    late_arrivals: LateArrivalPolicy,
+}
+
+/// A transaction rejected while processing a batch, see [Ledger::quarantined]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub transaction: Transaction,
+    /// Why the transaction was rejected when it was last attempted
+    pub reason: String,
+}
+
+impl Rejection {
+    fn new(transaction: Transaction, error: &TxError) -> Self {
+        Rejection {
+            transaction,
+            reason: error.to_string(),
+        }
+    }
+}
+
+/// What [Ledger::apply_tx] did with a transaction it didn't reject
+enum Applied {
+    /// Applied, and to be appended to the log, then the dispute rule it triggered applied
+    Retained(Option<DisputeDecision>),
+    /// Refers to a transaction that wasn't applied yet, see [LedgerBuilder::suspense]
+    Suspended,
+}
+
+/// A dispute, resolve or chargeback waiting for the transaction it refers to, see
+/// [LedgerBuilder::suspense]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suspended {
+    pub transaction: Transaction,
+    /// The amount given in the record, if any, for reconciliation
+    pub amount: Option<PositiveDecimal>,
+}
+
+/// Wraps every account operation performed by a [Ledger], so integrators can add logging,
+/// mirror balances to an external system, or enforce custom rules without forking the crate.
+/// Layers run in the order they were added. To keep extra state in the accounts themselves
+/// rather than in a layer, build the ledger with a custom [LedgerAccount].
+pub trait AccountLayer: Send + Sync {
+    /// Called before `transaction` is applied to `account`. Returning an error rejects the
+    /// transaction without touching the account.
+    fn before_tx(
+        &self,
+        _account: &dyn AccountView,
+        _transaction: &Transaction,
+    ) -> Result<(), TxError> {
+        Ok(())
+    }
+
+    /// Called after `transaction` was successfully applied to `account`, unless the ledger is
+    /// backfilling history
+    fn after_tx(&self, _account: &dyn AccountView, _transaction: &Transaction) {}
+
+    /// Called instead of [AccountLayer::after_tx] while the ledger is backfilling history, see
+    /// [Ledger::set_backfill]. Observers that notify clients or emit metrics ignore backfilled
+    /// transactions by default; implement this to handle them anyway, e.g. tagged as backfill.
+    fn after_backfill_tx(&self, _account: &dyn AccountView, _transaction: &Transaction) {}
+
+    /// Called after the ledger froze `account`, see [LedgerBuilder::freeze_thresholds]
+    fn after_freeze(&self, _account: &dyn AccountView, _freeze: &Freeze) {}
+}
+
+/// The [AccountLayer]s of a ledger. Layers are behaviour rather than state, so they are shared
+/// between clones and ignored when comparing ledgers.
+#[derive(Clone, Default)]
+pub(crate) struct AccountLayers(Vec<Arc<dyn AccountLayer>>);
+
+impl AccountLayers {
+    /// Runs [AccountLayer::before_tx] of every layer, and returns the account of the client of
+    /// `transaction` once they all pass. A client without an account is shown a new empty one,
+    /// only opened if no layer rejects the transaction.
+    fn before_tx<'a, A: LedgerAccount>(
+        &self,
+        accounts: &'a mut Map<u16, A>,
+        transaction: &Transaction,
+    ) -> Result<&'a mut A, TxError> {
+        let new_account = A::wrap(Account::new(transaction.client_id));
+        let account = accounts.get(&transaction.client_id).unwrap_or(&new_account);
+        for layer in &self.0 {
+            layer.before_tx(account, transaction)?;
+        }
+        Ok(accounts.entry(transaction.client_id).or_insert(new_account))
+    }
+}
+
+impl PartialEq for AccountLayers {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for AccountLayers {}
+
+/// The [CancellationToken] of a ledger, which like its layers is ignored when comparing ledgers
+#[derive(Clone, Default)]
+pub(crate) struct Cancellation(Option<CancellationToken>);
+
+impl Cancellation {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+impl PartialEq for Cancellation {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Cancellation {}
+
+/// The outcome of [Ledger::simulate]: the state the ledger would be in after applying a batch
+/// of transactions, along with every transaction of that batch that would have been rejected
+#[derive(Debug)]
+pub struct SimulationResult<A: LedgerAccount = Account> {
+    pub ledger: Ledger<A>,
+    pub rejected: Vec<(Transaction, TxError)>,
+}
+
+impl<A: LedgerAccount> SimulationResult<A> {
+    /// `true` if every transaction of the simulated batch would have been applied
+    pub fn is_clean(&self) -> bool {
+        self.rejected.is_empty()
+    }
+}
+
+/// How far processing a batch of records got
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Records read so far
+    pub records: usize,
+    /// Records that were applied to the ledger
+    pub applied: usize,
+    /// Records that were malformed or rejected by the ledger
+    pub rejected: usize,
+    /// `true` if processing stopped early because the ledger's [CancellationToken] was cancelled
+    pub cancelled: bool,
+    /// The limit that stopped processing early, see [LimitPolicy::Abort]
+    pub limit_exceeded: Option<Limit>,
+}
+
+impl Progress {
+    /// `true` if `ledger` shouldn't process any more records, recording why
+    pub(crate) fn should_stop<A: LedgerAccount>(&mut self, ledger: &Ledger<A>) -> bool {
+        self.cancelled = ledger.is_cancelled();
+        self.limit_exceeded = ledger.limit_exceeded;
+        self.cancelled || self.limit_exceeded.is_some()
+    }
+
+    pub(crate) fn record(&mut self, applied: bool) {
+        self.records += 1;
+        if applied {
+            self.applied += 1;
+        } else {
+            self.rejected += 1;
+        }
+    }
+
+    /// Processes up to `chunk_size` records. Returns `false` if there were none left or
+    /// processing was cancelled.
+    #[cfg(feature = "csv")]
+    fn process_chunk<A: LedgerAccount>(
+        &mut self,
+        ledger: &mut Ledger<A>,
+        transactions: &mut impl Iterator<Item = Result<TransactionRecord, impl fmt::Debug>>,
+        chunk_size: usize,
+    ) -> bool {
+        let records = self.records;
+        for _ in 0..chunk_size.max(1) {
+            if self.should_stop(ledger) {
+                return false;
+            }
+            match transactions.next() {
+                Some(record) => self.record(ledger.process_csv_record(record)),
+                None => break,
+            }
+        }
+        self.records > records
+    }
+}
+
+/// A future that is pending exactly once, handing control back to the executor
+#[cfg(feature = "csv")]
+struct YieldNow(bool);
+
+#[cfg(feature = "csv")]
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// The outcome of [Ledger::retry_quarantined]
+#[derive(Debug, Default)]
+pub struct RetryReport {
+    /// Quarantined transactions that were applied by this retry
+    pub applied: Vec<Transaction>,
+    /// Quarantined transactions that were rejected again, and stay quarantined
+    pub rejected: Vec<(Transaction, TxError)>,
+    /// `true` if the retry was cancelled, leaving the remaining transactions quarantined
+    pub cancelled: bool,
+}
+
+impl LedgerBuilder {
+    /// Starts the accounts from previously computed balances, see [Ledger::from_opening_balances]
+    pub fn opening_balances(
+        mut self,
+        balances: impl IntoIterator<Item = (u16, Decimal, Decimal, bool)>,
+    ) -> Self {
+        self.opening_balances.extend(balances);
+        self
+    }
+
+    /// Rejects every transaction of `transaction_type` with [TxError::DisabledTransactionType],
+    /// e.g. for a deployment that must never process chargebacks automatically
+    pub fn disable(mut self, transaction_type: TransactionRecordType) -> Self {
+        self.disabled.insert(transaction_type);
+        self
+    }
+
+    /// Rejects every transaction of the clients `clients` doesn't admit with
+    /// [TxError::ExcludedClient], quarantining them like any other rejection
+    pub fn clients(mut self, clients: ClientFilter) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Applies the transactions of `test_clients` like any other, but leaves them out of
+    /// [Ledger::settlement], [Ledger::aggregate] and [Ledger::category_totals], reporting them
+    /// with [Ledger::test_settlement] instead
+    pub fn test_clients(mut self, test_clients: TestClients) -> Self {
+        self.test_clients = test_clients;
+        self
+    }
+
+    /// Gives the external client ids of records, read from their `client_ref` column, the
+    /// client ids `resolver` picks. Records referring to an external client id the ledger has
+    /// no alias for are rejected with [TxError::UnresolvedClient] without a resolver. See
+    /// [Ledger::client_aliases].
+    pub fn client_id_resolver(mut self, resolver: impl ClientIdResolver + 'static) -> Self {
+        self.client_id_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Handles disputes by their [reason code](Transaction::reason) as `dispute_rules` say, e.g.
+    /// resolving small disputes of duplicates right away. Every dispute a rule resolved or
+    /// escalated is recorded in [Ledger::dispute_decisions].
+    pub fn dispute_rules(mut self, dispute_rules: DisputeRules) -> Self {
+        self.dispute_rules = dispute_rules;
+        self
+    }
+
+    /// Freezes the accounts exceeding `thresholds` as their transactions are applied, flagging
+    /// them [FROZEN] so their withdrawals are rejected until they're unflagged after a review.
+    /// Every freeze is recorded in [Ledger::freezes] and passed to the layers'
+    /// [AccountLayer::after_freeze].
+    pub fn freeze_thresholds(mut self, thresholds: FreezeThresholds) -> Self {
+        self.freeze_thresholds = thresholds;
+        self
+    }
+
+    /// Posts deposits as pending, holding their funds until they're `delay` old, like ACH
+    /// deposits. Pending deposits are listed by [Ledger::pending_deposits] and can be released
+    /// before they're due with [Ledger::release_deposit]. A dispute of a pending deposit holds
+    /// its funds as usual, and releases the deposit when the dispute is resolved.
+    pub fn availability(mut self, delay: AvailabilityDelay) -> Self {
+        self.availability = Some(delay);
+        self
+    }
+
+    /// Holds a share of every deposit of the clients of `reserves` for a period, as a rolling
+    /// reserve. Reserved funds are held, see [AccountView::reserved], until the period of their
+    /// deposit ended; a dispute of the deposit holds them for the dispute instead. Unreleased
+    /// reserves are listed by [Ledger::reserves].
+    pub fn reserves(mut self, reserves: RollingReserves) -> Self {
+        self.reserves = reserves;
+        self
+    }
+
+    /// See [Ledger::with_hash_state]
+    #[cfg(feature = "std")]
+    pub fn hash_state(mut self, hash_state: HashState) -> Self {
+        self.hash_state = Some(hash_state);
+        self
+    }
+
+    /// See [Ledger::add_layer]
+    pub fn layer(mut self, layer: impl AccountLayer + 'static) -> Self {
+        self.layers.0.push(Arc::new(layer));
+        self
+    }
+
+    /// Applies transactions of type `name`, read as [TransactionRecordType::Other], with
+    /// `handler`. Transactions of other unknown types are rejected with
+    /// [TxError::UnhandledTransactionType].
+    pub fn handler(mut self, name: &str, handler: impl TransactionHandler + 'static) -> Self {
+        self.handlers.0.insert(name.into(), Arc::new(handler));
+        self
+    }
+
+    /// See [Limits]
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The ids handed out to synthetic transactions instead of
+    /// [SYNTHETIC_TX_IDS](crate::tx_id::SYNTHETIC_TX_IDS). Input transactions must not use them.
+    pub fn synthetic_tx_ids(mut self, range: Range<u32>) -> Self {
+        self.synthetic_tx_ids = Some(range);
+        self
+    }
+
+    /// Also posts every applied transaction, and the opening balances, to the processor's
+    /// system accounts, see [Ledger::journal]
+    pub fn double_entry(mut self) -> Self {
+        self.double_entry = true;
+        self
+    }
+
+    /// Parks disputes, resolves and chargebacks of unknown transactions, rejecting them with
+    /// [TxError::Suspended] instead of quarantining them, and applies them once the transaction
+    /// they refer to arrives. Accommodates feeds that deliver records out of order.
+    pub fn suspense(mut self) -> Self {
+        self.suspense = true;
+        self
+    }
+
+    /// Configures the ledger's [Policies]. [Policies::duplicates], [Policies::unknown_types]
+    /// and [Policies::negative_amounts] apply to the input, so they are left to the caller.
+    pub fn policies(mut self, policies: &Policies) -> Self {
+        self.withdrawal_disputes = policies.withdrawal_disputes;
+        self.unknown_clients = policies.unknown_clients;
+        self.missing_deposit_amounts = policies.missing_deposit_amounts;
+        self.missing_withdrawal_amounts = policies.missing_withdrawal_amounts;
+        self.suspense = policies.unknown_transactions == UnknownTransactionPolicy::Suspend;
+        self.limits.policy = policies.limits;
+        self.late_arrivals = policies.late_arrivals;
+        self
+    }
+
+    /// See [Ledger::set_backfill]
+    pub fn backfill(mut self) -> Self {
+        self.backfill = true;
+        self
+    }
+
+    /// See [CancellationToken]
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Cancellation(Some(token));
+        self
+    }
+
+    pub fn build(self) -> Result<Ledger, TxError> {
+        self.build_with_accounts()
+    }
+
+    /// Builds a ledger keeping an `A` for each client rather than an [Account], see
+    /// [LedgerAccount]
+    pub fn build_with_accounts<A: LedgerAccount>(self) -> Result<Ledger<A>, TxError> {
+        let mut ledger = Ledger::<A>::with_opening_balances(self.opening_balances)?;
+        #[cfg(feature = "std")]
+        if let Some(hash_state) = self.hash_state {
+            ledger = ledger.with_hash_state(hash_state);
+        }
+        ledger.disabled = self.disabled;
+        ledger.clients = self.clients;
+        ledger.test_clients = self.test_clients;
+        ledger.aliases = ClientAliases::new(self.client_id_resolver);
+        ledger.dispute_rules = self.dispute_rules;
+        ledger.freeze_thresholds = self.freeze_thresholds;
+        ledger.track_risk();
+        ledger.availability = Availability::new(self.availability, !self.reserves.is_empty());
+        ledger.reserves = self.reserves;
+        ledger.layers = self.layers;
+        ledger.handlers = self.handlers;
+        ledger.cancellation = self.cancellation;
+        ledger.limits = self.limits;
+        ledger.backfill = self.backfill;
+        ledger.withdrawal_disputes = self.withdrawal_disputes;
+        ledger.unknown_clients = self.unknown_clients;
+        ledger.missing_deposit_amounts = self.missing_deposit_amounts;
+        ledger.missing_withdrawal_amounts = self.missing_withdrawal_amounts;
+        ledger.periods.policy = self.late_arrivals;
+        if let Some(range) = self.synthetic_tx_ids {
+            ledger.tx_ids = TxIdAllocator::new(range);
+        }
+        if self.double_entry {
+            let mut accounts = ledger.accounts().collect::<Vec<_>>();
+            accounts.sort_unstable_by_key(|account| account.client_id());
+            let mut journal = Journal::default();
+            for account in accounts {
+                journal.post_opening_balance(
+                    account.client_id(),
+                    account.available(),
+                    account.held(),
+                );
+            }
+            ledger.journal = Some(journal);
+        }
+        if self.suspense {
+            ledger.suspense = Some(Map::default());
+        }
+        Ok(ledger)
+    }
+}
+
+impl Ledger {
+    pub fn builder() -> LedgerBuilder {
+        LedgerBuilder::default()
+    }
+
+    /// Builds a `Ledger` whose accounts start from previously computed balances, e.g. the
+    /// closing balances of yesterday's run. Each item is `(client_id, available, held, locked)`.
+    /// Held funds are rejected with [TxError::HeldWithoutDispute], since no dispute would be
+    /// left to release them: carry open disputes over with a
+    /// [Snapshot](crate::snapshot::Snapshot) instead.
+    pub fn from_opening_balances(
+        balances: impl IntoIterator<Item = (u16, Decimal, Decimal, bool)>,
+    ) -> Result<Self, TxError> {
+        Ledger::with_opening_balances(balances)
+    }
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Ledger::empty()
+    }
+}
+
+impl<A: LedgerAccount> Ledger<A> {
+    fn empty() -> Self {
+        Ledger {
+            active_accounts: Map::default(),
+            locked_accounts: Map::default(),
+            archived_accounts: Map::default(),
+            transactions: TransactionLog::default(),
+            disputable: Disputable::default(),
+            history: ClientHistory::default(),
+            disputed_tx_map: Map::default(),
+            quarantine: Vec::new(),
+            quarantine_dropped: 0,
+            incomplete: Vec::new(),
+            missing_amounts: BTreeMap::new(),
+            disabled: BTreeSet::new(),
+            clients: ClientFilter::default(),
+            test_clients: TestClients::default(),
+            aliases: ClientAliases::default(),
+            dispute_rules: DisputeRules::default(),
+            dispute_decisions: Vec::new(),
+            freeze_thresholds: FreezeThresholds::default(),
+            risk: Map::default(),
+            freezes: Vec::new(),
+            availability: Availability::default(),
+            reserves: RollingReserves::default(),
+            layers: AccountLayers::default(),
+            handlers: Handlers::default(),
+            cancellation: Cancellation::default(),
+            limits: Limits::default(),
+            limit_exceeded: None,
+            tx_ids: TxIdAllocator::default(),
+            journal: None,
+            suspense: None,
+            backfill: false,
+            withdrawal_disputes: WithdrawalDisputePolicy::default(),
+            unknown_clients: UnknownClientPolicy::default(),
+            missing_deposit_amounts: MissingAmountPolicy::default(),
+            missing_withdrawal_amounts: MissingAmountPolicy::default(),
+            rounding: RoundingReserve::default(),
+            rollups: Rollups::default(),
+            periods: Periods::default(),
+        }
+    }
+
+    /// See [Ledger::from_opening_balances]
+    fn with_opening_balances(
+        balances: impl IntoIterator<Item = (u16, Decimal, Decimal, bool)>,
+    ) -> Result<Self, TxError> {
+        let mut ledger = Ledger::empty();
+        for (client_id, available, held, locked) in balances {
+            if ledger.active_accounts.contains_key(&client_id)
+                || ledger.locked_accounts.contains_key(&client_id)
+            {
+                return Err(TxError::DuplicateAccount);
+            }
+            if !held.is_zero() {
+                return Err(TxError::HeldWithoutDispute);
+            }
+
+            let account = Account::with_balance(
+                client_id,
+                PositiveDecimal::try_from(available)?,
+                PositiveDecimal::try_from(held)?,
+            );
+            if locked {
+                ledger
+                    .locked_accounts
+                    .insert(client_id, A::Locked::wrap(account.into()));
+            } else {
+                ledger.active_accounts.insert(client_id, A::wrap(account));
+            }
+        }
+
+        Ok(ledger)
+    }
+
+    /// Rebuilds the account and dispute maps of this ledger with `hash_state`. Use
+    /// [HashState::Deterministic] when iteration order has to be the same across runs, e.g. to
+    /// reproduce a fuzzing failure.
+    #[cfg(feature = "std")]
+    pub fn with_hash_state(self, hash_state: HashState) -> Self {
+        fn rehash<K: Eq + Hash, V>(map: Map<K, V>, hash_state: &HashState) -> Map<K, V> {
+            let mut rehashed = Map::with_capacity_and_hasher(map.len(), hash_state.clone());
+            rehashed.extend(map);
+            rehashed
+        }
+
+        Ledger {
+            active_accounts: rehash(self.active_accounts, &hash_state),
+            locked_accounts: rehash(self.locked_accounts, &hash_state),
+            archived_accounts: rehash(self.archived_accounts, &hash_state),
+            disputed_tx_map: rehash(self.disputed_tx_map, &hash_state),
+            ..self
+        }
+    }
+
+    /// Marks the transactions applied from now on as backfilled history, or as live again.
+    /// Backfilled transactions are applied as usual, but layers see them through
+    /// [AccountLayer::after_backfill_tx] rather than [AccountLayer::after_tx], and rejections are
+    /// only logged at debug level, so replaying a year of history doesn't send a year of
+    /// notifications. Switch it off once the replay caught up with the live feed.
+    pub fn set_backfill(&mut self, backfill: bool) {
+        self.backfill = backfill;
+    }
+
+    pub fn is_backfill(&self) -> bool {
+        self.backfill
+    }
+
+    /// Adds a layer that sees every transaction applied to an account of this ledger
+    pub fn add_layer(&mut self, layer: impl AccountLayer + 'static) {
+        self.layers.0.push(Arc::new(layer));
+    }
+
+    /// Applies every transaction of a batch, quarantining the ones that are rejected, see
+    /// [Ledger::retry_quarantined]
+    pub fn process_transactions(
+        &mut self,
+        transactions: impl IntoIterator<Item = Transaction>,
+    ) -> Progress {
+        let mut progress = Progress::default();
+        for transaction in transactions {
+            if progress.should_stop(self) {
+                break;
+            }
+            progress.record(self.add_tx_or_quarantine(transaction).is_ok());
+        }
+
+        progress
+    }
+
+    /// `true` if the [CancellationToken] this ledger was built with was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Allocates the ids of synthetic transactions, see [Ledger::add_synthetic_tx]. Ids can be
+    /// allocated through a shared reference, e.g. while preparing transactions on other
+    /// threads, and submitted later with [Transaction::synthetic].
+    pub fn tx_ids(&self) -> &TxIdAllocator {
+        &self.tx_ids
+    }
+
+    /// Applies a transaction created by the integrator rather than read from the input, e.g.
+    /// a fee, under the next id of the ledger's [TxIdAllocator]. Returns that id.
+    pub fn add_synthetic_tx(
+        &mut self,
+        client_id: u16,
+        tx_type: TransactionType,
+    ) -> Result<u32, TxError> {
+        let id = self.tx_ids.allocate().ok_or(TxError::TxIdsExhausted)?;
+        self.add_tx(Transaction::synthetic(client_id, id, tx_type))?;
+        Ok(id)
+    }
+
+    /// Applies an amount calculated to more places than the ledger keeps, e.g. interest or a
+    /// percentage fee, as a synthetic deposit if positive or withdrawal if negative. The amount
+    /// is rounded half to even and the residue accrues to the [RoundingReserve]; an amount that
+    /// rounds to zero only accrues to the reserve. Returns the id of the applied transaction.
+    pub fn add_calculated_tx(
+        &mut self,
+        client_id: u16,
+        calculated: Decimal,
+    ) -> Result<Option<u32>, TxError> {
+        let applied = rounding::round(calculated);
+        let id = if applied.is_zero() {
+            None
+        } else {
+            let amount = PositiveDecimal::try_from(applied.abs())?;
+            let tx_type = if applied.is_sign_positive() {
+                TransactionType::Deposit { amount }
+            } else {
+                TransactionType::Withdrawal { amount }
+            };
+            Some(self.add_synthetic_tx(client_id, tx_type)?)
+        };
+        self.rounding.record(calculated, applied);
+        Ok(id)
+    }
+
+    /// The residues of the amounts applied with [Ledger::add_calculated_tx]
+    pub fn rounding_reserve(&self) -> &RoundingReserve {
+        &self.rounding
+    }
+
+    /// The entries posted in double-entry mode, see [LedgerBuilder::double_entry]
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// The limit that stopped this ledger, if it was built with [LimitPolicy::Abort]. Every
+    /// transaction is rejected once a limit was exceeded.
+    pub fn limit_exceeded(&self) -> Option<Limit> {
+        self.limit_exceeded
+    }
+
+    /// Applies `transactions` to a copy of this ledger, leaving `self` untouched, so a batch
+    /// can be checked before it is committed with [Ledger::process_transactions]
+    pub fn simulate(
+        &self,
+        transactions: impl IntoIterator<Item = Transaction>,
+    ) -> SimulationResult<A> {
+        let mut ledger = self.clone();
+        let mut rejected = Vec::new();
+        for transaction in transactions {
+            if let Err(e) = ledger.add_tx_or_quarantine(transaction.clone()) {
+                rejected.push((transaction, e));
+            }
+        }
+
+        SimulationResult { ledger, rejected }
+    }
+
+    /// Applies every record of `source`, logging the ones that are malformed or rejected, until
+    /// it's exhausted or the ledger is cancelled or stopped by a limit. Any input can be
+    /// processed through this, see [TransactionSource].
+    #[cfg(feature = "csv")]
+    pub fn process_source(&mut self, source: &mut impl TransactionSource) -> Progress {
+        let mut progress = Progress::default();
+        while !progress.should_stop(self) {
+            match source.next_record() {
+                Some(record) => progress.record(self.process_csv_record(record)),
+                None => break,
+            }
+        }
+
+        progress
+    }
+
+    /// Applies the records of an iterator like [Ledger::process_source]
+    #[cfg(feature = "csv")]
+    pub fn process_csv_transactions<E: Into<SourceError>>(
+        &mut self,
+        transactions: impl IntoIterator<Item = Result<TransactionRecord, E>>,
+    ) -> Progress {
+        self.process_source(&mut IterSource::new(transactions))
+    }
+
+    /// Processes `chunk_size` records at a time, calling `on_chunk` with the progress so far
+    /// after every chunk that more records follow. Returning [ControlFlow::Break] from
+    /// `on_chunk` stops processing before the next chunk, e.g. to cancel or to enforce a time
+    /// budget, so it's never asked to stop once every record was processed.
+    #[cfg(feature = "csv")]
+    pub fn process_chunks(
+        &mut self,
+        transactions: impl IntoIterator<Item = Result<TransactionRecord, impl fmt::Debug>>,
+        chunk_size: usize,
+        mut on_chunk: impl FnMut(&Progress) -> ControlFlow<()>,
+    ) -> Progress {
+        let mut progress = Progress::default();
+        let mut transactions = transactions.into_iter().peekable();
+        while progress.process_chunk(self, &mut transactions, chunk_size) {
+            if transactions.peek().is_none() || on_chunk(&progress).is_break() {
+                break;
+            }
+        }
+
+        progress
+    }
+
+    /// Like [Ledger::process_chunks], but yields to the async runtime between chunks so other
+    /// tasks sharing it can make progress during a long run
+    #[cfg(feature = "csv")]
+    pub async fn process_chunks_async(
+        &mut self,
+        transactions: impl IntoIterator<Item = Result<TransactionRecord, impl fmt::Debug>>,
+        chunk_size: usize,
+        mut on_chunk: impl FnMut(&Progress) -> ControlFlow<()>,
+    ) -> Progress {
+        let mut progress = Progress::default();
+        let mut transactions = transactions.into_iter().peekable();
+        while progress.process_chunk(self, &mut transactions, chunk_size) {
+            if transactions.peek().is_none() || on_chunk(&progress).is_break() {
+                break;
+            }
+            YieldNow(false).await;
+        }
+
+        progress
+    }
+
+    /// Applies a single record, logging why it was rejected if it was. Returns `true` if it was
+    /// applied.
+    #[cfg(feature = "csv")]
+    fn process_csv_record(&mut self, record: Result<TransactionRecord, impl fmt::Debug>) -> bool {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                error!("Malformed CSV Record: {:?}", e);
+                return false;
+            }
+        };
+        let record = match self.resolve_client(record) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("Invalid Transaction: {:?}", e);
+                return false;
+            }
+        };
+        let Some(record) = self.fill_missing_amount(record) else {
+            return false;
+        };
+        let amount = record.amount;
+        let transaction = match Transaction::try_from(record) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                error!("Malformed Transaction: {:?}", e);
+                return false;
+            }
+        };
+        let transaction_id = transaction.transaction_id;
+        match self.add_tx_or_quarantine(transaction) {
+            Ok(()) => true,
+            Err(TxError::Suspended) => {
+                if let Some(suspended) = self
+                    .suspense
+                    .as_mut()
+                    .and_then(|suspense| suspense.get_mut(&transaction_id)?.last_mut())
+                {
+                    suspended.amount = amount.and_then(|amount| amount.try_into().ok());
+                }
+                false
+            }
+            Err(e) => {
+                if self.backfill {
+                    debug!("Invalid backfilled Transaction: {:?}", e);
+                } else {
+                    warn!("Invalid Transaction: {:?}", e);
+                }
+                false
+            }
+        }
+    }
+
+    /// Replaces the placeholder client id of a record given an external client id with the
+    /// client id it resolves to, see [Ledger::resolve_client_ref]
+    #[cfg(feature = "csv")]
+    fn resolve_client(
+        &mut self,
+        mut record: TransactionRecord,
+    ) -> Result<TransactionRecord, TxError> {
+        if let Some(client_ref) = record.client_ref.take() {
+            record.client_id = self.resolve_client_ref(&client_ref)?;
+        }
+        Ok(record)
+    }
+
+    /// Applies the [MissingAmountPolicy] of the record's type if it's a deposit or withdrawal
+    /// without an amount. Returns the record to convert to a transaction, which
+    /// [MissingAmountPolicy::Reject] leaves to fail with [TxError::MissingAmount], or `None` if
+    /// the record was set aside.
+    #[cfg(feature = "csv")]
+    pub(crate) fn fill_missing_amount(
+        &mut self,
+        mut record: TransactionRecord,
+    ) -> Option<TransactionRecord> {
+        if record.amount.is_some() {
+            return Some(record);
+        }
+        let policy = match record.transaction_type {
+            TransactionRecordType::Deposit => self.missing_deposit_amounts,
+            TransactionRecordType::Withdrawal => self.missing_withdrawal_amounts,
+            _ => return Some(record),
+        };
+        *self
+            .missing_amounts
+            .entry(record.transaction_type)
+            .or_default() += 1;
+        match policy {
+            MissingAmountPolicy::Reject => {}
+            MissingAmountPolicy::Zero => record.amount = Some(Decimal::ZERO),
+            MissingAmountPolicy::Quarantine => {
+                warn!("Incomplete Transaction: {:?}", record);
+                self.incomplete.push(record);
+                return None;
+            }
+        }
+        Some(record)
+    }
+
+    /// The disputes, resolves and chargebacks still waiting for the transaction they refer to,
+    /// see [LedgerBuilder::suspense]. Ordered by the id of that transaction.
+    pub fn suspended(&self) -> Vec<&Suspended> {
+        let mut suspended = self.suspense.iter().flatten().collect::<Vec<_>>();
+        suspended.sort_unstable_by_key(|(&id, _)| id);
+        suspended
+            .into_iter()
+            .flat_map(|(_, parked)| parked)
+            .collect()
+    }
+
+    /// The accounts frozen by the [FreezeThresholds], in the order they were frozen, since the
+    /// ledger was built or restored
+    pub fn freezes(&self) -> &[Freeze] {
+        &self.freezes
+    }
+
+    /// The disputes the [DisputeRules] resolved or escalated, in the order they were opened,
+    /// since the ledger was built or restored
+    pub fn dispute_decisions(&self) -> &[DisputeDecision] {
+        &self.dispute_decisions
+    }
+
+    /// The transactions rejected by [Ledger::process_transactions] and
+    /// [Ledger::process_csv_transactions] that haven't been applied by a retry yet
+    pub fn quarantined(&self) -> &[Rejection] {
+        &self.quarantine
+    }
+
+    /// The number of rejected transactions that weren't quarantined, since the ledger was built
+    /// or restored: those rejected once the quarantine was full, see [Limits::max_quarantined],
+    /// and those exceeding a limit with [LimitPolicy::Degrade]
+    pub fn quarantine_dropped(&self) -> u64 {
+        self.quarantine_dropped
+    }
+
+    /// Keeps `transaction` for retrying, or only counts it if the quarantine is full or it
+    /// exceeded a limit the ledger degrades at, as a file of random client ids would
+    fn quarantine(&mut self, transaction: Transaction, error: &TxError) {
+        let degraded = self.limits.policy == LimitPolicy::Degrade
+            && matches!(error, TxError::LimitExceeded(_));
+        if degraded
+            || self
+                .limits
+                .max_quarantined
+                .is_some_and(|max| self.quarantine.len() >= max)
+        {
+            self.quarantine_dropped += 1;
+        } else {
+            self.quarantine.push(Rejection::new(transaction, error));
+        }
+    }
+
+    /// The deposits and withdrawals without an amount set aside by
+    /// [MissingAmountPolicy::Quarantine], in the order they were read, to be corrected and
+    /// processed again
+    pub fn incomplete(&self) -> &[TransactionRecord] {
+        &self.incomplete
+    }
+
+    /// The number of deposits and withdrawals read without an amount, by type, whichever
+    /// [MissingAmountPolicy] applied to them
+    pub fn missing_amounts(&self) -> &BTreeMap<TransactionRecordType, u64> {
+        &self.missing_amounts
+    }
+
+    /// Re-attempts every quarantined transaction, in the order they were rejected. Meant for
+    /// after the state that caused the rejections was corrected, e.g. a missing deposit was
+    /// processed, so the original input doesn't need to be regenerated. Transactions that are
+    /// rejected again stay quarantined.
+    pub fn retry_quarantined(&mut self) -> RetryReport {
+        let mut report = RetryReport::default();
+        let mut quarantine = core::mem::take(&mut self.quarantine).into_iter();
+        while let Some(Rejection {
+            transaction,
+            reason,
+        }) = quarantine.next()
+        {
+            if self.is_cancelled() {
+                report.cancelled = true;
+                self.quarantine.push(Rejection {
+                    transaction,
+                    reason,
+                });
+                self.quarantine.extend(quarantine);
+                break;
+            }
+            match self.add_tx(transaction.clone()) {
+                Ok(()) => report.applied.push(transaction),
+                Err(TxError::Suspended) => {}
+                Err(e) => {
+                    self.quarantine(transaction.clone(), &e);
+                    report.rejected.push((transaction, e));
+                }
+            }
+        }
+
+        report
+    }
+
+    #[cfg_attr(feature = "profiling", inline(never))]
+    pub fn add_tx(&mut self, transaction: Transaction) -> Result<(), TxError> {
+        self.add_tx_and(transaction, false)
+    }
+
+    fn add_tx_or_quarantine(&mut self, transaction: Transaction) -> Result<(), TxError> {
+        self.add_tx_and(transaction, true)
+    }
+
+    /// Applies `transaction`, or quarantines it if it's rejected and `quarantine` is set. A
+    /// transaction is only moved once it's known whether it's retained, suspended or rejected,
+    /// so a rejected one is quarantined without having been copied.
+    fn add_tx_and(&mut self, transaction: Transaction, quarantine: bool) -> Result<(), TxError> {
+        match self.apply_tx(&transaction) {
+            Ok(Applied::Retained(rule)) => {
+                let transaction_id = transaction.transaction_id;
+                let arrived = matches!(
+                    transaction.tx_type,
+                    TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. }
+                );
+                self.retain(transaction);
+                if arrived {
+                    self.resume_suspended(transaction_id);
+                }
+                if let Some(decision) = rule {
+                    self.apply_dispute_rule(decision);
+                }
+                Ok(())
+            }
+            Ok(Applied::Suspended) => {
+                self.suspend(transaction);
+                Err(TxError::Suspended)
+            }
+            Err(e) => {
+                if quarantine {
+                    self.quarantine(transaction, &e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Checks `transaction` and applies it to its account, leaving the caller to retain or
+    /// suspend it as told
+    fn apply_tx(&mut self, transaction: &Transaction) -> Result<Applied, TxError> {
+        let (active, locked) = (&self.active_accounts, &self.locked_accounts);
+        self.rollups.advance(transaction.timestamp, |client_id| {
+            account_view(active, locked, client_id)
+        });
+        self.periods.check(transaction)?;
+        let (pending, reserves) = self.availability.advance(transaction.timestamp);
+        for (deposit, reserve) in pending
+            .iter()
+            .map(|deposit| (deposit, false))
+            .chain(reserves.iter().map(|deposit| (deposit, true)))
+        {
+            if let Err(err) = self.release(deposit, reserve) {
+                warn!(
+                    "Failed to release deposit {}: {}",
+                    deposit.transaction_id, err
+                );
+            }
+        }
+        if self.disabled.contains(&transaction.tx_type.record_type()) {
+            return Err(TxError::DisabledTransactionType);
+        }
+        if !self.clients.admits(transaction.client_id) {
+            return Err(TxError::ExcludedClient);
+        }
+        // disputes, resolves and chargebacks refer to an existing transaction, which may be a
+        // synthetic one
+        if transaction.origin == Origin::Ingested
+            && self.tx_ids.is_reserved(transaction.transaction_id)
+            && !matches!(
+                transaction.tx_type,
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+            )
+        {
+            return Err(TxError::ReservedTransactionId);
+        }
+        if let TransactionType::Flag { .. } | TransactionType::Unflag { .. } = transaction.tx_type {
+            return self
+                .apply_admin_tx(transaction)
+                .map(|()| Applied::Retained(None));
+        }
+        if self.locked_accounts.contains_key(&transaction.client_id) {
+            return Err(TxError::LockedAccount);
+        }
+        if self.archived_accounts.contains_key(&transaction.client_id) {
+            return Err(TxError::ArchivedAccount);
+        }
+        if let TransactionType::Remap { to } = transaction.tx_type {
+            self.remap_client(transaction.client_id, to)?;
+            return Ok(Applied::Retained(None));
+        }
+        if self.unknown_clients == UnknownClientPolicy::Reject
+            && !self.active_accounts.contains_key(&transaction.client_id)
+        {
+            return Err(TxError::UnknownClient);
+        }
+        self.check_limits(transaction)?;
+        if let TransactionType::Other { .. } = transaction.tx_type {
+            return self
+                .apply_other_tx(transaction)
+                .map(|()| Applied::Retained(None));
+        }
+        if self.suspense.is_some() && self.refers_to_unknown_tx(transaction) {
+            return Ok(Applied::Suspended);
+        }
+        self.check_dispute_chain(transaction)?;
+        // resolves and chargebacks remove the dispute they settle
+        let disputed_amount = self
+            .journal
+            .as_ref()
+            .and_then(|_| self.disputed_tx_map.get(&transaction.transaction_id))
+            .map(|&(_, amount)| amount);
+
+        let account = self
+            .layers
+            .before_tx(&mut self.active_accounts, transaction)?;
+        // the parts of a deposit held until they're due, pending or reserved, and of a disputed
+        // deposit the parts the dispute holds instead
+        let mut reserve = None;
+        let mut released = Vec::new();
+        match transaction.tx_type {
+            TransactionType::Deposit { amount } => {
+                reserve = self
+                    .reserves
+                    .get(transaction.client_id)
+                    .map(|reserve| (reserve.of(amount), reserve.period))
+                    .filter(|&(reserved, _)| reserved != PositiveDecimal::default());
+                match reserve {
+                    Some((reserved, _)) => account.account_mut().deposit_reserved(
+                        reserved,
+                        amount.checked_sub(reserved)?,
+                        self.availability.delays(),
+                    )?,
+                    None if self.availability.delays() => {
+                        account.account_mut().deposit_pending(amount)?
+                    }
+                    None => account.deposit(amount)?,
+                }
+            }
+            TransactionType::Withdrawal { amount } => {
+                if account.flags().contains(FROZEN) {
+                    return Err(TxError::FrozenAccount);
+                }
+                account.withdraw(amount)?;
+            }
+            TransactionType::Dispute => {
+                let transaction_id = transaction.transaction_id;
+                let held = |schedule: &Schedule| {
+                    schedule
+                        .get(transaction_id)
+                        .filter(|deposit| deposit.client_id == transaction.client_id)
+                        .map(|deposit| deposit.amount)
+                };
+                let (pending, reserved) = (
+                    held(&self.availability.pending),
+                    held(&self.availability.reserves),
+                );
+                if pending.is_none() && reserved.is_none() {
+                    account.dispute(transaction_id, &self.disputable, &mut self.disputed_tx_map)?;
+                } else {
+                    // the held parts stay held by the dispute, and only the rest is held from
+                    // the available funds
+                    let amount = self.disputable.get(transaction.client_id, transaction_id)?;
+                    let held = pending
+                        .unwrap_or_default()
+                        .checked_add(reserved.unwrap_or_default())?;
+                    let account = account.account_mut();
+                    account.hold(amount.checked_sub(held)?)?;
+                    account.unreserve(reserved.unwrap_or_default())?;
+                    self.disputed_tx_map
+                        .insert(transaction_id, (transaction.client_id, amount));
+                    released.extend(self.availability.pending.take(transaction_id));
+                    released.extend(self.availability.reserves.take(transaction_id));
+                }
+            }
+            TransactionType::Resolve => {
+                account.resolve(transaction.transaction_id, &mut self.disputed_tx_map)?;
+            }
+            TransactionType::Chargeback => {
+                let removed_account = self.active_accounts.remove(&transaction.client_id).unwrap();
+                let chargeback_res =
+                    removed_account.lock(transaction.transaction_id, &mut self.disputed_tx_map);
+                match chargeback_res {
+                    (Ok(locked_account), None) => {
+                        self.active_accounts.remove(&locked_account.client_id());
+                        self.locked_accounts
+                            .insert(locked_account.client_id(), locked_account);
+                    }
+                    (Err(e), Some(removed_account)) => {
+                        self.active_accounts
+                            .insert(transaction.client_id, removed_account);
+                        return Err(e);
+                    }
+                    (Ok(_), Some(_)) | (Err(_), None) => unreachable!(),
+                }
+            }
+            TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. }
+            | TransactionType::Other { .. } => unreachable!(),
+        }
+        if let Some(journal) = &mut self.journal {
+            let amount = match transaction.tx_type {
+                TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                    amount
+                }
+                TransactionType::Dispute => self.disputed_tx_map[&transaction.transaction_id].1,
+                // an applied resolve or chargeback always settles an open dispute
+                TransactionType::Resolve | TransactionType::Chargeback => disputed_amount.unwrap(),
+                TransactionType::Flag { .. }
+                | TransactionType::Unflag { .. }
+                | TransactionType::Remap { .. }
+                | TransactionType::Other { .. } => unreachable!(),
+            };
+            for deposit in &released {
+                journal.post_release(deposit);
+            }
+            match (&transaction.tx_type, reserve) {
+                (TransactionType::Deposit { .. }, _) if self.availability.delays() => {
+                    journal.post_deposit(transaction, PositiveDecimal::default(), amount);
+                }
+                (TransactionType::Deposit { .. }, Some((reserved, _))) => {
+                    journal.post_deposit(transaction, amount.checked_sub(reserved)?, reserved);
+                }
+                _ => journal.post(transaction, amount),
+            }
+        }
+        if let TransactionType::Deposit { amount } = transaction.tx_type {
+            let mut rest = amount;
+            if let Some((reserved, period)) = reserve {
+                rest = amount.checked_sub(reserved)?;
+                self.availability
+                    .post_reserve(transaction, reserved, period);
+            }
+            self.availability.post(transaction, rest);
+        }
+        if !self.layers.0.is_empty() {
+            let account: &dyn AccountView = match self.active_accounts.get(&transaction.client_id) {
+                Some(account) => account,
+                None => &self.locked_accounts[&transaction.client_id],
+            };
+            for layer in &self.layers.0 {
+                if self.backfill {
+                    layer.after_backfill_tx(account, transaction);
+                } else {
+                    layer.after_tx(account, transaction);
+                }
+            }
+        }
+        let transaction_id = transaction.transaction_id;
+        let rule = match (&transaction.tx_type, &transaction.reason) {
+            (TransactionType::Dispute, Some(reason)) => {
+                self.dispute_rules
+                    .action(reason)
+                    .map(|action| DisputeDecision {
+                        client_id: transaction.client_id,
+                        transaction_id,
+                        reason: reason.clone(),
+                        action,
+                        amount: self.disputed_tx_map[&transaction_id].1,
+                        provenance: transaction.provenance.as_deref().cloned(),
+                    })
+            }
+            _ => None,
+        };
+        if !self.freeze_thresholds.is_empty() {
+            self.freeze_if_exceeded(transaction);
+        }
+
+        Ok(Applied::Retained(rule))
+    }
+
+    /// Makes the funds of a pending deposit, or of the `reserve` of a deposit, available,
+    /// wherever its account is
+    fn release(&mut self, deposit: &PendingDeposit, reserve: bool) -> Result<(), TxError> {
+        fn release<const IS_LOCKED: bool>(
+            account: &mut Account<IS_LOCKED>,
+            amount: PositiveDecimal,
+            reserve: bool,
+        ) -> Result<(), TxError> {
+            if reserve {
+                account.release_reserve(amount)
+            } else {
+                account.release(amount)
+            }
+        }
+
+        let (client_id, amount) = (deposit.client_id, deposit.amount);
+        if let Some(account) = self.active_accounts.get_mut(&client_id) {
+            release(account.account_mut(), amount, reserve)?;
+        } else if let Some(account) = self.locked_accounts.get_mut(&client_id) {
+            release(account.account_mut(), amount, reserve)?;
+        } else {
+            match self.archived_accounts.get_mut(&client_id) {
+                Some(ArchivedAccount::Active(account)) => {
+                    release(account.account_mut(), amount, reserve)?
+                }
+                Some(ArchivedAccount::Locked(account)) => {
+                    release(account.account_mut(), amount, reserve)?
+                }
+                None => return Err(TxError::UnknownClient),
+            }
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.post_release(deposit);
+        }
+        Ok(())
+    }
+
+    /// Makes a pending deposit available before it is due, e.g. once its funds cleared early.
+    /// Fails with [TxError::NotFound] if the deposit isn't pending.
+    pub fn release_deposit(&mut self, transaction_id: u32) -> Result<(), TxError> {
+        let deposit = self
+            .availability
+            .pending
+            .take(transaction_id)
+            .ok_or(TxError::NotFound)?;
+        if let Err(err) = self.release(&deposit, false) {
+            self.availability.pending.insert(deposit);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Makes every pending deposit available, e.g. at the end of the last run under a schedule
+    pub fn release_pending(&mut self) -> Result<(), TxError> {
+        let pending = self
+            .availability
+            .pending
+            .iter()
+            .map(|deposit| deposit.transaction_id)
+            .collect::<Vec<_>>();
+        for transaction_id in pending {
+            self.release_deposit(transaction_id)?;
+        }
+        Ok(())
+    }
+
+    /// The deposits posted as pending by the [AvailabilityDelay], ordered by transaction id
+    pub fn pending_deposits(&self) -> impl Iterator<Item = &PendingDeposit> {
+        self.availability.pending.iter()
+    }
+
+    /// The reserved parts of deposits not released yet, see [LedgerBuilder::reserves], ordered
+    /// by transaction id
+    pub fn reserves(&self) -> impl Iterator<Item = &PendingDeposit> {
+        self.availability.reserves.iter()
+    }
+
+    /// Scores the client of `transaction` after it was applied, freezing its account if it
+    /// exceeds the [FreezeThresholds] for the first time
+    fn freeze_if_exceeded(&mut self, transaction: &Transaction) {
+        let client_id = transaction.client_id;
+        let Some(freeze) = self
+            .risk
+            .entry(client_id)
+            .or_default()
+            .freeze(transaction, &self.freeze_thresholds)
+        else {
+            return;
+        };
+        info!(
+            "Froze the account of client {} for {}",
+            client_id, freeze.reason
+        );
+        let account: &dyn AccountView = match self.active_accounts.get_mut(&client_id) {
+            Some(account) => {
+                account.account_mut().flags.insert(FROZEN.to_string());
+                account
+            }
+            None => {
+                let account = self.locked_accounts.get_mut(&client_id).unwrap();
+                account.account_mut().flags.insert(FROZEN.to_string());
+                account
+            }
+        };
+        for layer in &self.layers.0 {
+            layer.after_freeze(account, &freeze);
+        }
+        self.freezes.push(freeze);
+    }
+
+    /// Rebuilds the risk features of every client from the retained transactions, for
+    /// [FreezeThresholds], after the ledger was built or restored. The clients already frozen
+    /// aren't frozen again, nor are the ones unfrozen, if they still exceed a threshold.
+    pub(crate) fn track_risk(&mut self) {
+        if self.freeze_thresholds.is_empty() {
+            return;
+        }
+        self.risk.clear();
+        for transaction in self.transactions.iter() {
+            self.risk
+                .entry(transaction.client_id)
+                .or_default()
+                .freeze(&transaction, &self.freeze_thresholds);
+        }
+    }
+
+    /// Takes the action of the [DisputeRules] rule for the reason of a dispute just opened, and
+    /// records it unless the dispute is held. A resolve the ledger rejects is quarantined, and
+    /// leaves the dispute open.
+    fn apply_dispute_rule(&mut self, decision: DisputeDecision) {
+        match decision.action {
+            DisputeAction::Hold => return,
+            DisputeAction::Resolve { max } if max.is_some_and(|max| decision.amount > max) => {
+                return
+            }
+            DisputeAction::Resolve { .. } => {
+                let resolve = Transaction {
+                    provenance: decision.provenance.clone().map(Box::new),
+                    reason: Some(decision.reason.clone()),
+                    ..Transaction::new(
+                        decision.client_id,
+                        decision.transaction_id,
+                        TransactionType::Resolve,
+                    )
+                };
+                if self.add_tx_or_quarantine(resolve).is_err() {
+                    return;
+                }
+            }
+            DisputeAction::Escalate => {
+                if let Some(account) = self.active_accounts.get_mut(&decision.client_id) {
+                    account.account_mut().flags.insert(ESCALATED.to_string());
+                }
+            }
+        }
+        self.dispute_decisions.push(decision);
+    }
+
+    /// Rejects resolves and chargebacks of a known transaction that isn't disputed, e.g. because
+    /// they arrived before the dispute, with [TxError::NotDisputed] rather than
+    /// [TxError::NotFound], and disputes of withdrawals unless [WithdrawalDisputePolicy::Hold]
+    fn check_dispute_chain(&self, transaction: &Transaction) -> Result<(), TxError> {
+        if transaction.tx_type == TransactionType::Dispute
+            && self.withdrawal_disputes == WithdrawalDisputePolicy::Reject
+            && self
+                .disputable
+                .is_withdrawal(transaction.client_id, transaction.transaction_id)
+        {
+            return Err(TxError::NotDisputable);
+        }
+        if matches!(
+            transaction.tx_type,
+            TransactionType::Resolve | TransactionType::Chargeback
+        ) && !self
+            .disputed_tx_map
+            .contains_key(&transaction.transaction_id)
+            && self.disputable.contains(transaction.transaction_id)
+        {
+            return Err(TxError::NotDisputed);
+        }
+        Ok(())
+    }
+
+    /// `true` for disputes, resolves and chargebacks of transactions that weren't applied (yet)
+    fn refers_to_unknown_tx(&self, transaction: &Transaction) -> bool {
+        matches!(
+            transaction.tx_type,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+        ) && !self
+            .disputed_tx_map
+            .contains_key(&transaction.transaction_id)
+            && !matches!(
+                self.disputable
+                    .get(transaction.client_id, transaction.transaction_id),
+                Ok(_) | Err(TxError::InsufficientPermission)
+            )
+    }
+
+    fn suspend(&mut self, transaction: Transaction) {
+        if let Some(suspense) = &mut self.suspense {
+            suspense
+                .entry(transaction.transaction_id)
+                .or_default()
+                .push(Suspended {
+                    transaction,
+                    amount: None,
+                });
+        }
+    }
+
+    /// Applies the transactions suspended until `transaction_id` arrived, in the order they
+    /// were suspended, quarantining the ones that are rejected
+    fn resume_suspended(&mut self, transaction_id: u32) {
+        let suspended = match &mut self.suspense {
+            Some(suspense) => suspense.remove(&transaction_id),
+            None => None,
+        };
+        for Suspended { transaction, .. } in suspended.into_iter().flatten() {
+            // the failure is recorded in the quarantine
+            let _ = self.add_tx_or_quarantine(transaction);
+        }
+    }
+
+    /// Rejects `transaction` if applying it would exceed one of the ledger's [Limits]
+    fn check_limits(&mut self, transaction: &Transaction) -> Result<(), TxError> {
+        if let Some(limit) = self.limit_exceeded {
+            return Err(TxError::LimitExceeded(limit));
+        }
+
+        let limits = &self.limits;
+        let exceeded = if limits.max_accounts.is_some_and(|max| {
+            !self.active_accounts.contains_key(&transaction.client_id)
+                && self.active_accounts.len() + self.locked_accounts.len() >= max
+        }) {
+            Some(Limit::Accounts)
+        } else if limits.max_open_disputes.is_some_and(|max| {
+            transaction.tx_type == TransactionType::Dispute && self.disputed_tx_map.len() >= max
+        }) {
+            Some(Limit::OpenDisputes)
+        } else if limits.policy == LimitPolicy::Abort
+            && limits
+                .max_transactions
+                .is_some_and(|max| self.transactions.len() >= max)
+        {
+            Some(Limit::Transactions)
+        } else {
+            None
+        };
+
+        match exceeded {
+            Some(limit) => {
+                if limits.policy == LimitPolicy::Abort {
+                    self.limit_exceeded = Some(limit);
+                }
+                Err(TxError::LimitExceeded(limit))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Appends an applied transaction to the log, dropping its provenance unless it's a
+    /// chargeback. With [LimitPolicy::Degrade], the oldest tenth of the log is forgotten
+    /// whenever it grows beyond its limit.
+    fn retain(&mut self, mut transaction: Transaction) {
+        self.rollups.applied(transaction.client_id);
+        self.disputable.insert(&transaction);
+        self.history.insert(&transaction, self.transactions.len());
+        self.periods.arrive(&transaction);
+        if transaction.tx_type != TransactionType::Chargeback {
+            transaction.provenance = None;
+        }
+        self.transactions.push(transaction);
+        if let (Some(max), LimitPolicy::Degrade) =
+            (self.limits.max_transactions, self.limits.policy)
+        {
+            if self.transactions.len() > max {
+                let excess = self.transactions.len() - max + max / 10;
+                let disputable = &mut self.disputable;
+                self.transactions
+                    .forget(excess, |forgotten| disputable.remove(&forgotten));
+                self.history.forget(excess);
+                self.periods.forget(excess);
+            }
+        }
+    }
+
+    /// Admin records annotate an account rather than moving funds, so unlike other transactions
+    /// they also apply to locked accounts
+    fn apply_admin_tx(&mut self, transaction: &Transaction) -> Result<(), TxError> {
+        let client_id = transaction.client_id;
+        let flags = match self.active_accounts.get_mut(&client_id) {
+            Some(account) => &mut account.account_mut().flags,
+            None => match self.locked_accounts.get_mut(&client_id) {
+                Some(account) => &mut account.account_mut().flags,
+                None => return Err(TxError::NotFound),
+            },
+        };
+        match &transaction.tx_type {
+            TransactionType::Flag { flag } => {
+                flags.insert(flag.clone());
+            }
+            TransactionType::Unflag { flag } => {
+                if !flags.remove(flag) {
+                    return Err(TxError::NotFound);
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    /// Applies a transaction of a type this crate doesn't know with the
+    /// [TransactionHandler] registered for it. Its operations are posted to the journal, but
+    /// like admin records it can't be disputed.
+    fn apply_other_tx(&mut self, transaction: &Transaction) -> Result<(), TxError> {
+        let TransactionType::Other { name, amount } = &transaction.tx_type else {
+            unreachable!()
+        };
+        let handler = self.handlers.get(name)?;
+        let account = self
+            .layers
+            .before_tx(&mut self.active_accounts, transaction)?;
+        let mut handler_account = HandlerAccount::new(account.account());
+        handler.apply(&mut handler_account, transaction, *amount)?;
+        let ops = handler_account.commit(account.account_mut());
+        for layer in &self.layers.0 {
+            if self.backfill {
+                layer.after_backfill_tx(account, transaction);
+            } else {
+                layer.after_tx(account, transaction);
+            }
+        }
+        if let Some(journal) = &mut self.journal {
+            for op in ops {
+                journal.post_op(transaction, op);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn active_accounts(&self) -> &Map<u16, A> {
+        &self.active_accounts
+    }
+
+    pub fn locked_accounts(&self) -> &Map<u16, A::Locked> {
+        &self.locked_accounts
+    }
+
+    /// The account of `client_id`, whether active or locked
+    pub fn account(&self, client_id: u16) -> Option<&dyn AccountView> {
+        account_view(&self.active_accounts, &self.locked_accounts, client_id)
+    }
+
+    /// The client id of the external client id `client_ref`, e.g. the UUID of a customer in an
+    /// upstream system, given one by the [ClientIdResolver] if it has none yet. Records read
+    /// with a `client_ref` are resolved as they're processed; this resolves the references of
+    /// transactions applied with [Ledger::add_tx].
+    pub fn resolve_client_ref(&mut self, client_ref: &str) -> Result<u16, TxError> {
+        self.aliases.resolve(client_ref, |client_id| {
+            self.clients.denies(client_id)
+                || self.archived_accounts.contains_key(&client_id)
+                || account_view(&self.active_accounts, &self.locked_accounts, client_id).is_some()
+        })
+    }
+
+    /// The client ids of the external client ids this ledger resolved, see
+    /// [LedgerBuilder::client_id_resolver]
+    pub fn client_aliases(&self) -> &ClientAliases {
+        &self.aliases
+    }
+
+    /// Remaps the client `from` to `to`, e.g. when an upstream system re-keys a customer or
+    /// duplicate customer records are consolidated. The account of `from` is merged into the
+    /// account of `to`, opened if it has none: their balances and flags are added up, and the
+    /// retained transactions, open disputes, pending deposits and reserves of `from` become
+    /// `to`'s, to be disputed, resolved and charged back as `to`'s. Later records of `from` open
+    /// a new account.
+    ///
+    /// Fails without changing anything with [TxError::UnknownClient] if `from` has no account,
+    /// with [TxError::LockedAccount] or [TxError::ArchivedAccount] if either account is locked
+    /// or archived, with [TxError::ExcludedClient] if `to` isn't admitted, and with
+    /// [TxError::RemapConflict] if `from` and `to` are the same client or both have a retained
+    /// deposit or withdrawal with the same id.
+    pub fn remap_client(&mut self, from: u16, to: u16) -> Result<(), TxError> {
+        if from == to || self.disputable.conflicts(from, to) {
+            return Err(TxError::RemapConflict);
+        }
+        if [from, to]
+            .iter()
+            .any(|client_id| self.archived_accounts.contains_key(client_id))
+        {
+            return Err(TxError::ArchivedAccount);
+        }
+        if [from, to]
+            .iter()
+            .any(|client_id| self.locked_accounts.contains_key(client_id))
+        {
+            return Err(TxError::LockedAccount);
+        }
+        if !self.clients.admits(to) {
+            return Err(TxError::ExcludedClient);
+        }
+        if !self.active_accounts.contains_key(&from) {
+            return Err(TxError::UnknownClient);
+        }
+        // merged into a copy, so an overflow leaves both accounts as they were
+        let mut merged = self
+            .active_accounts
+            .get(&to)
+            .cloned()
+            .unwrap_or_else(|| A::wrap(Account::new(to)));
+        merged
+            .account_mut()
+            .merge(self.active_accounts[&from].account().clone())?;
+        self.active_accounts.remove(&from);
+        self.active_accounts.insert(to, merged);
+
+        self.transactions.remap(from, to);
+        self.disputable.remap(from, to);
+        self.history.remap(from, to);
+        for (client_id, _) in self.disputed_tx_map.values_mut() {
+            if *client_id == from {
+                *client_id = to;
+            }
+        }
+        self.availability.remap(from, to);
+        self.aliases.remap(from, to);
+        for parked in self.suspense.iter_mut().flat_map(Map::values_mut).flatten() {
+            if parked.transaction.client_id == from {
+                parked.transaction.client_id = to;
+            }
+        }
+        self.rollups.applied(to);
+        self.track_risk();
+        info!("Remapped client {} to {}", from, to);
+        Ok(())
+    }
+
+    /// Moves the account of `client_id`, active or locked, out of the ledger, e.g. for a
+    /// churned customer. It's left out of [Ledger::accounts] and the reports, and its client's
+    /// transactions are rejected with [TxError::ArchivedAccount], but its balances, flags and
+    /// transactions are kept, in snapshots too, until [Ledger::restore_account] brings it
+    /// back. Fails with [TxError::UnknownClient] if the client has no account.
+    pub fn archive_account(&mut self, client_id: u16) -> Result<(), TxError> {
+        let archived = if let Some(account) = self.active_accounts.remove(&client_id) {
+            ArchivedAccount::Active(account)
+        } else if let Some(account) = self.locked_accounts.remove(&client_id) {
+            ArchivedAccount::Locked(account)
+        } else {
+            return Err(TxError::UnknownClient);
+        };
+        self.archived_accounts.insert(client_id, archived);
+        Ok(())
+    }
+
+    /// Moves an account archived by [Ledger::archive_account] back into the ledger, active or
+    /// locked as it was. Fails with [TxError::UnknownClient] if it isn't archived.
+    pub fn restore_account(&mut self, client_id: u16) -> Result<(), TxError> {
+        match self.archived_accounts.remove(&client_id) {
+            Some(ArchivedAccount::Active(account)) => {
+                self.active_accounts.insert(client_id, account);
+            }
+            Some(ArchivedAccount::Locked(account)) => {
+                self.locked_accounts.insert(client_id, account);
+            }
+            None => return Err(TxError::UnknownClient),
+        }
+        Ok(())
+    }
+
+    /// The accounts archived by [Ledger::archive_account]
+    pub fn archived_accounts(&self) -> &Map<u16, ArchivedAccount<A>> {
+        &self.archived_accounts
+    }
+
+    /// Closes the open statement period as `label`, e.g. `2024-01` at a monthly close: the
+    /// transactions applied since the previous close are frozen into a checksummed
+    /// [PeriodArchive], the [Ledger::period_stats] start over, and records timestamped at or
+    /// before the latest timestamp of the period are late arrivals from now on, rejected with
+    /// [TxError::ClosedPeriod] unless a [LateArrivalPolicy] accepts them.
+    /// Disputes, resolves and chargebacks of the period's transactions still apply, to the
+    /// next period.
+    #[cfg(feature = "checksum")]
+    pub fn close_period(&mut self, label: impl Into<String>) -> &PeriodArchive {
+        self.periods.close(label.into(), &self.transactions)
+    }
+
+    /// The periods closed by [Ledger::close_period] since the ledger was built or restored, in
+    /// the order they were closed
+    pub fn closed_periods(&self) -> &[PeriodArchive] {
+        self.periods.closed()
+    }
+
+    /// The records timestamped within a closed period and what the [LateArrivalPolicy] did with
+    /// them, in the order they arrived, since the ledger was built or restored
+    pub fn late_arrivals(&self) -> &[LateArrival] {
+        self.periods.late_arrivals()
+    }
+
+    /// What was applied during the open period, see [Ledger::close_period]. Closing states
+    /// only keep deposits and withdrawals, so the disputes and chargebacks of earlier runs
+    /// don't count.
+    pub fn period_stats(&self) -> PeriodStats {
+        self.periods.stats(&self.transactions)
+    }
+
+    /// Every account of this ledger, active ones first, without their map keys
+    pub fn accounts(&self) -> impl Iterator<Item = &dyn AccountView> {
+        self.active_accounts
+            .values()
+            .map(|account| account as &dyn AccountView)
+            .chain(
+                self.locked_accounts
+                    .values()
+                    .map(|account| account as &dyn AccountView),
+            )
+    }
+
+    /// Writes the balance of every account, active or locked, as one
+    /// `client,available,held,total,locked` row per client ordered by client id, preceded by a
+    /// header row, like [write_accounts_csv](crate::report::write_accounts_csv)
+    #[cfg(feature = "csv")]
+    pub fn write_accounts<W: io::Write>(&self, writer: W) -> Result<(), TxError> {
+        self.write_accounts_ordered(AccountColumns::V1, AccountOrder::ClientId, writer)
+    }
+
+    /// Writes the accounts like [Ledger::write_accounts], with the given `columns` and in the
+    /// given `order`. The accounts are streamed straight from the ledger's maps rather than
+    /// collected and sorted first, so writing a large ledger takes little memory.
+    #[cfg(feature = "csv")]
+    pub fn write_accounts_ordered<W: io::Write>(
+        &self,
+        columns: AccountColumns,
+        order: AccountOrder,
+        writer: W,
+    ) -> Result<(), TxError> {
+        report::write_ledger_accounts_csv(self, columns, order, writer)
+    }
+
+    pub fn transactions(&self) -> &TransactionLog {
+        &self.transactions
+    }
+
+    /// The retained transactions of `client_id`, in the order they were applied, e.g. to print
+    /// the statement of a single client. Found through an index of the log by client, so this
+    /// doesn't go through the transactions of other clients.
+    pub fn transactions_for_client(
+        &self,
+        client_id: u16,
+    ) -> impl Iterator<Item = Transaction> + '_ {
+        self.history
+            .positions(client_id)
+            .iter()
+            .filter_map(|&position| self.transactions.get(position))
+    }
+
+    /// The [RiskScore] of every client with a transaction in this ledger, ordered by client id.
+    /// Closing states only keep deposits and withdrawals, so the disputes and chargebacks of
+    /// earlier runs don't count.
+    pub fn risk_scores(&self) -> Vec<RiskScore> {
+        risk::risk_scores(self.transactions.iter())
+    }
+
+    /// Aggregates every chargeback applied to this ledger, so the losses can be booked
+    pub fn chargeback_report(&self) -> Result<ChargebackReport, TxError> {
+        ChargebackReport::from_transactions(self.transactions.iter())
+    }
+
+    /// The net amount owed to or from each client for the transactions of this run, except
+    /// the [test clients](LedgerBuilder::test_clients)
+    pub fn settlement(&self) -> Result<Vec<Settlement>, TxError> {
+        settlement::net_settlement(self.production_transactions())
+    }
+
+    /// The net settlement of the [test clients](LedgerBuilder::test_clients) alone
+    pub fn test_settlement(&self) -> Result<Vec<Settlement>, TxError> {
+        settlement::net_settlement(
+            self.transactions
+                .iter()
+                .filter(|transaction| self.test_clients.contains(transaction.client_id)),
+        )
+    }
+
+    /// The number of transactions of this run of [test clients](LedgerBuilder::test_clients)
+    pub fn test_transactions(&self) -> usize {
+        self.transactions.len() - self.production_transactions().count()
+    }
+
+    /// The transactions of this run, except those of test clients
+    fn production_transactions(&self) -> impl Iterator<Item = Transaction> + '_ {
+        self.transactions
+            .iter()
+            .filter(|transaction| !self.test_clients.contains(transaction.client_id))
+    }
+
+    /// The counts and totals of the transactions of this run grouped by `by`, except those of
+    /// [test clients](LedgerBuilder::test_clients)
+    pub fn aggregate(&self, by: AggregationKey) -> Result<Vec<Aggregate>, TxError> {
+        aggregate::aggregate(self.production_transactions(), by)
+    }
+
+    /// The balance at the end of each day of every client with transactions that day, ordered
+    /// by day and then by client id, including the balances of the current day so far. A day
+    /// ends when a transaction with a timestamp of a later day arrives; transactions with an
+    /// earlier timestamp or none count towards the current day.
+    pub fn daily_balances(&self) -> Vec<DailyBalance> {
+        self.rollups.balances(|client_id| self.account(client_id))
+    }
+
+    /// The number and amount of each client's transactions by category, see
+    /// [Tagger](crate::tags::Tagger), except those of
+    /// [test clients](LedgerBuilder::test_clients)
+    pub fn category_totals(&self) -> Result<Vec<CategoryTotal>, TxError> {
+        tags::category_totals(self.production_transactions())
+    }
+
+    /// The open disputes contributing to a client's held balance as `(transaction_id, amount)`
+    /// pairs, ordered by transaction id
+    pub fn held_breakdown(&self, client_id: u16) -> Vec<(u32, PositiveDecimal)> {
+        let mut breakdown = self
+            .disputed_tx_map
+            .iter()
+            .filter(|(_, &(owner, _))| owner == client_id)
+            .map(|(&tx_id, &(_, amount))| (tx_id, amount))
+            .collect::<Vec<_>>();
+        breakdown.sort_unstable();
+        breakdown
+    }
+}
+
+fn account_view<'a, A: LedgerAccount>(
+    active: &'a Map<u16, A>,
+    locked: &'a Map<u16, A::Locked>,
+    client_id: u16,
+) -> Option<&'a dyn AccountView> {
+    match active.get(&client_id) {
+        Some(account) => Some(account),
+        None => locked
+            .get(&client_id)
+            .map(|account| account as &dyn AccountView),
+    }
+}
+
+/// Accounts and disputes are printed in key order so that two equal ledgers always have the
+/// same `Debug` output, regardless of map iteration order
+impl<A: LedgerAccount> fmt::Debug for Ledger<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ledger")
+            .field(
+                "active_accounts",
+                &self.active_accounts.iter().collect::<BTreeMap<_, _>>(),
+            )
+            .field(
+                "locked_accounts",
+                &self.locked_accounts.iter().collect::<BTreeMap<_, _>>(),
+            )
+            .field("transactions", &self.transactions)
+            .field(
+                "disputed_tx_map",
+                &self.disputed_tx_map.iter().collect::<BTreeMap<_, _>>(),
+            )
+            .field("quarantine", &self.quarantine)
+            .field("quarantine_dropped", &self.quarantine_dropped)
+            .field("incomplete", &self.incomplete)
+            .field("missing_amounts", &self.missing_amounts)
+            .field("disabled", &self.disabled)
+            .field("aliases", &self.aliases)
+            .field("limits", &self.limits)
+            .field("limit_exceeded", &self.limit_exceeded)
+            .field("tx_ids", &self.tx_ids)
+            .field("journal", &self.journal)
+            .field("backfill", &self.backfill)
+            .field("withdrawal_disputes", &self.withdrawal_disputes)
+            .field("unknown_clients", &self.unknown_clients)
+            .field("missing_deposit_amounts", &self.missing_deposit_amounts)
+            .field(
+                "missing_withdrawal_amounts",
+                &self.missing_withdrawal_amounts,
+            )
+            .field("rounding", &self.rounding)
+            .field("rollups", &self.rollups)
+            .field("availability", &self.availability)
+            .field("periods", &self.periods)
+            .field(
+                "suspense",
+                &self
+                    .suspense
+                    .as_ref()
+                    .map(|suspense| suspense.iter().collect::<BTreeMap<_, _>>()),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::risk::FreezeReason;
+    use crate::scenario::Scenario;
+    use rust_decimal::prelude::*;
+
+    #[test]
+    fn test_ledger() {
+        let mut ledger = Ledger::default();
+        let zero = PositiveDecimal::try_from(Decimal::ZERO).unwrap();
+        let amount = PositiveDecimal::try_from(10000.1000).unwrap();
+        let client_id = 10;
+        let tx_id = 1000;
+        let locked_account: Account<true> = Account::<true>::from(Account::new(1));
+        ledger.locked_accounts.insert(client_id, locked_account);
+
+        let tx = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
+        let res = ledger.add_tx(tx);
+        assert!(res.is_err());
+
+        let smaller_amount = PositiveDecimal::try_from(900.1000).unwrap();
+        let huge_amount = PositiveDecimal::try_from(9000000000.1000).unwrap();
+        // NOTE demonstation of weird specifications of behavior
+        // For a dispute, the instructions say:
+        // This means that the clients available funds should decrease by the amount disputed,
+        // their held funds should increase by the amount disputed, while their total funds should remain the same.
+        //
+        // However, if I'm disputing a withdrawal, my available funds should not decrease
+        let scenario = Scenario::new()
+            .client(client_id)
+            .deposit(amount)
+            .withdraw(smaller_amount)
+            .dispute_last()
+            .resolve_last()
+            .withdraw(huge_amount)
+            .withdraw(smaller_amount)
+            .dispute_last()
+            .chargeback_last();
+        let huge_withdrawal = &scenario.transactions()[4];
+
+        let mut ledger = Ledger::default();
+        let mut log = Vec::new();
+        for tx in scenario.transactions() {
+            if tx == huge_withdrawal {
+                assert!(ledger.add_tx(tx.clone()).is_err());
+            } else {
+                assert!(ledger.add_tx(tx.clone()).is_ok());
+                log.push(tx.clone());
+            }
+            assert_eq!(ledger.transactions().to_vec(), log);
+        }
+        scenario.verify(&ledger).unwrap();
+        assert!(!ledger.active_accounts().contains_key(&client_id));
+        let balance = &ledger.locked_accounts().get(&client_id).unwrap().balance;
+        let available = amount
+            .checked_sub(smaller_amount)
+            .unwrap()
+            .checked_sub(smaller_amount)
+            .unwrap()
+            .checked_sub(smaller_amount)
+            .unwrap();
+        assert_eq!(balance.available(), &available);
+        assert_eq!(balance.held(), &zero);
+
+        // the balances between steps match those of the scenario cut short
+        let resolved = Scenario::new()
+            .client(client_id)
+            .deposit(amount)
+            .withdraw(smaller_amount)
+            .dispute_last();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(resolved.transactions().to_vec());
+        resolved.verify(&ledger).unwrap();
+        let resolved = resolved.resolve_last();
+        ledger.add_tx(resolved.transactions()[3].clone()).unwrap();
+        resolved.verify(&ledger).unwrap();
+    }
+
+    #[test]
+    fn test_from_opening_balances() {
+        let available = Decimal::from_f64(100.5).unwrap();
+        let held = Decimal::ZERO;
+        let balances = vec![
+            (1, available, held, false),
+            (2, available, Decimal::ZERO, true),
+        ];
+        let mut ledger = Ledger::from_opening_balances(balances).unwrap();
+        let account = ledger.active_accounts().get(&1).unwrap();
+        assert_eq!(
+            account.balance.available(),
+            &PositiveDecimal::try_from(available).unwrap()
+        );
+        assert!(ledger.locked_accounts().contains_key(&2));
+
+        // transactions apply on top of the opening balances
+        let amount = PositiveDecimal::try_from(0.5).unwrap();
+        let tx = Transaction::new(1, 1, TransactionType::Withdrawal { amount });
+        assert!(ledger.add_tx(tx).is_ok());
+        let balance = &ledger.active_accounts().get(&1).unwrap().balance;
+        assert_eq!(
+            balance.available(),
+            &PositiveDecimal::try_from(100.0).unwrap()
+        );
+        let tx = Transaction::new(2, 2, TransactionType::Deposit { amount });
+        assert!(ledger.add_tx(tx).is_err());
+
+        // negative balances, held funds without a dispute and duplicate clients are rejected
+        let res = Ledger::from_opening_balances(vec![(1, -available, held, false)]);
+        assert!(res.is_err());
+        assert!(matches!(
+            Ledger::from_opening_balances(vec![(1, available, Decimal::ONE, false)]),
+            Err(TxError::HeldWithoutDispute)
+        ));
+        let res = Ledger::from_opening_balances(vec![
+            (1, available, held, false),
+            (1, available, held, true),
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_clone_and_compare() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(
+            (1..=100)
+                .map(|id| Transaction::new(id, id as u32, TransactionType::Deposit { amount })),
+        );
+
+        let mut fork = ledger.clone();
+        assert_eq!(fork, ledger);
+        let tx = Transaction::new(1, 101, TransactionType::Withdrawal { amount });
+        fork.add_tx(tx).unwrap();
+        assert_ne!(fork, ledger);
+
+        // the same state reached through a different insertion order prints identically
+        let mut reversed = Ledger::default();
+        let mut client_ids = ledger.active_accounts.keys().copied().collect::<Vec<_>>();
+        client_ids.sort_unstable_by(|a, b| b.cmp(a));
+        for client_id in client_ids {
+            let account = ledger.active_accounts[&client_id].clone();
+            reversed.active_accounts.insert(client_id, account);
+        }
+        reversed.transactions = ledger.transactions.clone();
+        let log = ledger.transactions.to_vec();
+        reversed.disputable = log.iter().collect();
+        reversed.history = log.iter().collect();
+        assert_eq!(reversed, ledger);
+        assert_eq!(format!("{:?}", reversed), format!("{:?}", ledger));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_deterministic_hash_state() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let run = || {
+            let mut ledger = Ledger::default().with_hash_state(HashState::Deterministic);
+            ledger
+                .process_transactions((1..=100).map(|id| {
+                    Transaction::new(id, id as u32, TransactionType::Deposit { amount })
+                }));
+            ledger.active_accounts.keys().copied().collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+
+        // rehashing keeps the state of the ledger
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ]);
+        let rehashed = ledger.clone().with_hash_state(HashState::Deterministic);
+        assert_eq!(rehashed, ledger);
+        assert_eq!(rehashed.held_breakdown(1), vec![(1, amount)]);
+    }
+
+    #[test]
+    fn test_simulate() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger
+            .add_tx(Transaction::new(1, 1, TransactionType::Deposit { amount }))
+            .unwrap();
+        let original = ledger.clone();
+
+        let batch = vec![
+            Transaction::new(1, 2, TransactionType::Withdrawal { amount }),
+            Transaction::new(1, 3, TransactionType::Withdrawal { amount }),
+            Transaction::new(2, 4, TransactionType::Deposit { amount }),
+        ];
+        let result = ledger.simulate(batch.clone());
+        assert_eq!(ledger, original);
+        assert!(!result.is_clean());
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].0, batch[1]);
+        assert!(matches!(result.rejected[0].1, TxError::InsufficientFunds));
+        assert_eq!(result.ledger.transactions().len(), 3);
+        assert_eq!(result.ledger.active_accounts().len(), 2);
+
+        // committing the batch for real yields the simulated state
+        ledger.process_transactions(batch);
+        assert_eq!(ledger, result.ledger);
+    }
+
+    #[test]
+    fn test_disabled_transaction_types() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .opening_balances(vec![(1, Decimal::from(10), Decimal::ZERO, false)])
+            .disable(TransactionRecordType::Chargeback)
+            .build()
+            .unwrap();
+        assert_eq!(ledger.active_accounts()[&1].available(), amount);
+
+        let chargeback = Transaction::new(1, 2, TransactionType::Chargeback);
+        ledger.process_transactions(vec![
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            chargeback.clone(),
+        ]);
+        assert_eq!(
+            ledger.quarantined(),
+            &[Rejection::new(
+                chargeback,
+                &TxError::DisabledTransactionType
+            )]
+        );
+        assert!(ledger.locked_accounts().is_empty());
+        assert_eq!(ledger.held_breakdown(1), vec![(2, amount)]);
+
+        // other types are still processed
+        let resolve = Transaction::new(1, 2, TransactionType::Resolve);
+        assert!(ledger.add_tx(resolve).is_ok());
+    }
+
+    #[test]
+    fn test_excluded_clients() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .opening_balances(vec![(2, Decimal::from(10), Decimal::ZERO, false)])
+            .clients(ClientFilter::default().deny([2]))
+            .build()
+            .unwrap();
+
+        let withdrawal = Transaction::new(2, 2, TransactionType::Withdrawal { amount });
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            withdrawal.clone(),
+        ]);
+        assert_eq!(
+            ledger.quarantined(),
+            &[Rejection::new(withdrawal, &TxError::ExcludedClient)]
+        );
+        // the denied client keeps its opening balance
+        assert_eq!(ledger.active_accounts()[&2].available(), amount);
+        assert_eq!(ledger.active_accounts()[&1].available(), amount);
+    }
+
+    #[test]
+    fn test_archive_account() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Dispute),
+            Transaction::new(2, 2, TransactionType::Chargeback),
+        ]);
+        assert!(matches!(
+            ledger.archive_account(3),
+            Err(TxError::UnknownClient)
+        ));
+        ledger.archive_account(1).unwrap();
+        ledger.archive_account(2).unwrap();
+        assert_eq!(ledger.accounts().count(), 0);
+        assert_eq!(ledger.archived_accounts().len(), 2);
+        assert!(ledger.archived_accounts()[&2].is_locked());
+
+        let deposit = Transaction::new(1, 3, TransactionType::Deposit { amount });
+        assert!(matches!(
+            ledger.add_tx(deposit.clone()),
+            Err(TxError::ArchivedAccount)
+        ));
+
+        ledger.restore_account(1).unwrap();
+        ledger.restore_account(2).unwrap();
+        assert!(matches!(
+            ledger.restore_account(1),
+            Err(TxError::UnknownClient)
+        ));
+        assert!(ledger.archived_accounts().is_empty());
+        assert!(ledger.locked_accounts().contains_key(&2));
+        ledger.add_tx(deposit).unwrap();
+        assert_eq!(
+            ledger.active_accounts()[&1].available(),
+            PositiveDecimal::try_from(20.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_remap_client() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Flag {
+                    flag: "vip".to_owned(),
+                },
+            ),
+            Transaction::new(2, 3, TransactionType::Deposit { amount }),
+            Transaction::new(3, 3, TransactionType::Deposit { amount }),
+        ]);
+        assert!(matches!(
+            ledger.remap_client(1, 1),
+            Err(TxError::RemapConflict)
+        ));
+        // both have a deposit 3
+        assert!(matches!(
+            ledger.remap_client(3, 2),
+            Err(TxError::RemapConflict)
+        ));
+        assert!(matches!(
+            ledger.remap_client(4, 2),
+            Err(TxError::UnknownClient)
+        ));
+
+        ledger.aliases.insert("cus_8f2a".into(), 1);
+        ledger
+            .add_tx(Transaction::new(1, 4, TransactionType::Remap { to: 2 }))
+            .unwrap();
+        assert!(!ledger.active_accounts().contains_key(&1));
+        assert_eq!(ledger.resolve_client_ref("cus_8f2a").unwrap(), 2);
+        let account = &ledger.active_accounts()[&2];
+        assert_eq!((account.available(), account.held()), (amount, amount));
+        assert!(account.flags().contains("vip"));
+
+        // the dispute of client 1's deposit is now client 2's to settle
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Resolve)),
+            Err(TxError::InsufficientPermission)
+        ));
+        ledger
+            .add_tx(Transaction::new(2, 1, TransactionType::Resolve))
+            .unwrap();
+        assert_eq!(
+            ledger.active_accounts()[&2].available(),
+            PositiveDecimal::try_from(20.0).unwrap()
+        );
+        assert_eq!(crate::consistency::check_balances(&ledger).unwrap(), []);
+
+        // client 2's history takes in client 1's, the remap record stays client 1's
+        let history = |client_id| {
+            ledger
+                .transactions_for_client(client_id)
+                .map(|transaction| transaction.transaction_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(history(2), [1, 1, 2, 3, 1]);
+        assert_eq!(history(1), [4]);
+        assert_eq!(history(3), [3]);
+    }
+
+    #[test]
+    fn test_dispute_rules() {
+        let small = PositiveDecimal::try_from(10.0).unwrap();
+        let large = PositiveDecimal::try_from(100.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .dispute_rules(
+                DisputeRules::default()
+                    .rule("duplicate", DisputeAction::Resolve { max: Some(small) })
+                    .rule("fraud", DisputeAction::Escalate),
+            )
+            .build()
+            .unwrap();
+        let dispute = |client_id, transaction_id, reason: Option<&str>| Transaction {
+            reason: reason.map(String::from),
+            ..Transaction::new(client_id, transaction_id, TransactionType::Dispute)
+        };
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount: small }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount: large }),
+            Transaction::new(2, 3, TransactionType::Deposit { amount: small }),
+            Transaction::new(3, 4, TransactionType::Deposit { amount: small }),
+            dispute(1, 1, Some("duplicate")),
+            dispute(1, 2, Some("duplicate")),
+            dispute(2, 3, Some("fraud")),
+            dispute(3, 4, None),
+        ]);
+        assert!(ledger.quarantined().is_empty());
+
+        // the small duplicate was resolved right away, the large one is held
+        assert!(!ledger.disputed_tx_map.contains_key(&1));
+        assert_eq!(ledger.active_accounts()[&1].held(), large);
+        assert!(ledger.transactions().iter().any(|transaction| {
+            transaction.tx_type == TransactionType::Resolve
+                && transaction.reason.as_deref() == Some("duplicate")
+        }));
+        // fraud is held and escalated, disputes without a reason held
+        assert_eq!(ledger.active_accounts()[&2].held(), small);
+        assert!(ledger.active_accounts()[&2].flags().contains(ESCALATED));
+        assert_eq!(ledger.active_accounts()[&3].held(), small);
+        assert!(ledger.active_accounts()[&3].flags().is_empty());
+
+        let decisions = ledger
+            .dispute_decisions()
+            .iter()
+            .map(|decision| (decision.transaction_id, decision.action.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(decisions, [(1, "resolve"), (3, "escalate")]);
+    }
+
+    #[test]
+    fn test_freeze_thresholds() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .freeze_thresholds(FreezeThresholds {
+                max_risk_score: Some(20),
+                max_chargebacks: None,
+            })
+            .build()
+            .unwrap();
+        let withdrawal = Transaction::new(1, 4, TransactionType::Withdrawal { amount });
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(2, 3, TransactionType::Deposit { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            withdrawal.clone(),
+            Transaction::new(1, 1, TransactionType::Resolve),
+        ]);
+        // one dispute in two deposits scores 25
+        assert_eq!(
+            ledger.quarantined(),
+            &[Rejection::new(withdrawal.clone(), &TxError::FrozenAccount)]
+        );
+        assert_eq!(ledger.freezes().len(), 1);
+        let freeze = &ledger.freezes()[0];
+        assert_eq!(
+            (freeze.client_id, freeze.transaction_id, freeze.reason),
+            (1, 1, FreezeReason::RiskScore(20))
+        );
+        assert_eq!(freeze.score.score, 25);
+        assert!(ledger.active_accounts()[&1].flags().contains(FROZEN));
+        assert!(ledger.active_accounts()[&2].flags().is_empty());
+
+        // unfreezing lets the account withdraw again, and it isn't frozen again
+        ledger
+            .add_tx(Transaction::new(
+                1,
+                5,
+                TransactionType::Unflag {
+                    flag: FROZEN.to_string(),
+                },
+            ))
+            .unwrap();
+        ledger.add_tx(withdrawal).unwrap();
+        assert_eq!(ledger.freezes().len(), 1);
+        assert_eq!(ledger.active_accounts()[&1].available(), amount);
+    }
+
+    #[test]
+    fn test_availability() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let deposit = |client_id, transaction_id, timestamp| Transaction {
+            timestamp: Some(timestamp),
+            ..Transaction::new(
+                client_id,
+                transaction_id,
+                TransactionType::Deposit { amount },
+            )
+        };
+        let mut ledger = Ledger::builder()
+            .availability(AvailabilityDelay::Seconds(100))
+            .double_entry()
+            .build()
+            .unwrap();
+        let withdrawal = Transaction::new(1, 4, TransactionType::Withdrawal { amount });
+        ledger.process_transactions(vec![
+            deposit(1, 1, 0),
+            deposit(1, 2, 50),
+            deposit(2, 3, 60),
+            withdrawal.clone(),
+            Transaction::new(2, 3, TransactionType::Dispute),
+        ]);
+        assert_eq!(
+            ledger.quarantined(),
+            &[Rejection::new(
+                withdrawal.clone(),
+                &TxError::InsufficientFunds
+            )]
+        );
+        assert_eq!(
+            ledger.active_accounts()[&1].held(),
+            amount.checked_add(amount).unwrap()
+        );
+        // the disputed deposit is no longer pending, its funds are held by the dispute
+        assert_eq!(
+            ledger
+                .pending_deposits()
+                .map(|deposit| deposit.transaction_id)
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(ledger.held_breakdown(2), [(3, amount)]);
+
+        // the first deposit is due at 100, before the withdrawal is applied
+        ledger.add_tx(deposit(2, 5, 100)).unwrap();
+        ledger.add_tx(withdrawal).unwrap();
+        assert_eq!(ledger.active_accounts()[&1].held(), amount);
+        ledger.release_deposit(2).unwrap();
+        assert!(matches!(ledger.release_deposit(2), Err(TxError::NotFound)));
+        assert_eq!(ledger.active_accounts()[&1].available(), amount);
+        ledger
+            .add_tx(Transaction::new(2, 3, TransactionType::Resolve))
+            .unwrap();
+        ledger.release_pending().unwrap();
+        assert_eq!(ledger.pending_deposits().count(), 0);
+        assert_eq!(
+            ledger.active_accounts()[&2].available(),
+            amount.checked_add(amount).unwrap()
+        );
+
+        assert_eq!(crate::consistency::check_balances(&ledger).unwrap(), []);
+        // nothing is held once every deposit was released and the dispute resolved
+        let trial_balance = ledger.journal().unwrap().trial_balance().unwrap();
+        let held = &trial_balance.accounts[2];
+        assert_eq!(held.account, crate::journal::SystemAccount::HeldPayables);
+        assert_eq!(held.debits, held.credits);
+    }
+
+    #[test]
+    fn test_reserves() {
+        use crate::reserve::{RollingReserve, RollingReserves};
+
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let reserved = PositiveDecimal::try_from(1.0).unwrap();
+        let deposit = |client_id, transaction_id, timestamp| Transaction {
+            timestamp: Some(timestamp),
+            ..Transaction::new(
+                client_id,
+                transaction_id,
+                TransactionType::Deposit { amount },
+            )
+        };
+        let mut ledger = Ledger::builder()
+            .reserves(RollingReserves::default().client(
+                1,
+                RollingReserve {
+                    rate: Decimal::new(1, 1),
+                    period: AvailabilityDelay::Seconds(100),
+                },
+            ))
+            .double_entry()
+            .build()
+            .unwrap();
+        ledger.process_transactions(vec![
+            deposit(1, 1, 0),
+            deposit(1, 2, 50),
+            deposit(2, 3, 60),
+            Transaction::new(1, 2, TransactionType::Dispute),
+        ]);
+        let account = &ledger.active_accounts()[&1];
+        assert_eq!(account.available(), PositiveDecimal::try_from(9.0).unwrap());
+        assert_eq!(account.held(), amount.checked_add(reserved).unwrap());
+        assert_eq!(account.reserved(), reserved);
+        assert_eq!(ledger.active_accounts()[&2].available(), amount);
+        // the reserve of the disputed deposit is held by the dispute instead
+        assert_eq!(
+            ledger
+                .reserves()
+                .map(|reserve| (reserve.transaction_id, reserve.amount))
+                .collect::<Vec<_>>(),
+            [(1, reserved)]
+        );
+        assert_eq!(ledger.held_breakdown(1), [(2, amount)]);
+
+        // the reserve of the first deposit is due at 100
+        ledger.add_tx(deposit(2, 4, 100)).unwrap();
+        let account = &ledger.active_accounts()[&1];
+        assert_eq!(account.available(), amount);
+        assert_eq!(account.held(), amount);
+        assert_eq!(account.reserved(), PositiveDecimal::default());
+        assert_eq!(ledger.reserves().count(), 0);
+        ledger
+            .add_tx(Transaction::new(1, 2, TransactionType::Resolve))
+            .unwrap();
+        assert_eq!(
+            ledger.active_accounts()[&1].available(),
+            amount.checked_add(amount).unwrap()
+        );
+
+        assert_eq!(crate::consistency::check_balances(&ledger).unwrap(), []);
+        let trial_balance = ledger.journal().unwrap().trial_balance().unwrap();
+        let held = &trial_balance.accounts[2];
+        assert_eq!(held.account, crate::journal::SystemAccount::HeldPayables);
+        assert_eq!(held.debits, held.credits);
+    }
+
+    #[test]
+    fn test_reserved_deposit_overflow() {
+        use crate::reserve::{RollingReserve, RollingReserves};
+
+        #[cfg(not(feature = "fixed-point"))]
+        let max = Decimal::MAX;
+        #[cfg(feature = "fixed-point")]
+        let max = Decimal::from_i128_with_scale(i128::from(u64::MAX), 4);
+        // the reserve of a second deposit fits, but its rest overflows the available funds
+        let amount = PositiveDecimal::try_from(max / Decimal::from(5) * Decimal::from(3)).unwrap();
+        let mut ledger = Ledger::builder()
+            .reserves(RollingReserves::default().client(
+                1,
+                RollingReserve {
+                    rate: Decimal::new(1, 1),
+                    period: AvailabilityDelay::Seconds(100),
+                },
+            ))
+            .build()
+            .unwrap();
+        let deposit = |transaction_id| Transaction {
+            timestamp: Some(0),
+            ..Transaction::new(1, transaction_id, TransactionType::Deposit { amount })
+        };
+        ledger.add_tx(deposit(1)).unwrap();
+        let account = &ledger.active_accounts()[&1];
+        let (available, held, reserved) = (account.available(), account.held(), account.reserved());
+
+        assert!(ledger.add_tx(deposit(2)).is_err());
+        let account = &ledger.active_accounts()[&1];
+        assert_eq!(account.available(), available);
+        assert_eq!(account.held(), held);
+        assert_eq!(account.reserved(), reserved);
+        assert_eq!(ledger.reserves().count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "checksum")]
+    fn test_close_period() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let deposit = |transaction_id, timestamp| Transaction {
+            timestamp: Some(timestamp),
+            ..Transaction::new(1, transaction_id, TransactionType::Deposit { amount })
+        };
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            deposit(1, 100),
+            deposit(2, 200),
+            Transaction::new(1, 3, TransactionType::Withdrawal { amount }),
+        ]);
+        assert_eq!(ledger.period_stats().deposits, 2);
+
+        let archive = ledger.close_period("2024-01");
+        assert_eq!(archive.label(), "2024-01");
+        assert_eq!(archive.end(), Some(200));
+        assert_eq!(archive.transactions().len(), 3);
+        assert_eq!(archive.stats().withdrawn, Decimal::from(amount));
+        assert_eq!(archive.sha256().len(), 64);
+        assert_eq!(ledger.period_stats(), PeriodStats::default());
+
+        // back-dated records are rejected, and the period's transactions can still be disputed
+        assert!(matches!(
+            ledger.add_tx(deposit(4, 200)),
+            Err(TxError::ClosedPeriod)
+        ));
+        ledger.add_tx(deposit(5, 300)).unwrap();
+        ledger
+            .add_tx(Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        let archive = ledger.close_period("2024-02");
+        assert_eq!(archive.transactions().len(), 2);
+        assert_eq!(archive.stats().disputes, 1);
+        assert_eq!(
+            ledger
+                .closed_periods()
+                .iter()
+                .map(PeriodArchive::label)
+                .collect::<Vec<_>>(),
+            ["2024-01", "2024-02"]
+        );
+        assert_ne!(
+            ledger.closed_periods()[0].sha256(),
+            ledger.closed_periods()[1].sha256()
+        );
+        assert_eq!(
+            ledger
+                .late_arrivals()
+                .iter()
+                .map(|late| (late.transaction_id, late.period.as_deref(), late.action))
+                .collect::<Vec<_>>(),
+            [(4, Some("2024-01"), LateArrivalPolicy::Reject)]
+        );
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_late_arrivals() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let deposit = |transaction_id, timestamp| Transaction {
+            timestamp: Some(timestamp),
+            ..Transaction::new(1, transaction_id, TransactionType::Deposit { amount })
+        };
+        let ledger_with = |late_arrivals| {
+            let mut ledger = LedgerBuilder::default()
+                .policies(&Policies {
+                    late_arrivals,
+                    ..Policies::default()
+                })
+                .build()
+                .unwrap();
+            ledger.add_tx(deposit(1, 100)).unwrap();
+            ledger.close_period("2024-01");
+            ledger.add_tx(deposit(2, 200)).unwrap();
+            ledger
+        };
+
+        // adjustments are applied to the open period, which is archived as usual
+        let mut ledger = ledger_with(LateArrivalPolicy::Adjust);
+        ledger.add_tx(deposit(3, 50)).unwrap();
+        assert_eq!(ledger.period_stats().adjustments, 1);
+        assert_eq!(ledger.late_arrivals()[0].action, LateArrivalPolicy::Adjust);
+        ledger.add_tx(deposit(4, 60)).unwrap();
+        let archive = ledger.close_period("2024-02");
+        assert_eq!(archive.stats().adjustments, 2);
+        assert_eq!(ledger.closed_periods()[0].transactions().len(), 1);
+
+        // reopening returns the period's transactions to the open period
+        let mut ledger = ledger_with(LateArrivalPolicy::Reopen);
+        ledger.add_tx(deposit(3, 50)).unwrap();
+        assert!(ledger.closed_periods().is_empty());
+        assert_eq!(ledger.period_stats().transactions, 3);
+        assert_eq!(ledger.period_stats().adjustments, 0);
+        let late = &ledger.late_arrivals()[0];
+        assert_eq!(
+            (late.period.as_deref(), late.action),
+            (Some("2024-01"), LateArrivalPolicy::Reopen)
+        );
+        assert_eq!(ledger.close_period("2024-01").transactions().len(), 3);
+
+        // rejections are logged without applying the record
+        let mut ledger = ledger_with(LateArrivalPolicy::Reject);
+        assert!(matches!(
+            ledger.add_tx(deposit(3, 50)),
+            Err(TxError::ClosedPeriod)
+        ));
+        assert_eq!(ledger.late_arrivals().len(), 1);
+        assert_eq!(ledger.period_stats().transactions, 1);
+    }
+
+    #[test]
+    fn test_test_clients() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .test_clients(TestClients::default().range(100..=199))
+            .build()
+            .unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(150, 2, TransactionType::Deposit { amount }),
+            Transaction::new(150, 3, TransactionType::Withdrawal { amount }),
+        ]);
+        // test clients transact like any other
+        assert!(ledger.quarantined().is_empty());
+        assert_eq!(ledger.active_accounts().len(), 2);
 
-#[derive(Debug, Default)]
-pub struct Ledger {
-    pub(crate) active_accounts: HashMap<u16, Account<false>>,
-    pub(crate) locked_accounts: HashMap<u16, Account<true>>,
-    pub(crate) transactions: Vec<Transaction>,
-    /// Map of `<transaction_id, (client_id, amount)`
-    pub(crate) disputed_tx_map: HashMap<u32, (u16, PositiveDecimal)>,
-}
+        let settlement = ledger.settlement().unwrap();
+        assert_eq!(settlement.len(), 1);
+        assert_eq!(settlement[0].client_id, 1);
+        let test_settlement = ledger.test_settlement().unwrap();
+        assert_eq!(test_settlement.len(), 1);
+        assert_eq!(test_settlement[0].client_id, 150);
+        assert_eq!(test_settlement[0].withdrawals, amount);
+        assert_eq!(ledger.test_transactions(), 2);
+        let aggregates = ledger.aggregate(AggregationKey::Client).unwrap();
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].key, "1");
+    }
 
-impl Ledger {
-    pub fn process_transactions(&mut self, transactions: impl IntoIterator<Item = Transaction>) {
-        for transaction in transactions {
-            self.add_tx(transaction).ok();
+    #[test]
+    fn test_account_layers() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct WithdrawalLimit(PositiveDecimal);
+        impl AccountLayer for WithdrawalLimit {
+            fn before_tx(&self, _: &dyn AccountView, tx: &Transaction) -> Result<(), TxError> {
+                match tx.tx_type {
+                    TransactionType::Withdrawal { amount } if amount > self.0 => {
+                        Err(TxError::InsufficientPermission)
+                    }
+                    _ => Ok(()),
+                }
+            }
         }
-    }
 
-    pub fn process_csv_transactions(
-        &mut self,
-        transactions: impl IntoIterator<Item = Result<TransactionRecord, csv::Error>>,
-    ) {
-        for transaction in transactions
-            .into_iter()
-            .map(|res| res.map_err(|e| error!("Malformed CSV Record: {:?}", e)))
-            .flatten()
-            .flat_map(|record| {
-                Transaction::try_from(record).map_err(|e| error!("Malformed Transaction: {:?}", e))
-            })
-        {
-            self.add_tx(transaction)
-                .map_err(|e| warn!("Invalid Transaction: {:?}", e))
-                .ok();
+        #[derive(Clone, Default)]
+        struct Mirror(Arc<AtomicUsize>);
+        impl AccountLayer for Mirror {
+            fn after_tx(&self, account: &dyn AccountView, tx: &Transaction) {
+                assert_eq!(account.client_id(), tx.client_id);
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
         }
+
+        let small = PositiveDecimal::try_from(1.0).unwrap();
+        let large = PositiveDecimal::try_from(100.0).unwrap();
+        let mirror = Mirror::default();
+        let mut ledger = Ledger::default();
+        ledger.add_layer(WithdrawalLimit(small));
+        ledger.add_layer(mirror.clone());
+
+        let tx = Transaction::new(1, 1, TransactionType::Deposit { amount: large });
+        assert!(ledger.add_tx(tx).is_ok());
+        let tx = Transaction::new(1, 2, TransactionType::Withdrawal { amount: large });
+        assert!(matches!(
+            ledger.add_tx(tx),
+            Err(TxError::InsufficientPermission)
+        ));
+        let tx = Transaction::new(1, 3, TransactionType::Withdrawal { amount: small });
+        assert!(ledger.add_tx(tx).is_ok());
+        let tx = Transaction::new(1, 3, TransactionType::Dispute);
+        assert!(ledger.add_tx(tx).is_ok());
+        let tx = Transaction::new(1, 3, TransactionType::Chargeback);
+        assert!(ledger.add_tx(tx).is_ok());
+        assert_eq!(mirror.0.load(Ordering::SeqCst), 4);
+        assert!(ledger.locked_accounts().get(&1).unwrap().is_locked());
+
+        // backfilled transactions are applied, but hidden from `after_tx`
+        ledger.set_backfill(true);
+        let tx = Transaction::new(2, 4, TransactionType::Deposit { amount: small });
+        assert!(ledger.add_tx(tx).is_ok());
+        let tx = Transaction::new(2, 5, TransactionType::Withdrawal { amount: large });
+        assert!(ledger.add_tx(tx).is_err());
+        assert_eq!(mirror.0.load(Ordering::SeqCst), 4);
+        ledger.set_backfill(false);
+        let tx = Transaction::new(2, 6, TransactionType::Deposit { amount: small });
+        assert!(ledger.add_tx(tx).is_ok());
+        assert_eq!(mirror.0.load(Ordering::SeqCst), 5);
+
+        // a client whose first transaction is rejected by a layer isn't given an account
+        let tx = Transaction::new(3, 7, TransactionType::Withdrawal { amount: large });
+        assert!(matches!(
+            ledger.add_tx(tx),
+            Err(TxError::InsufficientPermission)
+        ));
+        assert!(!ledger.active_accounts().contains_key(&3));
+
+        // the rejected withdrawal left the balance untouched
+        let account = ledger.locked_accounts().get(&1).unwrap();
+        assert_eq!(
+            account.available(),
+            large
+                .checked_sub(small)
+                .unwrap()
+                .checked_sub(small)
+                .unwrap()
+        );
     }
 
-    pub fn add_tx(&mut self, transaction: Transaction) -> Result<(), TxError> {
-        if self.locked_accounts.contains_key(&transaction.client_id) {
-            return Err(TxError::LockedAccount);
+    #[test]
+    fn test_custom_accounts() {
+        use crate::transaction::Transact;
+
+        // counts the deposits applied to an account, through its chargeback too
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Counted(Account, usize);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct LockedCounted(Account<true>, usize);
+
+        impl Transact for Counted {
+            fn deposit(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+                self.0.deposit(amount)?;
+                self.1 += 1;
+                Ok(())
+            }
+
+            fn withdraw(&mut self, amount: PositiveDecimal) -> Result<(), TxError> {
+                self.0.withdraw(amount)
+            }
+
+            fn dispute(
+                &mut self,
+                disputed_tx_id: u32,
+                disputable: &Disputable,
+                disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
+            ) -> Result<(), TxError> {
+                self.0.dispute(disputed_tx_id, disputable, disputed_tx_map)
+            }
+
+            fn resolve(
+                &mut self,
+                transaction_id: u32,
+                disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
+            ) -> Result<(), TxError> {
+                self.0.resolve(transaction_id, disputed_tx_map)
+            }
+
+            fn chargeback(
+                self,
+                transaction_id: u32,
+                disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
+            ) -> (Result<Account<true>, TxError>, Option<Account>) {
+                self.0.chargeback(transaction_id, disputed_tx_map)
+            }
         }
 
-        let account = self
-            .active_accounts
-            .entry(transaction.client_id)
-            .or_insert_with_key(|&k| Account::new(k));
-        match transaction.tx_type {
-            TransactionType::Deposit { amount } => {
-                account.deposit(amount)?;
+        impl AccountView for Counted {
+            fn client_id(&self) -> u16 {
+                self.0.client_id()
             }
-            TransactionType::Withdrawal { amount } => {
-                account.withdraw(amount)?;
+
+            fn available(&self) -> PositiveDecimal {
+                self.0.available()
             }
-            TransactionType::Dispute => {
-                account.dispute(
-                    transaction.transaction_id,
-                    &self.transactions,
-                    &mut self.disputed_tx_map,
-                )?;
+
+            fn held(&self) -> PositiveDecimal {
+                self.0.held()
             }
-            TransactionType::Resolve => {
-                account.resolve(transaction.transaction_id, &mut self.disputed_tx_map)?;
+
+            fn is_locked(&self) -> bool {
+                false
             }
-            TransactionType::Chargeback => {
-                let removed_account = self.active_accounts.remove(&transaction.client_id).unwrap();
-                let chargeback_res = removed_account
-                    .chargeback(transaction.transaction_id, &mut self.disputed_tx_map);
-                match chargeback_res {
-                    (Ok(locked_account), None) => {
-                        self.active_accounts.remove(&locked_account.client_id);
-                        self.locked_accounts
-                            .insert(locked_account.client_id, locked_account);
-                    }
-                    (Err(e), Some(removed_account)) => {
-                        self.active_accounts
-                            .insert(transaction.client_id, removed_account);
-                        return Err(e);
+
+            fn flags(&self) -> &BTreeSet<String> {
+                self.0.flags()
+            }
+        }
+
+        impl AccountView for LockedCounted {
+            fn client_id(&self) -> u16 {
+                self.0.client_id()
+            }
+
+            fn available(&self) -> PositiveDecimal {
+                self.0.available()
+            }
+
+            fn held(&self) -> PositiveDecimal {
+                self.0.held()
+            }
+
+            fn is_locked(&self) -> bool {
+                true
+            }
+
+            fn flags(&self) -> &BTreeSet<String> {
+                self.0.flags()
+            }
+        }
+
+        impl LedgerAccount for Counted {
+            type Locked = LockedCounted;
+
+            fn wrap(account: Account) -> Self {
+                Counted(account, 0)
+            }
+
+            fn account(&self) -> &Account {
+                &self.0
+            }
+
+            fn account_mut(&mut self) -> &mut Account {
+                &mut self.0
+            }
+
+            fn lock(
+                self,
+                transaction_id: u32,
+                disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
+            ) -> (Result<LockedCounted, TxError>, Option<Self>) {
+                let deposits = self.1;
+                match self.0.chargeback(transaction_id, disputed_tx_map) {
+                    (Ok(locked), _) => (Ok(LockedCounted(locked, deposits)), None),
+                    (Err(e), account) => {
+                        (Err(e), account.map(|account| Counted(account, deposits)))
                     }
-                    (Ok(_), Some(_)) | (Err(_), None) => unreachable!(),
                 }
             }
         }
-        self.transactions.push(transaction);
 
-        Ok(())
-    }
+        impl LockedAccount for LockedCounted {
+            fn wrap(account: Account<true>) -> Self {
+                LockedCounted(account, 0)
+            }
 
-    pub fn active_accounts(&self) -> &HashMap<u16, Account<false>> {
-        &self.active_accounts
-    }
+            fn account(&self) -> &Account<true> {
+                &self.0
+            }
 
-    pub fn locked_accounts(&self) -> &HashMap<u16, Account<true>> {
-        &self.locked_accounts
+            fn account_mut(&mut self) -> &mut Account<true> {
+                &mut self.0
+            }
+        }
+
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .opening_balances([(2, Decimal::TEN, Decimal::ZERO, true)])
+            .build_with_accounts::<Counted>()
+            .unwrap();
+        ledger.process_transactions([
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 3, TransactionType::Withdrawal { amount }),
+        ]);
+        assert_eq!(ledger.active_accounts()[&1].1, 2);
+        assert_eq!(ledger.account(1).unwrap().available(), amount);
+
+        ledger
+            .add_tx(Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+        // a rejected chargeback leaves the account, and what it counted, as they were
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Chargeback)),
+            Err(TxError::NotDisputed)
+        ));
+        assert_eq!(ledger.active_accounts()[&1].1, 2);
+        ledger
+            .add_tx(Transaction::new(1, 2, TransactionType::Chargeback))
+            .unwrap();
+        assert_eq!(ledger.locked_accounts()[&1].1, 2);
+        assert!(ledger.account(1).unwrap().is_locked());
+        assert_eq!(ledger.locked_accounts()[&2].0.available(), amount);
     }
 
-    pub fn transactions(&self) -> &Vec<Transaction> {
-        &self.transactions
+    #[test]
+    fn test_retry_quarantined() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let withdrawal = Transaction::new(1, 2, TransactionType::Withdrawal { amount });
+        let dispute = Transaction::new(2, 3, TransactionType::Dispute);
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![withdrawal.clone(), dispute.clone()]);
+        assert_eq!(
+            ledger.quarantined(),
+            &[
+                Rejection::new(withdrawal.clone(), &TxError::InsufficientFunds),
+                Rejection::new(dispute.clone(), &TxError::NotFound)
+            ]
+        );
+        assert!(ledger.transactions().is_empty());
+
+        // the missing deposit arrives late
+        ledger
+            .add_tx(Transaction::new(1, 1, TransactionType::Deposit { amount }))
+            .unwrap();
+        let report = ledger.retry_quarantined();
+        assert_eq!(report.applied, vec![withdrawal]);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].0, dispute);
+        assert!(matches!(report.rejected[0].1, TxError::NotFound));
+        assert_eq!(
+            ledger.quarantined(),
+            &[Rejection::new(dispute, &TxError::NotFound)]
+        );
+        assert_eq!(
+            ledger.active_accounts()[&1].available(),
+            PositiveDecimal::default()
+        );
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use rust_decimal::prelude::*;
+    #[cfg(feature = "csv")]
+    const CHUNKED_INPUT: &str = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,one
+withdrawal,1,3,20.0
+deposit,2,4,5.0
+dispute,2,4,
+";
 
+    #[cfg(feature = "csv")]
     #[test]
-    fn test_ledger() {
+    fn test_process_chunks() {
+        let mut reader = csv::Reader::from_reader(CHUNKED_INPUT.as_bytes());
         let mut ledger = Ledger::default();
-        let zero = PositiveDecimal::try_from(Decimal::ZERO).unwrap();
-        let amount = PositiveDecimal::try_from(10000.1000).unwrap();
-        let client_id = 10;
-        let tx_id = 1000;
-        let locked_account: Account<true> = Account::<true>::from(Account::new(1));
-        ledger.locked_accounts.insert(client_id, locked_account);
+        let mut chunks = Vec::new();
+        let progress = ledger.process_chunks(reader.deserialize(), 2, |progress| {
+            chunks.push(*progress);
+            ControlFlow::Continue(())
+        });
+        assert_eq!(
+            progress,
+            Progress {
+                records: 5,
+                applied: 3,
+                rejected: 2,
+                cancelled: false,
+                limit_exceeded: None
+            }
+        );
+        // the last chunk is followed by no record to stop before
+        assert_eq!(
+            chunks.iter().map(|p| p.records).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(ledger.held_breakdown(2).len(), 1);
 
-        let tx = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let res = ledger.add_tx(tx);
-        assert!(res.is_err());
+        // breaking stops before the next chunk
+        let mut reader = csv::Reader::from_reader(CHUNKED_INPUT.as_bytes());
+        let mut ledger = Ledger::default();
+        let progress = ledger.process_chunks(reader.deserialize(), 2, |_| ControlFlow::Break(()));
+        assert_eq!(progress.records, 2);
+        assert_eq!(ledger.transactions().len(), 1);
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_process_chunks_async() {
+        use std::sync::Arc;
+        use std::task::{Wake, Waker};
+
+        struct Unpark(std::thread::Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
 
+        let mut reader = csv::Reader::from_reader(CHUNKED_INPUT.as_bytes());
         let mut ledger = Ledger::default();
-        // deposit
-        let tx = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let res = ledger.add_tx(tx);
-        assert!(res.is_ok());
-        let log = ledger.transactions();
-        let tx = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        assert_eq!(log, &vec![tx]);
-        let mut account = Account::new(client_id);
-        account.deposit(amount).unwrap();
-        assert_eq!(ledger.active_accounts().get(&client_id).unwrap(), &account);
+        let mut chunks = 0;
+        let mut future = Box::pin(ledger.process_chunks_async(reader.deserialize(), 2, |_| {
+            chunks += 1;
+            ControlFlow::Continue(())
+        }));
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut polls = 0;
+        let progress = loop {
+            polls += 1;
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(progress) => break progress,
+                Poll::Pending => std::thread::park(),
+            }
+        };
+        drop(future);
+        assert_eq!(progress.records, 5);
+        assert_eq!(chunks, 2);
+        // control was handed back to the executor after every chunk but the last
+        assert_eq!(polls, 3);
+        assert_eq!(ledger.transactions().len(), 3);
+    }
 
-        // withdraw
-        let smaller_amount = PositiveDecimal::try_from(900.1000).unwrap();
-        let tx = Transaction::new(
-            client_id,
-            tx_id + 1,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+    #[test]
+    fn test_cancellation() {
+        /// Cancels the run once the second deposit was applied
+        struct CancelAfter(CancellationToken);
+        impl AccountLayer for CancelAfter {
+            fn after_tx(&self, _: &dyn AccountView, tx: &Transaction) {
+                if tx.transaction_id == 2 {
+                    self.0.cancel();
+                }
+            }
+        }
+
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let token = CancellationToken::new();
+        let mut ledger = Ledger::builder()
+            .cancellation_token(token.clone())
+            .layer(CancelAfter(token.clone()))
+            .build()
+            .unwrap();
+        let progress = ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Withdrawal { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 3, TransactionType::Deposit { amount }),
+        ]);
+        assert_eq!(
+            progress,
+            Progress {
+                records: 2,
+                applied: 1,
+                rejected: 1,
+                cancelled: true,
+                limit_exceeded: None
+            }
         );
-        let res = ledger.add_tx(tx);
-        assert!(res.is_ok());
-        let log = ledger.transactions();
-        let tx_1 = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let tx_2 = Transaction::new(
-            client_id,
-            tx_id + 1,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+        assert!(ledger.is_cancelled());
+        assert_eq!(ledger.transactions().len(), 1);
+
+        // a cancelled retry leaves the quarantine untouched
+        let report = ledger.retry_quarantined();
+        assert!(report.cancelled);
+        assert!(report.applied.is_empty());
+        assert_eq!(ledger.quarantined().len(), 1);
+    }
+
+    #[test]
+    fn test_synthetic_transactions() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .synthetic_tx_ids(100..102)
+            .build()
+            .unwrap();
+        ledger
+            .add_tx(Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount.checked_add(amount).unwrap(),
+                },
+            ))
+            .unwrap();
+        assert_eq!(
+            ledger
+                .add_synthetic_tx(1, TransactionType::Withdrawal { amount })
+                .unwrap(),
+            100
         );
-        assert_eq!(log, &vec![tx_1, tx_2]);
-        let mut account = Account::new(client_id);
-        account
-            .deposit(amount.checked_sub(smaller_amount).unwrap())
+        // input transactions can't take ids reserved for synthetic ones...
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(
+                1,
+                101,
+                TransactionType::Deposit { amount }
+            )),
+            Err(TxError::ReservedTransactionId)
+        ));
+        // ...but may still refer to them
+        ledger
+            .add_tx(Transaction::new(1, 100, TransactionType::Dispute))
             .unwrap();
-        assert_eq!(ledger.active_accounts().get(&client_id).unwrap(), &account);
 
-        // dispute
-        let tx = Transaction::new(client_id, tx_id + 1, TransactionType::Dispute);
-        let res = ledger.add_tx(tx);
-        assert!(res.is_ok());
-        let log = ledger.transactions();
-        let tx_1 = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let tx_2 = Transaction::new(
-            client_id,
-            tx_id + 1,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+        let id = ledger.tx_ids().allocate().unwrap();
+        ledger
+            .add_tx(Transaction::synthetic(
+                1,
+                id,
+                TransactionType::Deposit { amount },
+            ))
+            .unwrap();
+        assert!(matches!(
+            ledger.add_synthetic_tx(1, TransactionType::Deposit { amount }),
+            Err(TxError::TxIdsExhausted)
+        ));
+        assert_eq!(
+            ledger
+                .transactions()
+                .iter()
+                .map(|tx| (tx.transaction_id, tx.origin))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, Origin::Ingested),
+                (100, Origin::Synthetic),
+                (100, Origin::Ingested),
+                (101, Origin::Synthetic)
+            ]
         );
-        let tx_3 = Transaction::new(client_id, tx_id + 1, TransactionType::Dispute);
-        assert_eq!(log, &vec![tx_1, tx_2, tx_3]);
-        let balance = &ledger.active_accounts().get(&client_id).unwrap().balance;
-        // NOTE demonstation of weird specifications of behavior
-        // For a dispute, the instructions say:
-        // This means that the clients available funds should decrease by the amount disputed,
-        // their held funds should increase by the amount disputed, while their total funds should remain the same.
-        //
-        // However, if I'm disputing a withdrawal, my available funds should not decrease
-        let available = amount
-            .checked_sub(smaller_amount)
-            .unwrap()
-            .checked_sub(smaller_amount)
+    }
+
+    #[test]
+    fn test_malformed_dispute_chains() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Flag {
+                    flag: "vip".to_owned(),
+                },
+            ),
+        ]);
+
+        // a resolve or chargeback arriving before the dispute
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Resolve)),
+            Err(TxError::NotDisputed)
+        ));
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Chargeback)),
+            Err(TxError::NotDisputed)
+        ));
+        // while the transaction is unknown altogether
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 3, TransactionType::Resolve)),
+            Err(TxError::NotFound)
+        ));
+
+        ledger
+            .add_tx(Transaction::new(1, 1, TransactionType::Dispute))
             .unwrap();
-        assert_eq!(balance.available(), &available);
-        assert_eq!(balance.held(), &smaller_amount);
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Dispute)),
+            Err(TxError::AlreadyDisputed)
+        ));
+        ledger
+            .add_tx(Transaction::new(1, 1, TransactionType::Resolve))
+            .unwrap();
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Resolve)),
+            Err(TxError::NotDisputed)
+        ));
 
-        // resolve
-        let tx = Transaction::new(client_id, tx_id + 1, TransactionType::Resolve);
-        let res = ledger.add_tx(tx);
-        assert!(res.is_ok());
-        let log = ledger.transactions();
-        let tx_1 = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let tx_2 = Transaction::new(
-            client_id,
-            tx_id + 1,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
-        );
-        let tx_3 = Transaction::new(client_id, tx_id + 1, TransactionType::Dispute);
-        let tx_4 = Transaction::new(client_id, tx_id + 1, TransactionType::Resolve);
-        assert_eq!(log, &vec![tx_1, tx_2, tx_3, tx_4]);
-        let balance = &ledger.active_accounts().get(&client_id).unwrap().balance;
-        let available = amount.checked_sub(smaller_amount).unwrap();
-        assert_eq!(balance.available(), &available);
-        assert_eq!(balance.held(), &zero);
+        // admin records, and dispute records whose transaction was forgotten, aren't disputable
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 2, TransactionType::Dispute)),
+            Err(TxError::NotDisputable)
+        ));
+        let disputable = &mut ledger.disputable;
+        ledger
+            .transactions
+            .forget(1, |forgotten| disputable.remove(&forgotten));
+        ledger.history.forget(1);
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Dispute)),
+            Err(TxError::NotDisputable)
+        ));
+        assert_eq!(*ledger.active_accounts[&1].balance.available(), amount);
+    }
 
-        // withdraw
-        let huge_amount = PositiveDecimal::try_from(9000000000.1000).unwrap();
-        let tx = Transaction::new(
-            client_id,
-            tx_id + 2,
-            TransactionType::Withdrawal {
-                amount: huge_amount,
-            },
+    #[test]
+    fn test_policies() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let policies = Policies {
+            withdrawal_disputes: WithdrawalDisputePolicy::Reject,
+            unknown_clients: UnknownClientPolicy::Reject,
+            ..Policies::default()
+        };
+        let mut ledger = Ledger::builder()
+            .opening_balances([(1, Decimal::from(20), Decimal::ZERO, false)])
+            .policies(&policies)
+            .build()
+            .unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Withdrawal { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ]);
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 2, TransactionType::Dispute)),
+            Err(TxError::NotDisputable)
+        ));
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(2, 3, TransactionType::Deposit { amount })),
+            Err(TxError::UnknownClient)
+        ));
+        assert_eq!(ledger.active_accounts().len(), 1);
+        assert_eq!(*ledger.active_accounts()[&1].balance.held(), amount);
+    }
+
+    #[test]
+    fn test_suspense() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder().suspense().build().unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Chargeback),
+            Transaction::new(1, 2, TransactionType::Resolve),
+            // the dispute of a known transaction is rejected as usual
+            Transaction::new(1, 3, TransactionType::Deposit { amount }),
+            Transaction::new(2, 3, TransactionType::Dispute),
+        ]);
+        assert_eq!(ledger.suspended().len(), 3);
+        assert_eq!(ledger.quarantined().len(), 1);
+
+        ledger
+            .add_tx(Transaction::new(1, 1, TransactionType::Deposit { amount }))
+            .unwrap();
+        assert_eq!(
+            ledger
+                .suspended()
+                .iter()
+                .map(|suspended| suspended.transaction.transaction_id)
+                .collect::<Vec<_>>(),
+            vec![2]
         );
-        assert_eq!(ledger.transactions().len(), 4);
-        let res = ledger.add_tx(tx);
-        assert_eq!(ledger.transactions().len(), 4);
-        assert!(res.is_err());
-        let tx = Transaction::new(
-            client_id,
-            tx_id + 2,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+        assert!(ledger.locked_accounts().contains_key(&1));
+        assert_eq!(ledger.locked_accounts()[&1].total().unwrap(), amount);
+
+        // parked transactions that are rejected once their transaction arrives are quarantined
+        ledger
+            .add_tx(Transaction::new(2, 2, TransactionType::Deposit { amount }))
+            .unwrap();
+        assert!(ledger.suspended().is_empty());
+        assert_eq!(ledger.quarantined().len(), 2);
+    }
+
+    #[test]
+    fn test_limits_abort() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .limits(Limits {
+                max_accounts: Some(2),
+                ..Limits::default()
+            })
+            .build()
+            .unwrap();
+        let progress = ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Deposit { amount }),
+            Transaction::new(3, 3, TransactionType::Deposit { amount }),
+            Transaction::new(1, 4, TransactionType::Deposit { amount }),
+        ]);
+        assert_eq!(
+            progress,
+            Progress {
+                records: 3,
+                applied: 2,
+                rejected: 1,
+                cancelled: false,
+                limit_exceeded: Some(Limit::Accounts)
+            }
         );
-        let res = ledger.add_tx(tx);
-        assert!(res.is_ok());
-        let log = ledger.transactions();
-        let tx_1 = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let tx_2 = Transaction::new(
-            client_id,
-            tx_id + 1,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+        assert_eq!(ledger.limit_exceeded(), Some(Limit::Accounts));
+        // existing accounts are stopped too
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 4, TransactionType::Deposit { amount })),
+            Err(TxError::LimitExceeded(Limit::Accounts))
+        ));
+    }
+
+    #[test]
+    fn test_limits_degrade() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .limits(Limits {
+                max_accounts: Some(2),
+                max_transactions: Some(10),
+                max_open_disputes: Some(1),
+                policy: LimitPolicy::Degrade,
+                ..Limits::default()
+            })
+            .build()
+            .unwrap();
+        let progress = ledger.process_transactions(
+            (1..=20)
+                .map(|tx| Transaction::new(1, tx, TransactionType::Deposit { amount }))
+                .chain([
+                    Transaction::new(2, 21, TransactionType::Deposit { amount }),
+                    Transaction::new(3, 22, TransactionType::Deposit { amount }),
+                    Transaction::new(1, 20, TransactionType::Dispute),
+                    Transaction::new(1, 19, TransactionType::Dispute),
+                ]),
         );
-        let tx_3 = Transaction::new(client_id, tx_id + 1, TransactionType::Dispute);
-        let tx_4 = Transaction::new(client_id, tx_id + 1, TransactionType::Resolve);
-        let tx_5 = Transaction::new(
-            client_id,
-            tx_id + 2,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+        assert_eq!(progress.records, 24);
+        assert_eq!(progress.applied, 22);
+        assert_eq!(progress.limit_exceeded, None);
+        assert_eq!(ledger.limit_exceeded(), None);
+        assert_eq!(ledger.active_accounts().len(), 2);
+        assert_eq!(ledger.disputed_tx_map.len(), 1);
+        assert!(ledger.transactions().len() <= 10);
+        for client_id in 1..=3 {
+            assert!(ledger.transactions_for_client(client_id).eq(ledger
+                .transactions()
+                .iter()
+                .filter(|transaction| transaction.client_id == client_id)));
+        }
+        // rejections for exceeding a limit are only counted
+        assert!(ledger.quarantined().is_empty());
+        assert_eq!(ledger.quarantine_dropped(), 2);
+        let progress = ledger.process_transactions((4..=100).map(|client_id| {
+            Transaction::new(
+                client_id,
+                100 + client_id as u32,
+                TransactionType::Deposit { amount },
+            )
+        }));
+        assert_eq!(progress.rejected, 97);
+        assert!(ledger.quarantined().is_empty());
+        assert_eq!(ledger.quarantine_dropped(), 99);
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Dispute)),
+            Err(TxError::LimitExceeded(Limit::OpenDisputes))
+        ));
+    }
+
+    #[test]
+    fn test_limits_quarantine() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .limits(Limits {
+                max_quarantined: Some(2),
+                ..Limits::default()
+            })
+            .build()
+            .unwrap();
+        let progress = ledger.process_transactions(
+            (1..=5)
+                .map(|tx| Transaction::new(1, tx, TransactionType::Withdrawal { amount }))
+                .chain([Transaction::new(1, 6, TransactionType::Deposit { amount })]),
         );
-        assert_eq!(log, &vec![tx_1, tx_2, tx_3, tx_4, tx_5]);
-        let balance = &ledger.active_accounts().get(&client_id).unwrap().balance;
-        let available = amount
-            .checked_sub(smaller_amount)
-            .unwrap()
-            .checked_sub(smaller_amount)
+        assert_eq!(progress.rejected, 5);
+        assert_eq!(progress.limit_exceeded, None);
+        let kept = ledger
+            .quarantined()
+            .iter()
+            .map(|rejection| rejection.transaction.transaction_id)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, [1, 2]);
+        assert_eq!(ledger.quarantine_dropped(), 3);
+
+        // retrying frees the places of the transactions it applies
+        let report = ledger.retry_quarantined();
+        assert_eq!(report.applied.len(), 1);
+        assert_eq!(report.rejected.len(), 1);
+        ledger.process_transactions([Transaction::new(
+            1,
+            7,
+            TransactionType::Withdrawal { amount },
+        )]);
+        assert_eq!(ledger.quarantined().len(), 2);
+        assert_eq!(ledger.quarantine_dropped(), 3);
+    }
+
+    #[test]
+    fn test_held_breakdown() {
+        let amount_1 = PositiveDecimal::try_from(10.0).unwrap();
+        let amount_2 = PositiveDecimal::try_from(2.5).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 3, TransactionType::Deposit { amount: amount_1 }),
+            Transaction::new(1, 1, TransactionType::Deposit { amount: amount_2 }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount: amount_2 }),
+            Transaction::new(2, 4, TransactionType::Deposit { amount: amount_1 }),
+            Transaction::new(1, 3, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(2, 4, TransactionType::Dispute),
+        ]);
+        assert_eq!(ledger.held_breakdown(1), vec![(1, amount_2), (3, amount_1)]);
+        assert_eq!(ledger.held_breakdown(2), vec![(4, amount_1)]);
+        assert!(ledger.held_breakdown(3).is_empty());
+
+        ledger
+            .add_tx(Transaction::new(1, 3, TransactionType::Resolve))
             .unwrap();
-        assert_eq!(balance.available(), &available);
-        assert_eq!(balance.held(), &zero);
+        assert_eq!(ledger.held_breakdown(1), vec![(1, amount_2)]);
+    }
 
-        // dispute
-        let tx = Transaction::new(client_id, tx_id + 2, TransactionType::Dispute);
-        let res = ledger.add_tx(tx);
-        assert!(res.is_ok());
-        let log = ledger.transactions();
-        let tx_1 = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let tx_2 = Transaction::new(
-            client_id,
-            tx_id + 1,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
-        );
-        let tx_3 = Transaction::new(client_id, tx_id + 1, TransactionType::Dispute);
-        let tx_4 = Transaction::new(client_id, tx_id + 1, TransactionType::Resolve);
-        let tx_5 = Transaction::new(
-            client_id,
-            tx_id + 2,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+    #[test]
+    fn test_flags() {
+        let flag = |client_id, tx_id, flag: &str| {
+            Transaction::new(
+                client_id,
+                tx_id,
+                TransactionType::Flag {
+                    flag: flag.to_owned(),
+                },
+            )
+        };
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+
+        // only existing accounts can be flagged
+        assert!(matches!(
+            ledger.add_tx(flag(1, 1, "vip")),
+            Err(TxError::NotFound)
+        ));
+        ledger.process_transactions(vec![
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            flag(1, 3, "vip"),
+            flag(1, 4, "under_review"),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Chargeback),
+        ]);
+        let account = ledger.locked_accounts().get(&1).unwrap();
+        assert_eq!(
+            account.flags().iter().collect::<Vec<_>>(),
+            vec!["under_review", "vip"]
         );
-        let tx_6 = Transaction::new(client_id, tx_id + 2, TransactionType::Dispute);
-        assert_eq!(log, &vec![tx_1, tx_2, tx_3, tx_4, tx_5, tx_6]);
-        let balance = &ledger.active_accounts().get(&client_id).unwrap().balance;
-        let available = amount
-            .checked_sub(smaller_amount)
-            .unwrap()
-            .checked_sub(smaller_amount)
-            .unwrap()
-            .checked_sub(smaller_amount)
-            .unwrap();
-        assert_eq!(balance.available(), &available);
-        assert_eq!(balance.held(), &smaller_amount);
 
-        // chargeback
-        let tx = Transaction::new(client_id, tx_id + 2, TransactionType::Chargeback);
-        let res = ledger.add_tx(tx);
-        assert!(res.is_ok());
-        let log = ledger.transactions();
-        let tx_1 = Transaction::new(client_id, tx_id, TransactionType::Deposit { amount });
-        let tx_2 = Transaction::new(
-            client_id,
-            tx_id + 1,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
+        // locked accounts can still be annotated
+        let unflag = Transaction::new(
+            1,
+            5,
+            TransactionType::Unflag {
+                flag: "vip".to_owned(),
             },
         );
-        let tx_3 = Transaction::new(client_id, tx_id + 1, TransactionType::Dispute);
-        let tx_4 = Transaction::new(client_id, tx_id + 1, TransactionType::Resolve);
-        let tx_5 = Transaction::new(
-            client_id,
-            tx_id + 2,
-            TransactionType::Withdrawal {
-                amount: smaller_amount,
-            },
+        assert!(ledger.add_tx(unflag.clone()).is_ok());
+        assert!(matches!(ledger.add_tx(unflag), Err(TxError::NotFound)));
+        let account = ledger.locked_accounts().get(&1).unwrap();
+        assert_eq!(
+            account.flags().iter().collect::<Vec<_>>(),
+            vec!["under_review"]
         );
-        let tx_6 = Transaction::new(client_id, tx_id + 2, TransactionType::Dispute);
-        let tx_7 = Transaction::new(client_id, tx_id + 2, TransactionType::Chargeback);
-        assert_eq!(log, &vec![tx_1, tx_2, tx_3, tx_4, tx_5, tx_6, tx_7]);
-        assert!(!ledger.active_accounts().contains_key(&client_id));
-        let balance = &ledger.locked_accounts().get(&client_id).unwrap().balance;
-        let available = amount
-            .checked_sub(smaller_amount)
-            .unwrap()
-            .checked_sub(smaller_amount)
-            .unwrap()
-            .checked_sub(smaller_amount)
-            .unwrap();
-        assert_eq!(balance.available(), &available);
-        assert_eq!(balance.held(), &zero);
+        assert_eq!(ledger.transactions().len(), 6);
     }
 }
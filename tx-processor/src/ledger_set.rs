@@ -0,0 +1,138 @@
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+#[cfg(feature = "csv")]
+use core::fmt;
+
+use crate::error::TxError;
+use crate::ledger::Ledger;
+#[cfg(feature = "csv")]
+use crate::ledger::Progress;
+#[cfg(feature = "csv")]
+use crate::logging::{error, warn};
+use crate::transaction::Transaction;
+#[cfg(feature = "csv")]
+use crate::transaction::TransactionRecord;
+
+/// Isolated [Ledger]s for many merchants, keyed by the optional `namespace` column of the
+/// input. Records without a namespace go to the default namespace, `""`.
+///
+/// Every namespace starts as a clone of the same template ledger, so configuration such as
+/// [AccountLayer](crate::ledger::AccountLayer)s is shared across all of them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LedgerSet {
+    template: Ledger,
+    ledgers: BTreeMap<String, Ledger>,
+}
+
+impl LedgerSet {
+    pub fn new(template: Ledger) -> Self {
+        LedgerSet {
+            template,
+            ledgers: BTreeMap::new(),
+        }
+    }
+
+    pub fn ledger(&self, namespace: &str) -> Option<&Ledger> {
+        self.ledgers.get(namespace)
+    }
+
+    /// The ledger of `namespace`, created from the template if it doesn't exist yet
+    pub fn ledger_mut(&mut self, namespace: &str) -> &mut Ledger {
+        if !self.ledgers.contains_key(namespace) {
+            self.ledgers
+                .insert(namespace.to_owned(), self.template.clone());
+        }
+        self.ledgers.get_mut(namespace).unwrap()
+    }
+
+    /// Every namespace and its ledger, ordered by namespace
+    pub fn ledgers(&self) -> &BTreeMap<String, Ledger> {
+        &self.ledgers
+    }
+
+    pub fn add_tx(&mut self, namespace: &str, transaction: Transaction) -> Result<(), TxError> {
+        self.ledger_mut(namespace).add_tx(transaction)
+    }
+
+    #[cfg(feature = "csv")]
+    pub fn process_csv_transactions(
+        &mut self,
+        transactions: impl IntoIterator<Item = Result<TransactionRecord, impl fmt::Debug>>,
+    ) -> Progress {
+        let mut progress = Progress::default();
+        for record in transactions {
+            // every namespace shares the template's cancellation token
+            if self.template.is_cancelled() {
+                progress.cancelled = true;
+                break;
+            }
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    error!("Malformed CSV Record: {:?}", e);
+                    progress.record(false);
+                    continue;
+                }
+            };
+            let namespace = record.namespace.clone().unwrap_or_default();
+            let Some(record) = self.ledger_mut(&namespace).fill_missing_amount(record) else {
+                progress.record(false);
+                continue;
+            };
+            match Transaction::try_from(record) {
+                Ok(transaction) => {
+                    let applied = self
+                        .add_tx(&namespace, transaction)
+                        .map_err(|e| warn!("Invalid Transaction: {:?}", e))
+                        .is_ok();
+                    progress.record(applied);
+                }
+                Err(e) => {
+                    error!("Malformed Transaction: {:?}", e);
+                    progress.record(false);
+                }
+            }
+        }
+
+        progress
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod test {
+    use super::*;
+    use csv::{ReaderBuilder, Trim};
+
+    #[test]
+    fn test_namespaces_are_isolated() {
+        let input = "\
+type,client,tx,amount,namespace
+deposit,1,1,10.0,shop-a
+deposit,1,1,5.0,shop-b
+withdrawal,1,2,7.0,shop-a
+withdrawal,1,2,7.0,shop-b
+deposit,2,3,1.0,
+";
+        let mut reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(input.as_bytes());
+        let mut ledgers = LedgerSet::default();
+        ledgers.process_csv_transactions(reader.deserialize());
+
+        assert_eq!(
+            ledgers.ledgers().keys().collect::<Vec<_>>(),
+            vec!["", "shop-a", "shop-b"]
+        );
+        assert_eq!(ledgers.ledger("shop-a").unwrap().transactions().len(), 2);
+        // shop-b's withdrawal overdraws its own account, even though shop-a's client 1 could
+        // have covered it
+        assert_eq!(ledgers.ledger("shop-b").unwrap().transactions().len(), 1);
+        assert!(ledgers
+            .ledger("")
+            .unwrap()
+            .active_accounts()
+            .contains_key(&2));
+        assert!(ledgers.ledger("shop-c").is_none());
+    }
+}
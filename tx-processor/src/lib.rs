@@ -1,4 +1,80 @@
+// the tests run on the test harness, which needs `std` whatever the features
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod account;
+pub mod ack;
+pub mod aggregate;
+pub mod aliases;
+pub mod amount;
+pub mod availability;
+pub mod build_info;
+pub mod cancel;
+pub mod chaos;
+#[cfg(feature = "checksum")]
+pub mod checksum;
+pub mod clients;
+pub mod consistency;
+#[cfg(feature = "csv")]
+pub mod csv_limits;
+pub mod dedup;
+pub mod diagram;
+pub mod disputable;
+pub mod dispute_rules;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
+pub mod gaps;
+#[cfg(feature = "generate")]
+pub mod generate;
+pub mod handlers;
+pub mod history;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "std")]
+pub mod idempotency;
+#[cfg(feature = "std")]
+pub mod intake;
+pub mod intern;
+#[cfg(feature = "csv")]
+pub mod io;
+pub mod journal;
 pub mod ledger;
+pub mod ledger_set;
+pub mod limits;
+mod logging;
+pub mod map;
+pub mod normalize;
+pub mod period;
+pub mod policies;
+#[cfg(feature = "pseudonymize")]
+pub mod pseudonym;
+#[cfg(feature = "object-store")]
+pub mod remote;
+pub mod reorder;
+pub mod report;
+pub mod reserve;
+pub mod risk;
+pub mod rollup;
+pub mod rounding;
+pub mod sample;
+pub mod scenario;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod settlement;
+#[cfg(feature = "csv")]
+pub mod shards;
+#[cfg(feature = "std")]
+pub mod shared_ledger;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+pub mod tags;
+#[cfg(feature = "std")]
+pub mod throttle;
 pub mod transaction;
+pub mod transitions;
+pub mod tx_id;
+pub mod tx_log;
+#[cfg(feature = "csv")]
+pub mod types;
@@ -0,0 +1,55 @@
+//! Guardrails against inputs that would make a [Ledger](crate::ledger::Ledger) grow without
+//! bounds, e.g. a malformed file with random client ids
+
+use core::fmt;
+
+/// Upper bounds on the size of a ledger, see
+/// [LedgerBuilder::limits](crate::ledger::LedgerBuilder::limits). `None` means unlimited.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Number of accounts, active or locked
+    pub max_accounts: Option<usize>,
+    /// Number of transactions retained for disputes
+    pub max_transactions: Option<usize>,
+    /// Number of disputes open at the same time
+    pub max_open_disputes: Option<usize>,
+    /// Number of rejected transactions kept for retrying, see
+    /// [Ledger::quarantined](crate::ledger::Ledger::quarantined). Rejections beyond it are only
+    /// counted, see [Ledger::quarantine_dropped](crate::ledger::Ledger::quarantine_dropped), and
+    /// don't stop the ledger whatever the policy.
+    pub max_quarantined: Option<usize>,
+    pub policy: LimitPolicy,
+}
+
+/// What a ledger does once one of its [Limits] is reached
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// Reject the transaction that would exceed the limit and every transaction after it, and
+    /// stop processing
+    #[default]
+    Abort,
+    /// Keep processing. Transactions creating accounts or opening disputes beyond the limit are
+    /// rejected, and once too many transactions are retained the oldest ones are forgotten, so
+    /// they can no longer be disputed. Transactions rejected for exceeding a limit are only
+    /// counted rather than quarantined, see
+    /// [Ledger::quarantine_dropped](crate::ledger::Ledger::quarantine_dropped).
+    Degrade,
+}
+
+/// A limit of [Limits]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Accounts,
+    Transactions,
+    OpenDisputes,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Accounts => write!(f, "too many accounts"),
+            Limit::Transactions => write!(f, "too many retained transactions"),
+            Limit::OpenDisputes => write!(f, "too many open disputes"),
+        }
+    }
+}
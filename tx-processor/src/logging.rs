@@ -0,0 +1,19 @@
+//! The log macros of the `log` crate with the `logging` feature. Without it they still
+//! type-check their arguments, but discard them, so embedders without a logger don't link
+//! `log`.
+
+// only the CSV ingestion logs some of them
+#[cfg(feature = "logging")]
+#[allow(unused_imports)]
+pub(crate) use log::{debug, error, info, warn};
+
+#[cfg(not(feature = "logging"))]
+macro_rules! discard {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "logging"))]
+#[allow(unused_imports)]
+pub(crate) use {discard as debug, discard as error, discard as info, discard as warn};
@@ -0,0 +1,50 @@
+//! The map type used for the account and dispute lookups of a [Ledger](crate::ledger::Ledger).
+//!
+//! With the `std` feature this is a `HashMap`, without it the crate only
+//! depends on `alloc` and falls back to a `BTreeMap`. Keys are hashed with SipHash, or with
+//! aHash when the `fast-hash` feature is enabled.
+
+#[cfg(feature = "fast-hash")]
+use ahash::{AHasher as Hasher, RandomState};
+#[cfg(all(feature = "std", not(feature = "fast-hash")))]
+use std::collections::hash_map::{DefaultHasher as Hasher, RandomState};
+#[cfg(feature = "std")]
+use std::hash::BuildHasher;
+
+#[cfg(feature = "std")]
+pub type Map<K, V> = std::collections::HashMap<K, V, HashState>;
+
+#[cfg(not(feature = "std"))]
+pub type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+/// How the keys of a [Map] are hashed, see [Ledger::with_hash_state](crate::ledger::Ledger::with_hash_state)
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub enum HashState {
+    /// Randomly seeded keys, making collision attacks on client and transaction ids harder.
+    /// Iteration order differs between runs.
+    Random(RandomState),
+    /// Fixed keys, so the same sequence of transactions always produces the same
+    /// iteration order. Useful to reproduce failures bit-for-bit, but ids chosen by an attacker
+    /// could degrade lookups.
+    Deterministic,
+}
+
+#[cfg(feature = "std")]
+impl Default for HashState {
+    fn default() -> Self {
+        HashState::Random(RandomState::new())
+    }
+}
+
+#[cfg(feature = "std")]
+impl BuildHasher for HashState {
+    type Hasher = Hasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        match self {
+            HashState::Random(state) => state.build_hasher(),
+            HashState::Deterministic => Hasher::default(),
+        }
+    }
+}
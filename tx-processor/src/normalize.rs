@@ -0,0 +1,162 @@
+//! Normalization of records some upstream exports encode unusually, e.g. refunds exported as
+//! withdrawals of a negative amount
+
+use alloc::vec::Vec;
+
+use rust_decimal::Decimal;
+
+use crate::logging::info;
+use crate::transaction::{Provenance, TransactionRecord, TransactionRecordType};
+
+/// A record rewritten by [Normalize], as it was read and as it was passed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalization {
+    pub client_id: u16,
+    pub transaction_id: u32,
+    pub original_type: TransactionRecordType,
+    pub original_amount: Decimal,
+    pub transaction_type: TransactionRecordType,
+    pub amount: Decimal,
+    pub provenance: Option<Provenance>,
+}
+
+/// Rewrites withdrawals of a negative amount as deposits of the opposite amount, and deposits of
+/// a negative amount as withdrawals, before they reach the ledger, which would reject them as
+/// invalid. Every rewrite is logged and kept in the audit log, see [Normalize::audit_log].
+///
+/// Other records, and records that failed to parse, are passed through unchanged. Pass it by
+/// mutable reference to read the audit log afterwards.
+pub struct Normalize<I> {
+    records: I,
+    enabled: bool,
+    audit_log: Vec<Normalization>,
+}
+
+impl<I, E> Normalize<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    /// Normalizes negative amounts of `records`, or passes them all through unless `enabled`
+    pub fn new(records: impl IntoIterator<IntoIter = I>, enabled: bool) -> Self {
+        Normalize {
+            records: records.into_iter(),
+            enabled,
+            audit_log: Vec::new(),
+        }
+    }
+
+    /// The records rewritten so far, in the order they were read
+    pub fn audit_log(&self) -> &[Normalization] {
+        &self.audit_log
+    }
+}
+
+impl<I, E> Iterator for Normalize<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    type Item = Result<TransactionRecord, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = self.records.next()?;
+        if let (true, Ok(record)) = (self.enabled, &mut record) {
+            self.normalize(record);
+        }
+        Some(record)
+    }
+}
+
+impl<I> Normalize<I> {
+    fn normalize(&mut self, record: &mut TransactionRecord) {
+        let opposite = match record.transaction_type {
+            TransactionRecordType::Deposit => TransactionRecordType::Withdrawal,
+            TransactionRecordType::Withdrawal => TransactionRecordType::Deposit,
+            _ => return,
+        };
+        let original_amount = match record.amount {
+            Some(amount) if amount < Decimal::ZERO => amount,
+            _ => return,
+        };
+        let normalization = Normalization {
+            client_id: record.client_id,
+            transaction_id: record.transaction_id,
+            original_type: record.transaction_type,
+            original_amount,
+            transaction_type: opposite,
+            amount: -original_amount,
+            provenance: record.provenance.clone(),
+        };
+        info!("Normalized Transaction: {:?}", normalization);
+        record.transaction_type = opposite;
+        record.amount = Some(normalization.amount);
+        self.audit_log.push(normalization);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(
+        transaction_type: TransactionRecordType,
+        transaction_id: u32,
+        amount: Option<i64>,
+    ) -> Result<TransactionRecord, ()> {
+        Ok(TransactionRecord {
+            amount: amount.map(Decimal::from),
+            ..TransactionRecord::new(transaction_type, 1, transaction_id)
+        })
+    }
+
+    #[test]
+    fn test_normalize() {
+        let records = || {
+            vec![
+                record(TransactionRecordType::Deposit, 1, Some(10)),
+                // a refund
+                record(TransactionRecordType::Withdrawal, 2, Some(-3)),
+                record(TransactionRecordType::Deposit, 3, Some(-2)),
+                record(TransactionRecordType::Withdrawal, 4, None),
+                record(TransactionRecordType::Dispute, 2, Some(-3)),
+            ]
+        };
+        let normalized = |normalize: &mut Normalize<_>| {
+            normalize
+                .map(|record: Result<TransactionRecord, ()>| {
+                    let record = record.unwrap();
+                    (record.transaction_type, record.amount)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut normalize = Normalize::new(records(), true);
+        assert_eq!(
+            normalized(&mut normalize),
+            vec![
+                (TransactionRecordType::Deposit, Some(Decimal::from(10))),
+                (TransactionRecordType::Deposit, Some(Decimal::from(3))),
+                (TransactionRecordType::Withdrawal, Some(Decimal::from(2))),
+                (TransactionRecordType::Withdrawal, None),
+                (TransactionRecordType::Dispute, Some(Decimal::from(-3))),
+            ]
+        );
+        assert_eq!(
+            normalize
+                .audit_log()
+                .iter()
+                .map(|normalization| (normalization.transaction_id, normalization.original_type))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, TransactionRecordType::Withdrawal),
+                (3, TransactionRecordType::Deposit)
+            ]
+        );
+
+        let mut normalize = Normalize::new(records(), false);
+        assert_eq!(
+            normalized(&mut normalize)[1],
+            (TransactionRecordType::Withdrawal, Some(Decimal::from(-3)))
+        );
+        assert!(normalize.audit_log().is_empty());
+    }
+}
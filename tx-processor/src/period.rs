@@ -0,0 +1,426 @@
+//! Statement periods, closed like the books at a monthly close: the transactions applied since
+//! the previous close are frozen into an immutable, checksummed [PeriodArchive], and records
+//! dated within a closed period are handled by a [LateArrivalPolicy] rather than changing it
+//! after the fact
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use rust_decimal::Decimal;
+
+use crate::error::TxError;
+use crate::logging::info;
+use crate::policies::{LateArrivalPolicy, Policy};
+use crate::transaction::{Provenance, Transaction, TransactionType};
+use crate::tx_log::TransactionLog;
+
+/// The columns of [PeriodArchive::contents], in order
+pub const PERIOD_ARCHIVE_COLUMNS: [&str; 6] =
+    ["type", "client", "tx", "amount", "timestamp", "note"];
+
+/// What was applied during a period, counted from the transactions of the period in the
+/// ledger's log
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PeriodStats {
+    pub transactions: u64,
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub disputes: u64,
+    pub chargebacks: u64,
+    /// The sum of the deposits
+    pub deposited: Decimal,
+    /// The sum of the withdrawals
+    pub withdrawn: Decimal,
+    /// The transactions dated within an earlier, closed period, applied to this one by
+    /// [LateArrivalPolicy::Adjust]
+    pub adjustments: u64,
+}
+
+impl PeriodStats {
+    /// The stats of the `transactions` of a period following a period ending at `after`
+    fn of(transactions: impl IntoIterator<Item = Transaction>, after: Option<i64>) -> Self {
+        let mut stats = PeriodStats::default();
+        for transaction in transactions {
+            stats.record(&transaction, after);
+        }
+        stats
+    }
+
+    fn record(&mut self, transaction: &Transaction, after: Option<i64>) {
+        self.transactions += 1;
+        if is_late(transaction.timestamp, after) {
+            self.adjustments += 1;
+        }
+        match transaction.tx_type {
+            TransactionType::Deposit { amount } => {
+                self.deposits += 1;
+                self.deposited += Decimal::from(amount);
+            }
+            TransactionType::Withdrawal { amount } => {
+                self.withdrawals += 1;
+                self.withdrawn += Decimal::from(amount);
+            }
+            TransactionType::Dispute => self.disputes += 1,
+            TransactionType::Chargeback => self.chargebacks += 1,
+            TransactionType::Resolve
+            | TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. }
+            | TransactionType::Other { .. } => {}
+        }
+    }
+}
+
+/// A closed period, see [Ledger::close_period](crate::ledger::Ledger::close_period). Archives
+/// can't be changed once closed: [PeriodArchive::sha256] is the SHA-256 of
+/// [PeriodArchive::contents], so a copy written elsewhere can be checked with any SHA-256 tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodArchive {
+    label: String,
+    /// The end of the period before, see [PeriodArchive::end]
+    after: Option<i64>,
+    end: Option<i64>,
+    stats: PeriodStats,
+    transactions: Arc<[Transaction]>,
+    sha256: String,
+}
+
+impl PeriodArchive {
+    /// E.g. `2024-01`
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The latest timestamp of the period's transactions or of an earlier period, if any.
+    /// Records timestamped at or before it are late arrivals, see [LateArrivalPolicy].
+    pub fn end(&self) -> Option<i64> {
+        self.end
+    }
+
+    pub fn stats(&self) -> &PeriodStats {
+        &self.stats
+    }
+
+    /// The transactions applied during the period, in the order they were applied, of those
+    /// still in the ledger's log when it was closed
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// The hex-encoded SHA-256 of [PeriodArchive::contents]
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    /// The transactions of the period as CSV, one `type,client,tx,amount,timestamp,note` row
+    /// per transaction preceded by a header row. The note of flag and unflag records is the
+    /// flag, so the statement shows the annotations of each account.
+    pub fn contents(&self) -> String {
+        contents(&self.transactions)
+    }
+}
+
+fn contents(transactions: &[Transaction]) -> String {
+    let mut contents = PERIOD_ARCHIVE_COLUMNS.join(",");
+    contents.push('\n');
+    for transaction in transactions {
+        let amount = match &transaction.tx_type {
+            TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                Some(Decimal::from(*amount))
+            }
+            TransactionType::Other { amount, .. } => amount.map(Decimal::from),
+            _ => None,
+        };
+        // writing to a string can't fail
+        let _ = write!(
+            contents,
+            "{},{},{},",
+            transaction.tx_type.record_type().name(),
+            transaction.client_id,
+            transaction.transaction_id
+        );
+        if let Some(amount) = amount {
+            let _ = write!(contents, "{}", amount);
+        }
+        contents.push(',');
+        if let Some(timestamp) = transaction.timestamp {
+            let _ = write!(contents, "{}", timestamp);
+        }
+        contents.push(',');
+        if let TransactionType::Flag { flag } | TransactionType::Unflag { flag } =
+            &transaction.tx_type
+        {
+            push_field(&mut contents, flag);
+        }
+        contents.push('\n');
+    }
+    contents
+}
+
+/// Appends `field`, quoted if it contains a separator or quote
+fn push_field(contents: &mut String, field: &str) {
+    if field.contains([',', '"', '\n', '\r']) {
+        contents.push('"');
+        contents.push_str(&field.replace('"', "\"\""));
+        contents.push('"');
+    } else {
+        contents.push_str(field);
+    }
+}
+
+fn is_late(timestamp: Option<i64>, closed_through: Option<i64>) -> bool {
+    matches!((timestamp, closed_through), (Some(timestamp), Some(end)) if timestamp <= end)
+}
+
+/// A record timestamped within a closed period and what the [LateArrivalPolicy] did with it,
+/// kept as an audit entry, see [Ledger::late_arrivals](crate::ledger::Ledger::late_arrivals)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LateArrival {
+    pub client_id: u16,
+    pub transaction_id: u32,
+    pub timestamp: i64,
+    /// The label of the closed period the record is dated within, `None` for periods closed
+    /// before the ledger was restored
+    pub period: Option<String>,
+    pub action: LateArrivalPolicy,
+    /// Where the record was read from
+    pub provenance: Option<Provenance>,
+}
+
+/// The open period of a ledger and the periods it closed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Periods {
+    /// Where the open period starts in the ledger's log
+    pub(crate) start: usize,
+    /// The end of the latest closed period
+    pub(crate) closed_through: Option<i64>,
+    pub(crate) policy: LateArrivalPolicy,
+    closed: Vec<PeriodArchive>,
+    late_arrivals: Vec<LateArrival>,
+}
+
+impl Periods {
+    /// Rejects a record timestamped within a closed period under [LateArrivalPolicy::Reject].
+    /// Records the other policies accept are handled by [Periods::arrive] once they're applied.
+    pub(crate) fn check(&mut self, transaction: &Transaction) -> Result<(), TxError> {
+        if self.policy == LateArrivalPolicy::Reject
+            && is_late(transaction.timestamp, self.closed_through)
+        {
+            self.log(transaction, LateArrivalPolicy::Reject);
+            return Err(TxError::ClosedPeriod);
+        }
+        Ok(())
+    }
+
+    /// Handles an applied `transaction` if it is a late arrival: logs its adjustment of the
+    /// open period, or reopens the periods it is dated within
+    pub(crate) fn arrive(&mut self, transaction: &Transaction) {
+        let Some(timestamp) = transaction.timestamp else {
+            return;
+        };
+        if !is_late(Some(timestamp), self.closed_through) {
+            return;
+        }
+        self.log(transaction, self.policy);
+        if self.policy != LateArrivalPolicy::Reopen {
+            return;
+        }
+        while let Some(archive) = self.closed.pop() {
+            if archive.end < Some(timestamp) {
+                self.closed.push(archive);
+                break;
+            }
+            self.start = self.start.saturating_sub(archive.transactions.len());
+            self.closed_through = archive.after;
+        }
+        // periods closed before the ledger was restored are reopened from the record on
+        if is_late(Some(timestamp), self.closed_through) {
+            self.closed_through = Some(timestamp.saturating_sub(1));
+        }
+    }
+
+    fn log(&mut self, transaction: &Transaction, action: LateArrivalPolicy) {
+        let timestamp = transaction.timestamp.unwrap_or_default();
+        let period = self
+            .closed
+            .iter()
+            .find(|archive| archive.end >= Some(timestamp))
+            .map(|archive| archive.label.clone());
+        info!(
+            "Late arrival of transaction {} dated {} within a closed period: {}",
+            transaction.transaction_id,
+            timestamp,
+            action.name()
+        );
+        self.late_arrivals.push(LateArrival {
+            client_id: transaction.client_id,
+            transaction_id: transaction.transaction_id,
+            timestamp,
+            period,
+            action,
+            provenance: transaction.provenance.as_deref().cloned(),
+        });
+    }
+
+    pub(crate) fn late_arrivals(&self) -> &[LateArrival] {
+        &self.late_arrivals
+    }
+
+    /// The transactions of the open period in `log`, the ledger's log
+    pub(crate) fn open<'a>(
+        &self,
+        log: &'a TransactionLog,
+    ) -> impl Iterator<Item = Transaction> + 'a {
+        log.iter().skip(self.start)
+    }
+
+    /// Accounts for the oldest `forgotten` transactions dropped from the ledger's log
+    pub(crate) fn forget(&mut self, forgotten: usize) {
+        self.start = self.start.saturating_sub(forgotten);
+    }
+
+    /// What was applied during the open period of `log`
+    pub(crate) fn stats(&self, log: &TransactionLog) -> PeriodStats {
+        PeriodStats::of(self.open(log), self.closed_through)
+    }
+
+    pub(crate) fn closed(&self) -> &[PeriodArchive] {
+        &self.closed
+    }
+
+    /// Archives the transactions of `log` since the open period started as `label`, and opens
+    /// the next period
+    #[cfg(feature = "checksum")]
+    pub(crate) fn close(&mut self, label: String, log: &TransactionLog) -> &PeriodArchive {
+        use sha2::{Digest, Sha256};
+
+        let after = self.closed_through;
+        let mut stats = PeriodStats::default();
+        let transactions: Arc<[Transaction]> = self
+            .open(log)
+            .inspect(|transaction| stats.record(transaction, after))
+            .collect();
+        let sha256 = crate::checksum::hex(&Sha256::digest(contents(&transactions)));
+        let latest = transactions
+            .iter()
+            .filter_map(|transaction| transaction.timestamp)
+            .max();
+        self.closed_through = after.max(latest);
+        self.closed.push(PeriodArchive {
+            label,
+            after,
+            end: self.closed_through,
+            stats,
+            transactions,
+            sha256,
+        });
+        self.start = log.len();
+        self.closed.last().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::PositiveDecimal;
+
+    #[test]
+    fn test_contents() {
+        let amount = PositiveDecimal::try_from(10.5).unwrap();
+        let transactions = [
+            Transaction {
+                timestamp: Some(100),
+                ..Transaction::new(1, 1, TransactionType::Deposit { amount })
+            },
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ];
+        assert_eq!(
+            contents(&transactions),
+            "type,client,tx,amount,timestamp,note\n\
+             deposit,1,1,10.5000,100,\n\
+             dispute,1,1,,,\n"
+        );
+        let flags = [
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Flag {
+                    flag: "under_review".into(),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Unflag {
+                    flag: "vip, \"gold\"".into(),
+                },
+            ),
+        ];
+        assert_eq!(
+            contents(&flags),
+            "type,client,tx,amount,timestamp,note\n\
+             flag,1,2,,,under_review\n\
+             unflag,1,3,,,\"vip, \"\"gold\"\"\"\n"
+        );
+
+        let mut periods = Periods::default();
+        periods.check(&transactions[0]).unwrap();
+        periods.closed_through = Some(100);
+        assert!(matches!(
+            periods.check(&transactions[0]),
+            Err(TxError::ClosedPeriod)
+        ));
+        periods.check(&transactions[1]).unwrap();
+        assert_eq!(periods.late_arrivals().len(), 1);
+        periods.closed_through = Some(99);
+        periods.check(&transactions[0]).unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let amount = PositiveDecimal::try_from(1.0).unwrap();
+        let deposit = |transaction_id, timestamp| Transaction {
+            timestamp: Some(timestamp),
+            ..Transaction::new(1, transaction_id, TransactionType::Deposit { amount })
+        };
+        let mut periods = Periods {
+            policy: LateArrivalPolicy::Reopen,
+            ..Periods::default()
+        };
+        periods.closed.push(PeriodArchive {
+            label: "2024-01".into(),
+            after: None,
+            end: Some(100),
+            stats: PeriodStats::default(),
+            transactions: [deposit(1, 100)].into(),
+            sha256: String::new(),
+        });
+        periods.closed.push(PeriodArchive {
+            label: "2024-02".into(),
+            after: Some(100),
+            end: Some(200),
+            stats: PeriodStats::default(),
+            transactions: [deposit(2, 200)].into(),
+            sha256: String::new(),
+        });
+        periods.start = 2;
+        periods.closed_through = Some(200);
+
+        periods.check(&deposit(3, 150)).unwrap();
+        periods.arrive(&deposit(3, 150));
+        assert_eq!(
+            periods.late_arrivals()[0].period.as_deref(),
+            Some("2024-02")
+        );
+        assert_eq!(periods.closed().len(), 1);
+        assert_eq!((periods.start, periods.closed_through), (1, Some(100)));
+
+        // without the archive of the period, it is reopened from the record on
+        periods.closed.clear();
+        periods.arrive(&deposit(4, 50));
+        assert_eq!(periods.late_arrivals()[1].period, None);
+        assert_eq!(periods.closed_through, Some(49));
+    }
+}
@@ -0,0 +1,445 @@
+//! Every configurable behaviour of a [Ledger](crate::ledger::Ledger) and its input, each an enum
+//! that can describe itself, so a run can print the semantics it applied
+
+use alloc::vec::Vec;
+
+use crate::limits::LimitPolicy;
+use crate::transaction::TransactionRecordType;
+
+/// A configurable behaviour, one variant per choice
+pub trait Policy: Copy + Default + 'static {
+    /// The behaviour the policy configures, e.g. `withdrawal disputes`
+    const SUBJECT: &'static str;
+    /// Every choice
+    const ALL: &'static [Self];
+
+    /// The name selecting this choice, e.g. on the command line
+    fn name(&self) -> &'static str;
+
+    /// What this choice does, in a sentence for auditors
+    fn describe(&self) -> &'static str;
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|policy| policy.name() == name)
+    }
+}
+
+/// What disputing a withdrawal does
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WithdrawalDisputePolicy {
+    #[default]
+    Hold,
+    Reject,
+}
+
+impl Policy for WithdrawalDisputePolicy {
+    const SUBJECT: &'static str = "withdrawal disputes";
+    const ALL: &'static [Self] = &[
+        WithdrawalDisputePolicy::Hold,
+        WithdrawalDisputePolicy::Reject,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            WithdrawalDisputePolicy::Hold => "hold",
+            WithdrawalDisputePolicy::Reject => "reject",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            WithdrawalDisputePolicy::Hold => {
+                "Withdrawals can be disputed like deposits: the amount moves from the available \
+                 to the held funds until the dispute is resolved or charged back."
+            }
+            WithdrawalDisputePolicy::Reject => {
+                "Only deposits can be disputed. Disputes of withdrawals are rejected as not \
+                 disputable."
+            }
+        }
+    }
+}
+
+/// What happens to input records repeating an earlier record's transaction id and type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    #[default]
+    Apply,
+    Skip,
+}
+
+impl Policy for DuplicatePolicy {
+    const SUBJECT: &'static str = "duplicate records";
+    const ALL: &'static [Self] = &[DuplicatePolicy::Apply, DuplicatePolicy::Skip];
+
+    fn name(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Apply => "apply",
+            DuplicatePolicy::Skip => "skip",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Apply => {
+                "Repeated deposits and withdrawals are applied again. Repeated disputes, \
+                 resolves and chargebacks are rejected by the dispute rules."
+            }
+            DuplicatePolicy::Skip => {
+                "Records repeating the transaction id and type of an earlier record are counted \
+                 and skipped before they reach the ledger, see `tx_processor::dedup`."
+            }
+        }
+    }
+}
+
+/// What happens to transactions of clients without an account
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownClientPolicy {
+    #[default]
+    Open,
+    Reject,
+}
+
+impl Policy for UnknownClientPolicy {
+    const SUBJECT: &'static str = "unknown clients";
+    const ALL: &'static [Self] = &[UnknownClientPolicy::Open, UnknownClientPolicy::Reject];
+
+    fn name(&self) -> &'static str {
+        match self {
+            UnknownClientPolicy::Open => "open",
+            UnknownClientPolicy::Reject => "reject",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            UnknownClientPolicy::Open => {
+                "The first transaction of a client opens an account with no funds."
+            }
+            UnknownClientPolicy::Reject => {
+                "Only clients with an opening balance or state have accounts. Transactions of \
+                 other clients are rejected as unknown."
+            }
+        }
+    }
+}
+
+/// What happens to disputes, resolves and chargebacks of transactions that haven't arrived
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownTransactionPolicy {
+    #[default]
+    Reject,
+    Suspend,
+}
+
+impl Policy for UnknownTransactionPolicy {
+    const SUBJECT: &'static str = "unknown transactions";
+    const ALL: &'static [Self] = &[
+        UnknownTransactionPolicy::Reject,
+        UnknownTransactionPolicy::Suspend,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            UnknownTransactionPolicy::Reject => "reject",
+            UnknownTransactionPolicy::Suspend => "suspend",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            UnknownTransactionPolicy::Reject => {
+                "Disputes, resolves and chargebacks of unknown transactions are rejected as not \
+                 found."
+            }
+            UnknownTransactionPolicy::Suspend => {
+                "Disputes, resolves and chargebacks of unknown transactions are parked in \
+                 suspense and applied once the transaction arrives."
+            }
+        }
+    }
+}
+
+/// What happens to records whose type is neither a [TransactionRecordType] nor an alias of one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownTypePolicy {
+    #[default]
+    Reject,
+    Quarantine,
+    Skip,
+}
+
+impl Policy for UnknownTypePolicy {
+    const SUBJECT: &'static str = "unknown types";
+    const ALL: &'static [Self] = &[
+        UnknownTypePolicy::Reject,
+        UnknownTypePolicy::Quarantine,
+        UnknownTypePolicy::Skip,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            UnknownTypePolicy::Reject => "reject",
+            UnknownTypePolicy::Quarantine => "quarantine",
+            UnknownTypePolicy::Skip => "skip",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            UnknownTypePolicy::Reject => {
+                "Records of unknown types are rejected as malformed, and only logged."
+            }
+            UnknownTypePolicy::Quarantine => {
+                "Records of unknown types are set aside and listed in the unknown types report, \
+                 to be mapped to a type and processed again."
+            }
+            UnknownTypePolicy::Skip => "Records of unknown types are counted by type and skipped.",
+        }
+    }
+}
+
+/// What happens to deposit or withdrawal records without an amount
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissingAmountPolicy {
+    #[default]
+    Reject,
+    Zero,
+    Quarantine,
+}
+
+impl Policy for MissingAmountPolicy {
+    const SUBJECT: &'static str = "missing amounts";
+    const ALL: &'static [Self] = &[
+        MissingAmountPolicy::Reject,
+        MissingAmountPolicy::Zero,
+        MissingAmountPolicy::Quarantine,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            MissingAmountPolicy::Reject => "reject",
+            MissingAmountPolicy::Zero => "zero",
+            MissingAmountPolicy::Quarantine => "quarantine",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            MissingAmountPolicy::Reject => {
+                "Records without an amount are rejected as malformed, and only logged."
+            }
+            MissingAmountPolicy::Zero => {
+                "Records without an amount are applied with an amount of zero, as placeholders \
+                 that can be referenced and disputed like any other transaction."
+            }
+            MissingAmountPolicy::Quarantine => {
+                "Records without an amount are set aside and listed in the reject report, to be \
+                 corrected and processed again."
+            }
+        }
+    }
+}
+
+/// What happens to deposits and withdrawals of a negative amount
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NegativeAmountPolicy {
+    #[default]
+    Reject,
+    Normalize,
+}
+
+impl Policy for NegativeAmountPolicy {
+    const SUBJECT: &'static str = "negative amounts";
+    const ALL: &'static [Self] = &[
+        NegativeAmountPolicy::Reject,
+        NegativeAmountPolicy::Normalize,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            NegativeAmountPolicy::Reject => "reject",
+            NegativeAmountPolicy::Normalize => "normalize",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            NegativeAmountPolicy::Reject => {
+                "Deposits and withdrawals of a negative amount are rejected as invalid."
+            }
+            NegativeAmountPolicy::Normalize => {
+                "Withdrawals of a negative amount, e.g. exported refunds, are applied as deposits \
+                 of the opposite amount, and negative deposits as withdrawals. Every rewritten \
+                 record is kept in the audit log, see `tx_processor::normalize`."
+            }
+        }
+    }
+}
+
+/// What happens to records timestamped within a closed statement period, see
+/// [Ledger::close_period](crate::ledger::Ledger::close_period)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LateArrivalPolicy {
+    #[default]
+    Reject,
+    Adjust,
+    Reopen,
+}
+
+impl Policy for LateArrivalPolicy {
+    const SUBJECT: &'static str = "late arrivals";
+    const ALL: &'static [Self] = &[
+        LateArrivalPolicy::Reject,
+        LateArrivalPolicy::Adjust,
+        LateArrivalPolicy::Reopen,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            LateArrivalPolicy::Reject => "reject",
+            LateArrivalPolicy::Adjust => "adjust",
+            LateArrivalPolicy::Reopen => "reopen",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            LateArrivalPolicy::Reject => {
+                "Records timestamped within a closed period are rejected, leaving the period as \
+                 it was archived."
+            }
+            LateArrivalPolicy::Adjust => {
+                "Records timestamped within a closed period are applied to the open period and \
+                 counted as its adjustments, leaving the closed period as it was archived."
+            }
+            LateArrivalPolicy::Reopen => {
+                "As an administrator's override, records timestamped within a closed period \
+                 reopen it: its transactions return to the open period, to be archived again at \
+                 the next close."
+            }
+        }
+    }
+}
+
+impl Policy for LimitPolicy {
+    const SUBJECT: &'static str = "limits";
+    const ALL: &'static [Self] = &[LimitPolicy::Abort, LimitPolicy::Degrade];
+
+    fn name(&self) -> &'static str {
+        match self {
+            LimitPolicy::Abort => "abort",
+            LimitPolicy::Degrade => "degrade",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            LimitPolicy::Abort => {
+                "Reaching a configured limit on accounts, retained transactions or open \
+                 disputes stops the run without output."
+            }
+            LimitPolicy::Degrade => {
+                "Reaching a configured limit rejects new accounts and disputes beyond it, and \
+                 forgets the oldest transactions, which can then no longer be disputed."
+            }
+        }
+    }
+}
+
+/// The choice of every [Policy], see
+/// [LedgerBuilder::policies](crate::ledger::LedgerBuilder::policies)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Policies {
+    pub withdrawal_disputes: WithdrawalDisputePolicy,
+    /// Applied to the input rather than the ledger, e.g. with [Dedup](crate::dedup::Dedup)
+    pub duplicates: DuplicatePolicy,
+    pub unknown_clients: UnknownClientPolicy,
+    pub unknown_transactions: UnknownTransactionPolicy,
+    /// Applied to the input rather than the ledger, see [TypeTable](crate::types::TypeTable)
+    pub unknown_types: UnknownTypePolicy,
+    pub missing_deposit_amounts: MissingAmountPolicy,
+    pub missing_withdrawal_amounts: MissingAmountPolicy,
+    /// Applied to the input rather than the ledger, e.g. with
+    /// [Normalize](crate::normalize::Normalize)
+    pub negative_amounts: NegativeAmountPolicy,
+    pub limits: LimitPolicy,
+    pub late_arrivals: LateArrivalPolicy,
+}
+
+/// A policy's subject, the name of its choice and what the choice does
+pub type PolicyDescription = (&'static str, &'static str, &'static str);
+
+fn description<P: Policy>(policy: P) -> PolicyDescription {
+    (P::SUBJECT, policy.name(), policy.describe())
+}
+
+impl Policies {
+    /// The policy for records of `transaction_type` without an amount, `None` for the types
+    /// that don't have one
+    pub fn missing_amounts(
+        &self,
+        transaction_type: TransactionRecordType,
+    ) -> Option<MissingAmountPolicy> {
+        match transaction_type {
+            TransactionRecordType::Deposit => Some(self.missing_deposit_amounts),
+            TransactionRecordType::Withdrawal => Some(self.missing_withdrawal_amounts),
+            _ => None,
+        }
+    }
+
+    /// Every policy and what its choice does, in a fixed order
+    pub fn describe(&self) -> Vec<PolicyDescription> {
+        Vec::from([
+            description(self.withdrawal_disputes),
+            description(self.duplicates),
+            description(self.unknown_clients),
+            description(self.unknown_transactions),
+            description(self.unknown_types),
+            (
+                "missing deposit amounts",
+                self.missing_deposit_amounts.name(),
+                self.missing_deposit_amounts.describe(),
+            ),
+            (
+                "missing withdrawal amounts",
+                self.missing_withdrawal_amounts.name(),
+                self.missing_withdrawal_amounts.describe(),
+            ),
+            description(self.negative_amounts),
+            description(self.limits),
+            description(self.late_arrivals),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check_names<P: Policy + PartialEq + core::fmt::Debug>() {
+        for &policy in P::ALL {
+            assert_eq!(P::from_name(policy.name()), Some(policy));
+        }
+        assert!(P::ALL.contains(&P::default()));
+        assert_eq!(P::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn test_policy_names() {
+        check_names::<WithdrawalDisputePolicy>();
+        check_names::<DuplicatePolicy>();
+        check_names::<UnknownClientPolicy>();
+        check_names::<UnknownTransactionPolicy>();
+        check_names::<UnknownTypePolicy>();
+        check_names::<MissingAmountPolicy>();
+        check_names::<NegativeAmountPolicy>();
+        check_names::<LimitPolicy>();
+        check_names::<LateArrivalPolicy>();
+        assert_eq!(Policies::default().describe().len(), 10);
+    }
+}
@@ -0,0 +1,126 @@
+//! Pseudonymization of client ids in reports, so processing results can be shared with vendors
+//! without exposing the ids
+
+use alloc::string::String;
+use core::fmt::Write as _;
+use std::io;
+
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::TxError;
+
+/// The number of bytes of the HMAC kept in a pseudonym
+const PSEUDONYM_LEN: usize = 8;
+
+/// The prefix of references to opening balances in general ledger exports, see
+/// [Journal::write_gl_csv](crate::journal::Journal::write_gl_csv)
+const OPENING_REFERENCE: &str = "opening-";
+
+/// Replaces client ids with the hex-encoded HMAC-SHA256 of the id under a secret key. The same
+/// key always gives the same pseudonym, so reports of a run, or of runs sharing the key, can
+/// still be joined on them.
+#[derive(Clone)]
+pub struct Pseudonymizer {
+    mac: Hmac<Sha256>,
+}
+
+impl Pseudonymizer {
+    pub fn new(key: &[u8]) -> Self {
+        Pseudonymizer {
+            // HMAC accepts keys of any length
+            mac: Hmac::new_from_slice(key).unwrap(),
+        }
+    }
+
+    /// The pseudonym of `client_id`
+    pub fn client(&self, client_id: u16) -> String {
+        let mut mac = self.mac.clone();
+        mac.update(&client_id.to_be_bytes());
+        let mut pseudonym = String::with_capacity(2 * PSEUDONYM_LEN);
+        for byte in &mac.finalize().into_bytes()[..PSEUDONYM_LEN] {
+            write!(pseudonym, "{:02x}", byte).unwrap();
+        }
+        pseudonym
+    }
+
+    /// Copies a CSV report with a header row from `reader` to `writer`, replacing the ids of its
+    /// `client` column and of the opening balance references of its `reference` column
+    pub fn rewrite_csv<R: io::Read, W: io::Write>(
+        &self,
+        reader: R,
+        writer: W,
+    ) -> Result<(), TxError> {
+        let mut reader = ReaderBuilder::new().flexible(true).from_reader(reader);
+        let mut writer = WriterBuilder::new().flexible(true).from_writer(writer);
+        let headers = reader.headers()?.clone();
+        // reports without rows may be empty, without a header row either
+        if headers.is_empty() {
+            return Ok(());
+        }
+        writer.write_record(&headers)?;
+        let client = headers.iter().position(|header| header == "client");
+        let reference = headers.iter().position(|header| header == "reference");
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let fields = record.iter().enumerate().map(|(i, field)| {
+                let pseudonym = if Some(i) == client {
+                    field.parse().ok().map(|id| self.client(id))
+                } else if Some(i) == reference {
+                    field
+                        .strip_prefix(OPENING_REFERENCE)
+                        .and_then(|id| id.parse().ok())
+                        .map(|id| format!("{}{}", OPENING_REFERENCE, self.client(id)))
+                } else {
+                    None
+                };
+                pseudonym.unwrap_or_else(|| field.into())
+            });
+            writer.write_record(fields)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pseudonymizer() {
+        let pseudonymizer = Pseudonymizer::new(b"secret");
+        assert_eq!(pseudonymizer.client(1), pseudonymizer.client(1));
+        assert_ne!(pseudonymizer.client(1), pseudonymizer.client(2));
+        assert_ne!(
+            pseudonymizer.client(1),
+            Pseudonymizer::new(b"other").client(1)
+        );
+        assert_eq!(pseudonymizer.client(1).len(), 16);
+
+        let mut csv = Vec::new();
+        pseudonymizer
+            .rewrite_csv("client,available\n1,10.0000\n".as_bytes(), &mut csv)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!("client,available\n{},10.0000\n", pseudonymizer.client(1))
+        );
+
+        let mut csv = Vec::new();
+        pseudonymizer
+            .rewrite_csv(
+                "date,reference\n2024-01-31,opening-2\n2024-01-31,deposit-2\n".as_bytes(),
+                &mut csv,
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!(
+                "date,reference\n2024-01-31,opening-{}\n2024-01-31,deposit-2\n",
+                pseudonymizer.client(2)
+            )
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Reading inputs from and writing outputs to object stores, e.g. `s3://bucket/tx.csv` or
+//! `gs://bucket/tx.csv`, without downloading them first. Inputs are read in ranges as they are
+//! processed, and outputs are uploaded when they are finished.
+//!
+//! Credentials, regions and endpoints are read from the environment, e.g. `AWS_ACCESS_KEY_ID`,
+//! `AWS_REGION` or `GOOGLE_SERVICE_ACCOUNT`. `file://` URLs address the local file system
+//! through the same code.
+
+use std::io::{self, Cursor, Read, Write};
+
+use object_store::path::Path;
+use object_store::{GetOptions, ObjectMeta, ObjectStore};
+use tokio::runtime::Runtime;
+use url::Url;
+
+/// The URL schemes of object stores
+const SCHEMES: &[&str] = &["s3", "s3a", "gs", "file"];
+
+/// The size of the ranges inputs are read in
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// `true` if `path` is the URL of an object rather than a local path
+pub fn is_remote(path: &str) -> bool {
+    path.split_once("://")
+        .is_some_and(|(scheme, _)| SCHEMES.contains(&scheme))
+}
+
+/// An object of a store, with the runtime its requests are made on
+struct Object {
+    runtime: Runtime,
+    store: Box<dyn ObjectStore>,
+    path: Path,
+}
+
+impl Object {
+    fn new(url: &str) -> io::Result<Self> {
+        let url = Url::parse(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // the stores' configuration keys are the lowercase environment variables
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, path) =
+            object_store::parse_url_opts(&url, options).map_err(io::Error::other)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Object {
+            runtime,
+            store,
+            path,
+        })
+    }
+}
+
+/// An object read in ranges of 8 MiB as it's consumed, see [crate::io::open_input]. Every range
+/// is read from the version of the object opened: if it's replaced while it's read, reading
+/// fails rather than mixing the contents of both versions.
+pub struct ObjectReader {
+    object: Object,
+    /// The object as opened, whose ETag or version later ranges must match
+    meta: ObjectMeta,
+    /// The offset of the next range to fetch
+    offset: u64,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl ObjectReader {
+    pub fn open(url: &str) -> io::Result<Self> {
+        let object = Object::new(url)?;
+        let meta = object
+            .runtime
+            .block_on(object.store.head(&object.path))
+            .map_err(io::Error::other)?;
+        Ok(ObjectReader {
+            object,
+            meta,
+            offset: 0,
+            chunk: Cursor::default(),
+        })
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.chunk.read(buf)?;
+        if read > 0 || buf.is_empty() || self.offset >= self.meta.size {
+            return Ok(read);
+        }
+        let end = self.meta.size.min(self.offset + CHUNK_SIZE);
+        let Object {
+            runtime,
+            store,
+            path,
+        } = &self.object;
+        // stores without ETags are checked by modification time
+        let options = GetOptions {
+            if_match: self.meta.e_tag.clone(),
+            if_unmodified_since: self.meta.e_tag.is_none().then_some(self.meta.last_modified),
+            version: self.meta.version.clone(),
+            range: Some((self.offset..end).into()),
+            ..GetOptions::default()
+        };
+        let bytes = runtime
+            .block_on(async { store.get_opts(path, options).await?.bytes().await })
+            .map_err(|e| match e {
+                object_store::Error::Precondition { .. } => {
+                    io::Error::other(format!("{} changed while it was read", self.meta.location))
+                }
+                e => io::Error::other(e),
+            })?;
+        self.offset = end;
+        self.chunk = Cursor::new(bytes.into());
+        self.chunk.read(buf)
+    }
+}
+
+/// An object written in memory and uploaded by [ObjectWriter::finish], see
+/// [OutputFile](crate::io::OutputFile)
+pub struct ObjectWriter {
+    object: Object,
+    buffer: Vec<u8>,
+}
+
+impl ObjectWriter {
+    pub fn create(url: &str) -> io::Result<Self> {
+        Ok(ObjectWriter {
+            object: Object::new(url)?,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Uploads the object. It's only visible in the store once this succeeds.
+    pub fn finish(self) -> io::Result<()> {
+        let Object {
+            runtime,
+            store,
+            path,
+        } = self.object;
+        runtime
+            .block_on(store.put(&path, self.buffer.into()))
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+impl Write for ObjectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ObjectWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectWriter")
+            .field("path", &self.object.path)
+            .field("buffered", &self.buffer.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_remote() {
+        assert!(is_remote("s3://bucket/tx.csv"));
+        assert!(is_remote("gs://bucket/tx.csv"));
+        assert!(!is_remote("tx.csv"));
+        assert!(!is_remote("tcp://localhost:1234"));
+    }
+
+    #[test]
+    fn test_write_and_read_object() {
+        let path = std::env::temp_dir().join("tx-processor-test-object.csv");
+        let url = format!("file://{}", path.display());
+        let mut writer = ObjectWriter::create(&url).unwrap();
+        writer.write_all(b"type,client,tx,amount\n").unwrap();
+        // nothing is written before the upload
+        assert!(!path.exists());
+        writer.finish().unwrap();
+
+        let mut contents = String::new();
+        ObjectReader::open(&url)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "type,client,tx,amount\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_object_replaced_while_read() {
+        let path = std::env::temp_dir().join("tx-processor-test-replaced-object.csv");
+        let url = format!("file://{}", path.display());
+        let write = |contents: &[u8]| {
+            let mut writer = ObjectWriter::create(&url).unwrap();
+            writer.write_all(contents).unwrap();
+            writer.finish().unwrap();
+        };
+        write(b"type,client,tx,amount\n");
+        let mut reader = ObjectReader::open(&url).unwrap();
+        write(b"type,client,tx,amount\ndeposit,1,1,1.0\n");
+
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(error.to_string().ends_with("changed while it was read"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_object() {
+        let path = std::env::temp_dir().join("tx-processor-test-missing-object.csv");
+        assert!(ObjectReader::open(&format!("file://{}", path.display())).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Tolerance for feeds that deliver records slightly out of order, e.g. partitioned Kafka topics
+
+use alloc::collections::BinaryHeap;
+use core::cmp::{Ordering, Reverse};
+
+use crate::transaction::TransactionRecord;
+
+/// How often a [ReorderBuffer] had to reorder records
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReorderStats {
+    /// Records that arrived after a record with a higher `seq`, and were moved in front of it
+    pub reordered: usize,
+    /// Records that arrived after a record with a higher `seq` had already been released, so
+    /// they could only be released out of order
+    pub late: usize,
+}
+
+/// A record held back by a [ReorderBuffer], ordered by `seq` and then by arrival
+struct Held<E> {
+    seq: u64,
+    arrival: u64,
+    record: Result<TransactionRecord, E>,
+}
+
+impl<E> PartialEq for Held<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<E> Eq for Held<E> {}
+
+impl<E> PartialOrd for Held<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E> Ord for Held<E> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.seq, self.arrival).cmp(&(other.seq, other.arrival))
+    }
+}
+
+/// Delays records by a window of [TransactionRecord::seq] values, releasing them in `seq`
+/// order. A record is released once a record with a `seq` at least `window` higher arrived, or
+/// the input ended. Records without a `seq`, and records that failed to parse, are treated as
+/// having the highest `seq` seen so far.
+///
+/// Wraps the records passed to e.g.
+/// [Ledger::process_csv_transactions](crate::ledger::Ledger::process_csv_transactions). Pass it
+/// by mutable reference to read its [ReorderStats] afterwards.
+pub struct ReorderBuffer<I, E> {
+    records: I,
+    window: u64,
+    held: BinaryHeap<Reverse<Held<E>>>,
+    arrivals: u64,
+    /// The highest `seq` that arrived
+    highest: u64,
+    /// The `seq` of the last released record
+    released: Option<u64>,
+    stats: ReorderStats,
+}
+
+impl<I, E> ReorderBuffer<I, E>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    pub fn new(records: impl IntoIterator<IntoIter = I>, window: u64) -> Self {
+        ReorderBuffer {
+            records: records.into_iter(),
+            window,
+            held: BinaryHeap::new(),
+            arrivals: 0,
+            highest: 0,
+            released: None,
+            stats: ReorderStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> ReorderStats {
+        self.stats
+    }
+
+    fn hold(&mut self, record: Result<TransactionRecord, E>) {
+        let seq = match &record {
+            Ok(TransactionRecord { seq: Some(seq), .. }) => *seq,
+            _ => self.highest,
+        };
+        if seq < self.highest {
+            if self.released.is_some_and(|released| seq < released) {
+                self.stats.late += 1;
+            } else {
+                self.stats.reordered += 1;
+            }
+        }
+        self.highest = self.highest.max(seq);
+        self.held.push(Reverse(Held {
+            seq,
+            arrival: self.arrivals,
+            record,
+        }));
+        self.arrivals += 1;
+    }
+
+    fn release(&mut self) -> Option<Result<TransactionRecord, E>> {
+        let Reverse(held) = self.held.pop()?;
+        self.released = Some(
+            self.released
+                .map_or(held.seq, |released| released.max(held.seq)),
+        );
+        Some(held.record)
+    }
+}
+
+impl<I, E> Iterator for ReorderBuffer<I, E>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    type Item = Result<TransactionRecord, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(Reverse(oldest)) = self.held.peek() {
+                if self.highest - oldest.seq >= self.window {
+                    return self.release();
+                }
+            }
+            match self.records.next() {
+                Some(record) => self.hold(record),
+                None => return self.release(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionRecordType;
+    use alloc::vec::Vec;
+
+    fn record(seq: Option<u64>, transaction_id: u32) -> Result<TransactionRecord, ()> {
+        Ok(TransactionRecord {
+            seq,
+            ..TransactionRecord::new(TransactionRecordType::Dispute, 1, transaction_id)
+        })
+    }
+
+    #[test]
+    fn test_reorder_buffer() {
+        let records = vec![
+            record(Some(1), 1),
+            record(Some(3), 3),
+            record(Some(2), 2),
+            record(None, 4),
+            record(Some(10), 10),
+            record(Some(2), 5),
+            record(Some(11), 11),
+        ];
+        let mut buffer = ReorderBuffer::new(records, 5);
+        let released = (&mut buffer)
+            .map(|record| record.unwrap().transaction_id)
+            .collect::<Vec<_>>();
+        // 2 is moved in front of 3, while 5 arrives after 3 and 4 were released
+        assert_eq!(released, vec![1, 2, 3, 4, 5, 10, 11]);
+        assert_eq!(
+            buffer.stats(),
+            ReorderStats {
+                reordered: 1,
+                late: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,899 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "csv")]
+use std::io;
+
+#[cfg(feature = "csv")]
+use csv::WriterBuilder;
+#[cfg(feature = "csv")]
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::account::AccountView;
+#[cfg(feature = "csv")]
+use crate::account::{LedgerAccount, ACCOUNT_COLUMNS, ACCOUNT_COLUMNS_V2, ACCOUNT_COLUMNS_V3};
+#[cfg(feature = "csv")]
+use crate::aliases::ClientAliases;
+#[cfg(feature = "csv")]
+use crate::dispute_rules::DisputeDecision;
+use crate::error::TxError;
+#[cfg(feature = "csv")]
+use crate::gaps::Gap;
+#[cfg(feature = "csv")]
+use crate::ledger::{Ledger, Rejection, Suspended};
+use crate::map::Map;
+#[cfg(feature = "csv")]
+use crate::normalize::Normalization;
+#[cfg(feature = "csv")]
+use crate::period::LateArrival;
+#[cfg(feature = "csv")]
+use crate::policies::Policy;
+use crate::transaction::{PositiveDecimal, Provenance, Transaction, TransactionType};
+#[cfg(feature = "csv")]
+use crate::transaction::{TransactionRecord, TransactionRecordType};
+#[cfg(feature = "csv")]
+use crate::types::UnknownTypeRecord;
+
+/// A single charged back transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChargebackRecord {
+    pub client_id: u16,
+    pub transaction_id: u32,
+    pub amount: PositiveDecimal,
+    /// Where the chargeback was read from
+    pub provenance: Option<Provenance>,
+}
+
+/// A row of the chargeback report
+#[cfg(feature = "csv")]
+#[derive(Serialize)]
+struct ChargebackRow<'a> {
+    client: u16,
+    tx: u32,
+    amount: PositiveDecimal,
+    source: Option<&'a str>,
+    line: Option<u64>,
+    batch: Option<&'a str>,
+}
+
+/// The `source`, `line` and `batch` columns of a report row, empty if the transaction has no
+/// provenance
+#[cfg(feature = "csv")]
+fn provenance_columns(
+    provenance: Option<&Provenance>,
+) -> (Option<&str>, Option<u64>, Option<&str>) {
+    match provenance {
+        Some(provenance) => (
+            Some(&provenance.source),
+            Some(provenance.line),
+            provenance.batch.as_deref(),
+        ),
+        None => (None, None, None),
+    }
+}
+
+/// The losses booked through chargebacks, see [Ledger::chargeback_report](crate::ledger::Ledger::chargeback_report)
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChargebackReport {
+    /// Sum of every charged back amount
+    pub total: PositiveDecimal,
+    /// Number of chargebacks per client
+    pub per_client: BTreeMap<u16, usize>,
+    /// Every chargeback, in the order it was applied
+    pub chargebacks: Vec<ChargebackRecord>,
+}
+
+impl ChargebackReport {
+    /// Builds the report from a chronologically ordered log of applied transactions
+    pub(crate) fn from_transactions(
+        transactions: impl IntoIterator<Item = Transaction>,
+    ) -> Result<Self, TxError> {
+        let mut amounts = Map::default();
+        let mut report = ChargebackReport::default();
+        for transaction in transactions {
+            match transaction.tx_type {
+                TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                    amounts.entry(transaction.transaction_id).or_insert(amount);
+                }
+                TransactionType::Chargeback => {
+                    let amount = *amounts
+                        .get(&transaction.transaction_id)
+                        .ok_or(TxError::NotFound)?;
+                    report.total = report.total.checked_add(amount)?;
+                    *report.per_client.entry(transaction.client_id).or_default() += 1;
+                    report.chargebacks.push(ChargebackRecord {
+                        client_id: transaction.client_id,
+                        transaction_id: transaction.transaction_id,
+                        amount,
+                        provenance: transaction.provenance.map(|provenance| *provenance),
+                    });
+                }
+                TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Flag { .. }
+                | TransactionType::Unflag { .. }
+                | TransactionType::Remap { .. }
+                | TransactionType::Other { .. } => {}
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Writes one `client,tx,amount,source,line,batch` row per chargeback, preceded by a header
+    /// row
+    #[cfg(feature = "csv")]
+    pub fn write_csv<W: io::Write>(&self, writer: W) -> Result<(), TxError> {
+        let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+        for chargeback in &self.chargebacks {
+            let (source, line, batch) = provenance_columns(chargeback.provenance.as_ref());
+            writer.serialize(ChargebackRow {
+                client: chargeback.client_id,
+                tx: chargeback.transaction_id,
+                amount: chargeback.amount,
+                source,
+                line,
+                batch,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A single row of the accounts report. Unlike serializing the pairs yielded by iterating a
+/// ledger's account maps, this is always exactly the five [ACCOUNT_COLUMNS](crate::account::ACCOUNT_COLUMNS).
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountRow {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub available: PositiveDecimal,
+    pub held: PositiveDecimal,
+    pub total: PositiveDecimal,
+    pub locked: bool,
+}
+
+impl AccountRow {
+    pub fn new(account: &dyn AccountView) -> Result<Self, TxError> {
+        Ok(AccountRow {
+            client_id: account.client_id(),
+            available: account.available(),
+            held: account.held(),
+            total: account.total()?,
+            locked: account.is_locked(),
+        })
+    }
+}
+
+/// The layout of the accounts report. New columns are only added in new versions, so
+/// consumers parsing a version keep working as the report grows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccountColumns {
+    /// The five [ACCOUNT_COLUMNS](crate::account::ACCOUNT_COLUMNS)
+    #[default]
+    V1,
+    /// The [ACCOUNT_COLUMNS_V2](crate::account::ACCOUNT_COLUMNS_V2): those of
+    /// [AccountColumns::V1] followed by the account's flags, separated by `;`
+    V2,
+    /// The [ACCOUNT_COLUMNS_V3](crate::account::ACCOUNT_COLUMNS_V3): those of
+    /// [AccountColumns::V2] followed by the part of `held` that is a rolling reserve, see
+    /// [AccountView::reserved]
+    V3,
+}
+
+/// A row of the accounts report in [AccountColumns::V2]
+#[cfg(feature = "csv")]
+#[derive(Serialize)]
+struct AccountRowV2 {
+    client: u16,
+    available: PositiveDecimal,
+    held: PositiveDecimal,
+    total: PositiveDecimal,
+    locked: bool,
+    flags: String,
+}
+
+/// A row of the accounts report in [AccountColumns::V3]
+#[cfg(feature = "csv")]
+#[derive(Serialize)]
+struct AccountRowV3 {
+    client: u16,
+    available: PositiveDecimal,
+    held: PositiveDecimal,
+    total: PositiveDecimal,
+    locked: bool,
+    flags: String,
+    reserved: PositiveDecimal,
+}
+
+/// Writes the balance of every account of `ledger`, active or locked, as one
+/// `client,available,held,total,locked` row per client ordered by client id, preceded by a
+/// header row
+#[cfg(feature = "csv")]
+pub fn write_accounts_csv<W: io::Write>(ledger: &Ledger, writer: W) -> Result<(), TxError> {
+    write_accounts_csv_as(ledger, AccountColumns::V1, writer)
+}
+
+/// Writes the accounts report like [write_accounts_csv], with the given `columns`
+#[cfg(feature = "csv")]
+pub fn write_accounts_csv_as<W: io::Write>(
+    ledger: &Ledger,
+    columns: AccountColumns,
+    writer: W,
+) -> Result<(), TxError> {
+    write_ledger_accounts_csv(ledger, columns, AccountOrder::ClientId, writer)
+}
+
+/// The order of the rows of the accounts report, see
+/// [Ledger::write_accounts_ordered](crate::ledger::Ledger::write_accounts_ordered)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccountOrder {
+    /// By ascending client id
+    #[default]
+    ClientId,
+    /// By descending client id
+    ClientIdDescending,
+    /// In whatever order the ledger keeps its accounts, which differs between runs unless the
+    /// ledger was built with a fixed hash state
+    Unordered,
+}
+
+/// A set of client ids, one bit per id, to visit accounts by client id without collecting them
+#[cfg(feature = "csv")]
+struct ClientIds([u64; 1 << 10]);
+
+#[cfg(feature = "csv")]
+impl ClientIds {
+    fn of(client_ids: impl IntoIterator<Item = u16>) -> Self {
+        let mut set = ClientIds([0; 1 << 10]);
+        for client_id in client_ids {
+            set.0[usize::from(client_id >> 6)] |= 1 << (client_id & 63);
+        }
+        set
+    }
+
+    fn iter(&self) -> impl DoubleEndedIterator<Item = u16> + '_ {
+        (0..=u16::MAX)
+            .filter(|&client_id| self.0[usize::from(client_id >> 6)] & (1 << (client_id & 63)) != 0)
+    }
+}
+
+/// Writes the accounts report of `ledger` in `order`, streaming the accounts straight from the
+/// ledger rather than collecting them first
+#[cfg(feature = "csv")]
+pub(crate) fn write_ledger_accounts_csv<A: LedgerAccount, W: io::Write>(
+    ledger: &Ledger<A>,
+    columns: AccountColumns,
+    order: AccountOrder,
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    if ledger.accounts().next().is_none() {
+        write_accounts_header(&mut writer, columns)?;
+    }
+    let mut write = |client_id| match ledger.account(client_id) {
+        Some(account) => write_account_row(&mut writer, account, columns),
+        None => Ok(()),
+    };
+    match order {
+        AccountOrder::ClientId => {
+            let client_ids = ClientIds::of(ledger.accounts().map(|account| account.client_id()));
+            client_ids.iter().try_for_each(&mut write)?;
+        }
+        AccountOrder::ClientIdDescending => {
+            let client_ids = ClientIds::of(ledger.accounts().map(|account| account.client_id()));
+            client_ids.iter().rev().try_for_each(&mut write)?;
+        }
+        AccountOrder::Unordered => {
+            for account in ledger.accounts() {
+                write_account_row(&mut writer, account, columns)?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The accounts of `accounts` with a balance, leaving out the [empty](AccountView::is_empty)
+/// ones, locked or not, e.g. to keep them out of the accounts report written by
+/// [write_account_views_csv]
+pub fn non_empty<'a>(
+    accounts: impl IntoIterator<Item = &'a dyn AccountView>,
+) -> impl Iterator<Item = &'a dyn AccountView> {
+    accounts.into_iter().filter(|account| !account.is_empty())
+}
+
+/// Writes the accounts report of `accounts` like [write_accounts_csv_as], e.g. of the accounts
+/// of several ledgers
+#[cfg(feature = "csv")]
+pub fn write_account_views_csv<'a, W: io::Write>(
+    accounts: impl IntoIterator<Item = &'a dyn AccountView>,
+    columns: AccountColumns,
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    let mut accounts = accounts.into_iter().collect::<Vec<_>>();
+    accounts.sort_unstable_by_key(|account| account.client_id());
+    if accounts.is_empty() {
+        write_accounts_header(&mut writer, columns)?;
+    }
+    for account in accounts {
+        write_account_row(&mut writer, account, columns)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the header of an accounts report without rows, which is otherwise written along with
+/// the first serialized row
+#[cfg(feature = "csv")]
+fn write_accounts_header<W: io::Write>(
+    writer: &mut csv::Writer<W>,
+    columns: AccountColumns,
+) -> Result<(), TxError> {
+    match columns {
+        AccountColumns::V1 => writer.write_record(ACCOUNT_COLUMNS)?,
+        AccountColumns::V2 => writer.write_record(ACCOUNT_COLUMNS_V2)?,
+        AccountColumns::V3 => writer.write_record(ACCOUNT_COLUMNS_V3)?,
+    }
+    Ok(())
+}
+
+#[cfg(feature = "csv")]
+fn write_account_row<W: io::Write>(
+    writer: &mut csv::Writer<W>,
+    account: &dyn AccountView,
+    columns: AccountColumns,
+) -> Result<(), TxError> {
+    let row = AccountRow::new(account)?;
+    let flags = || {
+        account
+            .flags()
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(";")
+    };
+    match columns {
+        AccountColumns::V1 => writer.serialize(row)?,
+        AccountColumns::V2 => writer.serialize(AccountRowV2 {
+            client: row.client_id,
+            available: row.available,
+            held: row.held,
+            total: row.total,
+            locked: row.locked,
+            flags: flags(),
+        })?,
+        AccountColumns::V3 => writer.serialize(AccountRowV3 {
+            client: row.client_id,
+            available: row.available,
+            held: row.held,
+            total: row.total,
+            locked: row.locked,
+            flags: flags(),
+            reserved: account.reserved(),
+        })?,
+    }
+    Ok(())
+}
+
+/// A row of the reject report, the rejected record followed by why it was rejected
+#[cfg(feature = "csv")]
+#[derive(Serialize)]
+struct RejectRow<'a> {
+    #[serde(rename = "type")]
+    transaction_type: TransactionRecordType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+    reason: &'a str,
+    source: Option<&'a str>,
+    line: Option<u64>,
+    batch: Option<&'a str>,
+}
+
+/// Writes one `type,client,tx,amount,reason,source,line,batch` row per rejected transaction,
+/// followed by one per record set aside for lack of an amount, preceded by a header row, see
+/// [Ledger::quarantined] and [Ledger::incomplete]
+#[cfg(feature = "csv")]
+pub fn write_rejects_csv<W: io::Write>(
+    rejections: &[Rejection],
+    incomplete: &[TransactionRecord],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    let mut write = |record: &TransactionRecord, reason: &str| {
+        let (source, line, batch) = provenance_columns(record.provenance.as_ref());
+        writer.serialize(RejectRow {
+            transaction_type: record.transaction_type,
+            client: record.client_id,
+            tx: record.transaction_id,
+            amount: record.amount,
+            reason,
+            source,
+            line,
+            batch,
+        })
+    };
+    for rejection in rejections {
+        write(
+            &TransactionRecord::from(rejection.transaction.clone()),
+            &rejection.reason,
+        )?;
+    }
+    let missing_amount = TxError::MissingAmount.to_string();
+    for record in incomplete {
+        write(record, &missing_amount)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one `type,client,tx,amount,source,line,batch` row per transaction still waiting in
+/// suspense, preceded by a header row, see [Ledger::suspended]. The amount is the one given in
+/// the record, if any.
+#[cfg(feature = "csv")]
+pub fn write_suspense_csv<W: io::Write>(
+    suspended: &[&Suspended],
+    writer: W,
+) -> Result<(), TxError> {
+    #[derive(Serialize)]
+    struct Row<'a> {
+        #[serde(rename = "type")]
+        transaction_type: TransactionRecordType,
+        client: u16,
+        tx: u32,
+        amount: Option<PositiveDecimal>,
+        source: Option<&'a str>,
+        line: Option<u64>,
+        batch: Option<&'a str>,
+    }
+
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for suspended in suspended {
+        let transaction = &suspended.transaction;
+        let (source, line, batch) = provenance_columns(transaction.provenance.as_deref());
+        writer.serialize(Row {
+            transaction_type: transaction.tx_type.record_type(),
+            client: transaction.client_id,
+            tx: transaction.transaction_id,
+            amount: suspended.amount,
+            source,
+            line,
+            batch,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one `type,client,tx,amount,source,line,batch` row per record of an unknown type set
+/// aside, preceded by a header row, see [UnknownTypes](crate::types::UnknownTypes). Once the
+/// types are corrected, the report can be processed as input.
+#[cfg(feature = "csv")]
+pub fn write_unknown_types_csv<W: io::Write>(
+    records: &[UnknownTypeRecord],
+    writer: W,
+) -> Result<(), TxError> {
+    #[derive(Serialize)]
+    struct Row<'a> {
+        #[serde(rename = "type")]
+        transaction_type: &'a str,
+        client: u16,
+        tx: u32,
+        amount: Option<Decimal>,
+        source: Option<&'a str>,
+        line: Option<u64>,
+        batch: Option<&'a str>,
+    }
+
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for record in records {
+        let (source, line, batch) = provenance_columns(record.provenance.as_ref());
+        writer.serialize(Row {
+            transaction_type: &record.transaction_type,
+            client: record.client_id,
+            tx: record.transaction_id,
+            amount: record.amount,
+            source,
+            line,
+            batch,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one `client,first,last` row per range of missing sequence numbers, preceded by a header
+/// row, see [GapDetector::gaps](crate::gaps::GapDetector::gaps)
+#[cfg(feature = "csv")]
+pub fn write_gaps_csv<W: io::Write>(gaps: &[Gap], writer: W) -> Result<(), TxError> {
+    #[derive(Serialize)]
+    struct Row {
+        client: u16,
+        first: u64,
+        last: u64,
+    }
+
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for gap in gaps {
+        writer.serialize(Row {
+            client: gap.client_id,
+            first: gap.first,
+            last: gap.last,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A row of the audit log, the record as it was read followed by the record it was rewritten to
+#[cfg(feature = "csv")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Serialize)]
+pub(crate) struct AuditRow<'a> {
+    original_type: TransactionRecordType,
+    original_amount: Decimal,
+    #[serde(rename = "type")]
+    transaction_type: TransactionRecordType,
+    client: u16,
+    tx: u32,
+    amount: Decimal,
+    source: Option<&'a str>,
+    line: Option<u64>,
+    batch: Option<&'a str>,
+}
+
+/// Writes one `original_type,original_amount,type,client,tx,amount,source,line,batch` row per
+/// rewritten record, preceded by a header row, see
+/// [Normalize::audit_log](crate::normalize::Normalize::audit_log)
+#[cfg(feature = "csv")]
+pub fn write_audit_log_csv<W: io::Write>(
+    normalizations: &[Normalization],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for normalization in normalizations {
+        let (source, line, batch) = provenance_columns(normalization.provenance.as_ref());
+        writer.serialize(AuditRow {
+            original_type: normalization.original_type,
+            original_amount: normalization.original_amount,
+            transaction_type: normalization.transaction_type,
+            client: normalization.client_id,
+            tx: normalization.transaction_id,
+            amount: normalization.amount,
+            source,
+            line,
+            batch,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A row of the dispute decisions report
+#[cfg(feature = "csv")]
+#[derive(Serialize)]
+struct DisputeDecisionRow<'a> {
+    client: u16,
+    tx: u32,
+    reason: &'a str,
+    action: &'static str,
+    amount: PositiveDecimal,
+    source: Option<&'a str>,
+    line: Option<u64>,
+    batch: Option<&'a str>,
+}
+
+/// Writes one `client,tx,reason,action,amount,source,line,batch` row per dispute a rule resolved
+/// or escalated, preceded by a header row, see
+/// [Ledger::dispute_decisions](crate::ledger::Ledger::dispute_decisions)
+#[cfg(feature = "csv")]
+pub fn write_dispute_decisions_csv<W: io::Write>(
+    decisions: &[DisputeDecision],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for decision in decisions {
+        let (source, line, batch) = provenance_columns(decision.provenance.as_ref());
+        writer.serialize(DisputeDecisionRow {
+            client: decision.client_id,
+            tx: decision.transaction_id,
+            reason: &decision.reason,
+            action: decision.action.name(),
+            amount: decision.amount,
+            source,
+            line,
+            batch,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A row of the late arrivals report
+#[cfg(feature = "csv")]
+#[derive(Serialize)]
+struct LateArrivalRow<'a> {
+    client: u16,
+    tx: u32,
+    timestamp: i64,
+    period: Option<&'a str>,
+    action: &'static str,
+    source: Option<&'a str>,
+    line: Option<u64>,
+    batch: Option<&'a str>,
+}
+
+/// Writes one `client,tx,timestamp,period,action,source,line,batch` row per record timestamped
+/// within a closed period, preceded by a header row, see
+/// [Ledger::late_arrivals](crate::ledger::Ledger::late_arrivals)
+#[cfg(feature = "csv")]
+pub fn write_late_arrivals_csv<W: io::Write>(
+    late_arrivals: &[LateArrival],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for late in late_arrivals {
+        let (source, line, batch) = provenance_columns(late.provenance.as_ref());
+        writer.serialize(LateArrivalRow {
+            client: late.client_id,
+            tx: late.transaction_id,
+            timestamp: late.timestamp,
+            period: late.period.as_deref(),
+            action: late.action.name(),
+            source,
+            line,
+            batch,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A row of the client aliases report
+#[cfg(feature = "csv")]
+#[derive(Serialize)]
+struct ClientAliasRow<'a> {
+    client_ref: &'a str,
+    client: u16,
+}
+
+/// Writes one `client_ref,client` row per external client id, ordered by external id,
+/// preceded by a header row, see [Ledger::client_aliases](crate::ledger::Ledger::client_aliases)
+#[cfg(feature = "csv")]
+pub fn write_client_aliases_csv<W: io::Write>(
+    aliases: &ClientAliases,
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for (client_ref, client) in aliases.iter() {
+        writer.serialize(ClientAliasRow { client_ref, client })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chargeback_report() {
+        let amount_1 = PositiveDecimal::try_from(10.0).unwrap();
+        let amount_2 = PositiveDecimal::try_from(2.5).unwrap();
+        let transactions = vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount: amount_1 }),
+            Transaction::new(2, 2, TransactionType::Deposit { amount: amount_2 }),
+            Transaction::new(2, 3, TransactionType::Deposit { amount: amount_1 }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(2, 2, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Chargeback),
+            Transaction::new(2, 2, TransactionType::Chargeback),
+        ];
+        let report = ChargebackReport::from_transactions(transactions).unwrap();
+        assert_eq!(report.total, amount_1.checked_add(amount_2).unwrap());
+        assert_eq!(report.per_client, BTreeMap::from([(1, 1), (2, 1)]));
+        assert_eq!(
+            report.chargebacks,
+            vec![
+                ChargebackRecord {
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: amount_1,
+                    provenance: None
+                },
+                ChargebackRecord {
+                    client_id: 2,
+                    transaction_id: 2,
+                    amount: amount_2,
+                    provenance: None
+                },
+            ]
+        );
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "client,tx,amount,source,line,batch\n1,1,10.0000,,,\n2,2,2.5000,,,\n"
+        );
+    }
+
+    #[test]
+    fn test_write_accounts_csv() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(3, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(2, 3, TransactionType::Deposit { amount }),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Chargeback),
+            Transaction::new(1, 4, TransactionType::Deposit { amount }),
+            Transaction::new(1, 4, TransactionType::Dispute),
+        ]);
+
+        let mut csv = Vec::new();
+        write_accounts_csv(&ledger, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "client,available,held,total,locked\n\
+             1,10.0000,10.0000,20.0000,false\n\
+             2,0.0000,0.0000,0.0000,true\n\
+             3,10.0000,0,10.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_accounts_ordered() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(u16::MAX, 1, TransactionType::Deposit { amount }),
+            Transaction::new(64, 2, TransactionType::Deposit { amount }),
+            Transaction::new(0, 3, TransactionType::Deposit { amount }),
+            Transaction::new(63, 4, TransactionType::Deposit { amount }),
+        ]);
+        let client_ids = |order| {
+            let mut csv = Vec::new();
+            ledger
+                .write_accounts_ordered(AccountColumns::V1, order, &mut csv)
+                .unwrap();
+            String::from_utf8(csv)
+                .unwrap()
+                .lines()
+                .skip(1)
+                .map(|line| line.split(',').next().unwrap().parse::<u16>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(client_ids(AccountOrder::ClientId), [0, 63, 64, u16::MAX]);
+        assert_eq!(
+            client_ids(AccountOrder::ClientIdDescending),
+            [u16::MAX, 64, 63, 0]
+        );
+        let mut unordered = client_ids(AccountOrder::Unordered);
+        unordered.sort_unstable();
+        assert_eq!(unordered, [0, 63, 64, u16::MAX]);
+
+        let mut streamed = Vec::new();
+        ledger.write_accounts(&mut streamed).unwrap();
+        let mut collected = Vec::new();
+        write_account_views_csv(ledger.accounts(), AccountColumns::V1, &mut collected).unwrap();
+        assert_eq!(streamed, collected);
+    }
+
+    #[test]
+    fn test_account_header_matches_fields() {
+        let mut ledger = Ledger::default();
+        let mut csv = Vec::new();
+        write_accounts_csv(&ledger, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!("{}\n", ACCOUNT_COLUMNS.join(","))
+        );
+
+        // the header emitted by the serializer is the same as the one of an empty ledger
+        let amount = PositiveDecimal::try_from(1.0).unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Dispute),
+            Transaction::new(2, 2, TransactionType::Chargeback),
+        ]);
+        let mut csv = Vec::new();
+        write_accounts_csv(&ledger, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(ACCOUNT_COLUMNS.join(",").as_str()));
+        assert!(lines.all(|line| line.split(',').count() == ACCOUNT_COLUMNS.len()));
+    }
+
+    #[test]
+    fn test_write_accounts_csv_v2() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        let mut csv = Vec::new();
+        write_accounts_csv_as(&ledger, AccountColumns::V2, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!("{}\n", ACCOUNT_COLUMNS_V2.join(","))
+        );
+
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Deposit { amount }),
+            Transaction::new(2, 0, TransactionType::Flag { flag: "vip".into() }),
+            Transaction::new(
+                2,
+                0,
+                TransactionType::Flag {
+                    flag: "under_review".into(),
+                },
+            ),
+        ]);
+        let mut csv = Vec::new();
+        write_accounts_csv_as(&ledger, AccountColumns::V2, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "client,available,held,total,locked,flags\n\
+             1,10.0000,0,10.0000,false,\n\
+             2,10.0000,0,10.0000,false,under_review;vip\n"
+        );
+    }
+
+    #[test]
+    fn test_non_empty() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Withdrawal { amount }),
+            Transaction::new(3, 3, TransactionType::Deposit { amount }),
+            Transaction::new(3, 3, TransactionType::Dispute),
+            Transaction::new(4, 4, TransactionType::Deposit { amount }),
+            Transaction::new(4, 5, TransactionType::Withdrawal { amount }),
+        ]);
+
+        let mut csv = Vec::new();
+        write_account_views_csv(non_empty(ledger.accounts()), AccountColumns::V1, &mut csv)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "client,available,held,total,locked\n\
+             1,10.0000,0,10.0000,false\n\
+             3,0.0000,10.0000,10.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_rejects_csv() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder()
+            .disable(TransactionRecordType::Dispute)
+            .build()
+            .unwrap();
+        ledger.process_transactions(vec![
+            Transaction {
+                provenance: Some(Box::new(Provenance {
+                    source: "input.csv".into(),
+                    line: 2,
+                    offset: 0,
+                    batch: Some("2022-06-01".into()),
+                })),
+                ..Transaction::new(1, 1, TransactionType::Withdrawal { amount })
+            },
+            Transaction::new(1, 2, TransactionType::Dispute),
+        ]);
+
+        let mut csv = Vec::new();
+        write_rejects_csv(ledger.quarantined(), ledger.incomplete(), &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "type,client,tx,amount,reason,source,line,batch\n\
+             withdrawal,1,1,10.0000,Insufficient Funds,input.csv,2,2022-06-01\n\
+             dispute,1,2,,Transactions of this type are disabled,,,\n"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Rolling reserves, which hold a share of every deposit of a client for a period before it
+//! becomes available, as merchant processors do for high-risk merchants to cover their later
+//! chargebacks
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use thiserror::Error;
+
+use crate::availability::AvailabilityDelay;
+use crate::transaction::{PositiveDecimal, NUM_DECIMAL_PLACES};
+
+/// The seconds in a day, the unit of the periods of a list of reserves
+const DAY: i64 = 24 * 60 * 60;
+
+/// Holds `rate` of every deposit of a client for `period`, see
+/// [LedgerBuilder::reserves](crate::ledger::LedgerBuilder::reserves)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RollingReserve {
+    /// The share of every deposit held, from `0` to `1`
+    pub rate: Decimal,
+    pub period: AvailabilityDelay,
+}
+
+impl RollingReserve {
+    /// The part of a deposit of `amount` held, rounded down to the places the ledger keeps
+    pub(crate) fn of(&self, amount: PositiveDecimal) -> PositiveDecimal {
+        let reserved = (Decimal::from(amount) * self.rate.min(Decimal::ONE))
+            .round_dp_with_strategy(NUM_DECIMAL_PLACES, RoundingStrategy::ToZero);
+        PositiveDecimal::try_from(reserved).unwrap_or_default()
+    }
+}
+
+/// The [RollingReserve] of every client with one. The deposits of other clients are available
+/// as a whole.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RollingReserves(BTreeMap<u16, RollingReserve>);
+
+impl RollingReserves {
+    /// Holds `reserve` of the deposits of `client_id`, replacing an earlier reserve of it
+    pub fn client(mut self, client_id: u16, reserve: RollingReserve) -> Self {
+        self.0.insert(client_id, reserve);
+        self
+    }
+
+    pub fn get(&self, client_id: u16) -> Option<&RollingReserve> {
+        self.0.get(&client_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A line of a list of reserves that isn't one
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("`{0}` is not a reserve, e.g. `42,10,30` to hold 10% of client 42's deposits for 30 days")]
+pub struct InvalidReserve(pub String);
+
+/// Parses a list of reserves, one `CLIENT,PERCENT,DAYS` per line, e.g. `42,10,30` to hold 10%
+/// of every deposit of client 42 for 30 days, measured by the timestamps of the records. Blank
+/// lines and `#` comments to the end of a line are ignored.
+pub fn parse_reserves(list: &str) -> Result<RollingReserves, InvalidReserve> {
+    let mut reserves = RollingReserves::default();
+    for line in list.lines() {
+        let reserve = line
+            .split_once('#')
+            .map_or(line, |(reserve, _)| reserve)
+            .trim();
+        if reserve.is_empty() {
+            continue;
+        }
+        let invalid = || InvalidReserve(reserve.to_string());
+        let fields = reserve.split(',').map(str::trim).collect::<Vec<_>>();
+        let [client_id, percent, days] = fields[..] else {
+            return Err(invalid());
+        };
+        let client_id = client_id.parse::<u16>().map_err(|_| invalid())?;
+        let percent = Decimal::from_str(percent)
+            .ok()
+            .filter(|percent| (Decimal::ZERO..=Decimal::ONE_HUNDRED).contains(percent))
+            .ok_or_else(invalid)?;
+        let period = days
+            .parse::<i64>()
+            .ok()
+            .and_then(|days| days.checked_mul(DAY))
+            .filter(|seconds| *seconds >= 0)
+            .ok_or_else(invalid)?;
+        reserves = reserves.client(
+            client_id,
+            RollingReserve {
+                rate: percent / Decimal::ONE_HUNDRED,
+                period: AvailabilityDelay::Seconds(period),
+            },
+        );
+    }
+    Ok(reserves)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_reserves() {
+        let reserves = parse_reserves("42, 10, 30 # high risk\n\n7,2.5,1\n").unwrap();
+        let reserve = reserves.get(42).unwrap();
+        assert_eq!(
+            reserve,
+            &RollingReserve {
+                rate: Decimal::new(1, 1),
+                period: AvailabilityDelay::Seconds(30 * DAY),
+            }
+        );
+        assert_eq!(
+            reserve.of(PositiveDecimal::try_from(12.3456).unwrap()),
+            PositiveDecimal::try_from(1.2345).unwrap()
+        );
+        assert_eq!(reserves.get(7).unwrap().rate, Decimal::new(25, 3));
+        assert_eq!(reserves.get(1), None);
+
+        for invalid in [
+            "42",
+            "42,10",
+            "42,10,30,1",
+            "x,10,30",
+            "42,101,30",
+            "42,10,-1",
+        ] {
+            assert_eq!(
+                parse_reserves(invalid),
+                Err(InvalidReserve(invalid.to_string()))
+            );
+        }
+    }
+}
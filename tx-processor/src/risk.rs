@@ -0,0 +1,309 @@
+//! Risk scores of accounts computed from their transactions, for a first triage of which
+//! clients to review without exporting the ledger to a separate risk system, and the freezing
+//! of accounts whose score or chargebacks exceed a threshold
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+
+use rust_decimal::prelude::*;
+use serde::Serialize;
+
+use crate::map::Map;
+use crate::transaction::{PositiveDecimal, Transaction, TransactionType};
+
+/// The window of [RiskFeatures::deposit_velocity], a day
+pub const VELOCITY_WINDOW: i64 = 24 * 60 * 60;
+
+/// What a [RiskScore] is computed from
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct RiskFeatures {
+    pub deposits: u32,
+    pub withdrawals: u32,
+    pub disputes: u32,
+    pub chargebacks: u32,
+    /// Disputes per deposit or withdrawal
+    pub dispute_rate: f64,
+    /// The most deposits within [VELOCITY_WINDOW] seconds of each other, of the deposits with a
+    /// timestamp
+    pub deposit_velocity: u32,
+    /// The largest fall of the total balance from a peak, as a fraction of that peak: `1` when
+    /// everything deposited was withdrawn or charged back again
+    pub balance_volatility: f64,
+}
+
+// the ratios are computed from counts and balances, and never NaN
+impl Eq for RiskFeatures {}
+
+/// How risky an account looks, from `0` to `100`
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RiskScore {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub score: u8,
+    pub features: RiskFeatures,
+}
+
+impl RiskFeatures {
+    /// Weighs the features, each capped where it stops telling accounts apart: two
+    /// chargebacks, one dispute in five transactions, twenty deposits a day and a balance
+    /// emptied from its peak
+    pub fn score(&self) -> u8 {
+        let capped = |value: f64, cap: f64| (value / cap).min(1.0);
+        let score = 40.0 * capped(f64::from(self.chargebacks), 2.0)
+            + 25.0 * capped(self.dispute_rate, 0.2)
+            + 15.0 * capped(f64::from(self.deposit_velocity), 20.0)
+            + 20.0 * self.balance_volatility.min(1.0);
+        score as u8
+    }
+}
+
+/// The flag of frozen accounts. Withdrawals from a frozen account are rejected with
+/// [TxError::FrozenAccount](crate::error::TxError::FrozenAccount), while deposits and disputes
+/// are applied as usual. Unflagging the account unfreezes it.
+pub const FROZEN: &str = "frozen";
+
+/// When a ledger freezes an account as it processes its transactions, see
+/// [LedgerBuilder::freeze_thresholds](crate::ledger::LedgerBuilder::freeze_thresholds)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FreezeThresholds {
+    /// Freezes accounts whose [RiskScore] exceeds this
+    pub max_risk_score: Option<u8>,
+    /// Freezes accounts with more chargebacks than this. A chargeback also locks its account,
+    /// so this freezes the accounts that are unlocked again, e.g. by a restored state.
+    pub max_chargebacks: Option<u32>,
+}
+
+impl FreezeThresholds {
+    pub fn is_empty(&self) -> bool {
+        self.max_risk_score.is_none() && self.max_chargebacks.is_none()
+    }
+
+    /// The threshold `score` exceeds, if any
+    fn exceeded(&self, score: &RiskScore) -> Option<FreezeReason> {
+        if let Some(max) = self.max_chargebacks {
+            if score.features.chargebacks > max {
+                return Some(FreezeReason::Chargebacks(max));
+            }
+        }
+        match self.max_risk_score {
+            Some(max) if score.score > max => Some(FreezeReason::RiskScore(max)),
+            _ => None,
+        }
+    }
+}
+
+/// The threshold an account exceeded, see [FreezeThresholds]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreezeReason {
+    RiskScore(u8),
+    Chargebacks(u32),
+}
+
+/// `a risk score over 70` or `over 0 chargebacks`
+impl fmt::Display for FreezeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FreezeReason::RiskScore(max) => write!(f, "a risk score over {}", max),
+            FreezeReason::Chargebacks(max) => write!(f, "over {} chargebacks", max),
+        }
+    }
+}
+
+/// An account frozen by [FreezeThresholds], see
+/// [Ledger::freezes](crate::ledger::Ledger::freezes)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Freeze {
+    pub client_id: u16,
+    /// The transaction after which the account exceeded the threshold
+    pub transaction_id: u32,
+    pub reason: FreezeReason,
+    /// The score of the account when it was frozen
+    pub score: RiskScore,
+}
+
+/// The [RiskFeatures] of a single client, updated one applied transaction at a time
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct RiskTracker {
+    features: RiskFeatures,
+    /// The amounts of the client's deposits and withdrawals, for its chargebacks
+    amounts: Map<u32, PositiveDecimal>,
+    /// Sorted
+    deposit_times: Vec<i64>,
+    balance: Decimal,
+    peak: Decimal,
+    drawdown: Decimal,
+    /// See [FreezeThresholds], set once the account was frozen
+    pub(crate) frozen: bool,
+}
+
+impl RiskTracker {
+    pub(crate) fn observe(&mut self, transaction: &Transaction) {
+        match transaction.tx_type {
+            TransactionType::Deposit { amount } => {
+                self.amounts
+                    .entry(transaction.transaction_id)
+                    .or_insert(amount);
+                self.features.deposits += 1;
+                if let Some(time) = transaction.timestamp {
+                    self.add_deposit_time(time);
+                }
+                self.move_balance(amount.into());
+            }
+            TransactionType::Withdrawal { amount } => {
+                self.amounts
+                    .entry(transaction.transaction_id)
+                    .or_insert(amount);
+                self.features.withdrawals += 1;
+                self.move_balance(-Decimal::from(amount));
+            }
+            TransactionType::Dispute => self.features.disputes += 1,
+            TransactionType::Chargeback => {
+                self.features.chargebacks += 1;
+                if let Some(&amount) = self.amounts.get(&transaction.transaction_id) {
+                    self.move_balance(-Decimal::from(amount));
+                }
+            }
+            TransactionType::Resolve
+            | TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. }
+            | TransactionType::Other { .. } => {}
+        }
+    }
+
+    /// Counts the deposits within [VELOCITY_WINDOW] before a deposit at `time`, or every
+    /// window again if it arrived out of order
+    fn add_deposit_time(&mut self, time: i64) {
+        let at = self.deposit_times.partition_point(|&other| other <= time);
+        self.deposit_times.insert(at, time);
+        let times = &self.deposit_times;
+        let velocity = if at + 1 == times.len() {
+            times.len() - times.partition_point(|&other| time - other >= VELOCITY_WINDOW)
+        } else {
+            let mut start = 0;
+            let mut velocity = 0;
+            for (end, &time) in times.iter().enumerate() {
+                while time - times[start] >= VELOCITY_WINDOW {
+                    start += 1;
+                }
+                velocity = velocity.max(end - start + 1);
+            }
+            velocity
+        };
+        self.features.deposit_velocity = self.features.deposit_velocity.max(velocity as u32);
+    }
+
+    fn move_balance(&mut self, change: Decimal) {
+        self.balance += change;
+        self.peak = self.peak.max(self.balance);
+        if self.peak > Decimal::ZERO {
+            self.drawdown = self.drawdown.max((self.peak - self.balance) / self.peak);
+        }
+    }
+
+    /// Scores the client after `transaction` was applied to its account, returning the
+    /// [Freeze] of the account if it exceeds one of `thresholds` for the first time
+    pub(crate) fn freeze(
+        &mut self,
+        transaction: &Transaction,
+        thresholds: &FreezeThresholds,
+    ) -> Option<Freeze> {
+        self.observe(transaction);
+        if self.frozen {
+            return None;
+        }
+        let score = self.score(transaction.client_id);
+        let reason = thresholds.exceeded(&score)?;
+        self.frozen = true;
+        Some(Freeze {
+            client_id: transaction.client_id,
+            transaction_id: transaction.transaction_id,
+            reason,
+            score,
+        })
+    }
+
+    pub(crate) fn score(&self, client_id: u16) -> RiskScore {
+        let mut features = self.features.clone();
+        let transfers = features.deposits + features.withdrawals;
+        if transfers > 0 {
+            features.dispute_rate = f64::from(features.disputes) / f64::from(transfers);
+        }
+        features.balance_volatility = self.drawdown.to_f64().unwrap_or_default();
+        RiskScore {
+            client_id,
+            score: features.score(),
+            features,
+        }
+    }
+}
+
+/// Scores every client of a chronologically ordered log of applied transactions. Clients are
+/// ordered by id.
+pub fn risk_scores(transactions: impl IntoIterator<Item = Transaction>) -> Vec<RiskScore> {
+    let mut trackers = BTreeMap::<u16, RiskTracker>::new();
+    for transaction in transactions {
+        trackers
+            .entry(transaction.client_id)
+            .or_default()
+            .observe(&transaction);
+    }
+    trackers
+        .iter()
+        .map(|(&client_id, tracker)| tracker.score(client_id))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_risk_scores() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let deposit = |client_id, transaction_id, timestamp| Transaction {
+            timestamp: Some(timestamp),
+            ..Transaction::new(
+                client_id,
+                transaction_id,
+                TransactionType::Deposit { amount },
+            )
+        };
+        let transactions = vec![
+            deposit(1, 1, 0),
+            deposit(1, 2, VELOCITY_WINDOW),
+            Transaction::new(1, 3, TransactionType::Withdrawal { amount }),
+            deposit(2, 4, 0),
+            deposit(2, 5, 100),
+            Transaction::new(2, 4, TransactionType::Dispute),
+            Transaction::new(2, 4, TransactionType::Chargeback),
+        ];
+        let scores = risk_scores(transactions);
+        assert_eq!(scores.len(), 2);
+
+        let quiet = &scores[0];
+        assert_eq!(quiet.client_id, 1);
+        assert_eq!(quiet.features.deposit_velocity, 1);
+        assert_eq!(quiet.features.balance_volatility, 0.5);
+        assert_eq!(quiet.score, 10);
+
+        let risky = &scores[1];
+        assert_eq!(
+            risky.features,
+            RiskFeatures {
+                deposits: 2,
+                withdrawals: 0,
+                disputes: 1,
+                chargebacks: 1,
+                dispute_rate: 0.5,
+                deposit_velocity: 2,
+                balance_volatility: 0.5,
+            }
+        );
+        assert!(risky.score > quiet.score);
+        assert_eq!(risky.score, 20 + 25 + 1 + 10);
+    }
+}
@@ -0,0 +1,119 @@
+//! End-of-day balances of every client, recorded as a ledger applies timestamped transactions,
+//! so balance trends can be charted without replaying the transactions
+
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "csv")]
+use std::io;
+
+#[cfg(feature = "csv")]
+use csv::WriterBuilder;
+use serde::Serialize;
+
+use crate::account::AccountView;
+use crate::aggregate::{date, SECONDS_PER_DAY};
+#[cfg(feature = "csv")]
+use crate::error::TxError;
+use crate::transaction::PositiveDecimal;
+
+/// The balance of a client at the end of a UTC day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DailyBalance {
+    /// E.g. `2024-01-31`
+    pub date: String,
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub available: PositiveDecimal,
+    pub held: PositiveDecimal,
+    pub locked: bool,
+}
+
+impl DailyBalance {
+    fn new(day: i64, account: &dyn AccountView) -> Self {
+        DailyBalance {
+            date: date(day),
+            client_id: account.client_id(),
+            available: account.available(),
+            held: account.held(),
+            locked: account.is_locked(),
+        }
+    }
+}
+
+/// The days a ledger has closed and the day it is in. A day closes when a transaction with a
+/// timestamp of a later day arrives; transactions with an earlier timestamp or none count
+/// towards the day the ledger is in.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Rollups {
+    /// The day of the latest timestamp, in days since the Unix epoch
+    day: Option<i64>,
+    /// The clients with transactions applied during `day`
+    clients: BTreeSet<u16>,
+    closed: Vec<DailyBalance>,
+}
+
+impl Rollups {
+    /// Closes the current day if `timestamp` is of a later one, recording the balances of the
+    /// clients with transactions that day as looked up by `account`
+    pub(crate) fn advance<'a>(
+        &mut self,
+        timestamp: Option<i64>,
+        account: impl Fn(u16) -> Option<&'a dyn AccountView>,
+    ) {
+        let Some(day) = timestamp.map(|timestamp| timestamp.div_euclid(SECONDS_PER_DAY)) else {
+            return;
+        };
+        match self.day {
+            Some(current) if current >= day => {}
+            Some(current) => {
+                let clients = core::mem::take(&mut self.clients);
+                self.closed.extend(
+                    clients
+                        .into_iter()
+                        .filter_map(&account)
+                        .map(|account| DailyBalance::new(current, account)),
+                );
+                self.day = Some(day);
+            }
+            None => self.day = Some(day),
+        }
+    }
+
+    pub(crate) fn applied(&mut self, client_id: u16) {
+        if self.day.is_some() {
+            self.clients.insert(client_id);
+        }
+    }
+
+    /// The balances of the closed days, followed by those of the current day so far
+    pub(crate) fn balances<'a>(
+        &self,
+        account: impl Fn(u16) -> Option<&'a dyn AccountView>,
+    ) -> Vec<DailyBalance> {
+        let mut balances = self.closed.clone();
+        if let Some(day) = self.day {
+            balances.extend(
+                self.clients
+                    .iter()
+                    .filter_map(|&client_id| account(client_id))
+                    .map(|account| DailyBalance::new(day, account)),
+            );
+        }
+        balances
+    }
+}
+
+/// Writes one row per balance, preceded by a header row
+#[cfg(feature = "csv")]
+pub fn write_daily_balances_csv<W: io::Write>(
+    balances: &[DailyBalance],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for balance in balances {
+        writer.serialize(balance)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! The residues of amounts calculated to more places than a [Ledger](crate::ledger::Ledger)
+//! keeps, e.g. interest, percentage fees or currency conversions
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::error::TxError;
+use crate::transaction::NUM_DECIMAL_PLACES;
+
+/// Rounds a calculated amount to [NUM_DECIMAL_PLACES], half to even so residues don't drift
+/// in one direction
+pub fn round(calculated: Decimal) -> Decimal {
+    calculated.round_dp_with_strategy(NUM_DECIMAL_PLACES, RoundingStrategy::MidpointNearestEven)
+}
+
+/// The processor's account of rounding residues. Every calculated amount applied through
+/// [Ledger::add_calculated_tx](crate::ledger::Ledger::add_calculated_tx) is rounded before it
+/// reaches a client, and the difference is accumulated here, so the calculated amounts are
+/// exactly the applied amounts plus the reserve's [balance](RoundingReserve::balance).
+///
+/// Amounts are signed from the clients' side: positive amounts were credited to them, negative
+/// ones debited. A positive balance is owed to clients, a negative one owed by them.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundingReserve {
+    /// The sum of the calculated amounts, unrounded
+    pub calculated: Decimal,
+    /// The sum of the rounded amounts actually applied
+    pub applied: Decimal,
+    /// The number of calculated amounts
+    pub count: u64,
+}
+
+impl RoundingReserve {
+    /// The residues accumulated so far: what was calculated but not applied
+    pub fn balance(&self) -> Decimal {
+        self.calculated - self.applied
+    }
+
+    /// Records `calculated` having been applied as `applied`
+    pub(crate) fn record(&mut self, calculated: Decimal, applied: Decimal) {
+        self.calculated += calculated;
+        self.applied += applied;
+        self.count += 1;
+    }
+
+    /// Checks that the applied amounts are whole units of [NUM_DECIMAL_PLACES], and that
+    /// rounding never moved the balance by more than half a unit per calculated amount
+    pub fn check(&self) -> Result<(), TxError> {
+        let half_unit = Decimal::new(5, NUM_DECIMAL_PLACES + 1);
+        if round(self.applied) != self.applied
+            || self.balance().abs() > half_unit * Decimal::from(self.count)
+        {
+            return Err(TxError::InvalidAmount);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::account::AccountView;
+    use crate::ledger::Ledger;
+
+    #[test]
+    fn test_rounding_reserve() {
+        let mut ledger = Ledger::builder().double_entry().build().unwrap();
+        // a third of 10 in interest, three times, and a fee of a third of that
+        let interest = Decimal::from(10) / Decimal::from(3);
+        for _ in 0..3 {
+            assert!(ledger.add_calculated_tx(1, interest).unwrap().is_some());
+        }
+        ledger
+            .add_calculated_tx(1, -interest / Decimal::from(3))
+            .unwrap();
+        // too small to apply, so it only accrues to the reserve
+        assert_eq!(
+            ledger
+                .add_calculated_tx(1, Decimal::new(4, NUM_DECIMAL_PLACES + 1))
+                .unwrap(),
+            None
+        );
+
+        let account = ledger.active_accounts().get(&1).unwrap();
+        assert_eq!(
+            Decimal::from(account.available()),
+            Decimal::new(88888, NUM_DECIMAL_PLACES)
+        );
+        let reserve = ledger.rounding_reserve();
+        assert_eq!(reserve.count, 5);
+        assert_eq!(reserve.applied, Decimal::new(88888, NUM_DECIMAL_PLACES));
+        assert_eq!(reserve.calculated, reserve.applied + reserve.balance());
+        assert!(reserve.balance() > Decimal::ZERO);
+        reserve.check().unwrap();
+        // the journal only sees the applied amounts, so it still balances
+        assert!(ledger
+            .journal()
+            .unwrap()
+            .trial_balance()
+            .unwrap()
+            .is_balanced()
+            .unwrap());
+    }
+}
@@ -0,0 +1,147 @@
+//! Processing a reproducible sample of a large input, e.g. to iterate on configuration without
+//! processing all of it. For a prefix of the input, [Iterator::take] the records instead.
+
+use crate::aliases::hash_client_ref;
+use crate::transaction::TransactionRecord;
+
+/// Keeps every record of a sample of the clients, passing through the records of a `rate` of
+/// them chosen by a hash of the client id and `seed`. Records given an external client id are
+/// sampled by a hash of their [client_ref](TransactionRecord::client_ref) instead, as the
+/// ledger only resolves their client id after sampling. Sampling whole clients rather than
+/// records keeps their disputes with the transactions they refer to, so the sampled accounts
+/// balance like they would in a full run. The same seed samples the same clients of any input.
+///
+/// Records that failed to parse are passed through unchanged. Pass it by mutable reference to
+/// read [Sample::skipped] afterwards.
+pub struct Sample<I> {
+    records: I,
+    /// Hashes below this are sampled
+    threshold: u64,
+    seed: u64,
+    skipped: usize,
+}
+
+impl<I, E> Sample<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    /// Samples a `rate` between 0 and 1 of the clients of `records`; rates of 1 and above keep
+    /// every record
+    pub fn new(records: impl IntoIterator<IntoIter = I>, rate: f64, seed: u64) -> Self {
+        let threshold = if rate >= 1.0 {
+            u64::MAX
+        } else {
+            (rate.max(0.0) * u64::MAX as f64) as u64
+        };
+        Sample {
+            records: records.into_iter(),
+            threshold,
+            seed,
+            skipped: 0,
+        }
+    }
+
+    /// `true` if the records of `client_id` are sampled
+    pub fn is_sampled(&self, client_id: u16) -> bool {
+        self.threshold == u64::MAX || hash(self.seed, u64::from(client_id) + 1) < self.threshold
+    }
+
+    /// `true` if the records of the external client id `client_ref` are sampled
+    pub fn is_ref_sampled(&self, client_ref: &str) -> bool {
+        self.threshold == u64::MAX || hash(self.seed, hash_client_ref(client_ref)) < self.threshold
+    }
+
+    /// The number of records skipped so far
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// The `key`th output of SplitMix64 seeded with `seed`, so consecutive keys and seeds hash
+/// independently
+fn hash(seed: u64, key: u64) -> u64 {
+    let mut z = seed.wrapping_add(key.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl<I, E> Iterator for Sample<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    type Item = Result<TransactionRecord, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = self.records.next()?;
+            if let Ok(record) = &record {
+                let sampled = match &record.client_ref {
+                    Some(client_ref) => self.is_ref_sampled(client_ref),
+                    None => self.is_sampled(record.client_id),
+                };
+                if !sampled {
+                    self.skipped += 1;
+                    continue;
+                }
+            }
+            return Some(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::transaction::TransactionRecordType;
+
+    /// A deposit and a dispute of it for each of 1000 clients
+    fn records() -> impl Iterator<Item = Result<TransactionRecord, ()>> {
+        (0..1000u16).flat_map(|client_id| {
+            [
+                TransactionRecordType::Deposit,
+                TransactionRecordType::Dispute,
+            ]
+            .into_iter()
+            .map(move |transaction_type| {
+                Ok(TransactionRecord::new(
+                    transaction_type,
+                    client_id,
+                    u32::from(client_id),
+                ))
+            })
+        })
+    }
+
+    #[test]
+    fn test_sample_clients() {
+        let mut sample = Sample::new(records(), 0.1, 42);
+        let sampled = (&mut sample).map(Result::unwrap).collect::<Vec<_>>();
+        // every sampled client keeps its dispute along with its deposit
+        assert_eq!(sampled.len() % 2, 0);
+        assert!(sampled
+            .chunks(2)
+            .all(|pair| pair[0].client_id == pair[1].client_id));
+        let clients = sampled.len() / 2;
+        assert!((50..150).contains(&clients), "{} clients sampled", clients);
+        assert_eq!(sample.skipped(), 2000 - sampled.len());
+
+        // the same seed samples the same clients, another seed others
+        let again = Sample::new(records(), 0.1, 42).count();
+        assert_eq!(again, sampled.len());
+        let other = Sample::new(records(), 0.1, 7)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_ne!(other, sampled);
+    }
+
+    #[test]
+    fn test_sample_rates() {
+        assert_eq!(Sample::new(records(), 1.0, 0).count(), 2000);
+        assert_eq!(Sample::new(records(), 0.0, 0).count(), 0);
+        let errors = [Err::<TransactionRecord, _>(())];
+        assert_eq!(Sample::new(errors, 0.0, 0).count(), 1);
+    }
+}
@@ -0,0 +1,247 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use rust_decimal::Decimal;
+
+use crate::ledger::Ledger;
+use crate::transaction::{PositiveDecimal, Transaction, TransactionType};
+
+/// The balance a [Scenario] expects a client to end up with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExpectedBalance {
+    pub available: PositiveDecimal,
+    pub held: PositiveDecimal,
+    pub locked: bool,
+}
+
+/// A small DSL for building transaction streams along with the balances they should produce,
+/// instead of hand-writing vectors of [Transaction]s:
+///
+/// ```
+/// use tx_processor::scenario::Scenario;
+///
+/// let scenario = Scenario::new()
+///     .client(1)
+///     .deposit(100)
+///     .withdraw(30)
+///     .dispute_last()
+///     .chargeback_last();
+/// let mut ledger = tx_processor::ledger::Ledger::default();
+/// ledger.process_transactions(scenario.transactions().to_vec());
+/// scenario.verify(&ledger).unwrap();
+/// ```
+///
+/// Transaction ids are assigned sequentially. Operations the ledger would reject, such as
+/// overdrawing withdrawals or anything on a locked account, are still emitted but leave the
+/// expected balances unchanged.
+#[derive(Debug, Default, Clone)]
+pub struct Scenario {
+    client_id: u16,
+    next_tx_id: u32,
+    transactions: Vec<Transaction>,
+    expected: BTreeMap<u16, ExpectedBalance>,
+    /// Per client, the `(transaction_id, amount)` of every deposit and withdrawal
+    disputable: BTreeMap<u16, Vec<(u32, PositiveDecimal)>>,
+    /// Per client, the `(transaction_id, amount)` of every open dispute
+    disputed: BTreeMap<u16, Vec<(u32, PositiveDecimal)>>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Scenario {
+            client_id: 1,
+            next_tx_id: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Makes the following operations apply to `client_id`
+    pub fn client(mut self, client_id: u16) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    pub fn deposit(self, amount: impl Into<Decimal>) -> Self {
+        let amount = positive(amount);
+        self.funds(TransactionType::Deposit { amount }, |balance| {
+            balance.available = balance.available.checked_add(amount).ok()?;
+            Some(())
+        })
+    }
+
+    pub fn withdraw(self, amount: impl Into<Decimal>) -> Self {
+        let amount = positive(amount);
+        self.funds(TransactionType::Withdrawal { amount }, |balance| {
+            balance.available = balance.available.checked_sub(amount).ok()?;
+            Some(())
+        })
+    }
+
+    /// Disputes the most recent undisputed deposit or withdrawal of the current client
+    pub fn dispute_last(mut self) -> Self {
+        let client_id = self.client_id;
+        let (tx_id, amount) = self
+            .disputable
+            .get_mut(&client_id)
+            .and_then(Vec::pop)
+            .expect("no deposit or withdrawal left to dispute");
+        let balance = self.expected.entry(client_id).or_default();
+        if !balance.locked {
+            if let (Ok(available), Ok(held)) = (
+                balance.available.checked_sub(amount),
+                balance.held.checked_add(amount),
+            ) {
+                balance.available = available;
+                balance.held = held;
+                self.disputed
+                    .entry(client_id)
+                    .or_default()
+                    .push((tx_id, amount));
+            }
+        }
+        self.push(tx_id, TransactionType::Dispute)
+    }
+
+    /// Resolves the most recent open dispute of the current client
+    pub fn resolve_last(mut self) -> Self {
+        let (tx_id, amount) = self.pop_dispute();
+        let balance = self.expected.entry(self.client_id).or_default();
+        balance.available = balance.available.checked_add(amount).unwrap();
+        balance.held = balance.held.checked_sub(amount).unwrap();
+        self.push(tx_id, TransactionType::Resolve)
+    }
+
+    /// Charges back the most recent open dispute of the current client, locking the account
+    pub fn chargeback_last(mut self) -> Self {
+        let (tx_id, amount) = self.pop_dispute();
+        let balance = self.expected.entry(self.client_id).or_default();
+        balance.held = balance.held.checked_sub(amount).unwrap();
+        balance.locked = true;
+        self.push(tx_id, TransactionType::Chargeback)
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn expected_balances(&self) -> &BTreeMap<u16, ExpectedBalance> {
+        &self.expected
+    }
+
+    /// Checks that every client of this scenario has its expected balance in `ledger`
+    pub fn verify(&self, ledger: &Ledger) -> Result<(), String> {
+        for (&client_id, expected) in &self.expected {
+            let Some(account) = ledger.account(client_id) else {
+                return Err(format!("client {} has no account", client_id));
+            };
+            let actual = ExpectedBalance {
+                available: account.available(),
+                held: account.held(),
+                locked: account.is_locked(),
+            };
+            if &actual != expected {
+                return Err(format!(
+                    "client {}: expected {:?}, found {:?}",
+                    client_id, expected, actual
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn funds(
+        mut self,
+        tx_type: TransactionType,
+        apply: impl FnOnce(&mut ExpectedBalance) -> Option<()>,
+    ) -> Self {
+        let client_id = self.client_id;
+        let tx_id = self.next_tx_id;
+        self.next_tx_id += 1;
+        let balance = self.expected.entry(client_id).or_default();
+        if !balance.locked {
+            let mut updated = *balance;
+            if apply(&mut updated).is_some() {
+                *balance = updated;
+                if let TransactionType::Deposit { amount }
+                | TransactionType::Withdrawal { amount } = tx_type
+                {
+                    self.disputable
+                        .entry(client_id)
+                        .or_default()
+                        .push((tx_id, amount));
+                }
+            }
+        }
+        self.push(tx_id, tx_type)
+    }
+
+    fn pop_dispute(&mut self) -> (u32, PositiveDecimal) {
+        self.disputed
+            .get_mut(&self.client_id)
+            .and_then(Vec::pop)
+            .expect("no open dispute left")
+    }
+
+    fn push(mut self, tx_id: u32, tx_type: TransactionType) -> Self {
+        self.transactions
+            .push(Transaction::new(self.client_id, tx_id, tx_type));
+        self
+    }
+}
+
+fn positive(amount: impl Into<Decimal>) -> PositiveDecimal {
+    PositiveDecimal::try_from(amount.into()).expect("scenario amounts must not be negative")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scenario_matches_ledger() {
+        let scenario = Scenario::new()
+            .client(1)
+            .deposit(100)
+            .withdraw(30)
+            .withdraw(1000)
+            .dispute_last()
+            .resolve_last()
+            .client(2)
+            .deposit(Decimal::new(255, 1))
+            .deposit(10)
+            .dispute_last()
+            .chargeback_last()
+            .deposit(5);
+        assert_eq!(scenario.transactions().len(), 10);
+
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(scenario.transactions().to_vec());
+        scenario.verify(&ledger).unwrap();
+
+        let expected = scenario.expected_balances();
+        assert_eq!(
+            expected[&1].available,
+            PositiveDecimal::try_from(70.0).unwrap()
+        );
+        assert_eq!(
+            expected[&2],
+            ExpectedBalance {
+                available: PositiveDecimal::try_from(25.5).unwrap(),
+                held: PositiveDecimal::default(),
+                locked: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_reports_mismatch() {
+        let scenario = Scenario::new().client(3).deposit(1);
+        assert!(scenario.verify(&Ledger::default()).is_err());
+
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(Scenario::new().client(3).deposit(2).transactions().to_vec());
+        assert!(scenario.verify(&ledger).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! JSON Schemas of the records this crate reads and writes, derived from their types, so
+//! consumers can generate their readers rather than follow the columns by hand.
+//!
+//! CSV files are described one row at a time: each column is a property of the row, and empty
+//! columns are `null`.
+
+use std::borrow::Cow;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, StringValidation};
+use schemars::{schema_for, JsonSchema};
+
+use crate::report::{AccountRow, AuditRow};
+#[cfg(feature = "snapshot")]
+use crate::snapshot::Snapshot;
+use crate::transaction::{PositiveDecimal, TransactionRecord};
+
+/// A format of this crate's input or output with a schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A record of the input, see [TransactionRecord]
+    Transaction,
+    /// A row of the accounts report, see [AccountRow]
+    Account,
+    /// A row of the audit log of normalized records, see
+    /// [write_audit_log_csv](crate::report::write_audit_log_csv)
+    Audit,
+    /// A checkpoint or closing state, see [Snapshot]
+    #[cfg(feature = "snapshot")]
+    Snapshot,
+}
+
+impl Format {
+    pub const ALL: &'static [Format] = &[
+        Format::Transaction,
+        Format::Account,
+        Format::Audit,
+        #[cfg(feature = "snapshot")]
+        Format::Snapshot,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Transaction => "transaction",
+            Format::Account => "account",
+            Format::Audit => "audit",
+            #[cfg(feature = "snapshot")]
+            Format::Snapshot => "snapshot",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name() == name)
+    }
+
+    pub fn schema(self) -> RootSchema {
+        match self {
+            Format::Transaction => schema_for!(TransactionRecord),
+            Format::Account => schema_for!(AccountRow),
+            Format::Audit => schema_for!(AuditRow),
+            #[cfg(feature = "snapshot")]
+            Format::Snapshot => schema_for!(Snapshot),
+        }
+    }
+}
+
+impl JsonSchema for PositiveDecimal {
+    fn schema_name() -> String {
+        "PositiveDecimal".to_owned()
+    }
+
+    fn schema_id() -> Cow<'static, str> {
+        Cow::Borrowed("PositiveDecimal")
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    /// A decimal string like [Decimal](rust_decimal::Decimal)'s, without a sign
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(r"^[0-9]+(\.[0-9]+)?$".to_owned()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_names() {
+        for format in Format::ALL {
+            assert_eq!(Format::from_name(format.name()), Some(*format));
+        }
+        assert_eq!(Format::from_name("accounts"), None);
+    }
+
+    #[test]
+    fn test_account_schema() {
+        let schema = Format::Account.schema().schema;
+        let properties = schema.object.unwrap().properties;
+        assert_eq!(
+            properties.keys().collect::<Vec<_>>(),
+            vec!["available", "client", "held", "locked", "total"]
+        );
+        assert_eq!(
+            properties["available"],
+            PositiveDecimal::json_schema(&mut SchemaGenerator::default())
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "csv")]
+use std::io;
+
+#[cfg(feature = "csv")]
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::error::TxError;
+use crate::map::Map;
+use crate::transaction::{PositiveDecimal, Transaction, TransactionType};
+
+/// What a single client is owed (positive `net`) or owes (negative `net`) for one processing
+/// run. Only the transactions of the run are considered, never the opening balances, so `net`
+/// is the change of the client's total funds over the run.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Settlement {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub deposits: PositiveDecimal,
+    pub withdrawals: PositiveDecimal,
+    pub chargebacks: PositiveDecimal,
+    pub net: Decimal,
+}
+
+impl Settlement {
+    fn new(client_id: u16) -> Self {
+        Settlement {
+            client_id,
+            ..Default::default()
+        }
+    }
+}
+
+/// Computes the net settlement of every client from a chronologically ordered log of applied
+/// transactions. Clients are ordered by id.
+pub fn net_settlement(
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<Vec<Settlement>, TxError> {
+    let mut amounts = Map::default();
+    let mut settlements = BTreeMap::default();
+    for transaction in transactions {
+        let settlement = settlements
+            .entry(transaction.client_id)
+            .or_insert_with(|| Settlement::new(transaction.client_id));
+        match transaction.tx_type {
+            TransactionType::Deposit { amount } => {
+                amounts.entry(transaction.transaction_id).or_insert(amount);
+                settlement.deposits = settlement.deposits.checked_add(amount)?;
+            }
+            TransactionType::Withdrawal { amount } => {
+                amounts.entry(transaction.transaction_id).or_insert(amount);
+                settlement.withdrawals = settlement.withdrawals.checked_add(amount)?;
+            }
+            TransactionType::Chargeback => {
+                let amount = *amounts
+                    .get(&transaction.transaction_id)
+                    .ok_or(TxError::NotFound)?;
+                settlement.chargebacks = settlement.chargebacks.checked_add(amount)?;
+            }
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. }
+            | TransactionType::Other { .. } => {}
+        }
+    }
+
+    settlements
+        .into_values()
+        .map(|mut settlement| {
+            settlement.net = Decimal::from(settlement.deposits)
+                .checked_sub(settlement.withdrawals.into())
+                .and_then(|net| net.checked_sub(settlement.chargebacks.into()))
+                .ok_or(TxError::InvalidAmount)?;
+            Ok(settlement)
+        })
+        .collect()
+}
+
+/// Writes one row per settlement, preceded by a header row
+#[cfg(feature = "csv")]
+pub fn write_settlement_csv<W: io::Write>(
+    settlements: &[Settlement],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for settlement in settlements {
+        writer.serialize(settlement)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_net_settlement() {
+        let ten = PositiveDecimal::try_from(10.0).unwrap();
+        let two = PositiveDecimal::try_from(2.0).unwrap();
+        let transactions = vec![
+            Transaction::new(2, 1, TransactionType::Deposit { amount: ten }),
+            Transaction::new(2, 2, TransactionType::Withdrawal { amount: two }),
+            Transaction::new(1, 3, TransactionType::Deposit { amount: two }),
+            Transaction::new(1, 4, TransactionType::Deposit { amount: two }),
+            Transaction::new(1, 4, TransactionType::Dispute),
+            Transaction::new(1, 4, TransactionType::Chargeback),
+            Transaction::new(3, 5, TransactionType::Withdrawal { amount: ten }),
+        ];
+        let settlements = net_settlement(transactions).unwrap();
+        let nets = settlements
+            .iter()
+            .map(|s| (s.client_id, s.net))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nets,
+            vec![
+                (1, Decimal::new(2, 0)),
+                (2, Decimal::new(8, 0)),
+                (3, Decimal::new(-10, 0)),
+            ]
+        );
+        assert_eq!(settlements[0].chargebacks, two);
+
+        let mut csv = Vec::new();
+        write_settlement_csv(&settlements[..1], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "client,deposits,withdrawals,chargebacks,net\n1,4.0000,0,2.0000,2.0000\n"
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! Processing inputs pre-split by client concurrently, one [Ledger] per shard, for parallelism
+//! without sharing a ledger between threads.
+//!
+//! Every client's records must be in a single shard, e.g. as written by [split_csv]. Shards are
+//! independent ledgers, so transaction ids are only unique within a shard, and the
+//! [Limits](crate::limits::Limits) of the template ledger apply to every shard on its own.
+//! Likewise each shard resolves its external client ids on its own, so they must be given
+//! client ids from a separate range in every shard.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::thread;
+
+use csv::{ReaderBuilder, StringRecord, Trim, WriterBuilder};
+use thiserror::Error;
+
+use crate::account::AccountView;
+use crate::aliases::hash_client_ref;
+use crate::error::TxError;
+use crate::io::{SourceError, TransactionSource};
+use crate::ledger::{Ledger, Progress};
+use crate::report::{write_account_views_csv, AccountColumns};
+
+/// Why shards couldn't be processed or split
+#[derive(Error, Debug)]
+pub enum ShardError {
+    #[error("Shard {shard}: {error}")]
+    Open { shard: usize, error: SourceError },
+    #[error("Client {client_id} has records in shards {first} and {second}")]
+    Overlap {
+        client_id: u16,
+        first: usize,
+        second: usize,
+    },
+    #[error("The input has no `client` or `client_ref` column")]
+    MissingClientColumn,
+    #[error("CSV Error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("I/O Error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// The shard of `client_id` out of `shards`. This is part of the format of split inputs, so
+/// it stays the same across versions.
+pub fn shard_of(client_id: u16, shards: usize) -> usize {
+    // Fibonacci hashing, spreading consecutive ids across shards
+    let hash = (u64::from(client_id).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize;
+    hash % shards.max(1)
+}
+
+/// The shard of the external client id `client_ref` out of `shards`, for records whose client
+/// id is only resolved once they're processed. Like [shard_of], this stays the same across
+/// versions.
+pub fn shard_of_ref(client_ref: &str, shards: usize) -> usize {
+    let hash = (hash_client_ref(client_ref).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize;
+    hash % shards.max(1)
+}
+
+/// The ledgers of shards processed with [process_shards], in the order of the shards
+#[derive(Debug)]
+pub struct Shards {
+    pub ledgers: Vec<Ledger>,
+    /// How far processing every shard got
+    pub progress: Vec<Progress>,
+}
+
+impl Shards {
+    /// Every account of every shard
+    pub fn accounts(&self) -> impl Iterator<Item = &dyn AccountView> {
+        self.ledgers.iter().flat_map(Ledger::accounts)
+    }
+
+    /// The progress of all shards together. Processing stopped early if it stopped early in
+    /// any shard.
+    pub fn total_progress(&self) -> Progress {
+        self.progress
+            .iter()
+            .fold(Progress::default(), |total, progress| Progress {
+                records: total.records + progress.records,
+                applied: total.applied + progress.applied,
+                rejected: total.rejected + progress.rejected,
+                cancelled: total.cancelled || progress.cancelled,
+                limit_exceeded: total.limit_exceeded.or(progress.limit_exceeded),
+            })
+    }
+
+    /// Writes the accounts of every shard as a single report, like
+    /// [write_accounts_csv_as](crate::report::write_accounts_csv_as)
+    pub fn write_accounts_csv_as<W: io::Write>(
+        &self,
+        columns: AccountColumns,
+        writer: W,
+    ) -> Result<(), TxError> {
+        write_account_views_csv(self.accounts(), columns, writer)
+    }
+}
+
+/// Processes every shard on its own thread, in a clone of `template` sharing its
+/// [CancellationToken](crate::cancel::CancellationToken), with the source `open` returns for
+/// it. Sources are opened on the shard's thread, so they don't need to be [Send].
+///
+/// Fails if a shard couldn't be opened, or if a client has accounts in more than one shard,
+/// which means the input wasn't split by client.
+pub fn process_shards<T, S>(
+    template: &Ledger,
+    shards: &[T],
+    open: impl Fn(&T) -> Result<S, SourceError> + Sync,
+) -> Result<Shards, ShardError>
+where
+    T: Sync,
+    S: TransactionSource,
+{
+    let results = thread::scope(|scope| {
+        let threads = shards
+            .iter()
+            .map(|shard| {
+                let open = &open;
+                scope.spawn(move || {
+                    let mut source = open(shard)?;
+                    let mut ledger = template.clone();
+                    let progress = ledger.process_source(&mut source);
+                    Ok((ledger, progress))
+                })
+            })
+            .collect::<Vec<_>>();
+        threads
+            .into_iter()
+            .map(|thread| match thread.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect::<Vec<Result<_, SourceError>>>()
+    });
+
+    let mut ledgers = Vec::with_capacity(shards.len());
+    let mut progress = Vec::with_capacity(shards.len());
+    let mut clients = BTreeMap::new();
+    for (shard, result) in results.into_iter().enumerate() {
+        let (ledger, shard_progress) = result.map_err(|error| ShardError::Open { shard, error })?;
+        for account in ledger.accounts() {
+            if let Some(first) = clients.insert(account.client_id(), shard) {
+                return Err(ShardError::Overlap {
+                    client_id: account.client_id(),
+                    first,
+                    second: shard,
+                });
+            }
+        }
+        ledgers.push(ledger);
+        progress.push(shard_progress);
+    }
+    Ok(Shards { ledgers, progress })
+}
+
+/// Copies the records of a CSV input with a header row to one writer per shard, by the
+/// [shard_of] their `client` column, or the [shard_of_ref] of their `client_ref` column if it
+/// isn't empty, each preceded by the same header row. Records are copied as read, in order;
+/// ones without a valid client id go to the first shard, to be rejected there. Returns the
+/// number of records written to each shard.
+pub fn split_csv<R: io::Read, W: io::Write>(
+    reader: R,
+    writers: &mut [W],
+) -> Result<Vec<u64>, ShardError> {
+    let mut reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let client = headers.iter().position(|header| header == "client");
+    let client_ref = headers.iter().position(|header| header == "client_ref");
+    if client.is_none() && client_ref.is_none() {
+        return Err(ShardError::MissingClientColumn);
+    }
+    let mut writers = writers
+        .iter_mut()
+        .map(|writer| {
+            let mut writer = WriterBuilder::new().flexible(true).from_writer(writer);
+            writer.write_record(&headers)?;
+            Ok(writer)
+        })
+        .collect::<Result<Vec<_>, csv::Error>>()?;
+    let mut counts = vec![0; writers.len()];
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let shard = match (
+            client_ref
+                .and_then(|column| record.get(column))
+                .filter(|client_ref| !client_ref.is_empty()),
+            client
+                .and_then(|column| record.get(column))
+                .and_then(|id| id.parse().ok()),
+        ) {
+            (Some(client_ref), _) => shard_of_ref(client_ref, writers.len()),
+            (None, Some(client_id)) => shard_of(client_id, writers.len()),
+            (None, None) => 0,
+        };
+        writers[shard].write_record(&record)?;
+        counts[shard] += 1;
+    }
+    for writer in &mut writers {
+        writer.flush()?;
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::{CsvOptions, CsvSource};
+
+    const INPUT: &str = "type, client, tx, amount\n\
+                         deposit, 1, 1, 1.0\n\
+                         deposit, 2, 2, 2.0\n\
+                         deposit, 3, 3, 3.0\n\
+                         withdrawal, 1, 4, 0.5\n\
+                         deposit, x, 5, 1.0\n\
+                         dispute, 2, 2\n";
+
+    #[test]
+    fn test_shard_of() {
+        for shards in 1..8 {
+            for client_id in 0..100 {
+                assert!(shard_of(client_id, shards) < shards);
+            }
+        }
+        // consecutive ids are spread across shards
+        let used = (0..16)
+            .map(|client_id| shard_of(client_id, 4))
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(used.len(), 4);
+        let used = (0..16)
+            .map(|client| shard_of_ref(&format!("cus_{}", client), 4))
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(used.len(), 4);
+    }
+
+    #[test]
+    fn test_split_and_process() {
+        let mut outputs = vec![Vec::new(); 3];
+        let counts = split_csv(INPUT.as_bytes(), &mut outputs).unwrap();
+        assert_eq!(counts.iter().sum::<u64>(), 6);
+        let shards = process_shards(&Ledger::default(), &outputs, |shard| {
+            CsvSource::new(
+                io::Cursor::new(shard.clone()),
+                "shard",
+                &CsvOptions::default(),
+            )
+        })
+        .unwrap();
+        let progress = shards.total_progress();
+        assert_eq!((progress.records, progress.applied), (6, 5));
+
+        let mut sharded = Vec::new();
+        shards
+            .write_accounts_csv_as(AccountColumns::V1, &mut sharded)
+            .unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_source(
+            &mut CsvSource::new(INPUT.as_bytes(), "input", &CsvOptions::default()).unwrap(),
+        );
+        let mut expected = Vec::new();
+        crate::report::write_accounts_csv(&ledger, &mut expected).unwrap();
+        assert_eq!(
+            String::from_utf8(sharded).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_overlapping_shards() {
+        let shards = ["type,client,tx,amount\ndeposit,1,1,1.0\n"; 2];
+        let result = process_shards(&Ledger::default(), &shards, |shard| {
+            CsvSource::new(io::Cursor::new(*shard), "shard", &CsvOptions::default())
+        });
+        assert!(matches!(
+            result,
+            Err(ShardError::Overlap {
+                client_id: 1,
+                first: 0,
+                second: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_split_without_client_column() {
+        let result = split_csv("type,tx\ndeposit,1\n".as_bytes(), &mut [Vec::new()]);
+        assert!(matches!(result, Err(ShardError::MissingClientColumn)));
+    }
+}
@@ -0,0 +1,152 @@
+//! A [Ledger] shared between threads, locked per group of clients rather than as a whole
+
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::TxError;
+use crate::idempotency::{IdempotencyCache, Outcome, Receipt};
+use crate::ledger::Ledger;
+use crate::transaction::Transaction;
+
+/// Splits the accounts of a [Ledger] into shards, each behind its own lock, so transactions of
+/// clients in different shards are applied concurrently. A client always maps to the same
+/// shard, so the transactions of a client are applied one at a time, in the order their
+/// [SharedLedger::add_tx] calls acquired its shard's lock: the transactions a thread submits for
+/// a client are applied in the order it submitted them.
+///
+/// Every shard starts as a clone of the same template ledger, as in
+/// [LedgerSet](crate::ledger_set::LedgerSet), so [Limits](crate::limits::Limits) apply per
+/// shard, and a client disputing another client's transaction in a different shard gets
+/// [TxError::NotFound] rather than [TxError::InsufficientPermission].
+#[derive(Debug)]
+pub struct SharedLedger {
+    shards: Vec<Mutex<Shard>>,
+}
+
+#[derive(Debug)]
+struct Shard {
+    ledger: Ledger,
+    /// The outcomes of the shard's recent submissions, see [SharedLedger::submit]
+    idempotency: IdempotencyCache,
+}
+
+impl SharedLedger {
+    /// A ledger of `shards` clones of `template`, at least one. More shards than threads
+    /// applying transactions keeps threads from waiting on clients they don't share.
+    pub fn new(template: Ledger, shards: usize) -> Self {
+        SharedLedger::with_idempotency(template, shards, 0)
+    }
+
+    /// Like [SharedLedger::new], remembering the outcomes of the last `capacity` submissions
+    /// of each shard for [SharedLedger::submit]
+    pub fn with_idempotency(template: Ledger, shards: usize, capacity: usize) -> Self {
+        SharedLedger {
+            shards: (0..shards.max(1))
+                .map(|_| {
+                    Mutex::new(Shard {
+                        ledger: template.clone(),
+                        idempotency: IdempotencyCache::new(capacity),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Applies `transaction`, holding only the lock of its client's shard
+    pub fn add_tx(&self, transaction: Transaction) -> Result<(), TxError> {
+        self.lock(transaction.client_id).ledger.add_tx(transaction)
+    }
+
+    /// Applies `transaction` unless it was submitted under `key` before, e.g. by an HTTP
+    /// handler retrying a request, in which case the original outcome is returned without
+    /// applying it again. Only the outcome of a key's first submission is applied and kept, so
+    /// a retry racing the original waits for it and gets its outcome. Reusing a key for another
+    /// transaction is [TxError::IdempotencyKeyReused].
+    ///
+    /// Keys are remembered per shard, for as many submissions as given to
+    /// [SharedLedger::with_idempotency].
+    pub fn submit(&self, key: &str, transaction: Transaction) -> Result<Receipt, TxError> {
+        let mut shard = self.lock(transaction.client_id);
+        if let Some(outcome) = shard.idempotency.get(key, &transaction)? {
+            return Ok(Receipt {
+                outcome,
+                replayed: true,
+            });
+        }
+        let outcome = Outcome::new(&shard.ledger.add_tx(transaction.clone()));
+        shard.idempotency.insert(key, transaction, outcome.clone());
+        Ok(Receipt {
+            outcome,
+            replayed: false,
+        })
+    }
+
+    /// Calls `f` with the ledger holding `client_id`'s account, e.g. to read its balances,
+    /// while holding that shard's lock
+    pub fn with_client<R>(&self, client_id: u16, f: impl FnOnce(&Ledger) -> R) -> R {
+        f(&self.lock(client_id).ledger)
+    }
+
+    /// The ledger of each shard. Each client's account and transactions are in exactly one
+    /// of them.
+    pub fn into_shards(self) -> Vec<Ledger> {
+        self.shards
+            .into_iter()
+            .map(|shard| shard.into_inner().expect(POISONED).ledger)
+            .collect()
+    }
+
+    fn lock(&self, client_id: u16) -> MutexGuard<'_, Shard> {
+        self.shards[usize::from(client_id) % self.shards.len()]
+            .lock()
+            .expect(POISONED)
+    }
+}
+
+/// A thread panicked while applying a transaction, e.g. in an
+/// [AccountLayer](crate::ledger::AccountLayer), and may have left the shard half updated
+const POISONED: &str = "a thread panicked while holding a shard of the ledger";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::account::AccountView;
+    use crate::transaction::{PositiveDecimal, TransactionType};
+
+    #[test]
+    fn test_submit() {
+        let amount = PositiveDecimal::try_from(5.0).unwrap();
+        let ledger = SharedLedger::with_idempotency(Ledger::default(), 2, 10);
+        let deposit = Transaction::new(1, 1, TransactionType::Deposit { amount });
+        let withdrawal = Transaction::new(1, 2, TransactionType::Withdrawal { amount });
+
+        let receipt = ledger.submit("deposit-1", deposit.clone()).unwrap();
+        assert_eq!(receipt.outcome, Outcome::Applied);
+        assert!(!receipt.replayed);
+        let retry = ledger.submit("deposit-1", deposit.clone()).unwrap();
+        assert_eq!(retry.outcome, Outcome::Applied);
+        assert!(retry.replayed);
+
+        ledger.submit("withdrawal-2", withdrawal.clone()).unwrap();
+        let rejected = ledger.submit("withdrawal-3", withdrawal.clone()).unwrap();
+        assert_eq!(
+            rejected.outcome,
+            Outcome::Rejected(TxError::InsufficientFunds.to_string())
+        );
+        assert!(matches!(
+            ledger.submit("deposit-1", withdrawal),
+            Err(TxError::IdempotencyKeyReused)
+        ));
+
+        ledger.with_client(1, |ledger| {
+            assert_eq!(
+                ledger.active_accounts()[&1].available(),
+                PositiveDecimal::default()
+            );
+            assert_eq!(ledger.transactions().len(), 2);
+        });
+    }
+}
@@ -0,0 +1,761 @@
+//! The complete state of a [Ledger], so a run can be stopped and resumed later, or the next
+//! run can carry on with the accounts, transactions and open disputes of the previous one
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::account::{Account, AccountView, ArchivedAccount};
+use crate::availability::{Due, PendingDeposit};
+use crate::build_info::BuildInfo;
+#[cfg(feature = "encryption")]
+use crate::encryption::StateKey;
+use crate::error::TxError;
+use crate::journal::{Journal, JournalEntry, SystemAccount};
+use crate::ledger::{Ledger, Rejection, Suspended};
+use crate::rounding::RoundingReserve;
+use crate::transaction::{
+    Origin, PositiveDecimal, Provenance, Transaction, TransactionRecord, TransactionRecordType,
+    TransactionType,
+};
+
+/// A point in a run from which processing can resume. Everything except the configuration of
+/// the ledger (layers, disabled transaction types, ...) is captured.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Number of input records consumed when the snapshot was taken, i.e. where to resume
+    pub records: u64,
+    /// The number of runs closed so far, in closing states, see [Snapshot::closing]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub run: u64,
+    pub accounts: Vec<AccountSnapshot>,
+    /// The accounts moved out of the ledger, see [Ledger::archive_account]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archived: Vec<AccountSnapshot>,
+    /// The client ids of external client ids, see [Ledger::client_aliases]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub client_aliases: BTreeMap<String, u16>,
+    /// The applied transactions, in the order they were applied
+    pub transactions: Vec<TransactionSnapshot>,
+    pub disputes: Vec<DisputeSnapshot>,
+    /// The deposits whose funds are held until they're due, see [Ledger::pending_deposits]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending: Vec<PendingDepositSnapshot>,
+    /// The reserved parts of deposits held until they're due, see [Ledger::reserves]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reserves: Vec<PendingDepositSnapshot>,
+    pub quarantine: Vec<RejectionSnapshot>,
+    /// The transactions parked in suspense, with the amount given in their record, see
+    /// [Ledger::suspended]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suspense: Vec<TransactionSnapshot>,
+    /// The entries posted in double-entry mode, see [Ledger::journal]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub journal: Vec<JournalEntrySnapshot>,
+    /// The residues of calculated amounts, see [Ledger::rounding_reserve]
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub rounding: RoundingReserve,
+    /// The open statement period, of ledgers that closed one, see [Ledger::close_period]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<PeriodSnapshot>,
+    /// The input files of the run, see [Snapshot::with_inputs]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<InputDigest>,
+    /// The processor that took the snapshot, see [Snapshot::with_build]. Absent from snapshots
+    /// of earlier versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildInfo>,
+}
+
+/// An input file of a run and the SHA-256 of its contents, see
+/// [Checksum](crate::checksum::Checksum)
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputDigest {
+    pub source: String,
+    /// Hex-encoded
+    pub sha256: String,
+}
+
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    pub client: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub flags: BTreeSet<String>,
+    /// The part of `held` that is a rolling reserve, see [AccountView::reserved]
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub reserved: Decimal,
+}
+
+impl AccountSnapshot {
+    fn new(account: &dyn AccountView) -> Self {
+        AccountSnapshot {
+            client: account.client_id(),
+            available: account.available().into(),
+            held: account.held().into(),
+            locked: account.is_locked(),
+            flags: account.flags().clone(),
+            reserved: account.reserved().into(),
+        }
+    }
+
+    fn restore(self) -> Result<ArchivedAccount, TxError> {
+        let mut account = Account::with_balance(
+            self.client,
+            PositiveDecimal::try_from(self.available)?,
+            PositiveDecimal::try_from(self.held)?,
+        )
+        .with_reserved(PositiveDecimal::try_from(self.reserved)?);
+        account.flags = self.flags;
+        Ok(if self.locked {
+            ArchivedAccount::Locked(account.into())
+        } else {
+            ArchivedAccount::Active(account)
+        })
+    }
+}
+
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionSnapshot {
+    #[serde(flatten)]
+    pub record: TransactionRecord,
+    #[serde(default, skip_serializing_if = "is_ingested")]
+    pub origin: Origin,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// The run the transaction was applied in, in closing states
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<u64>,
+}
+
+fn is_zero(run: &u64) -> bool {
+    *run == 0
+}
+
+fn is_default(rounding: &RoundingReserve) -> bool {
+    *rounding == RoundingReserve::default()
+}
+
+fn is_ingested(origin: &Origin) -> bool {
+    *origin == Origin::Ingested
+}
+
+impl TransactionSnapshot {
+    fn new(mut transaction: Transaction) -> Self {
+        TransactionSnapshot {
+            origin: transaction.origin,
+            provenance: transaction.provenance.take().map(|provenance| *provenance),
+            record: transaction.into(),
+            run: None,
+        }
+    }
+
+    fn restore(self) -> Result<Transaction, TxError> {
+        Ok(Transaction {
+            origin: self.origin,
+            provenance: self.provenance.map(Box::new),
+            ..Transaction::try_from(self.record)?
+        })
+    }
+}
+
+/// The run each transaction of a closing state was applied in. Read it with
+/// [Snapshot::history] before the state is restored, so the next closing state can drop the
+/// transactions past the retention horizon.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RunHistory {
+    /// The run that wrote the closing state, or 0 before the first run
+    pub run: u64,
+    applied_in: BTreeMap<u32, u64>,
+}
+
+/// An open dispute of a transaction
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisputeSnapshot {
+    pub client: u16,
+    pub tx: u32,
+    pub amount: Decimal,
+}
+
+/// A pending deposit or the reserved part of a deposit, due either after a number of records or
+/// at a time
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingDepositSnapshot {
+    pub client: u16,
+    pub tx: u32,
+    pub amount: Decimal,
+    /// The records left to process until the deposit is due, counted from where processing
+    /// resumes, if it isn't due at a time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records_left: Option<u64>,
+    /// The timestamp the deposit is due at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<i64>,
+}
+
+/// Where the open statement period starts, see [Ledger::close_period]. The transactions of
+/// closed periods are kept in their archives, not in snapshots.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeriodSnapshot {
+    /// The end of the latest closed period: records timestamped at or before it are rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_through: Option<i64>,
+    /// The number of transactions at the end of the snapshot's `transactions` applied during
+    /// the open period
+    pub transactions: u64,
+}
+
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntrySnapshot {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx: Option<u32>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<TransactionRecordType>,
+    pub client: u16,
+    pub debit: SystemAccount,
+    pub credit: SystemAccount,
+    pub amount: Decimal,
+}
+
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RejectionSnapshot {
+    pub transaction: TransactionRecord,
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+impl Snapshot {
+    /// Captures the state of `ledger` after `records` input records were processed. Accounts
+    /// and disputes are ordered by id so equal ledgers produce equal snapshots.
+    pub fn new(ledger: &Ledger, records: u64) -> Self {
+        let mut accounts = ledger
+            .accounts()
+            .map(AccountSnapshot::new)
+            .collect::<Vec<_>>();
+        accounts.sort_unstable_by_key(|account| account.client);
+        let mut archived = ledger
+            .archived_accounts()
+            .values()
+            .map(|account| AccountSnapshot::new(account))
+            .collect::<Vec<_>>();
+        archived.sort_unstable_by_key(|account| account.client);
+
+        let mut disputes = ledger
+            .disputed_tx_map
+            .iter()
+            .map(|(&tx, &(client, amount))| DisputeSnapshot {
+                client,
+                tx,
+                amount: amount.into(),
+            })
+            .collect::<Vec<_>>();
+        disputes.sort_unstable_by_key(|dispute| dispute.tx);
+
+        let deposit = |deposit: &PendingDeposit| PendingDepositSnapshot {
+            client: deposit.client_id,
+            tx: deposit.transaction_id,
+            amount: deposit.amount.into(),
+            records_left: ledger.availability.records_left(deposit),
+            due: match deposit.due {
+                Due::Time(time) => Some(time),
+                Due::Record(_) => None,
+            },
+        };
+
+        Snapshot {
+            records,
+            run: 0,
+            accounts,
+            archived,
+            client_aliases: ledger
+                .client_aliases()
+                .iter()
+                .map(|(client_ref, client_id)| (client_ref.to_owned(), client_id))
+                .collect(),
+            transactions: ledger
+                .transactions()
+                .iter()
+                .map(TransactionSnapshot::new)
+                .collect(),
+            disputes,
+            pending: ledger.pending_deposits().map(deposit).collect(),
+            reserves: ledger.reserves().map(deposit).collect(),
+            quarantine: ledger
+                .quarantined()
+                .iter()
+                .map(|rejection| RejectionSnapshot {
+                    transaction: rejection.transaction.clone().into(),
+                    reason: rejection.reason.clone(),
+                    provenance: rejection.transaction.provenance.as_deref().cloned(),
+                })
+                .collect(),
+            suspense: ledger
+                .suspended()
+                .into_iter()
+                .map(|suspended| {
+                    let mut snapshot = TransactionSnapshot::new(suspended.transaction.clone());
+                    snapshot.record.amount = suspended.amount.map(Into::into);
+                    snapshot
+                })
+                .collect(),
+            journal: ledger
+                .journal()
+                .map_or(&[][..], Journal::entries)
+                .iter()
+                .map(|entry| JournalEntrySnapshot {
+                    tx: entry.transaction_id,
+                    transaction_type: entry.transaction_type,
+                    client: entry.client_id,
+                    debit: entry.debit,
+                    credit: entry.credit,
+                    amount: entry.amount.into(),
+                })
+                .collect(),
+            rounding: *ledger.rounding_reserve(),
+            period: (ledger.periods.start > 0 || ledger.periods.closed_through.is_some()).then(
+                || PeriodSnapshot {
+                    closed_through: ledger.periods.closed_through,
+                    transactions: (ledger.transactions().len() - ledger.periods.start) as u64,
+                },
+            ),
+            inputs: Vec::new(),
+            build: Some(BuildInfo::current()),
+        }
+    }
+
+    /// Records the processor and policies of the run that took the snapshot, by default the
+    /// [current](BuildInfo::current) build without policies
+    pub fn with_build(mut self, build: BuildInfo) -> Self {
+        self.build = Some(build);
+        self
+    }
+
+    /// Records the input files the snapshot was derived from, in the order they were read
+    pub fn with_inputs(mut self, inputs: Vec<InputDigest>) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Captures the state of `ledger` at the end of a run, to open the next run with. Disputes
+    /// opened in this run can then be resolved or charged back by the records of the next one,
+    /// and the deposits and withdrawals of the last `retention` runs, or of every run, disputed.
+    ///
+    /// `history` is the [Snapshot::history] of the state this run was opened with, if any. Only
+    /// what later runs need is kept: no input records are recorded as consumed, since the next
+    /// run reads its own input, and the quarantine and journal entries are left to this run's
+    /// reports.
+    pub fn closing(ledger: &Ledger, history: &RunHistory, retention: Option<u64>) -> Self {
+        let run = history.run + 1;
+        // the transactions kept of the open period
+        let mut open = 0;
+        let transactions = ledger
+            .transactions()
+            .iter()
+            .enumerate()
+            .filter(|(_, transaction)| {
+                matches!(
+                    transaction.tx_type,
+                    TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. }
+                )
+            })
+            .filter_map(|(index, transaction)| {
+                let applied_in = history
+                    .applied_in
+                    .get(&transaction.transaction_id)
+                    .copied()
+                    .unwrap_or(run);
+                if retention.is_some_and(|retention| run - applied_in >= retention) {
+                    return None;
+                }
+                if index >= ledger.periods.start {
+                    open += 1;
+                }
+                Some(TransactionSnapshot {
+                    run: Some(applied_in),
+                    ..TransactionSnapshot::new(transaction)
+                })
+            })
+            .collect();
+        let snapshot = Snapshot::new(ledger, 0);
+        Snapshot {
+            run,
+            transactions,
+            quarantine: Vec::new(),
+            journal: Vec::new(),
+            period: snapshot.period.map(|period| PeriodSnapshot {
+                transactions: open,
+                ..period
+            }),
+            ..snapshot
+        }
+    }
+
+    /// The run each transaction of this closing state was applied in, see [Snapshot::closing]
+    pub fn history(&self) -> RunHistory {
+        RunHistory {
+            run: self.run,
+            applied_in: self
+                .transactions
+                .iter()
+                .filter_map(|transaction| {
+                    Some((transaction.record.transaction_id, transaction.run?))
+                })
+                .collect(),
+        }
+    }
+
+    /// Rebuilds the ledger this snapshot was taken of, configured like `template`
+    pub fn restore(self, template: Ledger) -> Result<Ledger, TxError> {
+        let mut ledger = template;
+        let accounts = self.accounts.into_iter().map(|account| (false, account));
+        let archived = self.archived.into_iter().map(|account| (true, account));
+        for (is_archived, account) in accounts.chain(archived) {
+            let client = account.client;
+            if ledger.active_accounts.contains_key(&client)
+                || ledger.locked_accounts.contains_key(&client)
+                || ledger.archived_accounts.contains_key(&client)
+            {
+                return Err(TxError::DuplicateAccount);
+            }
+            match account.restore()? {
+                restored if is_archived => {
+                    ledger.archived_accounts.insert(client, restored);
+                }
+                ArchivedAccount::Active(restored) => {
+                    ledger.active_accounts.insert(client, restored);
+                }
+                ArchivedAccount::Locked(restored) => {
+                    ledger.locked_accounts.insert(client, restored);
+                }
+            }
+        }
+        for (client_ref, client_id) in self.client_aliases {
+            ledger.aliases.insert(client_ref, client_id);
+        }
+        for snapshot in self.transactions {
+            let transaction = snapshot.restore()?;
+            if transaction.origin == Origin::Synthetic {
+                ledger.tx_ids.skip_past(transaction.transaction_id);
+            }
+            ledger.disputable.insert(&transaction);
+            ledger
+                .history
+                .insert(&transaction, ledger.transactions.len());
+            ledger.transactions.push(transaction);
+        }
+        for dispute in self.disputes {
+            ledger.disputed_tx_map.insert(
+                dispute.tx,
+                (dispute.client, PositiveDecimal::try_from(dispute.amount)?),
+            );
+        }
+        let pending = self.pending.into_iter().map(|pending| (false, pending));
+        let reserves = self.reserves.into_iter().map(|reserve| (true, reserve));
+        for (is_reserve, deposit) in pending.chain(reserves) {
+            let due = match deposit.due {
+                Some(time) => Due::Time(time),
+                None => ledger
+                    .availability
+                    .due_after(deposit.records_left.unwrap_or_default()),
+            };
+            let deposit = PendingDeposit {
+                client_id: deposit.client,
+                transaction_id: deposit.tx,
+                amount: PositiveDecimal::try_from(deposit.amount)?,
+                due,
+            };
+            if is_reserve {
+                ledger.availability.reserves.insert(deposit);
+            } else {
+                ledger.availability.pending.insert(deposit);
+            }
+        }
+        for rejection in self.quarantine {
+            ledger.quarantine.push(Rejection {
+                transaction: Transaction {
+                    provenance: rejection.provenance.map(Box::new),
+                    ..Transaction::try_from(rejection.transaction)?
+                },
+                reason: rejection.reason,
+            });
+        }
+        if let Some(suspense) = &mut ledger.suspense {
+            for snapshot in self.suspense {
+                let amount = snapshot.record.amount.map(TryInto::try_into).transpose()?;
+                let transaction = snapshot.restore()?;
+                suspense
+                    .entry(transaction.transaction_id)
+                    .or_default()
+                    .push(Suspended {
+                        transaction,
+                        amount,
+                    });
+            }
+        }
+        ledger.track_risk();
+        if let Some(journal) = &mut ledger.journal {
+            journal.entries = self
+                .journal
+                .into_iter()
+                .map(|entry| {
+                    Ok(JournalEntry {
+                        transaction_id: entry.tx,
+                        transaction_type: entry.transaction_type,
+                        client_id: entry.client,
+                        debit: entry.debit,
+                        credit: entry.credit,
+                        amount: PositiveDecimal::try_from(entry.amount)?,
+                    })
+                })
+                .collect::<Result<_, TxError>>()?;
+        }
+        ledger.rounding = self.rounding;
+        if let Some(period) = self.period {
+            ledger.periods.closed_through = period.closed_through;
+            ledger.periods.start = ledger
+                .transactions
+                .len()
+                .saturating_sub(period.transactions as usize);
+        }
+
+        Ok(ledger)
+    }
+
+    pub fn write_json<W: io::Write>(&self, writer: W) -> Result<(), TxError> {
+        Ok(serde_json::to_writer(writer, self)?)
+    }
+
+    pub fn read_json<R: io::Read>(reader: R) -> Result<Self, TxError> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Writes the snapshot as JSON, encrypted if a key is given
+    #[cfg(feature = "encryption")]
+    pub fn write<W: io::Write>(
+        &self,
+        mut writer: W,
+        key: Option<&StateKey>,
+    ) -> Result<(), TxError> {
+        match key {
+            Some(key) => Ok(writer.write_all(&key.encrypt(&serde_json::to_vec(self)?))?),
+            None => self.write_json(writer),
+        }
+    }
+
+    /// Reads a snapshot written by [Snapshot::write] with `key`, or without a key if it's
+    /// `None`. With a key, a snapshot that isn't encrypted is rejected with
+    /// [TxError::DecryptionFailed] rather than trusted, as anyone could have written it.
+    #[cfg(feature = "encryption")]
+    pub fn read<R: io::Read>(mut reader: R, key: Option<&StateKey>) -> Result<Self, TxError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let json = match (key, StateKey::is_encrypted(&data)) {
+            (Some(key), true) => key.decrypt(&data)?,
+            (Some(_), false) => return Err(TxError::DecryptionFailed),
+            (None, true) => return Err(TxError::MissingKey),
+            (None, false) => data,
+        };
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::availability::AvailabilityDelay;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let amount = PositiveDecimal::try_from(10.1234).unwrap();
+        let mut ledger = Ledger::builder().double_entry().suspense().build().unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Flag {
+                    flag: "vip".to_owned(),
+                },
+            ),
+            Transaction::new(2, 4, TransactionType::Deposit { amount }),
+            Transaction::new(2, 4, TransactionType::Dispute),
+            // chargebacks keep their provenance in the log
+            Transaction {
+                provenance: Some(Box::new(Provenance {
+                    source: "input.csv".into(),
+                    line: 10,
+                    offset: 0,
+                    batch: None,
+                })),
+                ..Transaction::new(2, 4, TransactionType::Chargeback)
+            },
+            Transaction::new(3, 5, TransactionType::Withdrawal { amount }),
+            Transaction::new(3, 99, TransactionType::Dispute),
+            Transaction::new(3, 6, TransactionType::Deposit { amount }),
+        ]);
+        ledger
+            .add_synthetic_tx(1, TransactionType::Withdrawal { amount })
+            .unwrap();
+
+        let mut json = Vec::new();
+        Snapshot::new(&ledger, 8).write_json(&mut json).unwrap();
+        let snapshot = Snapshot::read_json(json.as_slice()).unwrap();
+        assert_eq!(snapshot.records, 8);
+        let restored = snapshot
+            .restore(Ledger::builder().double_entry().suspense().build().unwrap())
+            .unwrap();
+        assert_eq!(restored, ledger);
+
+        // the restored ledger carries on where the original left off
+        let resolve = Transaction::new(1, 1, TransactionType::Resolve);
+        let mut restored = restored;
+        restored.add_tx(resolve.clone()).unwrap();
+        ledger.add_tx(resolve).unwrap();
+        assert_eq!(restored, ledger);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_snapshot() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions([Transaction::new(1, 1, TransactionType::Deposit { amount })]);
+        let snapshot = Snapshot::new(&ledger, 1);
+        let key = StateKey::from_hex(&"0f".repeat(32)).unwrap();
+
+        let mut encrypted = Vec::new();
+        snapshot.write(&mut encrypted, Some(&key)).unwrap();
+        let read = Snapshot::read(encrypted.as_slice(), Some(&key)).unwrap();
+        assert_eq!(read.restore(Ledger::default()).unwrap(), ledger);
+        assert!(matches!(
+            Snapshot::read(encrypted.as_slice(), None),
+            Err(TxError::MissingKey)
+        ));
+
+        // a plain snapshot where an encrypted one is expected may have been swapped in
+        let mut plain = Vec::new();
+        snapshot.write(&mut plain, None).unwrap();
+        assert!(Snapshot::read(plain.as_slice(), None).is_ok());
+        assert!(matches!(
+            Snapshot::read(plain.as_slice(), Some(&key)),
+            Err(TxError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_archived_accounts_round_trip() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::default();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(2, 2, TransactionType::Deposit { amount }),
+        ]);
+        ledger.archive_account(2).unwrap();
+
+        let snapshot = Snapshot::new(&ledger, 2);
+        assert_eq!(snapshot.accounts.len(), 1);
+        assert_eq!(snapshot.archived.len(), 1);
+        assert_eq!(snapshot.archived[0].client, 2);
+        assert_eq!(snapshot.restore(Ledger::default()).unwrap(), ledger);
+    }
+
+    #[test]
+    fn test_pending_deposits_round_trip() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let builder = || Ledger::builder().availability(AvailabilityDelay::Records(2));
+        let mut ledger = builder().build().unwrap();
+        ledger.process_transactions(vec![Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit { amount },
+        )]);
+
+        let snapshot = Snapshot::new(&ledger, 1);
+        assert_eq!(snapshot.pending.len(), 1);
+        assert_eq!(snapshot.pending[0].records_left, Some(2));
+        let mut restored = snapshot.restore(builder().build().unwrap()).unwrap();
+        assert_eq!(restored.pending_deposits().count(), 1);
+        assert_eq!(restored.active_accounts()[&1].held(), amount);
+
+        // due two records after the deposit, whether the ledger was restored in between or not
+        for transaction_id in [2, 3] {
+            restored
+                .add_tx(Transaction::new(
+                    2,
+                    transaction_id,
+                    TransactionType::Deposit { amount },
+                ))
+                .unwrap();
+        }
+        assert_eq!(restored.active_accounts()[&1].available(), amount);
+        assert_eq!(
+            restored
+                .pending_deposits()
+                .map(|deposit| deposit.transaction_id)
+                .collect::<Vec<_>>(),
+            [2, 3]
+        );
+    }
+
+    #[test]
+    fn test_closing_state_retention() {
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let mut ledger = Ledger::builder().build().unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Dispute),
+        ]);
+        let closing = Snapshot::closing(&ledger, &RunHistory::default(), Some(2));
+        assert_eq!(closing.run, 1);
+        // the dispute itself is kept in the open disputes, not the transactions
+        assert_eq!(closing.transactions.len(), 2);
+        assert_eq!(closing.disputes.len(), 1);
+
+        let history = closing.history();
+        let mut ledger = closing.restore(Ledger::builder().build().unwrap()).unwrap();
+        ledger.process_transactions(vec![
+            Transaction::new(1, 2, TransactionType::Resolve),
+            Transaction::new(1, 3, TransactionType::Deposit { amount }),
+        ]);
+        let run_ids = |snapshot: &Snapshot| {
+            snapshot
+                .transactions
+                .iter()
+                .map(|transaction| (transaction.record.transaction_id, transaction.run.unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let closing = Snapshot::closing(&ledger, &history, Some(2));
+        assert_eq!(closing.run, 2);
+        assert_eq!(run_ids(&closing), vec![(1, 1), (2, 1), (3, 2)]);
+        assert!(closing.disputes.is_empty());
+        let closing = Snapshot::closing(&ledger, &history, Some(1));
+        assert_eq!(run_ids(&closing), vec![(3, 2)]);
+
+        // transactions past the horizon can no longer be disputed
+        let mut ledger = closing.restore(Ledger::builder().build().unwrap()).unwrap();
+        assert!(matches!(
+            ledger.add_tx(Transaction::new(1, 1, TransactionType::Dispute)),
+            Err(TxError::NotFound)
+        ));
+        ledger
+            .add_tx(Transaction::new(1, 3, TransactionType::Dispute))
+            .unwrap();
+    }
+}
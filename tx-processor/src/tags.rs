@@ -0,0 +1,288 @@
+//! Rules-based categorization of transactions, e.g. tagging deposits from an employer as
+//! `payroll`, and the totals of every client's transactions by category
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "csv")]
+use std::io;
+
+#[cfg(feature = "csv")]
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::error::TxError;
+use crate::transaction::{
+    PositiveDecimal, Transaction, TransactionRecord, TransactionRecordType, TransactionType,
+};
+
+/// Assigns the category of a record's transaction. A rule matches a record if every condition
+/// it gives holds; a rule without conditions matches every record.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagRule {
+    pub category: String,
+    /// The types the rule applies to, or every type if empty
+    #[serde(default)]
+    pub types: Vec<TransactionRecordType>,
+    /// The smallest amount the rule applies to, inclusive. Records without an amount don't match.
+    #[serde(default)]
+    pub min_amount: Option<Decimal>,
+    /// The largest amount the rule applies to, inclusive. Records without an amount don't match.
+    #[serde(default)]
+    pub max_amount: Option<Decimal>,
+    /// The counterparty the rule applies to, compared ignoring ASCII case
+    #[serde(default)]
+    pub counterparty: Option<String>,
+}
+
+impl TagRule {
+    fn matches(&self, record: &TransactionRecord) -> bool {
+        let in_range = |bound: Option<Decimal>, in_bound: fn(&Decimal, &Decimal) -> bool| {
+            bound.is_none_or(|bound| {
+                record
+                    .amount
+                    .is_some_and(|amount| in_bound(&amount, &bound))
+            })
+        };
+        (self.types.is_empty() || self.types.contains(&record.transaction_type))
+            && in_range(self.min_amount, Decimal::ge)
+            && in_range(self.max_amount, Decimal::le)
+            && self.counterparty.as_ref().is_none_or(|counterparty| {
+                record
+                    .counterparty
+                    .as_ref()
+                    .is_some_and(|c| c.eq_ignore_ascii_case(counterparty))
+            })
+    }
+}
+
+/// Categorizes records by the first of its rules they match, e.g. as read from a TOML file of
+/// `[[rule]]` tables. Records categorized by the input keep their category.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tagger {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<TagRule>,
+}
+
+impl Tagger {
+    /// Sets the category of `record`, unless it has one or no rule matches it
+    pub fn tag(&self, mut record: TransactionRecord) -> TransactionRecord {
+        if record.category.is_none() {
+            record.category = self
+                .rules
+                .iter()
+                .find(|rule| rule.matches(&record))
+                .map(|rule| rule.category.clone());
+        }
+        record
+    }
+}
+
+/// The transactions of one client in one category
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct CategoryTotal {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub category: String,
+    pub count: u64,
+    /// The sum of the amounts of the transactions, if they have any
+    pub amount: PositiveDecimal,
+}
+
+/// Totals the categorized transactions of a log of applied transactions by client and
+/// category, ordered by client id and then by category
+pub fn category_totals(
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<Vec<CategoryTotal>, TxError> {
+    let mut totals = BTreeMap::<(u16, String), CategoryTotal>::new();
+    for transaction in transactions {
+        let Some(category) = transaction.category else {
+            continue;
+        };
+        let total = totals
+            .entry((transaction.client_id, category))
+            .or_insert_with_key(|(client_id, category)| CategoryTotal {
+                client_id: *client_id,
+                category: category.clone(),
+                ..Default::default()
+            });
+        total.count += 1;
+        match transaction.tx_type {
+            TransactionType::Deposit { amount }
+            | TransactionType::Withdrawal { amount }
+            | TransactionType::Other {
+                amount: Some(amount),
+                ..
+            } => total.amount = total.amount.checked_add(amount)?,
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Chargeback
+            | TransactionType::Flag { .. }
+            | TransactionType::Unflag { .. }
+            | TransactionType::Remap { .. }
+            | TransactionType::Other { amount: None, .. } => {}
+        }
+    }
+    Ok(totals.into_values().collect())
+}
+
+/// Writes one row per total, preceded by a header row
+#[cfg(feature = "csv")]
+pub fn write_category_totals_csv<W: io::Write>(
+    totals: &[CategoryTotal],
+    writer: W,
+) -> Result<(), TxError> {
+    let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+    for total in totals {
+        writer.serialize(total)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(
+        transaction_type: TransactionRecordType,
+        amount: Option<i64>,
+        counterparty: Option<&str>,
+    ) -> TransactionRecord {
+        TransactionRecord {
+            amount: amount.map(Decimal::from),
+            counterparty: counterparty.map(Into::into),
+            ..TransactionRecord::new(transaction_type, 1, 1)
+        }
+    }
+
+    fn rule(category: &str) -> TagRule {
+        TagRule {
+            category: category.into(),
+            types: Vec::new(),
+            min_amount: None,
+            max_amount: None,
+            counterparty: None,
+        }
+    }
+
+    #[test]
+    fn test_tagger() {
+        let tagger = Tagger {
+            rules: vec![
+                TagRule {
+                    types: vec![TransactionRecordType::Deposit],
+                    counterparty: Some("ACME Payroll".into()),
+                    ..rule("payroll")
+                },
+                TagRule {
+                    types: vec![TransactionRecordType::Withdrawal],
+                    min_amount: Some(Decimal::from(1000)),
+                    ..rule("large-withdrawal")
+                },
+                TagRule {
+                    types: vec![TransactionRecordType::Deposit],
+                    max_amount: Some(Decimal::from(100)),
+                    ..rule("transfer-in")
+                },
+            ],
+        };
+        let category = |record| tagger.tag(record).category;
+        assert_eq!(
+            category(record(
+                TransactionRecordType::Deposit,
+                Some(5000),
+                Some("acme payroll")
+            )),
+            Some("payroll".into())
+        );
+        assert_eq!(
+            category(record(TransactionRecordType::Withdrawal, Some(1000), None)),
+            Some("large-withdrawal".into())
+        );
+        assert_eq!(
+            category(record(TransactionRecordType::Withdrawal, Some(999), None)),
+            None
+        );
+        assert_eq!(
+            category(record(
+                TransactionRecordType::Deposit,
+                Some(100),
+                Some("Bob")
+            )),
+            Some("transfer-in".into())
+        );
+        assert_eq!(
+            category(record(TransactionRecordType::Dispute, None, None)),
+            None
+        );
+
+        let tagged = TransactionRecord {
+            category: Some("gambling".into()),
+            ..record(TransactionRecordType::Deposit, Some(50), None)
+        };
+        assert_eq!(category(tagged), Some("gambling".into()));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_category_totals() {
+        let amount = |amount: f64| PositiveDecimal::try_from(amount).unwrap();
+        let tagged = |client_id, transaction_id, tx_type, category: Option<&str>| Transaction {
+            category: category.map(Into::into),
+            ..Transaction::new(client_id, transaction_id, tx_type)
+        };
+        let transactions = vec![
+            tagged(
+                2,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(10.0),
+                },
+                Some("payroll"),
+            ),
+            tagged(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(2.5),
+                },
+                Some("transfer-in"),
+            ),
+            tagged(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(5.0),
+                },
+                Some("payroll"),
+            ),
+            tagged(
+                2,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount(1.0),
+                },
+                None,
+            ),
+        ];
+        let totals = category_totals(transactions).unwrap();
+        assert_eq!(
+            totals
+                .iter()
+                .map(|total| (total.client_id, total.category.as_str(), total.count))
+                .collect::<Vec<_>>(),
+            vec![(1, "transfer-in", 1), (2, "payroll", 2)]
+        );
+
+        let mut csv = Vec::new();
+        write_category_totals_csv(&totals[1..], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "client,category,count,amount\n2,payroll,2,15.0000\n"
+        );
+    }
+}
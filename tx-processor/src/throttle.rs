@@ -0,0 +1,181 @@
+//! Rate limiting of ingestion, so replaying history into a live system doesn't overwhelm a
+//! shared database or whatever observes the ledger downstream, and pacing of replays by the
+//! time the records happened
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::transaction::TransactionRecord;
+
+/// Paces the items of an iterator, usually records, to at most a number per second by sleeping
+/// before yielding an item that would exceed the rate. Time spent waiting on the underlying
+/// iterator counts towards the pace, so a slow source isn't throttled further.
+pub struct Throttle<I> {
+    items: I,
+    per_second: f64,
+    /// Items that can be yielded without waiting, at most one
+    allowance: f64,
+    last: Instant,
+    waited: Duration,
+}
+
+impl<I: Iterator> Throttle<I> {
+    /// Yields at most `per_second` items per second. `f64::INFINITY` passes items through
+    /// without pacing.
+    pub fn new(items: impl IntoIterator<IntoIter = I>, per_second: f64) -> Self {
+        Throttle {
+            items: items.into_iter(),
+            per_second,
+            allowance: 1.0,
+            last: Instant::now(),
+            waited: Duration::ZERO,
+        }
+    }
+
+    /// The time spent sleeping to keep to the rate so far
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+}
+
+impl<I: Iterator> Iterator for Throttle<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.items.next()?;
+        if self.per_second.is_finite() {
+            let now = Instant::now();
+            self.allowance = (self.allowance
+                + now.duration_since(self.last).as_secs_f64() * self.per_second)
+                .min(1.0);
+            self.last = now;
+            if self.allowance < 1.0 {
+                let wait = Duration::from_secs_f64((1.0 - self.allowance) / self.per_second);
+                thread::sleep(wait);
+                self.waited += wait;
+                self.allowance = 1.0;
+                self.last = Instant::now();
+            }
+            self.allowance -= 1.0;
+        }
+        Some(item)
+    }
+}
+
+/// Paces records by their [timestamp](TransactionRecord::timestamp), yielding each one
+/// `speed` times faster than it happened after the first, e.g. an hour of history in a minute
+/// at a speed of 60. This replays the bursts and lulls of historical files into a live system,
+/// unlike the steady rate of a [Throttle].
+///
+/// Records without a timestamp, older than the last one paced, or that failed to parse are
+/// yielded right away.
+pub struct Replay<I> {
+    records: I,
+    speed: f64,
+    /// When the first record with a timestamp was yielded, and its timestamp
+    start: Option<(Instant, i64)>,
+    waited: Duration,
+}
+
+impl<I, E> Replay<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    /// Replays `records` `speed` times faster than they happened. `f64::INFINITY` passes them
+    /// through without pacing.
+    pub fn new(records: impl IntoIterator<IntoIter = I>, speed: f64) -> Self {
+        Replay {
+            records: records.into_iter(),
+            speed,
+            start: None,
+            waited: Duration::ZERO,
+        }
+    }
+
+    /// The time spent sleeping until records were due so far
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+}
+
+impl<I, E> Iterator for Replay<I>
+where
+    I: Iterator<Item = Result<TransactionRecord, E>>,
+{
+    type Item = Result<TransactionRecord, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        if let (
+            true,
+            Ok(TransactionRecord {
+                timestamp: Some(timestamp),
+                ..
+            }),
+        ) = (self.speed.is_finite(), &record)
+        {
+            match self.start {
+                None => self.start = Some((Instant::now(), *timestamp)),
+                Some((start, first)) => {
+                    let offset = timestamp.saturating_sub(first).max(0) as f64 / self.speed;
+                    let due = start + Duration::from_secs_f64(offset);
+                    let now = Instant::now();
+                    if due > now {
+                        thread::sleep(due - now);
+                        self.waited += due - now;
+                    }
+                }
+            }
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionRecordType;
+
+    #[test]
+    fn test_throttle() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(0..21, 200.0);
+        assert_eq!((&mut throttle).count(), 21);
+        // the first item is yielded right away, the other 20 5ms apart
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(throttle.waited() > Duration::ZERO);
+
+        let mut unthrottled = Throttle::new(0..1000, f64::INFINITY);
+        assert_eq!((&mut unthrottled).count(), 1000);
+        assert_eq!(unthrottled.waited(), Duration::ZERO);
+    }
+
+    fn record(timestamp: Option<i64>) -> Result<TransactionRecord, ()> {
+        Ok(TransactionRecord {
+            timestamp,
+            ..TransactionRecord::new(TransactionRecordType::Deposit, 1, 1)
+        })
+    }
+
+    #[test]
+    fn test_replay() {
+        // 2 seconds of history at 20 times the speed, the late record isn't paced
+        let records = [
+            record(Some(100)),
+            record(None),
+            record(Some(101)),
+            record(Some(99)),
+            record(Some(102)),
+        ];
+        let start = Instant::now();
+        let mut replay = Replay::new(records.clone(), 20.0);
+        assert_eq!((&mut replay).count(), 5);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(replay.waited() > Duration::ZERO);
+
+        let mut unpaced = Replay::new(records, f64::INFINITY);
+        assert_eq!((&mut unpaced).count(), 5);
+        assert_eq!(unpaced.waited(), Duration::ZERO);
+    }
+}
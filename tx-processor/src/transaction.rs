@@ -1,15 +1,28 @@
-use std::collections::HashMap;
-use std::convert::TryFrom;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+#[cfg(feature = "csv")]
+use std::io;
 
 use rust_decimal::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::account::Account;
+#[cfg(feature = "csv")]
+use crate::amount::{parse_amount, AmountParsing};
+#[cfg(feature = "csv")]
+use crate::csv_limits::LimitedReader;
+use crate::disputable::Disputable;
 use crate::error::TxError;
+use crate::map::Map;
+#[cfg(feature = "csv")]
+use crate::types::{Resolution, TypeTable, UnknownTypeRecord};
 
 pub const NUM_DECIMAL_PLACES: u32 = 4;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionRecordType {
     Deposit,
@@ -17,38 +30,193 @@ pub enum TransactionRecordType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Admin record attaching the `note` column to an account as a flag, e.g. `under_review`
+    Flag,
+    /// Admin record removing the flag given in the `note` column from an account
+    Unflag,
+    /// Admin record remapping the client to the client id given in the `note` column, see
+    /// [Ledger::remap_client](crate::ledger::Ledger::remap_client)
+    Remap,
+    /// A type this crate doesn't know, named by the record's
+    /// [type_name](TransactionRecord::type_name) and applied by the
+    /// [TransactionHandler](crate::handlers::TransactionHandler) registered under that name
+    Other,
 }
 
-#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TransactionRecord {
     #[serde(rename = "type")]
     pub transaction_type: TransactionRecordType,
     #[serde(rename = "client")]
     pub client_id: u16,
+    /// The client's id in the system the record comes from, e.g. a UUID, replacing `client`
+    /// once the ledger resolved it, see [ClientAliases](crate::aliases::ClientAliases)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ref: Option<String>,
     #[serde(rename = "tx")]
     pub transaction_id: u32,
+    #[serde(default, deserialize_with = "crate::amount::deserialize")]
     pub amount: Option<Decimal>,
+    /// The merchant or other namespace this record belongs to, see [LedgerSet](crate::ledger_set::LedgerSet)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Free text used by admin records, see [TransactionRecordType::Flag], and the reason code
+    /// of disputes, resolves and chargebacks, see [Transaction::reason]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// A sequence number or timestamp the feed is ordered by, see
+    /// [ReorderBuffer](crate::reorder::ReorderBuffer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// The type as read, for [TransactionRecordType::Other] records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    /// The other party of the transaction, e.g. the employer of a payroll deposit, see
+    /// [Tagger](crate::tags::Tagger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterparty: Option<String>,
+    /// The category of the transaction, e.g. `payroll`, if the input or a
+    /// [Tagger](crate::tags::Tagger) assigned one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// When the transaction happened, in seconds since the Unix epoch, see
+    /// [Ledger::aggregate](crate::ledger::Ledger::aggregate)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    /// Set by the ingestor rather than read from a column, see [TransactionRecord::read_csv]
+    #[serde(skip)]
+    pub provenance: Option<Provenance>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Where an ingested [Transaction] was read from, so any balance can be traced back to the
+/// input that caused it
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The input file or stream, e.g. its path
+    pub source: Arc<str>,
+    /// The line of the source the record starts on
+    pub line: u64,
+    /// The byte the record starts at, counted in the decompressed source
+    #[serde(default)]
+    pub offset: u64,
+    /// The batch the source was ingested in, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<Arc<str>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Transaction {
     pub client_id: u16,
     pub transaction_id: u32,
     pub tx_type: TransactionType,
+    pub origin: Origin,
+    /// Where the transaction was read from. Kept by rejected and suspended transactions and
+    /// by chargebacks, see [ChargebackReport](crate::report::ChargebackReport), but dropped
+    /// from other transactions once they're retained in the log, so the log doesn't pay for
+    /// it on every row.
+    pub provenance: Option<Box<Provenance>>,
+    /// See [TransactionRecord::category]
+    pub category: Option<String>,
+    /// See [TransactionRecord::timestamp]
+    pub timestamp: Option<i64>,
+    /// Why a dispute was opened, resolved or charged back, e.g. `fraud`, read from the record's
+    /// `note` column, see [DisputeRules](crate::dispute_rules::DisputeRules)
+    pub reason: Option<String>,
+}
+
+/// Where a [Transaction] came from
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// Read from the input
+    #[default]
+    Ingested,
+    /// Created by the ledger's integrator, e.g. a fee, under an id from the ledger's
+    /// [TxIdAllocator](crate::tx_id::TxIdAllocator)
+    Synthetic,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TransactionType {
-    Deposit { amount: PositiveDecimal },
-    Withdrawal { amount: PositiveDecimal },
+    Deposit {
+        amount: PositiveDecimal,
+    },
+    Withdrawal {
+        amount: PositiveDecimal,
+    },
     Dispute,
     Resolve,
     Chargeback,
+    Flag {
+        flag: String,
+    },
+    Unflag {
+        flag: String,
+    },
+    /// See [TransactionRecordType::Remap]
+    Remap {
+        to: u16,
+    },
+    /// See [TransactionRecordType::Other]
+    Other {
+        name: String,
+        amount: Option<PositiveDecimal>,
+    },
 }
 
-#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg(not(feature = "fixed-point"))]
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PositiveDecimal(Decimal);
 
+/// With the `fixed-point` feature, amounts are counts of minor units, 1/10^[NUM_DECIMAL_PLACES]
+/// each, so the ledger's arithmetic is integer arithmetic. They are converted to [Decimal] for
+/// input and output, and amounts of over `u64::MAX` minor units are rejected.
+#[cfg(feature = "fixed-point")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PositiveDecimal {
+    units: u64,
+    /// Whether the amount has [NUM_DECIMAL_PLACES] places, like every amount but the default
+    /// zero and sums of it, which are written as `0` like a zero [Decimal]
+    scaled: bool,
+}
+
+#[cfg(feature = "fixed-point")]
+impl PartialEq for PositiveDecimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.units == other.units
+    }
+}
+
+#[cfg(feature = "fixed-point")]
+impl Eq for PositiveDecimal {}
+
+#[cfg(feature = "fixed-point")]
+impl PartialOrd for PositiveDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(feature = "fixed-point")]
+impl Ord for PositiveDecimal {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.units.cmp(&other.units)
+    }
+}
+
+#[cfg(feature = "fixed-point")]
+impl core::hash::Hash for PositiveDecimal {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.units.hash(state)
+    }
+}
+
+/// The operations a ledger performs on an account. `chargeback` consumes the account to change
+/// its typestate, so it is only available on sized implementors; everything else can be
+/// called through a `&mut dyn Transact`.
 pub trait Transact {
     fn deposit(&mut self, amount: PositiveDecimal) -> Result<(), TxError>;
 
@@ -57,25 +225,29 @@ pub trait Transact {
     fn dispute(
         &mut self,
         disputed_tx_id: u32,
-        transaction_log: &[Transaction],
-        disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        disputable: &Disputable,
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> Result<(), TxError>;
 
     fn resolve(
         &mut self,
         transaction_id: u32,
-        disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
     ) -> Result<(), TxError>;
 
     fn chargeback(
         self,
         transaction_id: u32,
-        disputed_tx_map: &mut HashMap<u32, (u16, PositiveDecimal)>,
-    ) -> (Result<Account<true>, TxError>, Option<Account<false>>);
+        disputed_tx_map: &mut Map<u32, (u16, PositiveDecimal)>,
+    ) -> (Result<Account<true>, TxError>, Option<Account<false>>)
+    where
+        Self: Sized;
 }
 
+#[cfg(not(feature = "fixed-point"))]
 impl TryFrom<Decimal> for PositiveDecimal {
     type Error = TxError;
+    #[cfg_attr(feature = "profiling", inline(never))]
     fn try_from(mut decimal: Decimal) -> Result<Self, Self::Error> {
         if decimal >= Decimal::ZERO {
             decimal.rescale(NUM_DECIMAL_PLACES);
@@ -86,6 +258,35 @@ impl TryFrom<Decimal> for PositiveDecimal {
     }
 }
 
+#[cfg(feature = "fixed-point")]
+impl TryFrom<Decimal> for PositiveDecimal {
+    type Error = TxError;
+    #[cfg_attr(feature = "profiling", inline(never))]
+    fn try_from(mut decimal: Decimal) -> Result<Self, Self::Error> {
+        if decimal < Decimal::ZERO {
+            return Err(TxError::InvalidAmount);
+        }
+        decimal.rescale(NUM_DECIMAL_PLACES);
+        // decimals of over 28 digits can't be rescaled, and don't fit anyway
+        if decimal.scale() != NUM_DECIMAL_PLACES {
+            return Err(TxError::InvalidAmount);
+        }
+        let units = u64::try_from(decimal.mantissa()).map_err(|_| TxError::InvalidAmount)?;
+        Ok(PositiveDecimal {
+            units,
+            scaled: true,
+        })
+    }
+}
+
+#[cfg(feature = "fixed-point")]
+impl Serialize for PositiveDecimal {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // as the derived implementation serializes the `Decimal` representation
+        serializer.serialize_newtype_struct("PositiveDecimal", &Decimal::from(*self))
+    }
+}
+
 impl TryFrom<f64> for PositiveDecimal {
     type Error = TxError;
     fn try_from(decimal: f64) -> Result<Self, Self::Error> {
@@ -93,7 +294,27 @@ impl TryFrom<f64> for PositiveDecimal {
     }
 }
 
+#[cfg(not(feature = "fixed-point"))]
+impl From<PositiveDecimal> for Decimal {
+    fn from(decimal: PositiveDecimal) -> Self {
+        decimal.0
+    }
+}
+
+#[cfg(feature = "fixed-point")]
+impl From<PositiveDecimal> for Decimal {
+    fn from(amount: PositiveDecimal) -> Self {
+        if amount.scaled {
+            Decimal::from_i128_with_scale(i128::from(amount.units), NUM_DECIMAL_PLACES)
+        } else {
+            Decimal::ZERO
+        }
+    }
+}
+
+#[cfg(not(feature = "fixed-point"))]
 impl PositiveDecimal {
+    #[cfg_attr(feature = "profiling", inline(never))]
     pub fn checked_add(self, other: PositiveDecimal) -> Result<PositiveDecimal, TxError> {
         self.0
             .checked_add(other.0)
@@ -101,6 +322,7 @@ impl PositiveDecimal {
             .ok_or(TxError::InvalidAmount)
     }
 
+    #[cfg_attr(feature = "profiling", inline(never))]
     pub fn checked_sub(self, other: PositiveDecimal) -> Result<PositiveDecimal, TxError> {
         if self >= other {
             self.0
@@ -113,20 +335,302 @@ impl PositiveDecimal {
     }
 }
 
+#[cfg(feature = "fixed-point")]
+impl PositiveDecimal {
+    #[cfg_attr(feature = "profiling", inline(never))]
+    pub fn checked_add(self, other: PositiveDecimal) -> Result<PositiveDecimal, TxError> {
+        let units = self
+            .units
+            .checked_add(other.units)
+            .ok_or(TxError::InvalidAmount)?;
+        Ok(self.with_units(other, units))
+    }
+
+    #[cfg_attr(feature = "profiling", inline(never))]
+    pub fn checked_sub(self, other: PositiveDecimal) -> Result<PositiveDecimal, TxError> {
+        let units = self
+            .units
+            .checked_sub(other.units)
+            .ok_or(TxError::InsufficientFunds)?;
+        Ok(self.with_units(other, units))
+    }
+
+    /// The result of an operation on `self` and `other`, scaled like a [Decimal] result, which
+    /// is the other operand as is when one of them is zero
+    fn with_units(self, other: PositiveDecimal, units: u64) -> PositiveDecimal {
+        let scaled = if self.units == 0 {
+            other.scaled
+        } else if other.units == 0 {
+            self.scaled
+        } else {
+            true
+        };
+        PositiveDecimal { units, scaled }
+    }
+}
+
 impl Transaction {
     pub fn new(client_id: u16, transaction_id: u32, tx_type: TransactionType) -> Self {
         Transaction {
             client_id,
             transaction_id,
             tx_type,
+            origin: Origin::Ingested,
+            provenance: None,
+            category: None,
+            timestamp: None,
+            reason: None,
+        }
+    }
+
+    /// A transaction created by the ledger's integrator rather than read from the input, see
+    /// [Ledger::add_synthetic_tx](crate::ledger::Ledger::add_synthetic_tx)
+    pub fn synthetic(client_id: u16, transaction_id: u32, tx_type: TransactionType) -> Self {
+        Transaction {
+            origin: Origin::Synthetic,
+            ..Transaction::new(client_id, transaction_id, tx_type)
+        }
+    }
+}
+
+impl TransactionRecordType {
+    /// The value of the `type` column
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionRecordType::Deposit => "deposit",
+            TransactionRecordType::Withdrawal => "withdrawal",
+            TransactionRecordType::Dispute => "dispute",
+            TransactionRecordType::Resolve => "resolve",
+            TransactionRecordType::Chargeback => "chargeback",
+            TransactionRecordType::Flag => "flag",
+            TransactionRecordType::Unflag => "unflag",
+            TransactionRecordType::Remap => "remap",
+            TransactionRecordType::Other => "other",
+        }
+    }
+}
+
+impl TransactionType {
+    /// The `type` column this transaction is read from
+    pub fn record_type(&self) -> TransactionRecordType {
+        match self {
+            TransactionType::Deposit { .. } => TransactionRecordType::Deposit,
+            TransactionType::Withdrawal { .. } => TransactionRecordType::Withdrawal,
+            TransactionType::Dispute => TransactionRecordType::Dispute,
+            TransactionType::Resolve => TransactionRecordType::Resolve,
+            TransactionType::Chargeback => TransactionRecordType::Chargeback,
+            TransactionType::Flag { .. } => TransactionRecordType::Flag,
+            TransactionType::Unflag { .. } => TransactionRecordType::Unflag,
+            TransactionType::Remap { .. } => TransactionRecordType::Remap,
+            TransactionType::Other { .. } => TransactionRecordType::Other,
         }
     }
 }
 
+impl From<Transaction> for TransactionRecord {
+    fn from(transaction: Transaction) -> Self {
+        let transaction_type = transaction.tx_type.record_type();
+        let (amount, note, type_name) = match transaction.tx_type {
+            TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                (Some(amount.into()), None, None)
+            }
+            TransactionType::Flag { flag } | TransactionType::Unflag { flag } => {
+                (None, Some(flag), None)
+            }
+            TransactionType::Remap { to } => (None, Some(to.to_string()), None),
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                (None, transaction.reason, None)
+            }
+            TransactionType::Other { name, amount } => (amount.map(Into::into), None, Some(name)),
+        };
+        TransactionRecord {
+            transaction_type,
+            client_id: transaction.client_id,
+            client_ref: None,
+            transaction_id: transaction.transaction_id,
+            amount,
+            namespace: None,
+            note,
+            seq: None,
+            type_name,
+            counterparty: None,
+            category: transaction.category,
+            timestamp: transaction.timestamp,
+            provenance: transaction.provenance.map(|provenance| *provenance),
+        }
+    }
+}
+
+impl TransactionRecord {
+    /// A record of `transaction_type` without any of the optional columns, which can be set
+    /// with struct update syntax
+    pub fn new(
+        transaction_type: TransactionRecordType,
+        client_id: u16,
+        transaction_id: u32,
+    ) -> Self {
+        TransactionRecord {
+            transaction_type,
+            client_id,
+            client_ref: None,
+            transaction_id,
+            amount: None,
+            namespace: None,
+            note: None,
+            seq: None,
+            type_name: None,
+            counterparty: None,
+            category: None,
+            timestamp: None,
+            provenance: None,
+        }
+    }
+
+    /// Deserializes the records of `reader`, which must have a header row, tagging each with
+    /// its line of `source` and with `batch`, parsing amounts with `amounts` and types with
+    /// `types`. Records exceeding the reader's [CsvLimits](crate::csv_limits::CsvLimits),
+    /// containing NUL bytes or with amounts `amounts` rejects are errors in their place. Records
+    /// `types` skips or sets aside are left out.
+    #[cfg(feature = "csv")]
+    pub fn read_csv<R: io::Read>(
+        mut reader: csv::Reader<LimitedReader<R>>,
+        source: &str,
+        batch: Option<&str>,
+        amounts: AmountParsing,
+        types: TypeTable,
+    ) -> Result<impl Iterator<Item = Result<TransactionRecord, csv::Error>>, csv::Error> {
+        let limits = *reader.get_ref().limits();
+        let skipped = reader.get_ref().skipped();
+        let mut headers = reader.byte_headers()?.clone();
+        limits.check(&headers)?;
+        let amount_column = headers.iter().position(|header| header == b"amount");
+        let type_column = headers.iter().position(|header| header == b"type");
+        let client_column = headers.iter().position(|header| header == b"client");
+        let client_ref_column = headers.iter().position(|header| header == b"client_ref");
+        // records of a client given by `client_ref` alone get a placeholder client id, which
+        // the ledger replaces once it resolved the reference
+        let client_placeholder = client_column.is_none() && client_ref_column.is_some();
+        if client_placeholder {
+            headers.push_field(b"client");
+        }
+        let source = Arc::<str>::from(source);
+        let batch = batch.map(Arc::<str>::from);
+        let read = move |record: Result<csv::ByteRecord, csv::Error>| {
+            // the placeholders of records skipped by the reader are reported as what they
+            // exceeded, whether or not the reader is flexible about their single field
+            let placeholder = match &record {
+                Ok(record) => record.len() == 1 && record[0].first() == Some(&0),
+                Err(e) => matches!(e.kind(), csv::ErrorKind::UnequalLengths { len: 1, .. }),
+            };
+            let offset = match &record {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            }
+            .map(|position| position.byte());
+            if placeholder {
+                let mut skipped = skipped.lock().expect("a thread panicked reading the input");
+                if let Some(front) = skipped.front() {
+                    if record.is_ok() || offset == Some(front.offset) {
+                        let limit = front.limit;
+                        skipped.pop_front();
+                        return Err(limit.into());
+                    }
+                }
+            }
+
+            let mut record = record?;
+            limits.check(&record)?;
+            let provenance = record.position().map(|position| Provenance {
+                source: source.clone(),
+                line: position.line(),
+                offset: position.byte(),
+                batch: batch.clone(),
+            });
+            let has_client_ref = client_ref_column
+                .and_then(|column| record.get(column))
+                .is_some_and(|client_ref| !client_ref.is_empty());
+            if client_placeholder {
+                record.push_field(if has_client_ref { b"0" } else { b"" });
+            } else if let Some(column) = client_column.filter(|_| has_client_ref) {
+                if record.get(column).is_some_and(<[u8]>::is_empty) {
+                    record = replace_field(&record, column, b"0");
+                }
+            }
+            let name = type_column
+                .and_then(|column| record.get(column))
+                .and_then(|name| core::str::from_utf8(name).ok())
+                .unwrap_or_default();
+            let mut type_name = None;
+            match types.resolve(name) {
+                Resolution::Keep => {}
+                Resolution::Rename(transaction_type) => {
+                    if transaction_type == TransactionRecordType::Other {
+                        type_name = Some(String::from(name));
+                    }
+                    if let Some(column) = type_column {
+                        record = replace_field(&record, column, transaction_type.name().as_bytes());
+                    }
+                }
+                Resolution::Skip => return Ok(None),
+                Resolution::Quarantine => {
+                    let mut unknown = record.deserialize::<UnknownTypeRecord>(Some(&headers))?;
+                    unknown.provenance = provenance;
+                    types.unknown_types().quarantined.push(unknown);
+                    return Ok(None);
+                }
+            }
+            let mut transaction = record.deserialize::<TransactionRecord>(Some(&headers))?;
+            if amounts == AmountParsing::Strict && transaction.amount.is_some() {
+                // deserialized leniently, so check what was written
+                let amount = amount_column
+                    .and_then(|column| record.get(column))
+                    .and_then(|amount| core::str::from_utf8(amount).ok())
+                    .unwrap_or_default();
+                transaction.amount = Some(
+                    parse_amount(amount, amounts)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                );
+            }
+            transaction.type_name = type_name.or(transaction.type_name);
+            transaction.provenance = provenance;
+            Ok(Some(transaction))
+        };
+        Ok(reader
+            .into_byte_records()
+            .map(read)
+            .filter_map(Result::transpose))
+    }
+}
+
+/// `record` with the field in `column` replaced by `value`
+#[cfg(feature = "csv")]
+fn replace_field(record: &csv::ByteRecord, column: usize, value: &[u8]) -> csv::ByteRecord {
+    let mut replaced = record
+        .iter()
+        .enumerate()
+        .map(|(index, field)| if index == column { value } else { field })
+        .collect::<csv::ByteRecord>();
+    replaced.set_position(record.position().cloned());
+    replaced
+}
+
 impl TryFrom<TransactionRecord> for Transaction {
     type Error = TxError;
-    fn try_from(record: TransactionRecord) -> Result<Self, Self::Error> {
-        match record.transaction_type {
+    #[cfg_attr(feature = "profiling", inline(never))]
+    fn try_from(mut record: TransactionRecord) -> Result<Self, Self::Error> {
+        // the client id is a placeholder until the ledger resolved the reference
+        if record.client_ref.is_some() {
+            return Err(TxError::UnresolvedClient);
+        }
+        let provenance = record.provenance.take().map(Box::new);
+        let category = record.category.take();
+        let reason = match record.transaction_type {
+            TransactionRecordType::Dispute
+            | TransactionRecordType::Resolve
+            | TransactionRecordType::Chargeback => record.note.take(),
+            _ => None,
+        };
+        let transaction: Result<Self, Self::Error> = match record.transaction_type {
             TransactionRecordType::Deposit => {
                 let amount = record.amount.map_or(Err(TxError::MissingAmount), |val| {
                     PositiveDecimal::try_from(val)
@@ -162,7 +666,48 @@ impl TryFrom<TransactionRecord> for Transaction {
                 record.transaction_id,
                 TransactionType::Chargeback,
             )),
-        }
+            TransactionRecordType::Flag => Ok(Transaction::new(
+                record.client_id,
+                record.transaction_id,
+                TransactionType::Flag {
+                    flag: record.note.ok_or(TxError::MissingNote)?,
+                },
+            )),
+            TransactionRecordType::Unflag => Ok(Transaction::new(
+                record.client_id,
+                record.transaction_id,
+                TransactionType::Unflag {
+                    flag: record.note.ok_or(TxError::MissingNote)?,
+                },
+            )),
+            TransactionRecordType::Remap => Ok(Transaction::new(
+                record.client_id,
+                record.transaction_id,
+                TransactionType::Remap {
+                    to: record
+                        .note
+                        .ok_or(TxError::MissingNote)?
+                        .trim()
+                        .parse()
+                        .map_err(|_| TxError::InvalidClientId)?,
+                },
+            )),
+            TransactionRecordType::Other => Ok(Transaction::new(
+                record.client_id,
+                record.transaction_id,
+                TransactionType::Other {
+                    name: record.type_name.ok_or(TxError::UnhandledTransactionType)?,
+                    amount: record.amount.map(PositiveDecimal::try_from).transpose()?,
+                },
+            )),
+        };
+        Ok(Transaction {
+            provenance,
+            category,
+            timestamp: record.timestamp,
+            reason,
+            ..transaction?
+        })
     }
 }
 
@@ -196,10 +741,27 @@ mod tests {
         let result = pos_decimal_1.checked_add(pos_decimal_2);
         assert!(result.is_ok());
 
+        #[cfg(not(feature = "fixed-point"))]
         let pos_decimal_1 = PositiveDecimal::try_from(Decimal::MAX).unwrap();
+        #[cfg(feature = "fixed-point")]
+        let pos_decimal_1 = PositiveDecimal::try_from(Decimal::new(i64::MAX, 4)).unwrap();
         let pos_decimal_2 = PositiveDecimal::try_from(10.111).unwrap();
         let result = pos_decimal_1.checked_add(pos_decimal_2);
+        #[cfg(not(feature = "fixed-point"))]
         assert!(result.is_err());
+        #[cfg(feature = "fixed-point")]
+        assert!(result.unwrap().checked_add(pos_decimal_1).is_err());
+    }
+
+    #[cfg(feature = "fixed-point")]
+    #[test]
+    fn test_fixed_point() {
+        assert!(matches!(
+            PositiveDecimal::try_from(Decimal::MAX),
+            Err(TxError::InvalidAmount)
+        ));
+        let amount = PositiveDecimal::try_from(Decimal::new(123456789, 5)).unwrap();
+        assert_eq!(Decimal::from(amount).to_string(), "1234.5679");
     }
 
     #[test]
@@ -220,10 +782,8 @@ mod tests {
     fn test_tx_try_from_deposit_tx_record() {
         let deposit_amount = Decimal::from_f64(100.002).unwrap();
         let valid_deposit_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Deposit,
-            client_id: 1,
-            transaction_id: 100,
             amount: Some(deposit_amount),
+            ..TransactionRecord::new(TransactionRecordType::Deposit, 1, 100)
         };
 
         let valid_deposit = Transaction::try_from(valid_deposit_record);
@@ -239,12 +799,7 @@ mod tests {
             )
         );
 
-        let invalid_deposit_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Deposit,
-            client_id: 1,
-            transaction_id: 100,
-            amount: None,
-        };
+        let invalid_deposit_record = TransactionRecord::new(TransactionRecordType::Deposit, 1, 100);
 
         let invalid_deposit = Transaction::try_from(invalid_deposit_record);
         assert!(invalid_deposit.is_err());
@@ -254,10 +809,8 @@ mod tests {
     fn test_tx_try_from_withdrawal_tx_record() {
         let withdrawal_amount = Decimal::from_f64(100.002).unwrap();
         let valid_withdrawal_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Withdrawal,
-            client_id: 1,
-            transaction_id: 100,
             amount: Some(withdrawal_amount),
+            ..TransactionRecord::new(TransactionRecordType::Withdrawal, 1, 100)
         };
 
         let valid_withdrawal = Transaction::try_from(valid_withdrawal_record);
@@ -273,12 +826,8 @@ mod tests {
             )
         );
 
-        let invalid_withdrawal_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Withdrawal,
-            client_id: 1,
-            transaction_id: 100,
-            amount: None,
-        };
+        let invalid_withdrawal_record =
+            TransactionRecord::new(TransactionRecordType::Withdrawal, 1, 100);
 
         let invalid_withdrawal = Transaction::try_from(invalid_withdrawal_record);
         assert!(invalid_withdrawal.is_err());
@@ -287,12 +836,7 @@ mod tests {
     #[test]
     fn test_tx_try_from_dispute_tx_record() {
         let dispute_amount = Decimal::from_f64(100.002).unwrap();
-        let valid_dispute_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Dispute,
-            client_id: 1,
-            transaction_id: 100,
-            amount: None,
-        };
+        let valid_dispute_record = TransactionRecord::new(TransactionRecordType::Dispute, 1, 100);
 
         let valid_dispute = Transaction::try_from(valid_dispute_record);
         assert!(valid_dispute.is_ok());
@@ -302,10 +846,8 @@ mod tests {
         );
 
         let invalid_dispute_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Dispute,
-            client_id: 1,
-            transaction_id: 100,
             amount: Some(dispute_amount),
+            ..TransactionRecord::new(TransactionRecordType::Dispute, 1, 100)
         };
 
         let valid_dispute = Transaction::try_from(invalid_dispute_record);
@@ -319,12 +861,7 @@ mod tests {
     #[test]
     fn test_tx_try_from_resolve_tx_record() {
         let resolve_amount = Decimal::from_f64(100.002).unwrap();
-        let valid_resolve_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Resolve,
-            client_id: 1,
-            transaction_id: 100,
-            amount: None,
-        };
+        let valid_resolve_record = TransactionRecord::new(TransactionRecordType::Resolve, 1, 100);
 
         let valid_resolve = Transaction::try_from(valid_resolve_record);
         assert!(valid_resolve.is_ok());
@@ -334,10 +871,8 @@ mod tests {
         );
 
         let invalid_resolve_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Resolve,
-            client_id: 1,
-            transaction_id: 100,
             amount: Some(resolve_amount),
+            ..TransactionRecord::new(TransactionRecordType::Resolve, 1, 100)
         };
 
         let valid_resolve = Transaction::try_from(invalid_resolve_record);
@@ -351,12 +886,8 @@ mod tests {
     #[test]
     fn test_tx_try_from_chargeback_tx_record() {
         let chargeback_amount = Decimal::from_f64(100.002).unwrap();
-        let valid_chargeback_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Chargeback,
-            client_id: 1,
-            transaction_id: 100,
-            amount: None,
-        };
+        let valid_chargeback_record =
+            TransactionRecord::new(TransactionRecordType::Chargeback, 1, 100);
 
         let valid_chargeback = Transaction::try_from(valid_chargeback_record);
         assert!(valid_chargeback.is_ok());
@@ -366,10 +897,8 @@ mod tests {
         );
 
         let invalid_chargeback_record = TransactionRecord {
-            transaction_type: TransactionRecordType::Chargeback,
-            client_id: 1,
-            transaction_id: 100,
             amount: Some(chargeback_amount),
+            ..TransactionRecord::new(TransactionRecordType::Chargeback, 1, 100)
         };
 
         let valid_chargeback = Transaction::try_from(invalid_chargeback_record);
@@ -379,4 +908,52 @@ mod tests {
             Transaction::new(1, 100, TransactionType::Chargeback)
         );
     }
+
+    #[test]
+    fn test_tx_try_from_flag_tx_record() {
+        let valid_flag_record = TransactionRecord {
+            note: Some("vip".to_owned()),
+            ..TransactionRecord::new(TransactionRecordType::Flag, 1, 100)
+        };
+
+        let valid_flag = Transaction::try_from(valid_flag_record);
+        assert_eq!(
+            valid_flag.unwrap(),
+            Transaction::new(
+                1,
+                100,
+                TransactionType::Flag {
+                    flag: "vip".to_owned()
+                }
+            )
+        );
+
+        let invalid_unflag_record = TransactionRecord::new(TransactionRecordType::Unflag, 1, 100);
+
+        let invalid_unflag = Transaction::try_from(invalid_unflag_record);
+        assert!(matches!(invalid_unflag, Err(TxError::MissingNote)));
+    }
+
+    #[test]
+    fn test_tx_record_round_trip() {
+        let amount = PositiveDecimal::try_from(1.5).unwrap();
+        for tx_type in [
+            TransactionType::Deposit { amount },
+            TransactionType::Withdrawal { amount },
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+            TransactionType::Flag {
+                flag: "vip".to_owned(),
+            },
+            TransactionType::Unflag {
+                flag: "vip".to_owned(),
+            },
+        ] {
+            let transaction = Transaction::new(1, 100, tx_type);
+            let record = TransactionRecord::from(transaction.clone());
+            assert_eq!(record.transaction_type, transaction.tx_type.record_type());
+            assert_eq!(Transaction::try_from(record).unwrap(), transaction);
+        }
+    }
 }
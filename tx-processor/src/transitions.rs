@@ -0,0 +1,270 @@
+//! The ledger's semantics as a table: the outcome of every transaction type, applied to every
+//! state an account and the transaction it refers to can be in.
+//!
+//! | account | referenced | deposit | withdrawal         | dispute          | resolve, chargeback | flag      | unflag    | remap          | other     |
+//! |---------|------------|---------|--------------------|------------------|---------------------|-----------|-----------|----------------|-----------|
+//! | missing | unknown    | applied | insufficient funds | not found        | not found           | not found | not found | unknown client | unhandled |
+//! | active  | unknown    | applied | applied            | not found        | not found           | applied   | not found | applied        | unhandled |
+//! | active  | undisputed | applied | applied            | applied          | not disputed        | applied   | not found | applied        | unhandled |
+//! | active  | disputed   | applied | applied            | already disputed | applied             | applied   | not found | applied        | unhandled |
+//! | active  | resolved   | applied | applied            | applied          | not disputed        | applied   | not found | applied        | unhandled |
+//! | locked  | any        | locked  | locked             | locked           | locked              | applied   | not found | locked         | locked    |
+//!
+//! A charged back transaction always belongs to a locked account. Other types are applied by
+//! the handlers registered for them, so a ledger without handlers rejects them as unhandled. [Case::all] enumerates every
+//! feasible combination and [Case::check] asserts it against [Case::expected], which encodes this
+//! table.
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+
+use rust_decimal::Decimal;
+
+use crate::error::TxError;
+use crate::ledger::Ledger;
+use crate::transaction::{PositiveDecimal, Transaction, TransactionRecordType, TransactionType};
+
+/// The client every case is applied to
+const CLIENT: u16 = 1;
+/// The deposit disputes, resolves and chargebacks refer to
+const REFERENCED_TX: u32 = 1;
+/// A deposit giving active accounts funds to withdraw whatever the referenced deposit's state
+const FUNDING_TX: u32 = 2;
+/// A deposit charged back to lock an account without touching the referenced deposit
+const LOCKING_TX: u32 = 3;
+/// The id of the deposits, withdrawals and admin records under test
+const NEW_TX: u32 = 9;
+/// The client remap records remap [CLIENT] to, which has no account
+const REMAP_TO: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountState {
+    Missing,
+    Active,
+    Locked,
+}
+
+/// The state of the deposit a dispute, resolve or chargeback refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferencedState {
+    Unknown,
+    Undisputed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+/// What applying a transaction should do
+#[derive(Debug)]
+pub enum Outcome {
+    Applied,
+    /// Rejected with an error of the same variant
+    Rejected(TxError),
+}
+
+impl Outcome {
+    fn matches(&self, result: &Result<(), TxError>) -> bool {
+        match (self, result) {
+            (Outcome::Applied, Ok(())) => true,
+            (Outcome::Rejected(expected), Err(actual)) => {
+                mem::discriminant(expected) == mem::discriminant(actual)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A transaction type applied to an account and referenced transaction in a given state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Case {
+    pub account: AccountState,
+    pub referenced: ReferencedState,
+    pub transaction_type: TransactionRecordType,
+}
+
+impl Case {
+    /// Every feasible combination: missing accounts have no transactions, and only locked
+    /// accounts have charged back ones
+    pub fn all() -> impl Iterator<Item = Case> {
+        const ACCOUNTS: [AccountState; 3] = [
+            AccountState::Missing,
+            AccountState::Active,
+            AccountState::Locked,
+        ];
+        const REFERENCED: [ReferencedState; 5] = [
+            ReferencedState::Unknown,
+            ReferencedState::Undisputed,
+            ReferencedState::Disputed,
+            ReferencedState::Resolved,
+            ReferencedState::ChargedBack,
+        ];
+        const TYPES: [TransactionRecordType; 9] = [
+            TransactionRecordType::Deposit,
+            TransactionRecordType::Withdrawal,
+            TransactionRecordType::Dispute,
+            TransactionRecordType::Resolve,
+            TransactionRecordType::Chargeback,
+            TransactionRecordType::Flag,
+            TransactionRecordType::Unflag,
+            TransactionRecordType::Remap,
+            TransactionRecordType::Other,
+        ];
+        ACCOUNTS
+            .into_iter()
+            .flat_map(|account| {
+                REFERENCED
+                    .into_iter()
+                    .map(move |referenced| (account, referenced))
+            })
+            .filter(|&(account, referenced)| match referenced {
+                ReferencedState::Unknown => true,
+                ReferencedState::ChargedBack => account == AccountState::Locked,
+                _ => account != AccountState::Missing,
+            })
+            .flat_map(|(account, referenced)| {
+                TYPES.into_iter().map(move |transaction_type| Case {
+                    account,
+                    referenced,
+                    transaction_type,
+                })
+            })
+    }
+
+    /// The transactions bringing a fresh ledger into this case's state
+    pub fn setup(&self) -> Vec<Transaction> {
+        let deposit = |transaction_id, amount: i64| {
+            Transaction::new(
+                CLIENT,
+                transaction_id,
+                TransactionType::Deposit {
+                    amount: PositiveDecimal::try_from(Decimal::from(amount)).unwrap(),
+                },
+            )
+        };
+        let referencing = |tx_type| Transaction::new(CLIENT, REFERENCED_TX, tx_type);
+
+        let mut setup = Vec::new();
+        if self.account == AccountState::Missing {
+            return setup;
+        }
+        setup.push(deposit(FUNDING_TX, 5));
+        if self.referenced != ReferencedState::Unknown {
+            setup.push(deposit(REFERENCED_TX, 10));
+        }
+        match self.referenced {
+            ReferencedState::Unknown | ReferencedState::Undisputed => {}
+            ReferencedState::Disputed => setup.push(referencing(TransactionType::Dispute)),
+            ReferencedState::Resolved => setup.extend([
+                referencing(TransactionType::Dispute),
+                referencing(TransactionType::Resolve),
+            ]),
+            ReferencedState::ChargedBack => setup.extend([
+                referencing(TransactionType::Dispute),
+                referencing(TransactionType::Chargeback),
+            ]),
+        }
+        if self.account == AccountState::Locked && self.referenced != ReferencedState::ChargedBack {
+            setup.extend([
+                deposit(LOCKING_TX, 1),
+                Transaction::new(CLIENT, LOCKING_TX, TransactionType::Dispute),
+                Transaction::new(CLIENT, LOCKING_TX, TransactionType::Chargeback),
+            ]);
+        }
+        setup
+    }
+
+    /// The transaction under test
+    pub fn transaction(&self) -> Transaction {
+        let amount = PositiveDecimal::try_from(Decimal::ONE).unwrap();
+        let (transaction_id, tx_type) = match self.transaction_type {
+            TransactionRecordType::Deposit => (NEW_TX, TransactionType::Deposit { amount }),
+            TransactionRecordType::Withdrawal => (NEW_TX, TransactionType::Withdrawal { amount }),
+            TransactionRecordType::Dispute => (REFERENCED_TX, TransactionType::Dispute),
+            TransactionRecordType::Resolve => (REFERENCED_TX, TransactionType::Resolve),
+            TransactionRecordType::Chargeback => (REFERENCED_TX, TransactionType::Chargeback),
+            TransactionRecordType::Flag => (
+                NEW_TX,
+                TransactionType::Flag {
+                    flag: "under_review".to_owned(),
+                },
+            ),
+            TransactionRecordType::Unflag => (
+                NEW_TX,
+                TransactionType::Unflag {
+                    flag: "under_review".to_owned(),
+                },
+            ),
+            TransactionRecordType::Remap => (NEW_TX, TransactionType::Remap { to: REMAP_TO }),
+            TransactionRecordType::Other => (
+                NEW_TX,
+                TransactionType::Other {
+                    name: "cashback".to_owned(),
+                    amount: Some(amount),
+                },
+            ),
+        };
+        Transaction::new(CLIENT, transaction_id, tx_type)
+    }
+
+    /// The outcome documented in the [module](self) table
+    pub fn expected(&self) -> Outcome {
+        use Outcome::*;
+        use TransactionRecordType::*;
+
+        match (self.account, self.transaction_type) {
+            // admin records annotate accounts whatever their state, and no case sets a flag
+            (AccountState::Missing, Flag) | (_, Unflag) => Rejected(TxError::NotFound),
+            (_, Flag) => Applied,
+            (AccountState::Missing, Remap) => Rejected(TxError::UnknownClient),
+            (AccountState::Locked, _) => Rejected(TxError::LockedAccount),
+            (_, Remap) => Applied,
+            (_, Other) => Rejected(TxError::UnhandledTransactionType),
+            (_, Deposit) => Applied,
+            (AccountState::Missing, Withdrawal) => Rejected(TxError::InsufficientFunds),
+            (_, Withdrawal) => Applied,
+            (_, Dispute | Resolve | Chargeback) => match (self.referenced, self.transaction_type) {
+                (ReferencedState::Unknown, _) => Rejected(TxError::NotFound),
+                (ReferencedState::Disputed, Dispute) => Rejected(TxError::AlreadyDisputed),
+                (ReferencedState::Disputed, _) | (_, Dispute) => Applied,
+                _ => Rejected(TxError::NotDisputed),
+            },
+        }
+    }
+
+    /// Applies this case's transaction to a fresh ledger in this case's state, and compares the
+    /// result with [Case::expected]
+    pub fn check(&self) -> Result<(), String> {
+        let mut ledger = Ledger::default();
+        for transaction in self.setup() {
+            ledger
+                .add_tx(transaction)
+                .map_err(|e| format!("{}: setup failed with {:?}", self, e))?;
+        }
+        let result = ledger.add_tx(self.transaction());
+        let expected = self.expected();
+        if expected.matches(&result) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: expected {:?}, got {:?}",
+                self, expected, result
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} to a {:?} account with a {:?} referenced transaction",
+            self.transaction_type.name(),
+            self.account,
+            self.referenced
+        )
+    }
+}
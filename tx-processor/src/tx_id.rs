@@ -0,0 +1,102 @@
+//! Ids for transactions a ledger creates itself, e.g. fees or interest, rather than reads
+//! from its input
+
+use core::fmt;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The ids handed out to synthetic transactions by default. Input transactions must not use
+/// them, see [TxError::ReservedTransactionId](crate::error::TxError::ReservedTransactionId).
+pub const SYNTHETIC_TX_IDS: Range<u32> = 0xF000_0000..u32::MAX;
+
+/// Hands out the ids of a reserved range, in order and each at most once. Allocation only
+/// needs a shared reference, so ids can be allocated from several threads at once.
+pub struct TxIdAllocator {
+    range: Range<u32>,
+    next: AtomicU32,
+}
+
+impl TxIdAllocator {
+    pub fn new(range: Range<u32>) -> Self {
+        TxIdAllocator {
+            next: AtomicU32::new(range.start),
+            range,
+        }
+    }
+
+    /// The next unused id, or `None` once the range is exhausted
+    pub fn allocate(&self) -> Option<u32> {
+        self.next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                (next < self.range.end).then(|| next + 1)
+            })
+            .ok()
+    }
+
+    /// `true` if `id` belongs to the reserved range, whether it was handed out yet or not
+    pub fn is_reserved(&self, id: u32) -> bool {
+        self.range.contains(&id)
+    }
+
+    /// Makes sure `id` is never handed out, e.g. because a restored ledger already used it
+    pub fn skip_past(&self, id: u32) {
+        if self.is_reserved(id) {
+            self.next.fetch_max(id + 1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for TxIdAllocator {
+    fn default() -> Self {
+        TxIdAllocator::new(SYNTHETIC_TX_IDS)
+    }
+}
+
+impl Clone for TxIdAllocator {
+    fn clone(&self) -> Self {
+        TxIdAllocator {
+            range: self.range.clone(),
+            next: AtomicU32::new(self.next.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl PartialEq for TxIdAllocator {
+    fn eq(&self, other: &Self) -> bool {
+        self.range == other.range
+            && self.next.load(Ordering::Relaxed) == other.next.load(Ordering::Relaxed)
+    }
+}
+
+impl Eq for TxIdAllocator {}
+
+impl fmt::Debug for TxIdAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TxIdAllocator")
+            .field("range", &self.range)
+            .field("next", &self.next.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_allocate() {
+        let allocator = TxIdAllocator::new(10..12);
+        assert!(!allocator.is_reserved(9));
+        assert!(allocator.is_reserved(11));
+        assert_eq!(allocator.allocate(), Some(10));
+        assert_eq!(allocator.allocate(), Some(11));
+        assert_eq!(allocator.allocate(), None);
+        assert_eq!(allocator.allocate(), None);
+
+        let allocator = TxIdAllocator::new(10..20);
+        allocator.skip_past(14);
+        allocator.skip_past(12);
+        allocator.skip_past(30);
+        assert_eq!(allocator.allocate(), Some(15));
+    }
+}
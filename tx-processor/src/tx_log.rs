@@ -0,0 +1,256 @@
+//! The log of the transactions a [Ledger](crate::ledger::Ledger) retained, stored compactly as
+//! it holds every applied transaction: amounts are interned in an [AmountPool], and the fields
+//! few transactions have are boxed together
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::intern::{AmountId, AmountPool};
+use crate::transaction::{Origin, Provenance, Transaction, TransactionType};
+
+/// The type of a retained transaction, with the amount of deposits and withdrawals interned
+#[derive(Debug, Clone)]
+enum Kind {
+    Deposit(AmountId),
+    Withdrawal(AmountId),
+    Dispute,
+    Resolve,
+    Chargeback,
+    /// Admin records and transactions of other types, whose type is kept in [Extra::tx_type]
+    Other,
+}
+
+/// The fields of a retained transaction that few transactions have
+#[derive(Debug, Default, Clone)]
+struct Extra {
+    tx_type: Option<TransactionType>,
+    provenance: Option<Box<Provenance>>,
+    category: Option<String>,
+    reason: Option<String>,
+}
+
+/// A retained transaction, taking 40 bytes rather than the 128 of a [Transaction]
+#[derive(Debug, Clone)]
+struct Entry {
+    client_id: u16,
+    transaction_id: u32,
+    kind: Kind,
+    origin: Origin,
+    timestamp: Option<i64>,
+    extra: Option<Box<Extra>>,
+}
+
+/// The transactions retained by a [Ledger](crate::ledger::Ledger), in the order they were
+/// applied, see [Ledger::transactions](crate::ledger::Ledger::transactions). Transactions are
+/// stored compactly and turned back into a [Transaction] when read, so reading one allocates
+/// only if it has a category, a reason, a provenance or an admin type.
+#[derive(Default, Clone)]
+pub struct TransactionLog {
+    entries: Vec<Entry>,
+    amounts: AmountPool,
+}
+
+impl TransactionLog {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The transaction at `position`, the oldest retained one at 0
+    pub fn get(&self, position: usize) -> Option<Transaction> {
+        self.entries
+            .get(position)
+            .map(|entry| entry.clone().into_transaction(&self.amounts))
+    }
+
+    /// The transactions, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Transaction> + ExactSizeIterator + '_ {
+        self.entries
+            .iter()
+            .map(|entry| entry.clone().into_transaction(&self.amounts))
+    }
+
+    pub fn to_vec(&self) -> Vec<Transaction> {
+        self.iter().collect()
+    }
+
+    /// The number of distinct amounts of the retained deposits and withdrawals
+    pub fn distinct_amounts(&self) -> usize {
+        self.amounts.len()
+    }
+
+    pub(crate) fn push(&mut self, transaction: Transaction) {
+        let (kind, tx_type) = match transaction.tx_type {
+            TransactionType::Deposit { amount } => {
+                (Kind::Deposit(self.amounts.intern(amount)), None)
+            }
+            TransactionType::Withdrawal { amount } => {
+                (Kind::Withdrawal(self.amounts.intern(amount)), None)
+            }
+            TransactionType::Dispute => (Kind::Dispute, None),
+            TransactionType::Resolve => (Kind::Resolve, None),
+            TransactionType::Chargeback => (Kind::Chargeback, None),
+            tx_type => (Kind::Other, Some(tx_type)),
+        };
+        let extra = Extra {
+            tx_type,
+            provenance: transaction.provenance,
+            category: transaction.category,
+            reason: transaction.reason,
+        };
+        let has_extra = extra.tx_type.is_some()
+            || extra.provenance.is_some()
+            || extra.category.is_some()
+            || extra.reason.is_some();
+        self.entries.push(Entry {
+            client_id: transaction.client_id,
+            transaction_id: transaction.transaction_id,
+            kind,
+            origin: transaction.origin,
+            timestamp: transaction.timestamp,
+            extra: has_extra.then(|| Box::new(extra)),
+        });
+    }
+
+    /// Drops the oldest `count` transactions, handing each to `forgotten`
+    pub(crate) fn forget(&mut self, count: usize, mut forgotten: impl FnMut(Transaction)) {
+        for entry in self.entries.drain(..count) {
+            let amount = match entry.kind {
+                Kind::Deposit(amount) | Kind::Withdrawal(amount) => Some(amount),
+                _ => None,
+            };
+            forgotten(entry.into_transaction(&self.amounts));
+            if let Some(amount) = amount {
+                self.amounts.release(amount);
+            }
+        }
+    }
+
+    /// Makes the transactions of `from` the transactions of `to`, see
+    /// [Ledger::remap_client](crate::ledger::Ledger::remap_client)
+    pub(crate) fn remap(&mut self, from: u16, to: u16) {
+        for entry in &mut self.entries {
+            if entry.client_id == from {
+                entry.client_id = to;
+            }
+        }
+    }
+}
+
+impl Entry {
+    fn into_transaction(self, amounts: &AmountPool) -> Transaction {
+        let Extra {
+            tx_type,
+            provenance,
+            category,
+            reason,
+        } = self.extra.map_or_else(Extra::default, |extra| *extra);
+        let tx_type = match self.kind {
+            Kind::Deposit(amount) => TransactionType::Deposit {
+                amount: amounts.get(amount),
+            },
+            Kind::Withdrawal(amount) => TransactionType::Withdrawal {
+                amount: amounts.get(amount),
+            },
+            Kind::Dispute => TransactionType::Dispute,
+            Kind::Resolve => TransactionType::Resolve,
+            Kind::Chargeback => TransactionType::Chargeback,
+            Kind::Other => tx_type.expect("the type of other transactions is kept"),
+        };
+        Transaction {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            tx_type,
+            origin: self.origin,
+            provenance,
+            category,
+            timestamp: self.timestamp,
+            reason,
+        }
+    }
+}
+
+impl FromIterator<Transaction> for TransactionLog {
+    fn from_iter<I: IntoIterator<Item = Transaction>>(transactions: I) -> Self {
+        let mut log = TransactionLog::default();
+        for transaction in transactions {
+            log.push(transaction);
+        }
+        log
+    }
+}
+
+/// Compared by transaction, whatever the ids of their amounts
+impl PartialEq for TransactionLog {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for TransactionLog {}
+
+impl fmt::Debug for TransactionLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::PositiveDecimal;
+
+    #[test]
+    fn test_transaction_log() {
+        assert_eq!(core::mem::size_of::<Entry>(), 40);
+
+        let amount = PositiveDecimal::try_from(10.0).unwrap();
+        let transactions = vec![
+            Transaction {
+                timestamp: Some(100),
+                ..Transaction::new(1, 1, TransactionType::Deposit { amount })
+            },
+            Transaction::synthetic(2, 2, TransactionType::Withdrawal { amount }),
+            Transaction {
+                reason: Some("fraud".into()),
+                ..Transaction::new(1, 1, TransactionType::Dispute)
+            },
+            Transaction {
+                category: Some("review".into()),
+                ..Transaction::new(
+                    1,
+                    3,
+                    TransactionType::Flag {
+                        flag: "under_review".into(),
+                    },
+                )
+            },
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Deposit {
+                    amount: amount.checked_add(amount).unwrap(),
+                },
+            ),
+        ];
+        let mut log = transactions.iter().cloned().collect::<TransactionLog>();
+        assert_eq!(log.to_vec(), transactions);
+        assert_eq!(log.get(3), Some(transactions[3].clone()));
+        assert_eq!(log.distinct_amounts(), 2);
+
+        // forgotten amounts are dropped once no transaction has them
+        let mut forgotten = Vec::new();
+        log.forget(2, |transaction| forgotten.push(transaction));
+        assert_eq!(forgotten, transactions[..2]);
+        assert_eq!(log.distinct_amounts(), 1);
+        assert_eq!(log.get(0), Some(transactions[2].clone()));
+
+        log.remap(1, 2);
+        assert!(log.iter().all(|transaction| transaction.client_id == 2));
+    }
+}
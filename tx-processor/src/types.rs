@@ -0,0 +1,113 @@
+//! Mapping of the `type` column of files from other processors onto [TransactionRecordType]s,
+//! e.g. `payment` onto deposits, so they can be ingested without a preprocessing script
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use std::sync::{Mutex, MutexGuard};
+
+use rust_decimal::Decimal;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+use crate::policies::UnknownTypePolicy;
+use crate::transaction::{Provenance, TransactionRecordType};
+
+/// What a name in the `type` column stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeAlias {
+    Type(TransactionRecordType),
+    /// Records of this type are counted and skipped, e.g. balance inquiries
+    Ignore,
+    /// Records of this type are read as [TransactionRecordType::Other], for the
+    /// [TransactionHandler](crate::handlers::TransactionHandler) registered under the same name
+    Other,
+}
+
+/// The names the `type` column may hold besides those of [TransactionRecordType], and what
+/// happens to records of other types, see
+/// [TransactionRecord::read_csv](crate::transaction::TransactionRecord::read_csv).
+///
+/// Clones share their [UnknownTypes], so a table can be cloned for each of several inputs.
+#[derive(Debug, Clone, Default)]
+pub struct TypeTable {
+    aliases: BTreeMap<String, TypeAlias>,
+    unknown: UnknownTypePolicy,
+    unknown_types: Arc<Mutex<UnknownTypes>>,
+}
+
+/// The records a [TypeTable] kept from the ledger
+#[derive(Debug, Default)]
+pub struct UnknownTypes {
+    /// The number of records skipped, by their type as read
+    pub skipped: BTreeMap<String, u64>,
+    /// The records set aside by [UnknownTypePolicy::Quarantine], in the order they were read
+    pub quarantined: Vec<UnknownTypeRecord>,
+}
+
+/// A record of an unknown type set aside by [UnknownTypePolicy::Quarantine]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UnknownTypeRecord {
+    #[serde(rename = "type")]
+    pub transaction_type: String,
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(rename = "tx")]
+    pub transaction_id: u32,
+    #[serde(default, deserialize_with = "crate::amount::deserialize")]
+    pub amount: Option<Decimal>,
+    #[serde(skip)]
+    pub provenance: Option<Provenance>,
+}
+
+/// What to do with a record, by its type
+pub(crate) enum Resolution {
+    /// Deserialize it as it is
+    Keep,
+    /// Deserialize it as a record of this type
+    Rename(TransactionRecordType),
+    Skip,
+    Quarantine,
+}
+
+impl TypeTable {
+    /// A table without aliases, applying `unknown` to records of unknown types
+    pub fn new(unknown: UnknownTypePolicy) -> Self {
+        TypeTable {
+            unknown,
+            ..TypeTable::default()
+        }
+    }
+
+    /// Makes `name` stand for `alias`. Names of [TransactionRecordType]s can't be aliased.
+    pub fn alias(mut self, name: &str, alias: TypeAlias) -> Self {
+        self.aliases.insert(name.into(), alias);
+        self
+    }
+
+    /// The records skipped and set aside so far, shared with every clone of this table
+    pub fn unknown_types(&self) -> MutexGuard<'_, UnknownTypes> {
+        self.unknown_types.lock().expect(POISONED)
+    }
+
+    pub(crate) fn resolve(&self, name: &str) -> Resolution {
+        let known: Result<TransactionRecordType, serde::de::value::Error> =
+            TransactionRecordType::deserialize(name.into_deserializer());
+        if known.is_ok() {
+            return Resolution::Keep;
+        }
+        match (self.aliases.get(name), self.unknown) {
+            (Some(TypeAlias::Type(transaction_type)), _) => Resolution::Rename(*transaction_type),
+            (Some(TypeAlias::Other), _) => Resolution::Rename(TransactionRecordType::Other),
+            (Some(TypeAlias::Ignore), _) | (None, UnknownTypePolicy::Skip) => {
+                *self.unknown_types().skipped.entry(name.into()).or_default() += 1;
+                Resolution::Skip
+            }
+            (None, UnknownTypePolicy::Quarantine) => Resolution::Quarantine,
+            (None, UnknownTypePolicy::Reject) => Resolution::Keep,
+        }
+    }
+}
+
+const POISONED: &str = "a thread panicked while holding the unknown types";
@@ -0,0 +1,141 @@
+//! A corpus of adversarial CSV inputs: each must be rejected with a specific error, without
+//! buffering more than the limits allow, while the well-formed records around it are read
+use csv::{ReaderBuilder, Trim};
+use tx_processor::amount::AmountParsing;
+use tx_processor::csv_limits::{CsvLimits, InputLimit, LimitedReader};
+use tx_processor::error::TxError;
+use tx_processor::transaction::TransactionRecord;
+use tx_processor::types::TypeTable;
+
+const HEADER: &str = "type,client,tx,amount,note\n";
+
+/// A record read, as its line number, or an error, as the limit it exceeded if any
+type Read = Result<u64, Option<InputLimit>>;
+
+/// The records read from `body`, by a reader that is `flexible` about the number of fields
+fn read(body: &[u8], flexible: bool) -> Vec<Read> {
+    let input = [HEADER.as_bytes(), body].concat();
+    let reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(flexible)
+        .from_reader(LimitedReader::new(input.as_slice(), CsvLimits::default()));
+    TransactionRecord::read_csv(
+        reader,
+        "corpus.csv",
+        None,
+        AmountParsing::Lenient,
+        TypeTable::default(),
+    )
+    .unwrap()
+    .map(|record| match record {
+        Ok(record) => Ok(record.provenance.unwrap().line),
+        Err(e) => Err(match e.kind() {
+            csv::ErrorKind::Io(e) => match e.get_ref().and_then(|e| e.downcast_ref()) {
+                Some(TxError::InputLimitExceeded(limit)) => Some(*limit),
+                _ => None,
+            },
+            _ => None,
+        }),
+    })
+    .collect()
+}
+
+fn corpus() -> Vec<(&'static str, Vec<u8>, Vec<Read>)> {
+    let deposit = "deposit,1,1,1.0,\n";
+    vec![
+        (
+            "extremely long field",
+            format!("deposit,1,1,1.0,{}\n{}", "x".repeat(2000), deposit).into_bytes(),
+            vec![
+                Err(Some(InputLimit::FieldLength {
+                    line: 2,
+                    field: 5,
+                    max: 1024,
+                })),
+                Ok(3),
+            ],
+        ),
+        (
+            "extremely long record spanning lines",
+            format!(
+                "{}deposit,1,2,1.0,\"{}\"\n{}",
+                deposit,
+                "line\n".repeat(10_000),
+                deposit
+            )
+            .into_bytes(),
+            vec![
+                Ok(2),
+                Err(Some(InputLimit::RecordLength {
+                    line: 3,
+                    max: 16 * 1024,
+                })),
+                Ok(10_004),
+            ],
+        ),
+        (
+            "millions of columns",
+            format!("deposit,1,1,1.0{}\n{}", ",".repeat(2_000_000), deposit).into_bytes(),
+            vec![
+                Err(Some(InputLimit::RecordLength {
+                    line: 2,
+                    max: 16 * 1024,
+                })),
+                Ok(3),
+            ],
+        ),
+        (
+            "too many columns",
+            format!("deposit,1,1,1.0{}\n{}", ",".repeat(100), deposit).into_bytes(),
+            vec![
+                Err(Some(InputLimit::FieldCount {
+                    line: 2,
+                    count: 104,
+                    max: 64,
+                })),
+                Ok(3),
+            ],
+        ),
+        (
+            "embedded NUL",
+            format!("deposit,1,1,1.0,a\0b\n{}", deposit).into_bytes(),
+            vec![Err(Some(InputLimit::NulByte { line: 2, field: 5 })), Ok(3)],
+        ),
+        (
+            "huge numeric string",
+            format!("deposit,1,1,{}\n{}", "9".repeat(1000), deposit).into_bytes(),
+            vec![Err(None), Ok(3)],
+        ),
+        (
+            "record too long at the end of the input, without a newline",
+            format!("{}deposit,1,1,{}", deposit, "1".repeat(20_000)).into_bytes(),
+            vec![
+                Ok(2),
+                Err(Some(InputLimit::RecordLength {
+                    line: 3,
+                    max: 16 * 1024,
+                })),
+            ],
+        ),
+    ]
+}
+
+#[test]
+fn test_adversarial_corpus() {
+    for (name, body, expected) in corpus() {
+        assert_eq!(read(&body, true), expected, "{}", name);
+    }
+
+    // placeholders of skipped records are reported the same by strict readers
+    let body = format!("deposit,1,1,{}\ndeposit,1,2,1.0,\n", "1".repeat(20_000));
+    assert_eq!(
+        read(body.as_bytes(), false),
+        vec![
+            Err(Some(InputLimit::RecordLength {
+                line: 2,
+                max: 16 * 1024
+            })),
+            Ok(3)
+        ]
+    );
+}
@@ -0,0 +1,171 @@
+//! Ordering sensitivity of the ledger: every perturbation of a valid stream is applied and the
+//! documented policy for its class is asserted
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use tx_processor::account::AccountView;
+use tx_processor::chaos::{check_conservation, Perturbation};
+use tx_processor::ledger::Ledger;
+use tx_processor::scenario::Scenario;
+use tx_processor::transaction::{Transaction, TransactionType};
+
+fn valid_stream() -> Vec<Transaction> {
+    Scenario::new()
+        .client(1)
+        .deposit(100)
+        .client(2)
+        .deposit(50)
+        .client(1)
+        .withdraw(20)
+        .dispute_last()
+        .client(2)
+        .deposit(Decimal::new(125, 1))
+        .dispute_last()
+        .client(1)
+        .resolve_last()
+        .client(2)
+        .chargeback_last()
+        .client(3)
+        .deposit(7)
+        .withdraw(2)
+        .transactions()
+        .to_vec()
+}
+
+fn process(transactions: Vec<Transaction>) -> Ledger {
+    let mut ledger = Ledger::default();
+    ledger.process_transactions(transactions);
+    ledger
+}
+
+/// `(available, held, locked)` of every client
+fn balances(ledger: &Ledger) -> HashMap<u16, (Decimal, Decimal, bool)> {
+    let active = ledger
+        .active_accounts()
+        .values()
+        .map(|a| a as &dyn AccountView);
+    let locked = ledger
+        .locked_accounts()
+        .values()
+        .map(|a| a as &dyn AccountView);
+    active
+        .chain(locked)
+        .map(|a| {
+            let balance = (a.available().into(), a.held().into(), a.is_locked());
+            (a.client_id(), balance)
+        })
+        .collect()
+}
+
+fn is_dispute_lifecycle(transaction: &Transaction) -> bool {
+    matches!(
+        transaction.tx_type,
+        TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+    )
+}
+
+#[test]
+fn test_every_perturbation_conserves_funds() {
+    let stream = valid_stream();
+    for perturbation in Perturbation::all(stream.len()) {
+        let ledger = process(perturbation.apply(&stream));
+        assert!(
+            check_conservation(&ledger).is_ok(),
+            "{:?} broke conservation",
+            perturbation
+        );
+    }
+}
+
+/// Policy: dispute, resolve and chargeback records are idempotent, a duplicate is rejected
+#[test]
+fn test_duplicated_dispute_lifecycle_records_are_ignored() {
+    let stream = valid_stream();
+    let expected = balances(&process(stream.clone()));
+    for (index, _) in stream
+        .iter()
+        .enumerate()
+        .filter(|(_, tx)| is_dispute_lifecycle(tx))
+    {
+        let ledger = process(Perturbation::Duplicate(index).apply(&stream));
+        assert_eq!(balances(&ledger), expected, "duplicate of row {}", index);
+        assert_eq!(ledger.transactions().len(), stream.len());
+    }
+}
+
+/// Policy: deposits and withdrawals are not deduplicated, a duplicate is applied again
+#[test]
+fn test_duplicated_funds_records_are_reapplied() {
+    let stream = valid_stream();
+    for (index, _) in stream
+        .iter()
+        .enumerate()
+        .filter(|(_, tx)| !is_dispute_lifecycle(tx))
+    {
+        let ledger = process(Perturbation::Duplicate(index).apply(&stream));
+        let copies = ledger
+            .transactions()
+            .iter()
+            .filter(|tx| *tx == stream[index])
+            .count();
+        assert_eq!(copies, 2, "duplicate of row {}", index);
+    }
+}
+
+/// Policy: without its dispute, a resolve or chargeback is rejected as not found
+#[test]
+fn test_dropped_dispute_orphans_its_lifecycle() {
+    let stream = valid_stream();
+    for (index, dispute) in stream
+        .iter()
+        .enumerate()
+        .filter(|(_, tx)| tx.tx_type == TransactionType::Dispute)
+    {
+        let ledger = process(Perturbation::Drop(index).apply(&stream));
+        assert!(ledger
+            .transactions()
+            .iter()
+            .all(|tx| !is_dispute_lifecycle(&tx) || tx.transaction_id != dispute.transaction_id));
+        // client 2's dispute is the only one that gets charged back
+        if dispute.client_id == 2 {
+            assert!(ledger.locked_accounts().is_empty());
+        }
+    }
+}
+
+/// Policy: clients are independent, so reordering records of different clients never changes
+/// any balance
+#[test]
+fn test_swapping_different_clients_is_harmless() {
+    let stream = valid_stream();
+    let expected = balances(&process(stream.clone()));
+    for index in 0..stream.len() - 1 {
+        if stream[index].client_id == stream[index + 1].client_id {
+            continue;
+        }
+        let ledger = process(Perturbation::SwapWithNext(index).apply(&stream));
+        assert_eq!(balances(&ledger), expected, "swap of rows {}", index);
+    }
+}
+
+/// Policy: a dispute-lifecycle record delivered before the record it references is rejected
+#[test]
+fn test_lifecycle_record_before_its_target_is_rejected() {
+    let stream = valid_stream();
+    for index in 0..stream.len() - 1 {
+        let (first, second) = (&stream[index], &stream[index + 1]);
+        if first.client_id != second.client_id
+            || !is_dispute_lifecycle(second)
+            || first.transaction_id != second.transaction_id
+        {
+            continue;
+        }
+        let ledger = process(Perturbation::SwapWithNext(index).apply(&stream));
+        let applied = ledger
+            .transactions()
+            .iter()
+            .filter(|tx| tx == second)
+            .count();
+        assert_eq!(applied, 0, "swap of rows {}", index);
+    }
+}
@@ -0,0 +1,103 @@
+//! Linearizability of a [SharedLedger] per client: threads applying transactions to the same
+//! clients concurrently leave every shard as if its transactions had been applied one at a time
+use std::thread;
+
+use rust_decimal::Decimal;
+use tx_processor::account::AccountView;
+use tx_processor::ledger::Ledger;
+use tx_processor::shared_ledger::SharedLedger;
+use tx_processor::transaction::{PositiveDecimal, Transaction, TransactionType};
+
+const THREADS: u32 = 8;
+const CLIENTS: u16 = 64;
+const ROUNDS: u32 = 20;
+
+/// The transactions a thread submits for a client in one round. Each leaves the client with 7
+/// more available and none held, and none can overdraw the account, so every one is applied
+/// whatever the interleaving.
+fn round(thread: u32, client_id: u16, round: u32) -> Vec<Transaction> {
+    let amount = |amount: i64| PositiveDecimal::try_from(Decimal::from(amount)).unwrap();
+    let tx = |index: u32| {
+        ((thread * ROUNDS + round) * u32::from(CLIENTS) + u32::from(client_id)) * 3 + index
+    };
+    vec![
+        Transaction::new(
+            client_id,
+            tx(0),
+            TransactionType::Deposit { amount: amount(10) },
+        ),
+        Transaction::new(
+            client_id,
+            tx(1),
+            TransactionType::Deposit { amount: amount(5) },
+        ),
+        Transaction::new(
+            client_id,
+            tx(2),
+            TransactionType::Withdrawal { amount: amount(8) },
+        ),
+        Transaction::new(client_id, tx(1), TransactionType::Dispute),
+        Transaction::new(client_id, tx(1), TransactionType::Resolve),
+    ]
+}
+
+/// Every transaction a thread submits, interleaving its clients
+fn submitted(thread: u32) -> Vec<Transaction> {
+    (0..ROUNDS)
+        .flat_map(|r| (0..CLIENTS).flat_map(move |client_id| round(thread, client_id, r)))
+        .collect()
+}
+
+/// The thread that submitted `transaction`, from its id
+fn submitted_by(transaction: &Transaction) -> u32 {
+    transaction.transaction_id / 3 / u32::from(CLIENTS) / ROUNDS
+}
+
+#[test]
+fn test_concurrent_clients_are_linearizable() {
+    let ledger = SharedLedger::new(Ledger::default(), 4);
+    thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let ledger = &ledger;
+            scope.spawn(move || {
+                for transaction in submitted(thread) {
+                    ledger.add_tx(transaction).unwrap();
+                }
+            });
+        }
+    });
+
+    for client_id in 0..CLIENTS {
+        ledger.with_client(client_id, |shard| {
+            let account = shard.active_accounts().get(&client_id).unwrap();
+            assert_eq!(
+                Decimal::from(account.available()),
+                Decimal::from(7 * THREADS * ROUNDS)
+            );
+            assert_eq!(Decimal::from(account.held()), Decimal::ZERO);
+        });
+    }
+
+    for (index, shard) in ledger.into_shards().into_iter().enumerate() {
+        // the shard's log is a sequential history: replaying it gives the same shard
+        let mut replayed = Ledger::default();
+        for transaction in shard.transactions().iter() {
+            replayed.add_tx(transaction).unwrap();
+        }
+        assert_eq!(replayed, shard);
+
+        // which preserves the order each thread submitted its transactions in
+        for thread in 0..THREADS {
+            let expected: Vec<_> = submitted(thread)
+                .into_iter()
+                .filter(|transaction| usize::from(transaction.client_id) % 4 == index)
+                .collect();
+            let applied: Vec<_> = shard
+                .transactions()
+                .iter()
+                .filter(|transaction| submitted_by(transaction) == thread)
+                .collect();
+            assert_eq!(applied, expected);
+        }
+    }
+}
@@ -1,6 +1,21 @@
 use csv::{ReaderBuilder, Trim};
+use rust_decimal::Decimal;
+use std::fs::File;
+use tx_processor::account::AccountView;
+use tx_processor::aliases::SequentialClientIds;
+use tx_processor::amount::AmountParsing;
+use tx_processor::csv_limits::{CsvLimits, LimitedReader};
+use tx_processor::error::TxError;
+use tx_processor::handlers::{HandlerAccount, TransactionHandler};
+use tx_processor::io::{CsvOptions, CsvSource, MemorySource};
 use tx_processor::ledger::Ledger;
-use tx_processor::transaction::{PositiveDecimal, Transaction, TransactionType};
+use tx_processor::policies::{MissingAmountPolicy, Policies, UnknownTypePolicy};
+use tx_processor::scenario::Scenario;
+use tx_processor::transaction::{
+    PositiveDecimal, Provenance, Transaction, TransactionRecord, TransactionRecordType,
+    TransactionType,
+};
+use tx_processor::types::{TypeAlias, TypeTable};
 
 fn make_simple_tx() -> Vec<Transaction> {
     let amount_1 = PositiveDecimal::try_from(1.0000).unwrap();
@@ -19,13 +34,36 @@ fn make_simple_tx() -> Vec<Transaction> {
 fn test_simple_transactions() {
     let mut reader = ReaderBuilder::new()
         .trim(Trim::All)
-        .from_path(&"../resources/input/tx-input1.csv")
+        .from_path("../resources/input/tx-input1.csv")
         .unwrap();
     let mut ledger = Ledger::default();
     ledger.process_csv_transactions(reader.deserialize());
 
     let txs = make_simple_tx();
-    assert_eq!(ledger.transactions(), &txs);
+    assert_eq!(ledger.transactions().to_vec(), txs);
+}
+
+#[test]
+fn test_process_source() {
+    let mut source = CsvSource::new(
+        File::open("../resources/input/tx-input1.csv").unwrap(),
+        "tx-input1.csv",
+        &CsvOptions::default(),
+    )
+    .unwrap();
+    let mut ledger = Ledger::default();
+    let progress = ledger.process_source(&mut source);
+    assert_eq!((progress.applied, progress.rejected), (4, 1));
+
+    // the same transactions, from memory
+    let mut memory = make_simple_tx().into_iter().collect::<MemorySource>();
+    let mut from_memory = Ledger::default();
+    from_memory.process_source(&mut memory);
+    assert_eq!(from_memory.accounts().count(), 2);
+    for account in ledger.accounts() {
+        let other = from_memory.account(account.client_id()).unwrap();
+        assert_eq!(account.available(), other.available());
+    }
 }
 
 #[test]
@@ -33,7 +71,7 @@ fn test_invalid_record() {
     let mut reader = ReaderBuilder::new()
         .flexible(true)
         .trim(Trim::All)
-        .from_path(&"../resources/input/bad-record.csv")
+        .from_path("../resources/input/bad-record.csv")
         .unwrap();
     let mut ledger = Ledger::default();
     ledger.process_csv_transactions(reader.deserialize());
@@ -45,7 +83,7 @@ fn test_invalid_tx_struct() {
     let mut reader = ReaderBuilder::new()
         .flexible(true)
         .trim(Trim::All)
-        .from_path(&"../resources/input/invalid-transaction.csv")
+        .from_path("../resources/input/invalid-transaction.csv")
         .unwrap();
     let mut ledger = Ledger::default();
     ledger.process_csv_transactions(reader.deserialize());
@@ -57,7 +95,7 @@ fn test_resolve() {
     let mut reader = ReaderBuilder::new()
         .flexible(true)
         .trim(Trim::All)
-        .from_path(&"../resources/input/resolve.csv")
+        .from_path("../resources/input/resolve.csv")
         .unwrap();
     let mut ledger = Ledger::default();
     ledger.process_csv_transactions(reader.deserialize());
@@ -73,7 +111,7 @@ fn test_resolve() {
     txs.push(tx_2);
     txs.push(tx_3);
     txs.push(tx_4);
-    assert_eq!(ledger.transactions(), &txs);
+    assert_eq!(ledger.transactions().to_vec(), txs);
     assert_eq!(ledger.active_accounts().len(), 3);
     assert_eq!(ledger.locked_accounts().len(), 0);
 }
@@ -83,7 +121,7 @@ fn test_chargeback() {
     let mut reader = ReaderBuilder::new()
         .flexible(true)
         .trim(Trim::All)
-        .from_path(&"../resources/input/chargeback.csv")
+        .from_path("../resources/input/chargeback.csv")
         .unwrap();
     let mut ledger = Ledger::default();
     ledger.process_csv_transactions(reader.deserialize());
@@ -101,7 +139,367 @@ fn test_chargeback() {
     txs.push(tx_3);
     txs.push(tx_4);
 
-    assert_eq!(ledger.transactions(), &txs);
+    assert_eq!(ledger.transactions().to_vec(), txs);
     assert_eq!(ledger.active_accounts().len(), 2);
     assert_eq!(ledger.locked_accounts().len(), 1);
 }
+
+#[test]
+fn test_chargeback_scenario() {
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .trim(Trim::All)
+        .from_path("../resources/input/chargeback.csv")
+        .unwrap();
+    let mut ledger = Ledger::default();
+    ledger.process_csv_transactions(reader.deserialize());
+
+    let scenario = Scenario::new()
+        .client(1)
+        .deposit(1)
+        .client(2)
+        .deposit(2)
+        .client(1)
+        .deposit(2)
+        .withdraw(Decimal::new(15, 1))
+        .client(2)
+        .withdraw(3)
+        .client(3)
+        .deposit(2000)
+        .withdraw(10)
+        .dispute_last()
+        .chargeback_last();
+    scenario.verify(&ledger).unwrap();
+}
+
+#[test]
+fn test_provenance() {
+    let reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_reader(LimitedReader::new(
+            File::open("../resources/input/chargeback.csv").unwrap(),
+            CsvLimits::default(),
+        ));
+    let mut ledger = Ledger::default();
+    ledger.process_csv_transactions(
+        TransactionRecord::read_csv(
+            reader,
+            "chargeback.csv",
+            Some("batch-1"),
+            AmountParsing::Strict,
+            TypeTable::default(),
+        )
+        .unwrap(),
+    );
+
+    let provenance = |line, offset| Provenance {
+        source: "chargeback.csv".into(),
+        line,
+        offset,
+        batch: Some("batch-1".into()),
+    };
+    // only the chargeback keeps its provenance in the log
+    assert_eq!(
+        ledger
+            .transactions()
+            .iter()
+            .map(|tx| tx.provenance.as_deref().map(|provenance| provenance.line))
+            .collect::<Vec<_>>(),
+        [None, None, None, None, None, None, None, Some(10)]
+    );
+    assert_eq!(
+        ledger.chargeback_report().unwrap().chargebacks[0].provenance,
+        Some(provenance(10, 186))
+    );
+    // the rejected withdrawal is on line 6
+    assert_eq!(
+        ledger.quarantined()[0].transaction.provenance.as_deref(),
+        Some(&provenance(6, 104))
+    );
+}
+
+#[test]
+fn test_missing_amount_policies() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,
+withdrawal,1,3,
+withdrawal,1,4
+dispute,1,2,
+";
+    let process = |policies: Policies| {
+        let mut ledger = Ledger::builder().policies(&policies).build().unwrap();
+        let mut reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        ledger.process_csv_transactions(reader.deserialize());
+        ledger
+    };
+
+    let ledger = process(Policies::default());
+    assert_eq!(ledger.transactions().len(), 1);
+    assert_eq!(ledger.missing_amounts()[&TransactionRecordType::Deposit], 1);
+    assert_eq!(
+        ledger.missing_amounts()[&TransactionRecordType::Withdrawal],
+        2
+    );
+    assert!(ledger.incomplete().is_empty());
+
+    // a placeholder deposit can be disputed, a quarantined withdrawal is set aside
+    let ledger = process(Policies {
+        missing_deposit_amounts: MissingAmountPolicy::Zero,
+        missing_withdrawal_amounts: MissingAmountPolicy::Quarantine,
+        ..Policies::default()
+    });
+    assert_eq!(
+        ledger
+            .transactions()
+            .iter()
+            .map(|tx| tx.transaction_id)
+            .collect::<Vec<_>>(),
+        [1, 2, 2]
+    );
+    assert_eq!(
+        ledger
+            .incomplete()
+            .iter()
+            .map(|record| record.transaction_id)
+            .collect::<Vec<_>>(),
+        [3, 4]
+    );
+    let account = &ledger.active_accounts()[&1];
+    assert_eq!(Decimal::from(account.available()), Decimal::from(10));
+}
+
+#[test]
+fn test_type_aliases() {
+    let input = "\
+type,client,tx,amount
+payment,1,1,10.0
+cashout,1,2,4.0
+inquiry,1,3,
+bonus,1,4,1.0
+inquiry,1,5,
+deposit,1,6,1.0
+";
+    let read = |unknown| {
+        let types = TypeTable::new(unknown)
+            .alias("payment", TypeAlias::Type(TransactionRecordType::Deposit))
+            .alias(
+                "cashout",
+                TypeAlias::Type(TransactionRecordType::Withdrawal),
+            )
+            .alias("inquiry", TypeAlias::Ignore);
+        let reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(LimitedReader::new(input.as_bytes(), CsvLimits::default()));
+        let records = TransactionRecord::read_csv(
+            reader,
+            "processor.csv",
+            None,
+            AmountParsing::Lenient,
+            types.clone(),
+        )
+        .unwrap()
+        .collect::<Vec<_>>();
+        (records, types)
+    };
+    let ids = |records: &[Result<TransactionRecord, csv::Error>]| {
+        records
+            .iter()
+            .map(|record| {
+                record
+                    .as_ref()
+                    .map(|record| (record.transaction_type, record.transaction_id))
+                    .ok()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let (records, types) = read(UnknownTypePolicy::Reject);
+    assert_eq!(
+        ids(&records),
+        [
+            Some((TransactionRecordType::Deposit, 1)),
+            Some((TransactionRecordType::Withdrawal, 2)),
+            None,
+            Some((TransactionRecordType::Deposit, 6)),
+        ]
+    );
+    assert_eq!(types.unknown_types().skipped["inquiry"], 2);
+
+    let (records, types) = read(UnknownTypePolicy::Quarantine);
+    assert_eq!(records.len(), 3);
+    let unknown = types.unknown_types();
+    let quarantined = &unknown.quarantined[0];
+    assert_eq!(quarantined.transaction_type, "bonus");
+    assert_eq!(quarantined.amount, Some(Decimal::from(1)));
+    assert_eq!(quarantined.provenance.as_ref().unwrap().line, 5);
+    drop(unknown);
+
+    let (records, types) = read(UnknownTypePolicy::Skip);
+    assert_eq!(records.len(), 3);
+    assert_eq!(types.unknown_types().skipped["bonus"], 1);
+    assert!(types.unknown_types().quarantined.is_empty());
+}
+
+/// Pays out a reversal: the amount is credited back, but held until reviewed
+struct Reversal;
+
+impl TransactionHandler for Reversal {
+    fn apply(
+        &self,
+        account: &mut HandlerAccount,
+        _transaction: &Transaction,
+        amount: Option<PositiveDecimal>,
+    ) -> Result<(), TxError> {
+        let amount = amount.ok_or(TxError::MissingAmount)?;
+        account.deposit(amount)?;
+        account.hold(amount)
+    }
+}
+
+#[test]
+fn test_custom_handler() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+reversal,1,2,4.0
+reversal,1,3,
+";
+    let types = TypeTable::default().alias("reversal", TypeAlias::Other);
+    let reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_reader(LimitedReader::new(input.as_bytes(), CsvLimits::default()));
+    let mut ledger = Ledger::builder()
+        .handler("reversal", Reversal)
+        .build()
+        .unwrap();
+    let progress = ledger.process_csv_transactions(
+        TransactionRecord::read_csv(reader, "input.csv", None, AmountParsing::Lenient, types)
+            .unwrap(),
+    );
+
+    assert_eq!(progress.applied, 2);
+    let account = &ledger.active_accounts()[&1];
+    assert_eq!(Decimal::from(account.available()), Decimal::from(10));
+    assert_eq!(Decimal::from(account.held()), Decimal::from(4));
+    assert_eq!(
+        ledger.transactions().get(1).unwrap().tx_type,
+        TransactionType::Other {
+            name: "reversal".into(),
+            amount: Some(PositiveDecimal::try_from(4.0).unwrap()),
+        }
+    );
+}
+
+#[test]
+fn test_daily_balances() {
+    // 2024-01-01 and 2024-01-03, with an untimed and a late record on the 3rd
+    let input = "\
+type,client,tx,amount,timestamp
+deposit,1,1,10.0,1704067200
+deposit,2,2,5.0,1704070800
+withdrawal,1,3,4.0,1704240000
+deposit,2,4,1.0,
+deposit,1,5,2.0,1704067300
+";
+    let mut ledger = Ledger::default();
+    let mut reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .from_reader(input.as_bytes());
+    ledger.process_csv_transactions(reader.deserialize());
+
+    let balances = ledger
+        .daily_balances()
+        .into_iter()
+        .map(|balance| {
+            (
+                balance.date,
+                balance.client_id,
+                Decimal::from(balance.available),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        balances,
+        vec![
+            ("2024-01-01".to_string(), 1, Decimal::from(10)),
+            ("2024-01-01".to_string(), 2, Decimal::from(5)),
+            ("2024-01-03".to_string(), 1, Decimal::from(8)),
+            ("2024-01-03".to_string(), 2, Decimal::from(6)),
+        ]
+    );
+}
+
+#[test]
+fn test_client_refs() {
+    let read = |input: &'static str| {
+        let reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(LimitedReader::new(input.as_bytes(), CsvLimits::default()));
+        TransactionRecord::read_csv(
+            reader,
+            "input.csv",
+            None,
+            AmountParsing::Lenient,
+            TypeTable::default(),
+        )
+        .unwrap()
+    };
+    let balance = |ledger: &Ledger, client_id| {
+        ledger
+            .account(client_id)
+            .map(|account| Decimal::from(account.available()))
+    };
+
+    // client 1 comes from a source numbering its clients, the others from one that doesn't
+    let input = "\
+type,client,client_ref,tx,amount
+deposit,,cus_8f2a,1,10.0
+deposit,1,,2,5.0
+deposit,,b7e1c1c4-0d5e-4a3b-9a57-3f1e6f0c2d11,3,7.0
+withdrawal,,cus_8f2a,4,4.0
+deposit,,,5,1.0
+";
+    let mut ledger = Ledger::builder()
+        .client_id_resolver(SequentialClientIds::new(1000..=1999))
+        .build()
+        .unwrap();
+    let progress = ledger.process_csv_transactions(read(input));
+    assert_eq!((progress.applied, progress.rejected), (4, 1));
+    assert_eq!(balance(&ledger, 1000), Some(Decimal::from(6)));
+    assert_eq!(balance(&ledger, 1), Some(Decimal::from(5)));
+    assert_eq!(balance(&ledger, 1001), Some(Decimal::from(7)));
+    assert_eq!(
+        ledger.client_aliases().iter().collect::<Vec<_>>(),
+        [
+            ("b7e1c1c4-0d5e-4a3b-9a57-3f1e6f0c2d11", 1001),
+            ("cus_8f2a", 1000)
+        ]
+    );
+    assert_eq!(ledger.resolve_client_ref("cus_8f2a").unwrap(), 1000);
+
+    // without a resolver only known references resolve, and the client column may be left out
+    let input = "\
+type,client_ref,tx,amount
+deposit,cus_8f2a,1,10.0
+deposit,cus_0c9d,2,5.0
+";
+    let mut ledger = Ledger::default();
+    let progress = ledger.process_csv_transactions(read(input));
+    assert_eq!((progress.applied, progress.rejected), (0, 2));
+    assert!(matches!(
+        ledger.resolve_client_ref("cus_8f2a"),
+        Err(TxError::UnresolvedClient)
+    ));
+    // records with a reference aren't transactions until the ledger resolved it
+    let record = read(input).next().unwrap().unwrap();
+    assert!(matches!(
+        Transaction::try_from(record),
+        Err(TxError::UnresolvedClient)
+    ));
+}
@@ -0,0 +1,22 @@
+//! The documented outcome of every transaction type in every account and dispute state, see
+//! `tx_processor::transitions`
+use tx_processor::transitions::{AccountState, Case, ReferencedState};
+
+#[test]
+fn test_state_transition_table() {
+    let failures = Case::all()
+        .filter_map(|case| case.check().err())
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_every_feasible_state_is_covered() {
+    let cases = Case::all().collect::<Vec<_>>();
+    // 1 missing, 4 active and 5 locked states, times 9 transaction types
+    assert_eq!(cases.len(), (1 + 4 + 5) * 9);
+    assert!(!cases
+        .iter()
+        .any(|case| case.account == AccountState::Missing
+            && case.referenced != ReferencedState::Unknown));
+}